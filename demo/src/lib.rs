@@ -1,24 +1,26 @@
 use maverick_os::{Application, Context, start};
-use maverick_os::air::{self, Contract, Reactants, Reactant, Instance, Name, Service, Services, Listner};
-use maverick_os::air::names::Id;
+use maverick_os::air::{self, Contract, Contracts, Reactants, Reactant, Governed, Guard, Policy, Rule, Name, Id, Service, Services, async_trait, from};
 use maverick_os::window::{self, Input, KeyEvent, Renderer, Handle};
 
 use std::time::Duration;
 
 use serde::{Serialize, Deserialize};
 
+/// Quotes every new message posted to a room.
 #[derive(Default)]
-pub struct ChatBot(Listner<Room>);
+pub struct ChatBot(usize);
+#[async_trait]
 impl Service for ChatBot {
     async fn run(&mut self, ctx: &mut air::Context) -> Option<Duration> {
-        if let (room, Some(update)) = self.0.listen(ctx).await
-        && let Some(msg_idx) = update.as_reactant::<_, SendMessage>() {
-            let message = room.confirmed().unwrap().messages.get(msg_idx).unwrap().clone();
-            if !message.body.contains("ChatBot Quoting") {
-                room.apply(SendMessage(format!("ChatBot Quoting {} Saying: \"{}\"", message.author, message.body)));
+        if let Some(id) = ctx.list(&Room::id()).pop()
+        && let Some(room) = ctx.get::<Room>(&id).and_then(|s| from::<Room>(s).ok()) {
+            for message in room.messages.iter().skip(self.0).filter(|m| !m.body.contains("ChatBot Quoting")) {
+                let quote = SendMessage(format!("ChatBot Quoting {} Saying: \"{}\"", message.author, message.body));
+                if let Err(e) = ctx.send(id, "/", quote) {log::error!("ChatBot could not reply: {e}");}
             }
+            self.0 = room.messages.len();
         }
-        Some(Duration::from_secs(0))
+        Some(Duration::from_millis(250))
     }
 }
 
@@ -51,6 +53,10 @@ impl Contract for Room {
         Reactants::default().add::<SendMessage>()
     }
 }
+impl Governed for Room {
+    fn policy() -> Policy<Room> {Policy::default().route("/messages", Rule::Anyone)}
+    fn guard(guard: Guard<Room>) -> Guard<Room> {guard.add::<SendMessage>()}
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SendMessage(String);
@@ -75,7 +81,7 @@ impl<'surface> Renderer<'surface> for DemoRenderer<'surface> {
     }
 }
 
-pub struct DemoApplication(Instance<Room>);
+pub struct DemoApplication(Id);
 impl Application for DemoApplication {
     type Renderer<'surface> = DemoRenderer<'surface>;
 
@@ -83,17 +89,18 @@ impl Application for DemoApplication {
       //ctx.air.register::<Room>();
       //std::thread::sleep(Duration::from_secs(1));
       //let room = ctx.air.list::<Room>().pop().unwrap();
-        let room = ctx.air.create::<Room>("The Room".to_string());
+        let room = ctx.air.create::<Room>("The Room".to_string()).unwrap();
         DemoApplication(room)
     }
-    fn on_input(&mut self, _ctx: &mut Context, input: Input) {
+    fn on_input(&mut self, ctx: &mut Context, input: Input) {
         if let Input::Keyboard{event: KeyEvent{text: Some(text), ..}, ..} = input {
-            self.0.apply(SendMessage(text.to_string()));
-            log::info!("\n\n\n\n\n\n\n\n\n\n\n\n\nRoom: {:#?}", self.0.pending());
+            if let Err(e) = ctx.air.send(self.0, "/", SendMessage(text.to_string())) {log::error!("Could not send message: {e}");}
+            log::info!("\n\n\n\n\n\n\n\n\n\n\n\n\nRoom: {:#?}", ctx.air.get::<Room>(&self.0));
         }
     }
     
-    fn services() -> Services {Services::default().add(ChatBot::default())}
+    fn contracts() -> Contracts {Contracts::default().add::<Room>()}
+    fn services() -> Services {vec![Box::new(ChatBot::default())]}
 }
 
 start!(DemoApplication);
@@ -0,0 +1,315 @@
+use proc_macro2::{TokenStream, Ident};
+use quote::{quote, quote_spanned};
+use syn::Type;
+
+use crate::parse::{Contract, Reactant, Rule, Stmt, Cond, Cmp, Expr, Path, Base, Segment};
+use crate::schema::{Schema, last};
+
+fn lang() -> TokenStream {quote!(::maverick_os::air::lang)}
+
+fn path(path: &Path) -> TokenStream {
+    let lang = lang();
+    let segments = path.segments.iter().map(|s| match s {
+        Segment::Key(key) => quote!(#key.to_string()),
+        Segment::Arg(None) => quote!(#lang::segment(&__value)?),
+        Segment::Arg(Some(field)) => quote!(#lang::segment(&#lang::field(&__value, #field)?)?),
+    });
+    let ups = match path.base {
+        Base::Root => quote!(None),
+        Base::Relative(ups) => quote!(Some(#ups)),
+    };
+    quote!(#lang::resolve(&__at, #ups, vec![#(#segments),*])?)
+}
+
+fn expr(e: &Expr) -> TokenStream {
+    let lang = lang();
+    match e {
+        Expr::Value => quote!(__value.clone()),
+        Expr::Field(field) => quote!(#lang::field(&__value, #field)?),
+        Expr::Author => quote!(__author.clone()),
+        Expr::Timestamp => quote!(__timestamp.clone()),
+        Expr::Path(p) if p.exists => {let p = path(p); quote!(#lang::Value::Bool(#lang::exists(&__root, &#p)))},
+        Expr::Path(p) => {let p = path(p); quote!(#lang::read(&__root, &#p)?.clone())},
+        Expr::Lit(l) => quote!(#lang::to_value(&#l)?),
+        Expr::Neg(l) => quote!(#lang::to_value(&-#l)?),
+        Expr::Bool(b) => quote!(#lang::Value::Bool(#b)),
+        Expr::Null => quote!(#lang::Value::Null),
+        Expr::Array(items) => {
+            let items = items.iter().map(expr);
+            quote!(#lang::Value::Array(vec![#(#items),*]))
+        },
+        Expr::Map(fields) => {
+            let (keys, values): (Vec<_>, Vec<_>) = fields.iter().map(|(k, v)| (k.to_string(), expr(v))).unzip();
+            quote!({
+                let mut __map = #lang::Map::new();
+                #(__map.insert(#keys.to_string(), #values);)*
+                #lang::Value::Object(__map)
+            })
+        },
+        Expr::Struct(name, fields) => {
+            let (keys, values): (Vec<_>, Vec<_>) = fields.iter().map(|(k, v)| (k.to_string(), expr(v))).unzip();
+            quote!({
+                let mut __map = #lang::Map::new();
+                #(__map.insert(#keys.to_string(), #values);)*
+                #lang::to_value(&#lang::from_value::<#name>(#lang::Value::Object(__map))?)?
+            })
+        }
+    }
+}
+
+/// Builds `e` as a Rust value of `ty` for `init`, so an init that does not fit the state fails to compile.
+fn typed(schema: &Schema, e: &Expr, ty: Option<&Type>) -> TokenStream {
+    let shape = ty.and_then(last);
+    match (e, shape.as_ref().map(|(name, args)| (name.as_str(), args.as_slice()))) {
+        (Expr::Value, _) => quote!(::std::convert::Into::into(init.clone())),
+        (Expr::Field(field), _) => {
+            let field = syn::parse_str::<syn::Member>(field).expect("parsed as a field");
+            quote!(::std::convert::Into::into(init.#field.clone()))
+        },
+        (Expr::Author, _) => quote!(::std::convert::Into::into(signer.clone())),
+        (Expr::Timestamp, _) => quote!(::std::convert::Into::into(timestamp)),
+        (Expr::Null, _) => quote!(None),
+        (e, Some(("Option", [inner]))) => {let e = typed(schema, e, Some(inner)); quote!(Some(#e))},
+        (e, Some(("Box", [inner]))) => {let e = typed(schema, e, Some(inner)); quote!(::std::boxed::Box::new(#e))},
+        (Expr::Lit(l), _) if l.to_string().starts_with('"') => quote!(::std::convert::From::from(#l)),
+        (Expr::Lit(l), _) => quote!(#l),
+        (Expr::Neg(l), _) => quote!(-#l),
+        (Expr::Bool(b), _) => quote!(#b),
+        (Expr::Array(items), _) if items.is_empty() => quote!(::std::default::Default::default()),
+        (Expr::Array(items), shape) => {
+            let item = match shape {Some((_, [item])) => Some(*item), _ => None};
+            let items = items.iter().map(|e| typed(schema, e, item));
+            quote!(::std::iter::FromIterator::from_iter([#(#items),*]))
+        },
+        (Expr::Map(fields), Some((name, []))) if let Some(s) = schema.get(name) => construct(schema, &s.name, fields),
+        (Expr::Map(fields), _) if fields.is_empty() => quote!(::std::default::Default::default()),
+        (Expr::Map(fields), shape) => {
+            let value = match shape {Some((_, [_, value])) => Some(*value), _ => None};
+            let (keys, values): (Vec<_>, Vec<_>) = fields.iter().map(|(k, v)| (k.to_string(), typed(schema, v, value))).unzip();
+            quote!(::std::iter::FromIterator::from_iter([#((::std::convert::From::from(#keys), #values)),*]))
+        },
+        (Expr::Struct(name, fields), _) => construct(schema, name, fields),
+        (Expr::Path(_), _) => unreachable!("paths are rejected in init by the schema check"),
+    }
+}
+
+/// A struct literal of the declared struct `name`, optional fields that are not given are `None`.
+fn construct(schema: &Schema, name: &Ident, fields: &[(Ident, Expr)]) -> TokenStream {
+    let declared = schema.get(&name.to_string()).expect("checked");
+    let given = fields.iter().map(|(ident, e)| {
+        let ty = declared.fields.iter().find(|f| f.ident.as_ref() == Some(ident)).map(|f| &f.ty);
+        let e = typed(schema, e, ty);
+        quote_spanned!(ident.span()=> #ident: #e)
+    });
+    let missing = declared.fields.iter().filter_map(|f| f.ident.as_ref()).filter(|f| !fields.iter().any(|(i, _)| i == *f));
+    quote!(#name{#(#given,)* #(#missing: None),*})
+}
+
+fn cond(c: &Cond) -> TokenStream {
+    let lang = lang();
+    match c {
+        Cond::Cmp(a, Cmp::Eq, b) => {let (a, b) = (expr(a), expr(b)); quote!((#a == #b))},
+        Cond::Cmp(a, Cmp::Ne, b) => {let (a, b) = (expr(a), expr(b)); quote!((#a != #b))},
+        Cond::Cmp(a, op, b) => {
+            let (a, b) = (expr(a), expr(b));
+            let ordering = match op {
+                Cmp::Lt => quote!(Some(::std::cmp::Ordering::Less)),
+                Cmp::Le => quote!(Some(::std::cmp::Ordering::Less | ::std::cmp::Ordering::Equal)),
+                Cmp::Gt => quote!(Some(::std::cmp::Ordering::Greater)),
+                _ => quote!(Some(::std::cmp::Ordering::Greater | ::std::cmp::Ordering::Equal)),
+            };
+            quote!(matches!(#lang::compare(&#a, &#b), #ordering))
+        },
+        Cond::In(a, b) => {let (a, b) = (expr(a), expr(b)); quote!(#lang::contains(&#b, &#a))},
+        Cond::And(a, b) => {let (a, b) = (cond(a), cond(b)); quote!((#a && #b))},
+        Cond::Or(a, b) => {let (a, b) = (cond(a), cond(b)); quote!((#a || #b))},
+        Cond::Not(c) => {let c = cond(c); quote!((!#c))},
+        Cond::Expr(e) => {let e = expr(e); quote!(#lang::truthy(&#e))},
+    }
+}
+
+fn stmts(list: &[Stmt]) -> TokenStream {
+    let lang = lang();
+    list.iter().map(|s| match s {
+        Stmt::Write(e, p) | Stmt::Push(e, p) | Stmt::Insert(e, p) | Stmt::Remove(e, p) => {
+            let op = match s {
+                Stmt::Write(..) => quote!(write),
+                Stmt::Push(..) => quote!(push),
+                Stmt::Insert(..) => quote!(insert),
+                _ => quote!(remove),
+            };
+            let (e, p) = (expr(e), path(p));
+            quote!({
+                let __item = #e;
+                let __path = #p;
+                #lang::#op(&mut __root, &__path, __item)?;
+            })
+        },
+        Stmt::Delete(p) => {let p = path(p); quote!({let __path = #p; #lang::delete(&mut __root, &__path)?;})},
+        Stmt::Read(p) => {let p = path(p); quote!(#lang::read(&__root, &#p)?;)},
+        Stmt::If(c, then, otherwise) => {
+            let (c, then, otherwise) = (cond(c), stmts(then), stmts(otherwise));
+            quote!(if #c {#then} else {#otherwise})
+        },
+        Stmt::Ensure(c, text) => {
+            let c = cond(c);
+            quote!(if !#c {return Err(#lang::Rejected::Condition(#text.to_string()));})
+        },
+        Stmt::Reject(reason) => quote!(return Err(#lang::Rejected::Reason(#reason.to_string()));),
+    }).collect()
+}
+
+fn rule(r: &Rule) -> TokenStream {
+    let policy = quote!(::maverick_os::air::policy);
+    match r {
+        Rule::Anyone => quote!(#policy::Rule::Anyone),
+        Rule::Nobody => quote!(#policy::Rule::Nobody),
+        Rule::Owner(p) => {let p = p.to_string(); quote!(#policy::Rule::owner(#p))},
+        Rule::Member(p) => {let p = p.to_string(); quote!(#policy::Rule::member(#p))},
+        Rule::Role(p, role) => {let p = p.to_string(); quote!(#policy::Rule::role(#p, ::maverick_os::air::members::Role::#role))},
+        Rule::Any(rules) => {let rules = rules.iter().map(rule); quote!(#policy::Rule::Any(vec![#(#rules),*]))},
+        Rule::All(rules) => {let rules = rules.iter().map(rule); quote!(#policy::Rule::All(vec![#(#rules),*]))},
+    }
+}
+
+fn reactant(contract: &Ident, routes: &[(String, &Ident)], r: &Reactant) -> TokenStream {
+    let lang = lang();
+    let name = &r.name;
+    let id = name.to_string();
+    let args = &r.args;
+    let ty = match args.as_slice() {
+        [single] => quote!(#single),
+        args => quote!((#(#args),*)),
+    };
+    let bound = routes.iter().filter(|(_, n)| *n == name).map(|(p, _)| p.as_str()).collect::<Vec<_>>();
+    let new = match bound.as_slice() {
+        [only] if !only.contains('*') => quote!(pub fn new(value: #ty) -> Self {#name{at: #only.to_string(), value}}),
+        _ => quote!()
+    };
+    let body = stmts(&r.body);
+    quote! {
+        #[derive(#lang::serde::Serialize, #lang::serde::Deserialize, Clone, Debug)]
+        #[serde(crate = "::maverick_os::air::lang::serde")]
+        pub struct #name {
+            pub at: String,
+            pub value: #ty,
+        }
+        impl #name {
+            pub const ROUTES: &'static [&'static str] = &[#(#bound),*];
+
+            /// Targets the concrete path `at`, which must match one of the `ROUTES`.
+            pub fn at(at: impl Into<String>, value: #ty) -> Self {#name{at: at.into(), value}}
+            #new
+        }
+        impl ::maverick_os::air::Reactant<#contract> for #name {
+            type Result = Result<(), #lang::Rejected>;
+
+            fn id() -> ::maverick_os::air::Id {::maverick_os::air::Id::hash(#id)}
+
+            #[allow(unreachable_code, unused_variables, clippy::all)]
+            fn apply(self, state: &mut #contract, signer: ::maverick_os::air::Name, timestamp: u64) -> Self::Result {
+                let __at = #lang::route(&self.at, Self::ROUTES)?;
+                let __value = #lang::to_value(&self.value)?;
+                let __author = #lang::to_value(&signer)?;
+                let __timestamp = #lang::Value::from(timestamp);
+                let mut __root = #lang::to_value(&*state)?;
+                #body
+                *state = #lang::from_value(__root)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The type of a built-in reactant for `contract`.
+fn builtin(name: &Ident, contract: &TokenStream) -> Option<TokenStream> {
+    match name.to_string().as_str() {
+        "Revert" => Some(quote!(::maverick_os::air::undo::Revert<#contract>)),
+        "Splice" => Some(quote!(::maverick_os::air::text::Splice<#contract>)),
+        "Membership" => Some(quote!(::maverick_os::air::members::Membership<#contract>)),
+        _ => None,
+    }
+}
+
+pub fn contract(c: &Contract) -> TokenStream {
+    let lang = lang();
+    let structs = c.structs.iter().map(|s| {
+        let (attrs, name) = (&s.attrs, &s.name);
+        let fields = s.fields.iter().map(|f| {
+            let (attrs, ident, ty) = (&f.attrs, &f.ident, &f.ty);
+            quote!(#(#attrs)* pub #ident: #ty)
+        });
+        quote! {
+            #(#attrs)*
+            #[derive(#lang::serde::Serialize, #lang::serde::Deserialize, Clone, Debug)]
+            #[serde(crate = "::maverick_os::air::lang::serde")]
+            pub struct #name {#(#fields),*}
+        }
+    });
+
+    let contract = &c.structs[0].name;
+    let id = contract.to_string();
+    let init = match &c.init {
+        Some(init) => {
+            let ty = &init.ty;
+            let state = construct(&Schema::new(c).expect("checked"), contract, &init.fields);
+            quote! {
+                type Init = #ty;
+                #[allow(unused_variables, clippy::all)]
+                fn init(init: Self::Init, signer: ::maverick_os::air::Name, timestamp: u64) -> Self {#state}
+            }
+        },
+        None => quote! {
+            type Init = Self;
+            fn init(init: Self::Init, _signer: ::maverick_os::air::Name, _timestamp: u64) -> Self {init}
+        }
+    };
+
+    let routes = c.routes.iter().flat_map(|r| r.reactants.iter().map(|n| (r.path.clone(), n))).collect::<Vec<_>>();
+    let table = routes.iter().map(|(p, n)| {let n = n.to_string(); quote!((#p, #n))});
+    let names = c.reactants.iter().map(|r| &r.name);
+    let guarded = names.clone();
+    let builtins = c.builtins.iter().filter_map(|n| builtin(n, &quote!(Self))).collect::<Vec<_>>();
+    let reactants = c.reactants.iter().map(|r| reactant(contract, &routes, r));
+    let access = c.access.iter().map(|a| {
+        let (route, r) = (&a.route.path, rule(&a.rule));
+        match a.route.reactants.as_slice() {
+            [] => quote!(.route(#route, #r)),
+            names => {
+                let names = names.iter().map(|n| builtin(n, &quote!(#contract)).unwrap_or_else(|| quote!(#n)));
+                quote!(.reactants(#route, &[#(<#names as ::maverick_os::air::Reactant<#contract>>::id()),*], #r))
+            },
+        }
+    });
+
+    quote! {
+        #(#structs)*
+
+        impl #contract {
+            /// Every `(route, reactant)` pair declared in `routes`.
+            pub const ROUTES: &'static [(&'static str, &'static str)] = &[#(#table),*];
+        }
+
+        impl ::maverick_os::air::Contract for #contract {
+            #init
+
+            fn id() -> ::maverick_os::air::Id {::maverick_os::air::Id::hash(#id)}
+
+            fn reactants() -> ::maverick_os::air::Reactants<Self> {
+                ::maverick_os::air::Reactants::default()#(.add::<#names>())*#(.add::<#builtins>())*
+            }
+        }
+
+        impl ::maverick_os::air::policy::Governed for #contract {
+            fn policy() -> ::maverick_os::air::policy::Policy<Self> {
+                ::maverick_os::air::policy::Policy::default()#(#access)*
+            }
+
+            fn guard(guard: ::maverick_os::air::policy::Guard<Self>) -> ::maverick_os::air::policy::Guard<Self> {
+                guard #(.add::<#guarded>())*#(.add::<#builtins>())*
+            }
+        }
+
+        #(#reactants)*
+    }
+}
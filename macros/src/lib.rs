@@ -0,0 +1,17 @@
+//! Compiler for the contract language, re-exported as `maverick_os::air::contract!`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+
+mod parse;
+mod schema;
+mod generate;
+
+#[proc_macro]
+pub fn contract(input: TokenStream) -> TokenStream {
+    let input = parse::Cursor::new(input.into(), Span::call_site());
+    parse::contract(input)
+        .and_then(|contract| schema::check(&contract).map(|_| generate::contract(&contract)))
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
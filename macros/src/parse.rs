@@ -0,0 +1,549 @@
+use proc_macro2::{TokenStream, TokenTree, Delimiter, Span, Ident, Literal};
+use syn::{Error, Result, Type, Field, Token};
+use syn::parse::{Parser, ParseStream};
+use syn::punctuated::Punctuated;
+
+pub struct Contract {
+    pub structs: Vec<Struct>,
+    pub init: Option<Init>,
+    pub routes: Vec<Route>,
+    pub access: Vec<Access>,
+    pub reactants: Vec<Reactant>,
+    /// The built-in reactants the contract opts into, `Revert`, `Splice` and `Membership`.
+    pub builtins: Vec<Ident>,
+}
+
+pub struct Struct {
+    pub attrs: Vec<TokenStream>,
+    pub name: Ident,
+    pub fields: Vec<Field>,
+}
+
+pub struct Init {
+    pub ty: Type,
+    pub fields: Vec<(Ident, Expr)>,
+    pub span: Span,
+}
+
+pub struct Route {
+    pub path: String,
+    pub segments: Vec<String>,
+    pub reactants: Vec<Ident>,
+    pub span: Span,
+}
+
+/// A rule over the signer for every reactant sent below `route`, or only the listed ones.
+pub struct Access {
+    pub route: Route,
+    pub rule: Rule,
+}
+
+pub enum Rule {
+    Anyone,
+    Nobody,
+    Owner(Path),
+    Member(Path),
+    /// An active entry of the `Members` at the path with at least the role.
+    Role(Path, Ident),
+    Any(Vec<Rule>),
+    All(Vec<Rule>),
+}
+
+pub struct Reactant {
+    pub name: Ident,
+    pub args: Vec<Type>,
+    pub body: Vec<Stmt>,
+}
+
+pub enum Stmt {
+    Write(Expr, Path),
+    Push(Expr, Path),
+    Insert(Expr, Path),
+    Remove(Expr, Path),
+    Delete(Path),
+    Read(Path),
+    If(Cond, Vec<Stmt>, Vec<Stmt>),
+    Ensure(Cond, String),
+    Reject(Literal),
+}
+
+#[derive(Clone, Copy)]
+pub enum Cmp {Eq, Ne, Lt, Le, Gt, Ge}
+
+pub enum Cond {
+    Cmp(Expr, Cmp, Expr),
+    In(Expr, Expr),
+    And(Box<Cond>, Box<Cond>),
+    Or(Box<Cond>, Box<Cond>),
+    Not(Box<Cond>),
+    Expr(Expr),
+}
+
+pub enum Expr {
+    Value,
+    Field(String),
+    Author,
+    Timestamp,
+    Path(Path),
+    Lit(Literal),
+    Neg(Literal),
+    Bool(bool),
+    Null,
+    Array(Vec<Expr>),
+    Map(Vec<(Ident, Expr)>),
+    Struct(Ident, Vec<(Ident, Expr)>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Base {Root, Relative(usize)}
+
+/// A path segment, either a literal key or index or one taken from the reactant argument, `./self.name`.
+#[derive(Clone)]
+pub enum Segment {
+    Key(String),
+    Arg(Option<String>),
+}
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Segment::Key(key) => write!(f, "{key}"),
+            Segment::Arg(None) => write!(f, "self"),
+            Segment::Arg(Some(field)) => write!(f, "self.{field}"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Path {
+    pub base: Base,
+    pub segments: Vec<Segment>,
+    pub exists: bool,
+    pub span: Span,
+}
+impl std::fmt::Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.base {
+            Base::Root => write!(f, "/")?,
+            Base::Relative(0) => write!(f, "./")?,
+            Base::Relative(ups) => write!(f, "{}", "../".repeat(ups))?,
+        }
+        write!(f, "{}", self.segments.iter().map(Segment::to_string).collect::<Vec<_>>().join("/"))?;
+        if self.exists {write!(f, "/@exists")?;}
+        Ok(())
+    }
+}
+
+pub struct Cursor {
+    tokens: Vec<TokenTree>,
+    pos: usize,
+    end: Span,
+}
+
+impl Cursor {
+    pub fn new(tokens: TokenStream, end: Span) -> Self {
+        Cursor{tokens: tokens.into_iter().collect(), pos: 0, end}
+    }
+
+    pub fn is_empty(&self) -> bool {self.pos >= self.tokens.len()}
+    pub fn peek(&self) -> Option<&TokenTree> {self.tokens.get(self.pos)}
+    fn peek_at(&self, n: usize) -> Option<&TokenTree> {self.tokens.get(self.pos + n)}
+    pub fn span(&self) -> Span {self.peek().map(|t| t.span()).unwrap_or(self.end)}
+
+    pub fn next(&mut self) -> Result<TokenTree> {
+        let token = self.peek().cloned().ok_or_else(|| Error::new(self.end, "unexpected end of input"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    pub fn is_punct(&self, c: char) -> bool {matches!(self.peek(), Some(TokenTree::Punct(p)) if p.as_char() == c)}
+    fn is_punct_at(&self, n: usize, c: char) -> bool {matches!(self.peek_at(n), Some(TokenTree::Punct(p)) if p.as_char() == c)}
+    pub fn is_ident(&self, name: &str) -> bool {matches!(self.peek(), Some(TokenTree::Ident(i)) if i == name)}
+    fn is_group(&self, delimiter: Delimiter) -> bool {matches!(self.peek(), Some(TokenTree::Group(g)) if g.delimiter() == delimiter)}
+
+    pub fn eat_punct(&mut self, c: char) -> bool {
+        if self.is_punct(c) {self.pos += 1; true} else {false}
+    }
+
+    fn eat_puncts(&mut self, a: char, b: char) -> bool {
+        if self.is_punct(a) && self.is_punct_at(1, b) {self.pos += 2; true} else {false}
+    }
+
+    pub fn eat_ident(&mut self, name: &str) -> bool {
+        if self.is_ident(name) {self.pos += 1; true} else {false}
+    }
+
+    pub fn expect_punct(&mut self, c: char) -> Result<()> {
+        if self.eat_punct(c) {Ok(())} else {Err(Error::new(self.span(), format!("expected `{c}`")))}
+    }
+
+    pub fn expect_keyword(&mut self, name: &str) -> Result<()> {
+        if self.eat_ident(name) {Ok(())} else {Err(Error::new(self.span(), format!("expected `{name}`")))}
+    }
+
+    pub fn ident(&mut self) -> Result<Ident> {
+        match self.next()? {
+            TokenTree::Ident(i) => Ok(i),
+            t => Err(Error::new(t.span(), "expected an identifier"))
+        }
+    }
+
+    pub fn group(&mut self, delimiter: Delimiter) -> Result<(Cursor, Span)> {
+        match self.next()? {
+            TokenTree::Group(g) if g.delimiter() == delimiter => Ok((Cursor::new(g.stream(), g.span_close()), g.span())),
+            t => Err(Error::new(t.span(), match delimiter {
+                Delimiter::Brace => "expected `{ .. }`",
+                Delimiter::Bracket => "expected `[ .. ]`",
+                Delimiter::Parenthesis => "expected `( .. )`",
+                Delimiter::None => "expected a group",
+            }))
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.eat_punct(',') || self.eat_punct(';') {}
+    }
+
+    fn rest(&mut self) -> TokenStream {
+        let rest = self.tokens[self.pos..].iter().cloned().collect();
+        self.pos = self.tokens.len();
+        rest
+    }
+}
+
+pub fn contract(mut input: Cursor) -> Result<Contract> {
+    let mut contract = Contract{structs: Vec::new(), init: None, routes: Vec::new(), access: Vec::new(), reactants: Vec::new(), builtins: Vec::new()};
+    while !input.is_empty() {
+        if input.is_ident("builtins") && matches!(input.peek_at(1), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Bracket) {
+            input.next()?;
+            contract.builtins.extend(reactant_list(&mut input)?);
+        } else if input.eat_ident("routes") {
+            let (mut body, _) = input.group(Delimiter::Brace)?;
+            while !body.is_empty() {
+                contract.routes.push(route(&mut body)?);
+                body.skip_separators();
+            }
+        } else if input.eat_ident("access") {
+            let (mut body, _) = input.group(Delimiter::Brace)?;
+            while !body.is_empty() {
+                contract.access.push(access(&mut body)?);
+                body.skip_separators();
+            }
+        } else if input.eat_ident("reactants") {
+            let (mut body, _) = input.group(Delimiter::Brace)?;
+            while !body.is_empty() {
+                contract.reactants.push(reactant(&mut body)?);
+                body.skip_separators();
+            }
+        } else if input.is_ident("init") && matches!(input.peek_at(1), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis) {
+            let span = input.next()?.span();
+            if contract.init.is_some() {return Err(Error::new(span, "duplicate `init`"));}
+            let (mut ty, _) = input.group(Delimiter::Parenthesis)?;
+            let ty = syn::parse2::<Type>(ty.rest())?;
+            let (mut body, _) = input.group(Delimiter::Brace)?;
+            contract.init = Some(Init{ty, fields: fields(&mut body)?, span});
+        } else {
+            let mut attrs = Vec::new();
+            while input.is_punct('#') {
+                let pound = input.next()?;
+                attrs.push([pound, input.next()?].into_iter().collect());
+            }
+            let name = input.ident()?;
+            let (mut body, _) = input.group(Delimiter::Brace)?;
+            let fields = (|input: ParseStream| Punctuated::<Field, Token![,]>::parse_terminated_with(input, Field::parse_named)).parse2(body.rest())?;
+            contract.structs.push(Struct{attrs, name, fields: fields.into_iter().collect()});
+        }
+        input.skip_separators();
+    }
+    Ok(contract)
+}
+
+fn route_path(input: &mut Cursor) -> Result<(String, Vec<String>, Span)> {
+    let (path, span) = match input.next()? {
+        TokenTree::Literal(l) => match syn::parse2::<syn::LitStr>(TokenTree::Literal(l.clone()).into()) {
+            Ok(s) => (s.value(), l.span()),
+            Err(_) => return Err(Error::new(l.span(), "expected a route string like \"/messages/*\""))
+        },
+        t => return Err(Error::new(t.span(), "expected a route string like \"/messages/*\""))
+    };
+    let segments = path.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect();
+    Ok((path, segments, span))
+}
+
+fn reactant_list(input: &mut Cursor) -> Result<Vec<Ident>> {
+    let (mut list, _) = input.group(Delimiter::Bracket)?;
+    let mut reactants = Vec::new();
+    while !list.is_empty() {
+        reactants.push(list.ident()?);
+        list.skip_separators();
+    }
+    Ok(reactants)
+}
+
+fn route(input: &mut Cursor) -> Result<Route> {
+    let (path, segments, span) = route_path(input)?;
+    input.expect_punct(':')?;
+    Ok(Route{path, segments, reactants: reactant_list(input)?, span})
+}
+
+fn access(input: &mut Cursor) -> Result<Access> {
+    let (path, segments, span) = route_path(input)?;
+    let reactants = if input.is_group(Delimiter::Bracket) {reactant_list(input)?} else {Vec::new()};
+    input.expect_punct(':')?;
+    Ok(Access{route: Route{path, segments, reactants, span}, rule: rule(input)?})
+}
+
+fn rule(input: &mut Cursor) -> Result<Rule> {
+    let mut any = vec![rule_all(input)?];
+    while input.eat_punct('|') {
+        input.eat_punct('|');
+        any.push(rule_all(input)?);
+    }
+    Ok(if any.len() == 1 {any.pop().unwrap()} else {Rule::Any(any)})
+}
+
+fn rule_all(input: &mut Cursor) -> Result<Rule> {
+    let mut all = vec![rule_term(input)?];
+    while input.eat_punct('&') {
+        input.eat_punct('&');
+        all.push(rule_term(input)?);
+    }
+    Ok(if all.len() == 1 {all.pop().unwrap()} else {Rule::All(all)})
+}
+
+fn rule_term(input: &mut Cursor) -> Result<Rule> {
+    if input.is_group(Delimiter::Parenthesis) {
+        let (mut inner, _) = input.group(Delimiter::Parenthesis)?;
+        return rule(&mut inner);
+    }
+    let name = input.ident()?;
+    Ok(match name.to_string().as_str() {
+        "anyone" => Rule::Anyone,
+        "nobody" => Rule::Nobody,
+        "owner" | "member" => {
+            let (mut inner, _) = input.group(Delimiter::Parenthesis)?;
+            let p = path(&mut inner)?;
+            if !inner.is_empty() {return Err(Error::new(inner.span(), "expected `)`"));}
+            if name == "owner" {Rule::Owner(p)} else {Rule::Member(p)}
+        },
+        "role" => {
+            let (mut inner, _) = input.group(Delimiter::Parenthesis)?;
+            let p = path(&mut inner)?;
+            inner.expect_punct(',')?;
+            let role = inner.ident()?;
+            if !["Reader", "Writer", "Admin", "Owner"].contains(&role.to_string().as_str()) {
+                return Err(Error::new(role.span(), "expected `Reader`, `Writer`, `Admin` or `Owner`"));
+            }
+            if !inner.is_empty() {return Err(Error::new(inner.span(), "expected `)`"));}
+            Rule::Role(p, role)
+        },
+        _ => return Err(Error::new(name.span(), "expected `anyone`, `nobody`, `owner(path)`, `member(path)` or `role(path, Role)`"))
+    })
+}
+
+fn reactant(input: &mut Cursor) -> Result<Reactant> {
+    let name = input.ident()?;
+    let args = if input.is_group(Delimiter::Parenthesis) {
+        let (mut args, _) = input.group(Delimiter::Parenthesis)?;
+        Punctuated::<Type, Token![,]>::parse_terminated.parse2(args.rest())?.into_iter().collect()
+    } else {Vec::new()};
+    let (mut body, _) = input.group(Delimiter::Brace)?;
+    Ok(Reactant{name, args, body: block(&mut body)?})
+}
+
+fn block(input: &mut Cursor) -> Result<Vec<Stmt>> {
+    let mut stmts = Vec::new();
+    input.skip_separators();
+    while !input.is_empty() {
+        stmts.push(stmt(input)?);
+        input.skip_separators();
+    }
+    Ok(stmts)
+}
+
+fn stmt(input: &mut Cursor) -> Result<Stmt> {
+    let keyword = input.ident()?;
+    Ok(match keyword.to_string().as_str() {
+        "write" => {let e = expr(input)?; input.expect_keyword("to")?; Stmt::Write(e, path(input)?)},
+        "push" => {let e = expr(input)?; input.expect_keyword("to")?; Stmt::Push(e, path(input)?)},
+        "insert" => {let e = expr(input)?; input.expect_keyword("into")?; Stmt::Insert(e, path(input)?)},
+        "remove" => {let e = expr(input)?; input.expect_keyword("from")?; Stmt::Remove(e, path(input)?)},
+        "delete" => Stmt::Delete(path(input)?),
+        "read" => {
+            let p = path(input)?;
+            if input.eat_punct('=') {
+                input.eat_punct('=');
+                let value = expr(input)?;
+                let (mut body, _) = input.group(Delimiter::Brace)?;
+                Stmt::If(Cond::Cmp(Expr::Path(p), Cmp::Eq, value), block(&mut body)?, Vec::new())
+            } else {Stmt::Read(p)}
+        },
+        "if" => if_stmt(input)?,
+        "ensure" => {
+            let start = input.pos;
+            let c = cond(input)?;
+            let text = input.tokens[start..input.pos].iter().cloned().collect::<TokenStream>().to_string().replace("/ ", "/").replace(" /@ ", "/@").replace("@ ", "@");
+            Stmt::Ensure(c, text)
+        },
+        "reject" => match input.next()? {
+            TokenTree::Literal(l) => Stmt::Reject(l),
+            t => return Err(Error::new(t.span(), "expected a reason string"))
+        },
+        _ => return Err(Error::new(keyword.span(), "expected one of `write`, `push`, `insert`, `remove`, `delete`, `read`, `if`, `ensure` or `reject`"))
+    })
+}
+
+fn if_stmt(input: &mut Cursor) -> Result<Stmt> {
+    let c = cond(input)?;
+    let (mut body, _) = input.group(Delimiter::Brace)?;
+    let then = block(&mut body)?;
+    let otherwise = if input.eat_ident("else") {
+        if input.eat_ident("if") {vec![if_stmt(input)?]} else {
+            let (mut body, _) = input.group(Delimiter::Brace)?;
+            block(&mut body)?
+        }
+    } else {Vec::new()};
+    Ok(Stmt::If(c, then, otherwise))
+}
+
+pub fn cond(input: &mut Cursor) -> Result<Cond> {
+    let mut left = and(input)?;
+    while input.eat_puncts('|', '|') {left = Cond::Or(Box::new(left), Box::new(and(input)?));}
+    Ok(left)
+}
+
+fn and(input: &mut Cursor) -> Result<Cond> {
+    let mut left = not(input)?;
+    while input.eat_puncts('&', '&') {left = Cond::And(Box::new(left), Box::new(not(input)?));}
+    Ok(left)
+}
+
+fn not(input: &mut Cursor) -> Result<Cond> {
+    if input.eat_punct('!') {return Ok(Cond::Not(Box::new(not(input)?)));}
+    if input.is_group(Delimiter::Parenthesis) {
+        let (mut inner, _) = input.group(Delimiter::Parenthesis)?;
+        return cond(&mut inner);
+    }
+    let left = expr(input)?;
+    if input.eat_ident("in") {return Ok(Cond::In(left, expr(input)?));}
+    let op = if input.eat_puncts('=', '=') {Cmp::Eq}
+        else if input.eat_puncts('!', '=') {Cmp::Ne}
+        else if input.eat_puncts('<', '=') {Cmp::Le}
+        else if input.eat_puncts('>', '=') {Cmp::Ge}
+        else if input.eat_punct('<') {Cmp::Lt}
+        else if input.eat_punct('>') {Cmp::Gt}
+        else {return Ok(Cond::Expr(left));};
+    Ok(Cond::Cmp(left, op, expr(input)?))
+}
+
+pub fn expr(input: &mut Cursor) -> Result<Expr> {
+    if input.is_punct('/') || input.is_punct('.') {return Ok(Expr::Path(path(input)?));}
+    if input.is_group(Delimiter::Bracket) {
+        let (mut items, _) = input.group(Delimiter::Bracket)?;
+        let mut array = Vec::new();
+        while !items.is_empty() {
+            array.push(expr(&mut items)?);
+            items.skip_separators();
+        }
+        return Ok(Expr::Array(array));
+    }
+    if input.is_group(Delimiter::Brace) {
+        let (mut body, _) = input.group(Delimiter::Brace)?;
+        return Ok(Expr::Map(fields(&mut body)?));
+    }
+    if input.eat_punct('-') {
+        return match input.next()? {
+            TokenTree::Literal(l) => Ok(Expr::Neg(l)),
+            t => Err(Error::new(t.span(), "expected a number"))
+        };
+    }
+    match input.next()? {
+        TokenTree::Literal(l) => Ok(Expr::Lit(l)),
+        TokenTree::Ident(i) => Ok(match i.to_string().as_str() {
+            "self" if input.is_punct('.') => {
+                input.next()?;
+                match input.next()? {
+                    TokenTree::Literal(l) => Expr::Field(l.to_string()),
+                    TokenTree::Ident(f) => Expr::Field(f.to_string()),
+                    t => return Err(Error::new(t.span(), "expected a field or index"))
+                }
+            },
+            "self" => Expr::Value,
+            "author" | "signer" => Expr::Author,
+            "timestamp" => Expr::Timestamp,
+            "true" => Expr::Bool(true),
+            "false" => Expr::Bool(false),
+            "null" => Expr::Null,
+            name if name.starts_with(char::is_uppercase) && input.is_group(Delimiter::Brace) => {
+                let (mut body, _) = input.group(Delimiter::Brace)?;
+                Expr::Struct(i, fields(&mut body)?)
+            },
+            _ => return Err(Error::new(i.span(), format!("unknown value `{i}`, expected `self`, `author`, `timestamp`, a path or a literal")))
+        }),
+        t => Err(Error::new(t.span(), "expected a value"))
+    }
+}
+
+fn fields(input: &mut Cursor) -> Result<Vec<(Ident, Expr)>> {
+    let mut fields = Vec::new();
+    while !input.is_empty() {
+        let name = input.ident()?;
+        let value = if input.eat_punct(':') {expr(input)?} else {
+            let mut shorthand = Cursor::new(TokenTree::Ident(name.clone()).into(), name.span());
+            expr(&mut shorthand)?
+        };
+        fields.push((name, value));
+        input.skip_separators();
+    }
+    Ok(fields)
+}
+
+pub fn path(input: &mut Cursor) -> Result<Path> {
+    let span = input.span();
+    let base = if input.eat_punct('.') {
+        let ups = if input.eat_punct('.') {1} else {0};
+        input.expect_punct('/')?;
+        let mut ups = ups;
+        while input.is_punct('.') && input.is_punct_at(1, '.') && input.is_punct_at(2, '/') {
+            input.pos += 3;
+            ups += 1;
+        }
+        Base::Relative(ups)
+    } else {
+        input.expect_punct('/')?;
+        Base::Root
+    };
+    let mut path = Path{base, segments: Vec::new(), exists: false, span};
+    loop {
+        match input.peek() {
+            Some(TokenTree::Ident(i)) if i == "self" => {
+                input.pos += 1;
+                let field = if input.is_punct('.') && !input.is_punct_at(1, '/') {
+                    input.pos += 1;
+                    match input.next()? {
+                        TokenTree::Literal(l) => Some(l.to_string()),
+                        TokenTree::Ident(f) => Some(f.to_string()),
+                        t => return Err(Error::new(t.span(), "expected a field or index"))
+                    }
+                } else {None};
+                path.segments.push(Segment::Arg(field));
+            },
+            Some(TokenTree::Ident(i)) => {path.segments.push(Segment::Key(i.to_string())); input.pos += 1;},
+            Some(TokenTree::Literal(l)) => {
+                let segment = syn::parse2::<syn::LitStr>(TokenTree::Literal(l.clone()).into()).map(|s| s.value()).unwrap_or_else(|_| l.to_string());
+                path.segments.push(Segment::Key(segment));
+                input.pos += 1;
+            },
+            Some(TokenTree::Punct(p)) if p.as_char() == '*' => return Err(Error::new(p.span(), "wildcards are only allowed in routes")),
+            Some(TokenTree::Punct(p)) if p.as_char() == '@' => {
+                input.pos += 1;
+                let attribute = input.ident()?;
+                if attribute != "exists" {return Err(Error::new(attribute.span(), "unknown attribute, expected `@exists`"));}
+                path.exists = true;
+                break;
+            },
+            _ => break
+        }
+        if !input.eat_punct('/') {break;}
+    }
+    Ok(path)
+}
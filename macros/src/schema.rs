@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use proc_macro2::Span;
+use syn::{Error, Result, Type, GenericArgument, PathArguments};
+
+use crate::parse::{Contract, Struct, Route, Rule, Stmt, Cond, Expr, Path, Base, Segment};
+
+#[derive(Clone, Copy)]
+pub enum Node<'a> {
+    Struct(&'a Struct),
+    Seq(&'a Type),
+    Map(&'a Type),
+    Leaf,
+}
+impl Node<'_> {
+    fn describe(&self) -> &'static str {
+        match self {
+            Node::Struct(_) => "a struct",
+            Node::Seq(_) => "a list",
+            Node::Map(_) => "a map",
+            Node::Leaf => "a value",
+        }
+    }
+}
+
+pub fn last(ty: &Type) -> Option<(String, Vec<&Type>)> {
+    let Type::Path(path) = ty else {return None;};
+    let segment = path.path.segments.last()?;
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(a) => a.args.iter().filter_map(|a| match a {GenericArgument::Type(t) => Some(t), _ => None}).collect(),
+        _ => Vec::new()
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+pub fn is_optional(ty: &Type) -> bool {matches!(last(ty), Some((name, _)) if name == "Option")}
+
+pub struct Schema<'a> {
+    structs: BTreeMap<String, &'a Struct>,
+    root: &'a Struct,
+}
+
+impl<'a> Schema<'a> {
+    pub fn new(contract: &'a Contract) -> Result<Self> {
+        let root = contract.structs.first().ok_or_else(|| Error::new(Span::call_site(), "expected a contract struct like `Room { name: String }`"))?;
+        let mut structs = BTreeMap::new();
+        for s in &contract.structs {
+            if structs.insert(s.name.to_string(), s).is_some() {
+                return Err(Error::new(s.name.span(), format!("`{}` is declared twice", s.name)));
+            }
+        }
+        Ok(Schema{structs, root})
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'a Struct> {self.structs.get(name).copied()}
+
+    fn node(&self, ty: &'a Type) -> Node<'a> {
+        match last(ty) {
+            Some((name, args)) => match (name.as_str(), args.as_slice()) {
+                ("Option" | "Box", [inner]) => self.node(inner),
+                ("Vec" | "VecDeque" | "BTreeSet" | "HashSet", [item]) => Node::Seq(item),
+                ("BTreeMap" | "HashMap", [_, value]) => Node::Map(value),
+                (name, []) => self.get(name).map(Node::Struct).unwrap_or(Node::Leaf),
+                _ => Node::Leaf
+            },
+            None => Node::Leaf
+        }
+    }
+
+    fn child(&self, node: Node<'a>, segment: &str, wildcards: bool, span: Span) -> Result<Node<'a>> {
+        match node {
+            Node::Struct(s) => s.fields.iter().find(|f| f.ident.as_ref().is_some_and(|i| i == segment)).map(|f| self.node(&f.ty)).ok_or_else(|| {
+                let fields = s.fields.iter().filter_map(|f| f.ident.as_ref().map(|i| format!("`{i}`"))).collect::<Vec<_>>().join(", ");
+                Error::new(span, format!("`{}` has no field `{segment}`, expected one of {fields}", s.name))
+            }),
+            Node::Seq(item) if (wildcards && segment == "*") || segment.parse::<usize>().is_ok() => Ok(self.node(item)),
+            Node::Seq(_) => Err(Error::new(span, format!("list items are addressed by index{}, found `{segment}`", if wildcards {" or `*`"} else {""}))),
+            Node::Map(_) if segment == "*" && !wildcards => Err(Error::new(span, "wildcards are only allowed in routes")),
+            Node::Map(value) => Ok(self.node(value)),
+            Node::Leaf => Err(Error::new(span, format!("cannot address `{segment}` inside a value"))),
+        }
+    }
+
+    /// Walks `segments` from the contract root, returning the addressed node and its parent.
+    pub fn walk(&self, segments: &[String], wildcards: bool, span: Span) -> Result<(Node<'a>, Option<Node<'a>>)> {
+        let mut node = Node::Struct(self.root);
+        let mut parent = None;
+        for segment in segments {
+            parent = Some(node);
+            node = self.child(node, segment, wildcards, span)?;
+        }
+        Ok((node, parent))
+    }
+}
+
+struct Checker<'a> {
+    schema: Schema<'a>,
+    errors: Vec<Error>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, error: Error) {self.errors.push(error);}
+
+    /// Segments taken from the argument are only known at runtime and are checked like a route wildcard.
+    fn absolute(&mut self, route: &Route, path: &Path) -> Option<Vec<String>> {
+        let segments = path.segments.iter().map(|s| match s {
+            Segment::Key(key) => key.clone(),
+            Segment::Arg(_) => "*".to_string(),
+        });
+        match path.base {
+            Base::Root => Some(segments.collect()),
+            Base::Relative(ups) if ups > route.segments.len() => {
+                self.error(Error::new(path.span, format!("`{path}` climbs above the root from route \"{}\"", route.path)));
+                None
+            },
+            Base::Relative(ups) => Some(route.segments[..route.segments.len()-ups].iter().cloned().chain(segments).collect())
+        }
+    }
+
+    fn path(&mut self, route: &Route, path: &Path) -> Option<(Node<'a>, Option<Node<'a>>)> {
+        let segments = self.absolute(route, path)?;
+        match self.schema.walk(&segments, true, path.span) {
+            Ok(nodes) => Some(nodes),
+            Err(e) => {
+                let message = format!("{e} (in `{path}` from route \"{}\")", route.path);
+                self.error(Error::new(path.span, message));
+                None
+            }
+        }
+    }
+
+    fn collection(&mut self, route: &Route, path: &Path, verb: &str) {
+        match self.path(route, path) {
+            Some((Node::Seq(_), _)) | None => {},
+            Some((node, _)) => {
+                let message = format!("cannot {verb} `{path}`, it is {} not a list", node.describe());
+                self.error(Error::new(path.span, message));
+            }
+        }
+    }
+
+    fn stmts(&mut self, route: &Route, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Write(e, p) => {self.expr(Some(route), e); self.path(route, p);},
+                Stmt::Push(e, p) => {self.expr(Some(route), e); self.collection(route, p, "push to");},
+                Stmt::Insert(e, p) => {self.expr(Some(route), e); self.collection(route, p, "insert into");},
+                Stmt::Remove(e, p) => {self.expr(Some(route), e); self.collection(route, p, "remove from");},
+                Stmt::Delete(p) => match self.path(route, p) {
+                    Some((_, Some(Node::Seq(_) | Node::Map(_)))) | None => {},
+                    Some(_) => self.error(Error::new(p.span, format!("cannot delete `{p}`, only list items and map entries can be deleted"))),
+                },
+                Stmt::Read(p) => {self.path(route, p);},
+                Stmt::If(c, then, otherwise) => {
+                    self.cond(route, c);
+                    self.stmts(route, then);
+                    self.stmts(route, otherwise);
+                },
+                Stmt::Ensure(c, _) => self.cond(route, c),
+                Stmt::Reject(_) => {},
+            }
+        }
+    }
+
+    fn cond(&mut self, route: &Route, cond: &Cond) {
+        match cond {
+            Cond::Cmp(a, _, b) | Cond::In(a, b) => {self.expr(Some(route), a); self.expr(Some(route), b);},
+            Cond::And(a, b) | Cond::Or(a, b) => {self.cond(route, a); self.cond(route, b);},
+            Cond::Not(c) => self.cond(route, c),
+            Cond::Expr(e) => self.expr(Some(route), e),
+        }
+    }
+
+    fn rule(&mut self, route: &Route, rule: &Rule) {
+        match rule {
+            Rule::Anyone | Rule::Nobody => {},
+            Rule::Owner(p) | Rule::Member(p) | Rule::Role(p, _) if p.exists || p.segments.iter().any(|s| matches!(s, Segment::Arg(_))) =>
+                self.error(Error::new(p.span, "rule paths cannot use `@exists` or the reactant argument")),
+            Rule::Owner(p) | Rule::Role(p, _) => {self.path(route, p);},
+            Rule::Member(p) => self.collection(route, p, "check membership of"),
+            Rule::Any(rules) | Rule::All(rules) => rules.iter().for_each(|r| self.rule(route, r)),
+        }
+    }
+
+    /// `route` is `None` inside `init`, where there is no state to read from yet.
+    fn expr(&mut self, route: Option<&Route>, expr: &Expr) {
+        match expr {
+            Expr::Path(p) => match route {
+                Some(route) => {self.path(route, p);},
+                None => self.error(Error::new(p.span, "paths cannot be read in `init`")),
+            },
+            Expr::Array(items) => items.iter().for_each(|e| self.expr(route, e)),
+            Expr::Map(fields) => fields.iter().for_each(|(_, e)| self.expr(route, e)),
+            Expr::Struct(name, fields) => {
+                fields.iter().for_each(|(_, e)| self.expr(route, e));
+                let Some(declared) = self.schema.get(&name.to_string()) else {
+                    return self.error(Error::new(name.span(), format!("unknown struct `{name}`")));
+                };
+                self.fields(declared, fields.iter().map(|(i, _)| i), name.span());
+            },
+            _ => {}
+        }
+    }
+
+    fn fields<'b>(&mut self, declared: &Struct, given: impl Iterator<Item = &'b proc_macro2::Ident>, span: Span) {
+        let given = given.collect::<Vec<_>>();
+        for ident in &given {
+            if !declared.fields.iter().any(|f| f.ident.as_ref() == Some(ident)) {
+                self.error(Error::new(ident.span(), format!("`{}` has no field `{ident}`", declared.name)));
+            }
+        }
+        for field in &declared.fields {
+            let ident = field.ident.as_ref().unwrap();
+            if !is_optional(&field.ty) && !given.contains(&ident) {
+                self.error(Error::new(span, format!("missing field `{ident}` of `{}`", declared.name)));
+            }
+        }
+    }
+}
+
+/// Reactants provided by maverick_os that a contract can opt into.
+pub const BUILTINS: [&str; 3] = ["Revert", "Splice", "Membership"];
+
+/// Validates every route and reactant path against the declared structs.
+pub fn check(contract: &Contract) -> Result<()> {
+    let mut checker = Checker{schema: Schema::new(contract)?, errors: Vec::new()};
+
+    if let Some(init) = &contract.init {
+        init.fields.iter().for_each(|(_, e)| checker.expr(None, e));
+        let root = checker.schema.root;
+        checker.fields(root, init.fields.iter().map(|(i, _)| i), init.span);
+    }
+
+    for route in &contract.routes {
+        if let Err(e) = checker.schema.walk(&route.segments, true, route.span) {checker.error(e);}
+        for name in &route.reactants {
+            match contract.reactants.iter().find(|r| r.name == *name) {
+                Some(reactant) => checker.stmts(route, &reactant.body),
+                None => checker.error(Error::new(name.span(), format!("unknown reactant `{name}`"))),
+            }
+        }
+    }
+
+    for (i, name) in contract.builtins.iter().enumerate() {
+        if !BUILTINS.contains(&name.to_string().as_str()) {
+            checker.error(Error::new(name.span(), format!("unknown built-in `{name}`, expected one of `Revert`, `Splice` or `Membership`")));
+        } else if contract.builtins[..i].contains(name) {
+            checker.error(Error::new(name.span(), format!("`{name}` is listed twice")));
+        }
+    }
+
+    for access in &contract.access {
+        let route = &access.route;
+        if let Err(e) = checker.schema.walk(&route.segments, true, route.span) {checker.error(e);}
+        for name in &route.reactants {
+            if BUILTINS.contains(&name.to_string().as_str()) && !contract.builtins.contains(name) {
+                checker.error(Error::new(name.span(), format!("`{name}` is not enabled, add it to `builtins [..]`")));
+            } else if !contract.builtins.contains(name) && !contract.reactants.iter().any(|r| r.name == *name) {
+                checker.error(Error::new(name.span(), format!("unknown reactant `{name}`")));
+            }
+        }
+        checker.rule(route, &access.rule);
+    }
+
+    for reactant in &contract.reactants {
+        if !contract.routes.iter().any(|r| r.reactants.contains(&reactant.name)) {
+            checker.error(Error::new(reactant.name.span(), format!("reactant `{}` is not bound to any route", reactant.name)));
+        }
+    }
+
+    let mut errors = checker.errors.into_iter();
+    match errors.next() {
+        Some(mut first) => {
+            errors.for_each(|e| first.combine(e));
+            Err(first)
+        },
+        None => Ok(())
+    }
+}
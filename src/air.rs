@@ -1,111 +1,635 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use arc_swap::ArcSwap;
 
-pub use air::contract::{Contract, Contracts, Substance, Reactants, Reactant, from, into, Error, Beaker, RequestBuilder, Request};
-pub use air::names::{Name, Id};
+use rand::Rng;
 
-use air::names::Secret;
-use air::contract::Manager;
+pub use ::air::contract::{Contract, Substance, Reactants, Reactant, from, into, Error, Beaker, RequestBuilder, Request};
+pub use ::air::names::{Name, Id, Secret};
+
+use ::air::contract::Manager;
 
 use serde::{Serialize, Deserialize};
 
-use crossfire::{MTx, Rx, mpsc::{Array, bounded_blocking}};
+use crossfire::{MTx, Rx, MAsyncTx, AsyncRx, TrySendError, mpsc::{Array, List, bounded_async, unbounded_blocking}};
 
 use rusqlite::{OptionalExtension, Connection};
 
 use crate::hardware;
+pub use crate::runtime::{Service, Services, async_trait};
+use crate::profiles::Profile;
+use crate::vault::{Vault, VaultError};
+
+pub mod query;
+use query::{Query, QueryError, Match};
+
+pub mod snapshot;
+use snapshot::{Snapshot, SnapshotError, Conflict};
+
+pub mod migration;
+pub use migration::{Versioned, Migrations, MigrationError};
+
+pub mod simulation;
+pub(crate) use simulation::Transport;
+
+pub mod lang;
+pub use maverick_os_macros::contract;
+
+pub mod policy;
+pub use policy::{Governed, Guard, Policy, Rule, Denied};
+use policy::Governing;
+
+pub mod history;
+use history::{History, HistoryError, Entry, Origin, Outcome};
+
+pub mod undo;
+pub use undo::{Revert, Invertible, UndoError};
+use undo::{Step, Action, Stack};
+
+pub mod store;
+pub use store::{Lazy, StoreError};
+use store::Store;
+
+pub mod text;
+pub use text::{Text, Splice, Edit, Stamp};
+
+pub mod members;
+pub use members::{Members, Member, Role, Membership};
+
+pub mod blobs;
+pub use blobs::{Blob, BlobError, BlobReader, BlobWriter, BlobSource, Attachment};
+use blobs::Blobs;
+
+/// Capacity of the interactive lane, user actions arrive far slower than air applies them.
+const INTERACTIVE_CAPACITY: usize = 100;
+/// Capacity of the background lane.
+const BACKGROUND_CAPACITY: usize = 1000;
+/// How often an idle runtime reads the manager state for changes that arrived from other devices. Reading it
+/// copies every instance, ticks that apply a local request read it right away.
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// The contracts air runs, every contract an application creates or receives instances of has to be added.
+/// Contracts are registered with the reactants of [`Governed::guard`], held to their policy.
+#[derive(Clone, Default)]
+pub struct Contracts {
+    registry: ::air::contract::Contracts,
+    ids: BTreeSet<Id>,
+}
+impl Contracts {
+    pub fn add<C: Governed>(mut self) -> Self {
+        self.registry = self.registry.add::<Governing<C>>();
+        self.ids.insert(C::id());
+        self
+    }
+
+    /// Adds a contract whose instances are migrated to its current version, see [`migration`].
+    pub fn versioned<C: Versioned + Governed>(self) -> Self {
+        migration::register(C::id(), C::VERSION, C::migrations());
+        self.add::<C>()
+    }
+
+    pub fn contains(&self, id: &Id) -> bool {self.ids.contains(id)}
+}
+impl std::fmt::Debug for Contracts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {write!(f, "Contracts({:?})", self.ids)}
+}
+
+/// Lane a request is submitted on. `Interactive` requests are always processed before any queued `Background` request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    #[default]
+    Interactive,
+    Background
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    /// The request could not be built.
+    Invalid(Error),
+    /// The lane is at capacity, the request is handed back to the caller.
+    Full(Request),
+    /// The air runtime has shut down.
+    Closed(Request)
+}
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RequestError::Invalid(e) => write!(f, "Invalid air request: {e}"),
+            RequestError::Full(_) => write!(f, "Air request lane is full"),
+            RequestError::Closed(_) => write!(f, "Air runtime has shut down"),
+        }
+    }
+}
+impl std::error::Error for RequestError {}
+impl From<Error> for RequestError {fn from(e: Error) -> Self {RequestError::Invalid(e)}}
+
+/// A request on its way to the runtime, with what is known about it for the history.
+pub(crate) struct Submission {
+    pub request: Request,
+    pub origin: Option<Origin>,
+}
+impl From<Request> for Submission {
+    fn from(request: Request) -> Self {Submission{request, origin: None}}
+}
+
+/// Instructions for the air runtime that are not requests to the manager.
+pub(crate) enum Control {
+    Import(Box<Snapshot>),
+}
+
+#[derive(Debug, Clone)]
+struct Lanes {
+    interactive: MAsyncTx<Array<Submission>>,
+    background: MAsyncTx<Array<Submission>>,
+}
+impl Lanes {
+    fn lane(&self, priority: Priority) -> &MAsyncTx<Array<Submission>> {
+        match priority {
+            Priority::Interactive => &self.interactive,
+            Priority::Background => &self.background,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Context {
-    builder: RequestBuilder,
-    value: Arc<ArcSwap<BTreeMap<Id, BTreeMap<Id, Substance>>>>,
-    tx: MTx<Array<Request>>,
+    /// Replaced when a snapshot is imported.
+    builder: Arc<ArcSwap<RequestBuilder>>,
+    contracts: Contracts,
+    store: Arc<Store>,
+    blobs: Arc<Blobs>,
+    lanes: Lanes,
+    control: MTx<List<Control>>,
+    cache: Arc<Mutex<Connection>>,
+    undo: Arc<Mutex<Stack>>,
+    /// Stamps the [`Text`] edits made through this runtime, drawn anew each time it opens.
+    site: Id,
+    /// Text edits sent through this context that the stored copy does not show yet. They are replayed over the
+    /// stored copy, so edits made before the runtime applied the previous ones still build on them.
+    edits: Arc<Mutex<text::Sent>>,
+    /// Encrypts the snapshots of the local identity.
+    snapshots: snapshot::Key,
 }
 impl Context {
-    pub fn name(&self) -> Name {self.builder.name()}
-    pub fn builder(&self) -> &RequestBuilder {&self.builder}
-    pub fn request(&self, request: Request) {
-        let _ = self.tx.send(request);
+    pub fn name(&self) -> Name {self.builder.load().name()}
+    pub fn builder(&self) -> Arc<RequestBuilder> {self.builder.load_full()}
+
+    /// Submits on the interactive lane, never blocks the calling thread.
+    pub fn request(&self, request: Request) -> Result<(), RequestError> {
+        self.try_request(request, Priority::Interactive)
     }
 
+    pub fn try_request(&self, request: Request, priority: Priority) -> Result<(), RequestError> {
+        self.submit(request.into(), priority)
+    }
+
+    fn submit(&self, submission: Submission, priority: Priority) -> Result<(), RequestError> {
+        self.lanes.lane(priority).try_send(submission).map_err(|e| match e {
+            TrySendError::Full(s) => RequestError::Full(s.request),
+            TrySendError::Disconnected(s) => RequestError::Closed(s.request),
+        })
+    }
+
+    /// Submits from async code such as a `Service`, waiting for room on the lane instead of failing.
+    pub async fn request_async(&self, request: Request, priority: Priority) -> Result<(), RequestError> {
+        self.lanes.lane(priority).send(request.into()).await.map_err(|e| RequestError::Closed(e.0.request))
+    }
+
+    /// Loads only the subtree at `path`.
     pub fn query(&self, id: &Id, iid: &Id, path: PathBuf) -> Option<Substance> {
-        self.value.load().get(id)?.get(iid)?.query(path).ok()
+        into(&self.store.read(id, iid, &lang::split(&path.to_string_lossy())).ok()??).ok()
+    }
+
+    /// Evaluates `query` against the current state of a single instance.
+    pub fn select<T: for<'a> Deserialize<'a>>(&self, id: &Id, iid: &Id, query: &Query) -> Result<Vec<Match<T>>, QueryError> {
+        let Some(value) = self.store.read(id, iid, &[]).map_err(QueryError::Store)? else {return Ok(vec![]);};
+        query.select(&value)
     }
 
     pub fn get<C: Contract>(&self, iid: &Id) -> Option<Substance> {
-        Some(self.value.load().get(&C::id())?.get(iid)?.clone())
+        into(&self.store.read(&C::id(), iid, &[]).ok()??).ok()
+    }
+
+    /// A handle on the list or map at `path` that loads its items on demand.
+    pub fn lazy<C: Contract, T: for<'a> Deserialize<'a>>(&self, iid: &Id, path: &str) -> Lazy<T> {
+        Lazy::new(self.store.clone(), C::id(), *iid, path)
+    }
+
+    /// Caps the memory held by loaded subtrees, the least recently read are evicted first. The manager keeps
+    /// its own copy of every instance, which the budget does not cover.
+    pub fn set_memory_budget(&self, bytes: usize) {self.store.set_budget(bytes)}
+    pub fn resident_memory(&self) -> usize {self.store.resident()}
+
+    /// Streams a new blob into the local cache, see [`blobs`].
+    pub fn blob_writer(&self) -> BlobWriter {BlobWriter::new(self.blobs.clone())}
+
+    pub fn write_blob(&self, mut reader: impl std::io::Read) -> Result<Blob, BlobError> {
+        let mut writer = self.blob_writer();
+        std::io::copy(&mut reader, &mut writer)?;
+        writer.finish()
+    }
+
+    pub fn open_blob(&self, blob: &Blob) -> Result<BlobReader, BlobError> {BlobReader::open(self.blobs.clone(), blob)}
+
+    /// Where blobs that are referenced but not held locally are fetched from when opened.
+    pub fn set_blob_source(&self, source: impl BlobSource + 'static) {self.blobs.set_source(Some(Arc::new(source)))}
+
+    /// The blobs referenced at or below `path`, their bytes are only read when an attachment is opened.
+    pub fn attachments<C: Contract>(&self, iid: &Id, path: &str) -> Vec<Attachment> {
+        let at = lang::split(path);
+        let Some(value) = self.store.read(&C::id(), iid, &at).ok().flatten() else {return vec![];};
+        Blob::find(&value).into_iter().map(|(p, blob)| {
+            let path = lang::display(&at.iter().cloned().chain(lang::split(&p)).collect::<Vec<_>>());
+            Attachment::new(self.blobs.clone(), blob, path)
+        }).collect()
+    }
+
+    /// Reads an instance as the current version of `C`, state written by older versions was migrated when it was
+    /// read from the manager.
+    pub fn load<C: Versioned>(&self, iid: &Id) -> Result<Option<C>, MigrationError> {
+        self.get::<C>(iid).map(|s| C::upgrade(s, C::VERSION)).transpose()
     }
 
     pub fn list(&self, c_id: &Id) -> Vec<Id> {
-        match self.value.load().get(c_id) {
+        match self.store.digests().get(c_id) {
             Some(instances) => instances.keys().copied().collect(),
             None => vec![]
         }
     }
 
-    pub fn create<C: Contract>(&self, contract: C) -> Result<Id, Error> {
-        let (id, request) = self.builder.create(contract)?;
-        let _ = self.tx.send(request);
+    /// Captures the persisted manager state, instances and pending requests.
+    pub fn export(&self) -> Result<Snapshot, SnapshotError> {
+        Snapshot::export(&self.builder.load(), &self.cache.lock().unwrap(), &self.blobs, &self.snapshots)
+    }
+
+    /// Reads a snapshot written by [`Snapshot::write`], only those exported by the local identity open.
+    pub fn read_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<Snapshot, SnapshotError> {
+        Snapshot::read(path.as_ref(), &self.snapshots)
+    }
+
+    /// Lists how `snapshot` disagrees with the local state without changing anything.
+    pub fn check(&self, snapshot: &Snapshot) -> Result<Vec<Conflict>, SnapshotError> {
+        snapshot.conflicts(self.name(), &self.contracts, &self.store.digests())
+    }
+
+    /// Replaces the local state with `snapshot`, diverged or local only instances are overridden only when `force`
+    /// is set. A snapshot of another identity is always refused.
+    /// Returns the conflicts that were overridden.
+    pub fn import(&self, snapshot: Snapshot, force: bool) -> Result<Vec<Conflict>, SnapshotError> {
+        let conflicts = self.check(&snapshot)?;
+        if conflicts.iter().any(Conflict::is_fatal) || (!force && !conflicts.is_empty()) {
+            return Err(SnapshotError::Conflicts(conflicts));
+        }
+        let _ = self.control.send(Control::Import(Box::new(snapshot)));
+        Ok(conflicts)
+    }
+
+    pub fn create<C: Contract>(&self, init: C::Init) -> Result<Id, RequestError> {
+        let (id, request) = self.builder.load().create::<C>(init)?;
+        self.request(request)?;
         Ok(id)
     }
 
-    pub fn share<C: Contract>(&self, iid: Id, name: Name) -> Result<(), Error> {
-        let request = self.builder.share::<C>(iid, name)?;
-        let _ = self.tx.send(request);
+    pub fn share<C: Contract>(&self, iid: Id, name: Name) -> Result<(), RequestError> {
+        let request = self.builder.load().share::<C>(iid, name)?;
+        self.request(request)
+    }
+
+    pub fn send<C: Contract, P: AsRef<Path>, R: Reactant<C> + Serialize>(&self, id: Id, path: P, reactant: R) -> Result<(), RequestError> {
+        self.dispatch(id, path, reactant).map(|_| ())
+    }
+
+    /// Submits on the interactive lane and returns the token the history entry will carry.
+    pub(crate) fn dispatch<C: Contract, P: AsRef<Path>, R: Reactant<C> + Serialize>(&self, id: Id, path: P, reactant: R) -> Result<u64, RequestError> {
+        let origin = Origin{
+            token: rand::random(),
+            contract: C::id(),
+            instance: id,
+            path: path.as_ref().to_string_lossy().to_string(),
+            reactant: R::id(),
+            body: serde_json::to_value(&reactant).unwrap_or_default(),
+        };
+        let token = origin.token;
+        let request = self.builder.load().send(id, path, reactant)?;
+        self.submit(Submission{request, origin: Some(origin)}, Priority::Interactive)?;
+        Ok(token)
+    }
+
+    /// Sends `reactant` and records it on the undo stack, `C` must register `Revert<C>` in its reactants.
+    pub fn send_undoable<C: Governed + Serialize + for<'a> Deserialize<'a>, P: AsRef<Path>, R: Reactant<C> + Serialize + 'static>(&self, id: Id, path: P, reactant: R) -> Result<(), RequestError> {
+        let path = path.as_ref().to_path_buf();
+        let token = self.dispatch(id, &path, reactant)?;
+        self.remember(Step{contract: C::id(), instance: id, path, token, action: Action::Revert(undo::send_revert::<C>)});
+        Ok(())
+    }
+
+    /// Sends `reactant` and records its inverse, computed from the local state, on the undo stack.
+    pub fn send_invertible<C: Contract + for<'a> Deserialize<'a>, P: AsRef<Path>, R: Invertible<C>>(&self, id: Id, path: P, reactant: R) -> Result<(), RequestError> {
+        let path = path.as_ref().to_path_buf();
+        let inverse = self.get::<C>(&id).and_then(|s| from::<C>(s).ok()).and_then(|before| reactant.inverse(&before));
+        let forward = undo::resend::<C, R>(id, path.clone(), reactant.clone());
+        let token = self.dispatch(id, &path, reactant)?;
+        if let Some(inverse) = inverse {
+            let backward = undo::resend::<C, R::Inverse>(id, path.clone(), inverse);
+            self.remember(Step{contract: C::id(), instance: id, path, token, action: Action::Custom{forward, backward}});
+        }
         Ok(())
     }
 
-    pub fn send<P: AsRef<Path>, R: Reactant + 'static>(&self, id: Id, path: P, reactant: R) -> Result<Result<(), R::Error>, Error> {
-        let request = self.builder.send(id, path, reactant)?;
-        let _ = self.tx.send(request); 
-        Ok(Ok(()))
+    /// Replaces `range` of the [`Text`] at `path` with `text`, positions refer to the local copy along with the
+    /// edits sent through this context that the runtime has not applied yet. Returns false when no text is held
+    /// locally at the path.
+    pub fn edit_text<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: &Id, path: &str, range: Range<usize>, text: &str) -> Result<bool, RequestError> {
+        let Some(mut local) = self.store.read(&C::id(), iid, &lang::split(path)).ok().flatten().and_then(|v| serde_json::from_value::<Text>(v).ok()) else {return Ok(false);};
+        let mut sent = self.edits.lock().unwrap();
+        let unseen = sent.entry((C::id(), *iid, path.to_string())).or_default();
+        unseen.retain(|e| !local.has(e));
+        unseen.iter().for_each(|e| local.apply(e.clone()));
+        let edits = local.splice(range, text, self.site);
+        if !edits.is_empty() {
+            self.dispatch(*iid, path, Splice::<C>::new(path, edits.clone()))?;
+            unseen.extend(edits);
+        }
+        Ok(true)
+    }
+
+    /// The [`Members`] at `path`, empty when the instance is not held locally.
+    pub fn members<C: Contract>(&self, iid: &Id, path: &str) -> Members {
+        self.store.read(&C::id(), iid, &lang::split(path)).ok().flatten().and_then(|v| serde_json::from_value(v).ok()).unwrap_or_default()
+    }
+
+    /// Shares the instance with `name` and invites them into the [`Members`] at `path` with `role`.
+    pub fn invite<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str, name: Name, role: Role) -> Result<(), RequestError> {
+        self.share::<C>(iid, name.clone())?;
+        self.membership::<C>(iid, path, members::Action::Invite{name, role})
+    }
+
+    /// Joins after an invitation.
+    pub fn accept<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str) -> Result<(), RequestError> {
+        self.membership::<C>(iid, path, members::Action::Accept)
+    }
+    pub fn decline<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str) -> Result<(), RequestError> {
+        self.membership::<C>(iid, path, members::Action::Decline)
+    }
+
+    /// Removes a member or withdraws an invitation, every `role` rule checking `path` refuses them once the
+    /// revoke is applied. When [`Membership`] refuses the revoke they stay a member.
+    pub fn revoke<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str, name: Name) -> Result<(), RequestError> {
+        self.membership::<C>(iid, path, members::Action::Revoke(name))
+    }
+    pub fn leave<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str) -> Result<(), RequestError> {
+        self.membership::<C>(iid, path, members::Action::Leave)
+    }
+    pub fn set_role<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str, name: Name, role: Role) -> Result<(), RequestError> {
+        self.membership::<C>(iid, path, members::Action::SetRole{name, role})
+    }
+
+    fn membership<C: Governed + Serialize + for<'a> Deserialize<'a>>(&self, iid: Id, path: &str, action: members::Action) -> Result<(), RequestError> {
+        self.dispatch(iid, path, Membership::<C>::new(path, action)).map(|_| ())
+    }
+
+    /// Instances of `C` held locally whose [`Members`] at `path` have a pending invitation for the local signer.
+    pub fn invitations<C: Contract>(&self, path: &str) -> Vec<(Id, Member)> {
+        let name = self.name();
+        self.list(&C::id()).into_iter().filter_map(|iid| {
+            self.members::<C>(&iid, path).get(&name).filter(|m| !m.is_active()).cloned().map(|m| (iid, m))
+        }).collect()
+    }
+
+    /// How the [`Members`] at `path` changed in the history entries after `since`, oldest first, each with the
+    /// seq of its entry. Pass the last seq seen to pick up only what is new.
+    pub fn membership_changes<C: Contract>(&self, iid: &Id, path: &str, since: u64) -> Result<Vec<(u64, members::Change)>, HistoryError> {
+        let cache = self.cache.lock().unwrap();
+        let at = lang::split(path);
+        let members = |seq: u64| -> Result<Members, HistoryError> {
+            Ok(history::state_at(&cache, &C::id(), iid, seq)?.and_then(|s| from::<serde_json::Value>(s).ok())
+                .and_then(|v| lang::read(&v, &at).ok().cloned()).and_then(|m| serde_json::from_value(m).ok()).unwrap_or_default())
+        };
+        let mut entries = History::default().fetch(&cache, &C::id(), iid)?;
+        entries.retain(|e| e.seq > since && e.result == Outcome::Applied);
+        let mut before = members(since)?;
+        let mut changes = Vec::new();
+        for entry in entries.into_iter().rev() {
+            let after = members(entry.seq)?;
+            changes.extend(before.changes(&after).into_iter().map(|c| (entry.seq, c)));
+            before = after;
+        }
+        Ok(changes)
+    }
+
+    fn remember(&self, step: Step) {
+        let mut stack = self.undo.lock().unwrap();
+        stack.undo.push(step);
+        stack.redo.clear();
+    }
+
+    /// Undoes the newest local step that nobody else has changed since, skipping those that were.
+    /// Returns false when there is nothing left to undo.
+    pub fn undo(&self) -> Result<bool, UndoError> {self.reverse(true)}
+    pub fn redo(&self) -> Result<bool, UndoError> {self.reverse(false)}
+    pub fn can_undo(&self) -> bool {!self.undo.lock().unwrap().undo.is_empty()}
+    pub fn can_redo(&self) -> bool {!self.undo.lock().unwrap().redo.is_empty()}
+
+    fn reverse(&self, undo: bool) -> Result<bool, UndoError> {
+        loop {
+            let step = {
+                let mut stack = self.undo.lock().unwrap();
+                match if undo {stack.undo.pop()} else {stack.redo.pop()} {Some(step) => step, None => return Ok(false)}
+            };
+            let (before, after) = {
+                let cache = self.cache.lock().unwrap();
+                let seq = match history::find(&cache, step.token)? {
+                    Some((seq, Outcome::Applied)) => seq,
+                    Some((_, Outcome::Refused)) => continue,
+                    None => {
+                        let mut stack = self.undo.lock().unwrap();
+                        if undo {stack.undo.push(step)} else {stack.redo.push(step)}
+                        return Err(UndoError::Pending);
+                    }
+                };
+                (history::state_at(&cache, &step.contract, &step.instance, seq - 1)?, history::state_at(&cache, &step.contract, &step.instance, seq)?)
+            };
+            let value = |s: Option<Substance>| s.and_then(|s| from::<serde_json::Value>(s).ok());
+            let (Some(before), after) = (value(before), value(after)) else {continue;};
+            let changes = undo::diff(Some(&before), after.as_ref());
+            let current = self.store.read(&step.contract, &step.instance, &[]).ok().flatten();
+            if changes.is_empty() || !current.is_some_and(|c| changes.iter().all(|change| change.holds(&c))) {continue;}
+            let (token, action) = match &step.action {
+                Action::Revert(send) => (send(self, step.instance, &step.path, changes)?, step.action.clone()),
+                Action::Custom{forward, backward} => (backward(self)?, Action::Custom{forward: backward.clone(), backward: forward.clone()}),
+            };
+            let next = Step{token, action, ..step};
+            let mut stack = self.undo.lock().unwrap();
+            if undo {stack.redo.push(next)} else {stack.undo.push(next)}
+            return Ok(true);
+        }
+    }
+
+    /// Evaluates `C`'s policy for the local signer against the current state, instances not held locally pass.
+    pub fn authorize<C: Governed>(&self, iid: &Id, path: &Path, reactant: Id) -> Result<(), Denied> {
+        let Ok(Some(state)) = self.store.read(&C::id(), iid, &[]) else {return Ok(());};
+        C::policy().check(&state, &lang::split(&path.to_string_lossy()), reactant, &self.name())
+    }
+
+    /// Like `send`, but refuses locally when `C`'s policy denies the reactant.
+    pub fn send_checked<C: Governed, P: AsRef<Path>, R: Reactant<C> + Serialize + 'static>(&self, id: Id, path: P, reactant: R) -> Result<Result<(), Denied>, RequestError> {
+        if let Err(denied) = self.authorize::<C>(&id, path.as_ref(), R::id()) {return Ok(Err(denied));}
+        self.send(id, path, reactant).map(|_| Ok(()))
+    }
+
+    /// Pages through the locally recorded history of an instance, newest first.
+    pub fn history(&self, id: &Id, iid: &Id, filter: &History) -> Result<Vec<Entry>, HistoryError> {
+        filter.fetch(&self.cache.lock().unwrap(), id, iid)
+    }
+
+    /// Reconstructs the instance as it was right after history entry `seq`.
+    pub fn state_at(&self, id: &Id, iid: &Id, seq: u64) -> Result<Option<Substance>, HistoryError> {
+        history::state_at(&self.cache.lock().unwrap(), id, iid, seq)
     }
 }
 
 pub(crate) struct Air {
     cache: Connection,
+    contracts: Contracts,
+    builder: Arc<ArcSwap<RequestBuilder>>,
     manager: Manager,
-    value: Arc<ArcSwap<BTreeMap<Id, BTreeMap<Id, Substance>>>>,
-    rx: Rx<Array<Request>>
+    /// Relays requests to and from other runtimes in the same process, see [`simulation`].
+    transport: Option<Arc<dyn Transport>>,
+    store: Arc<Store>,
+    blobs: Arc<Blobs>,
+    /// When the manager state was last read, `None` reads it on the next tick. An idle runtime waits `refresh`
+    /// before reading it again.
+    synced: Option<Instant>,
+    refresh: Duration,
+    interactive: AsyncRx<Array<Submission>>,
+    background: AsyncRx<Array<Submission>>,
+    control: Rx<List<Control>>,
 }
 
 impl Air {
-    pub fn start(_hardware: &hardware::Context, contracts: Contracts) -> Result<(Self, Context), rusqlite::Error> {
-      //let secret = hardware.cloud().get("secret").and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_else(|| {
-      //    let secret = Secret::new();
-      //    hardware.cloud().save("secret", &serde_json::to_string(&secret).unwrap());
-      //    secret
-      //});
-        let cache = Connection::open("./air_cache.db")?;
+    /// Opens the encrypted air cache of `profile`, a new cache is owned by `secret`.
+    pub fn start(_hardware: &hardware::Context, contracts: Contracts, profile: &Profile, secret: Secret, vault: &Vault) -> Result<(Self, Context), VaultError> {
+        let path = profile.cache();
+        if let Some(directory) = path.parent() {std::fs::create_dir_all(directory)?;}
+        Self::open_as(&path, secret, contracts, None, REFRESH, |path| vault.connect(path))
+    }
+
+    /// Opens a plaintext cache with a fresh identity whose requests are relayed through `transport`, its state is read
+    /// after every tick.
+    pub(crate) fn open(path: &Path, contracts: Contracts, transport: Arc<dyn Transport>) -> Result<(Self, Context), rusqlite::Error> {
+        Self::open_as(path, Secret::new(), contracts, Some(transport), Duration::ZERO, |path| Connection::open(path))
+    }
+
+    fn open_as<E: From<rusqlite::Error>>(path: &Path, secret: Secret, contracts: Contracts, transport: Option<Arc<dyn Transport>>, refresh: Duration, connect: impl Fn(&Path) -> Result<Connection, E>) -> Result<(Self, Context), E> {
+        let snapshots = snapshot::Key::new(&secret);
+        let cache = connect(path)?;
         init(&cache)?;
-        let mut manager = get(&cache, "manager")?.unwrap_or_else(|| Manager::new(Secret::new()));
+        history::init(&cache)?;
+        let mut manager = get(&cache, "manager")?.unwrap_or_else(|| Manager::new(secret));
         //let mut manager = Manager::new(Secret::new());
-        manager.init(contracts);
-        let builder = manager.request_builder();
+        manager.init(contracts.registry.clone());
+        let builder = Arc::new(ArcSwap::from_pointee(manager.request_builder()));
 
-        let value = Arc::new(ArcSwap::from(Arc::new(manager.get())));
+        let store = Arc::new(Store::new(connect(path)?, store::DEFAULT_BUDGET)?);
+        if let Err(e) = store.sync(&migration::current(manager.get())) {log::error!("Could not store air instances: {e}");}
+        let blobs = Arc::new(Blobs::new(connect(path)?)?);
+        let ledger = Arc::new(Mutex::new(connect(path)?));
 
-        let (tx, rx) = bounded_blocking(100);
+        let (interactive_tx, interactive) = bounded_async(INTERACTIVE_CAPACITY);
+        let (background_tx, background) = bounded_async(BACKGROUND_CAPACITY);
+        let (control_tx, control) = unbounded_blocking();
 
         Ok((Air{
             cache,
+            contracts: contracts.clone(),
+            builder: builder.clone(),
             manager,
-            value: value.clone(),
-            rx,
-        }, Context{builder, value, tx}))
+            transport,
+            store: store.clone(),
+            blobs: blobs.clone(),
+            synced: Some(Instant::now()),
+            refresh,
+            interactive,
+            background,
+            control,
+        }, Context{
+            builder,
+            contracts,
+            store,
+            blobs,
+            lanes: Lanes{interactive: interactive_tx, background: background_tx},
+            control: control_tx,
+            cache: ledger,
+            undo: Arc::new(Mutex::new(Stack::default())),
+            site: Id::hash(&rand::rng().random::<[u8; 32]>()),
+            edits: Arc::new(Mutex::new(BTreeMap::new())),
+            snapshots,
+        }))
     }
 
     pub async fn run(mut self) {
         loop {
-            //let mut request = Vec::new();
-            //while let Ok(r) = self.rx.try_recv() {request.push(r);}
-            self.manager.tick(self.rx.try_recv().ok()).await;
-            self.value.store(Arc::new(self.manager.get()));
-            insert(&self.cache, "manager", &self.manager).unwrap();
+            let request = self.next();
+            self.apply(request).await;
+        }
+    }
+
+    /// Handles pending control messages and takes the next request, interactive lane first.
+    pub(crate) fn next(&mut self) -> Option<Submission> {
+        while let Ok(control) = self.control.try_recv() {
+            if let Err(e) = self.handle(control) {log::error!("Air control failed: {e}");}
+        }
+        //let mut request = Vec::new();
+        //while let Ok(r) = self.rx.try_recv() {request.push(r);}
+        self.interactive.try_recv().ok().or_else(|| self.background.try_recv().ok())
+    }
+
+    /// Ticks the manager and, after a local request or once [`REFRESH`] has passed, writes what changed. With a
+    /// transport the requests other runtimes relayed are ticked first and a local request is relayed to them.
+    pub(crate) async fn apply(&mut self, submission: Option<Submission>) {
+        let (request, origin) = submission.map(|s| (s.request, s.origin)).unzip();
+        let mut local = request.is_some();
+        if let Some(transport) = &self.transport {
+            for (from, message) in transport.receive() {
+                match serde_json::from_slice::<Request>(&message) {
+                    Ok(relayed) => {self.manager.tick(Some(relayed)).await; local = true;},
+                    Err(e) => log::error!("Dropped a request relayed by {from:?}: {e}"),
+                }
+            }
+            if let Some(request) = &request {transport.broadcast(serde_json::to_vec(request).unwrap());}
+        }
+        self.manager.tick(request).await;
+        if !local && self.synced.is_some_and(|s| s.elapsed() < self.refresh) {return;}
+        self.synced = Some(Instant::now());
+        let after = migration::current(self.manager.get());
+        match self.store.sync(&after) {
+            Ok(changed) => {
+                if let Err(e) = history::record(&self.cache, &self.manager.request_builder().name(), &origin.flatten().into_iter().collect::<Vec<_>>(), &changed, &after) {
+                    log::error!("Could not record air history: {e}");
+                }
+                if let Err(e) = self.blobs.sync(&changed, &after) {log::error!("Could not collect air blobs: {e}");}
+            },
+            Err(e) => log::error!("Could not store air instances: {e}"),
+        }
+        insert(&self.cache, "manager", &self.manager).unwrap();
+    }
+
+    fn handle(&mut self, control: Control) -> Result<(), SnapshotError> {
+        match control {
+            Control::Import(snapshot) => {
+                let mut manager = snapshot.manager()?;
+                manager.init(self.contracts.registry.clone());
+                snapshot.restore(&self.cache, &self.blobs)?;
+                self.builder.store(Arc::new(manager.request_builder()));
+                self.manager = manager;
+                self.synced = None;
+            }
         }
+        Ok(())
     }
 }
 
@@ -116,6 +640,7 @@ fn init(connection: &Connection) -> Result<(), rusqlite::Error> {
     );", [])?;
     Ok(())
 }
+
 fn get<T: for<'a> Deserialize<'a>>(connection: &Connection, key: &str) -> Result<Option<T>, rusqlite::Error> {
     Ok(connection.query_row(
         &format!("SELECT value FROM Cache WHERE key='{key}'"),
@@ -0,0 +1,405 @@
+//! Large binary data kept outside of instance state.
+//!
+//! A blob is stored once per content, keyed by the SHA-256 of its bytes, and split into chunks of [`CHUNK`]
+//! bytes that are themselves keyed by content, so blobs sharing data share chunks. Contracts hold a [`Blob`],
+//! the hash and size, instead of the bytes:
+//! ```ignore
+//! let blob = ctx.write_blob(std::fs::File::open("photo.jpg")?)?;
+//! ctx.send(Album::id(), "/photos", AddPhoto(blob))?;
+//! for attachment in ctx.attachments::<Album>(&album, "/photos") {
+//!     let mut reader = attachment.open()?;
+//! }
+//! ```
+//! After every tick the blobs referenced by each changed instance are counted again, a blob no instance refers
+//! to is deleted along with the chunks only it used. Blobs that were never referenced are kept for [`GRACE`]
+//! seconds so there is time to send the reactant that refers to them.
+//!
+//! The manager does not transfer blobs, they travel in [snapshots](super::Snapshot) or are fetched through the
+//! [`BlobSource`] set with [`Context::set_blob_source`](super::Context::set_blob_source) the first time they are
+//! opened. The fetch runs on its own thread, opening the blob fails with [`BlobError::Pending`] until its bytes
+//! arrived and matched the hash. Without a source a [`Blob`] in an instance received from another device opens
+//! with [`BlobError::Missing`] until the same bytes are written locally.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use sha2::{Sha256, Digest};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Id, Substance, from};
+use super::lang::display;
+
+/// Bytes per chunk, the last chunk of a blob may be shorter.
+pub const CHUNK: usize = 256 * 1024;
+
+/// Seconds a blob no instance refers to is kept after it was written.
+pub const GRACE: u64 = 60 * 60;
+
+#[derive(Debug)]
+pub enum BlobError {
+    Database(rusqlite::Error),
+    Io(std::io::Error),
+    /// The blob is not held locally.
+    Missing(String),
+    /// The blob is being fetched from the source, it opens once its bytes arrived.
+    Pending(String),
+    Corrupt(String),
+}
+impl std::fmt::Display for BlobError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlobError::Database(e) => write!(f, "Blob database error: {e}"),
+            BlobError::Io(e) => write!(f, "Blob io error: {e}"),
+            BlobError::Missing(h) => write!(f, "Blob {h} is not held locally"),
+            BlobError::Pending(h) => write!(f, "Blob {h} is not available yet"),
+            BlobError::Corrupt(e) => write!(f, "Blob is corrupt: {e}"),
+        }
+    }
+}
+impl std::error::Error for BlobError {}
+impl From<rusqlite::Error> for BlobError {fn from(e: rusqlite::Error) -> Self {BlobError::Database(e)}}
+impl From<std::io::Error> for BlobError {fn from(e: std::io::Error) -> Self {BlobError::Io(e)}}
+impl From<BlobError> for std::io::Error {
+    fn from(e: BlobError) -> Self {
+        match e {
+            BlobError::Io(e) => e,
+            e => std::io::Error::other(e),
+        }
+    }
+}
+
+/// A reference to a blob, stored in contract state in place of the bytes. It is encoded with a `"$type": "Blob"`
+/// marker so it is recognized anywhere in a state.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(tag = "$type", rename = "Blob")]
+pub struct Blob {
+    /// Hex encoded SHA-256 of the content.
+    pub hash: String,
+    pub size: u64,
+}
+impl Blob {
+    /// Recognizes an encoded `Blob` anywhere in a state by its marker.
+    fn parse(value: &Value) -> Option<Blob> {
+        let Value::Object(map) = value else {return None;};
+        if map.get("$type").and_then(Value::as_str) != Some("Blob") {return None;}
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    fn collect(value: &Value, path: &mut Vec<String>, out: &mut Vec<(String, Blob)>) {
+        if let Some(blob) = Blob::parse(value) {out.push((display(path), blob)); return;}
+        let mut child = |key: String, value: &Value| {
+            path.push(key);
+            Blob::collect(value, path, out);
+            path.pop();
+        };
+        match value {
+            Value::Array(items) => items.iter().enumerate().for_each(|(i, v)| child(i.to_string(), v)),
+            Value::Object(map) => map.iter().for_each(|(k, v)| child(k.clone(), v)),
+            _ => {}
+        }
+    }
+
+    /// Every blob referenced in `value` with the path it is at.
+    pub fn find(value: &Value) -> Vec<(String, Blob)> {
+        let mut out = Vec::new();
+        Blob::collect(value, &mut Vec::new(), &mut out);
+        out
+    }
+}
+
+/// A blob with the bytes of each of its chunks, as carried by a snapshot.
+pub(crate) type Chunked = (Blob, Vec<Vec<u8>>);
+
+/// Fetches the blobs that are referenced but not held locally.
+pub trait BlobSource: Send + Sync {
+    /// The bytes of `blob`, anything that does not hash to it is discarded.
+    fn fetch(&self, blob: &Blob) -> Result<Box<dyn Read + Send>, BlobError>;
+}
+
+pub(crate) struct Blobs {
+    connection: Mutex<Connection>,
+    source: Mutex<Option<Arc<dyn BlobSource>>>,
+    /// Blobs being fetched from the source.
+    fetching: Mutex<BTreeSet<String>>,
+}
+impl std::fmt::Debug for Blobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {write!(f, "Blobs")}
+}
+impl Blobs {
+    pub fn new(connection: Connection) -> Result<Self, rusqlite::Error> {
+        connection.execute("CREATE TABLE if not exists Blobs(
+            hash TEXT NOT NULL PRIMARY KEY,
+            size INTEGER NOT NULL,
+            chunks TEXT NOT NULL,
+            created INTEGER NOT NULL
+        );", [])?;
+        connection.execute("CREATE TABLE if not exists Chunks(
+            hash TEXT NOT NULL PRIMARY KEY,
+            data BLOB NOT NULL,
+            refs INTEGER NOT NULL
+        );", [])?;
+        connection.execute("CREATE TABLE if not exists BlobRefs(
+            contract TEXT NOT NULL,
+            instance TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            PRIMARY KEY(contract, instance, hash)
+        );", [])?;
+        Ok(Blobs{connection: Mutex::new(connection), source: Mutex::new(None), fetching: Mutex::new(BTreeSet::new())})
+    }
+
+    pub fn set_source(&self, source: Option<Arc<dyn BlobSource>>) {*self.source.lock().unwrap() = source;}
+
+    /// Starts fetching `blob` from the source on its own thread unless it already is, the error to open it with
+    /// until then.
+    fn fetch(self: &Arc<Self>, blob: &Blob) -> BlobError {
+        let Some(source) = self.source.lock().unwrap().clone() else {return BlobError::Missing(blob.hash.clone());};
+        if self.fetching.lock().unwrap().insert(blob.hash.clone()) {
+            let (blobs, blob) = (self.clone(), blob.clone());
+            std::thread::spawn(move || {
+                if let Err(e) = blobs.download(source.as_ref(), &blob) {log::error!("Could not fetch blob {}: {e}", blob.hash);}
+                blobs.fetching.lock().unwrap().remove(&blob.hash);
+            });
+        }
+        BlobError::Pending(blob.hash.clone())
+    }
+
+    /// Reads `blob` from `source` and keeps it once its bytes match.
+    fn download(self: &Arc<Self>, source: &dyn BlobSource, blob: &Blob) -> Result<(), BlobError> {
+        let mut writer = BlobWriter::new(self.clone());
+        std::io::copy(&mut source.fetch(blob)?.take(blob.size.saturating_add(1)), &mut writer)?;
+        let fetched = writer.finish()?;
+        if fetched != *blob {return Err(BlobError::Corrupt(format!("Fetched {} bytes hashing to {} for {}", fetched.size, fetched.hash, blob.hash)));}
+        Ok(())
+    }
+
+    /// Every blob an instance refers to with the bytes of its chunks, for a snapshot.
+    pub fn export(&self) -> Result<Vec<Chunked>, BlobError> {
+        let blobs = {
+            let connection = self.connection.lock().unwrap();
+            let mut statement = connection.prepare("SELECT hash, size FROM Blobs WHERE hash IN (SELECT hash FROM BlobRefs)")?;
+            statement.query_map([], |r| Ok(Blob{hash: r.get(0)?, size: r.get::<_, i64>(1)? as u64}))?.collect::<Result<Vec<_>, _>>()?
+        };
+        blobs.into_iter().map(|blob| {
+            let chunks = self.chunks(&blob)?.iter().map(|c| self.chunk(c)).collect::<Result<Vec<_>, _>>()?;
+            Ok((blob, chunks))
+        }).collect()
+    }
+
+    /// Stores the blobs of a snapshot that are not held yet, each is checked against its hash.
+    pub fn import(self: &Arc<Self>, blobs: Vec<Chunked>) -> Result<(), BlobError> {
+        for (blob, chunks) in blobs {
+            if self.contains(&blob)? {continue;}
+            let mut writer = BlobWriter::new(self.clone());
+            chunks.iter().try_for_each(|c| writer.write_all(c))?;
+            let imported = writer.finish()?;
+            if imported != blob {return Err(BlobError::Corrupt(format!("Snapshot blob {} hashes to {}", blob.hash, imported.hash)));}
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, blob: &Blob) -> Result<bool, BlobError> {
+        Ok(self.connection.lock().unwrap().query_row("SELECT 1 FROM Blobs WHERE hash = ?1", [&blob.hash], |_| Ok(())).optional()?.is_some())
+    }
+
+    /// How many instances refer to `blob`.
+    pub fn refs(&self, blob: &Blob) -> Result<usize, BlobError> {
+        Ok(self.connection.lock().unwrap().query_row("SELECT COUNT(*) FROM BlobRefs WHERE hash = ?1", [&blob.hash], |r| r.get::<_, i64>(0))? as usize)
+    }
+
+    fn chunks(&self, blob: &Blob) -> Result<Vec<String>, BlobError> {
+        let chunks = self.connection.lock().unwrap().query_row("SELECT chunks FROM Blobs WHERE hash = ?1", [&blob.hash], |r| r.get::<_, String>(0)).optional()?
+            .ok_or_else(|| BlobError::Missing(blob.hash.clone()))?;
+        serde_json::from_str(&chunks).map_err(|e| BlobError::Corrupt(e.to_string()))
+    }
+
+    fn chunk(&self, hash: &str) -> Result<Vec<u8>, BlobError> {
+        self.connection.lock().unwrap().query_row("SELECT data FROM Chunks WHERE hash = ?1", [hash], |r| r.get::<_, Vec<u8>>(0)).optional()?
+            .ok_or_else(|| BlobError::Corrupt(format!("Missing chunk {hash}")))
+    }
+
+    /// Stores a chunk or takes another reference on it.
+    fn hold(&self, data: &[u8]) -> Result<String, BlobError> {
+        let hash = hex::encode(Sha256::digest(data));
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO Chunks(hash, data, refs) VALUES (?1, ?2, 1) ON CONFLICT DO UPDATE SET refs = refs + 1",
+            params![hash, data]
+        )?;
+        Ok(hash)
+    }
+
+    fn release(connection: &Connection, chunks: &[String]) -> Result<(), rusqlite::Error> {
+        for chunk in chunks {
+            connection.execute("UPDATE Chunks SET refs = refs - 1 WHERE hash = ?1", [chunk])?;
+        }
+        connection.execute("DELETE FROM Chunks WHERE refs <= 0", [])?;
+        Ok(())
+    }
+
+    /// Records the blob made of `chunks`, which the caller holds. If it already exists the holds are dropped.
+    fn commit(&self, blob: &Blob, chunks: &[String]) -> Result<(), BlobError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let inserted = transaction.execute(
+            "INSERT INTO Blobs(hash, size, chunks, created) VALUES (?1, ?2, ?3, ?4) ON CONFLICT DO NOTHING",
+            params![blob.hash, blob.size as i64, serde_json::to_string(chunks).unwrap(), now() as i64]
+        )?;
+        if inserted == 0 {Self::release(&transaction, chunks)?;}
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Recounts the references of the `changed` instances and deletes the blobs nothing refers to anymore.
+    pub fn sync(&self, changed: &BTreeSet<(Id, Id)>, state: &BTreeMap<Id, BTreeMap<Id, Substance>>) -> Result<(), BlobError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for (contract, instance) in changed {
+            transaction.execute("DELETE FROM BlobRefs WHERE contract = ?1 AND instance = ?2", params![key(contract), key(instance)])?;
+            let Some(substance) = state.get(contract).and_then(|i| i.get(instance)) else {continue;};
+            let value = from::<Value>(substance.clone()).map_err(|e| BlobError::Corrupt(format!("{e:?}")))?;
+            for hash in Blob::find(&value).into_iter().map(|(_, b)| b.hash).collect::<BTreeSet<_>>() {
+                transaction.execute("INSERT INTO BlobRefs(contract, instance, hash) VALUES (?1, ?2, ?3) ON CONFLICT DO NOTHING", params![key(contract), key(instance), hash])?;
+            }
+        }
+        let unreferenced = {
+            let mut statement = transaction.prepare("SELECT hash, chunks FROM Blobs WHERE created < ?1 AND hash NOT IN (SELECT hash FROM BlobRefs)")?;
+            statement.query_map([now().saturating_sub(GRACE) as i64], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        for (hash, chunks) in unreferenced {
+            transaction.execute("DELETE FROM Blobs WHERE hash = ?1", [&hash])?;
+            Self::release(&transaction, &serde_json::from_str::<Vec<String>>(&chunks).map_err(|e| BlobError::Corrupt(e.to_string()))?)?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+fn key<T: Serialize>(value: &T) -> String {serde_json::to_string(value).unwrap()}
+
+fn now() -> u64 {SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()}
+
+/// Streams bytes into a new blob, chunks are stored as they fill up. Dropping it without `finish` discards
+/// what was written.
+pub struct BlobWriter {
+    blobs: Arc<Blobs>,
+    hasher: Sha256,
+    buffer: Vec<u8>,
+    chunks: Vec<String>,
+    size: u64,
+}
+impl BlobWriter {
+    pub(crate) fn new(blobs: Arc<Blobs>) -> Self {
+        BlobWriter{blobs, hasher: Sha256::new(), buffer: Vec::with_capacity(CHUNK), chunks: Vec::new(), size: 0}
+    }
+
+    fn store(&mut self) -> Result<(), BlobError> {
+        if self.buffer.is_empty() {return Ok(());}
+        self.chunks.push(self.blobs.hold(&self.buffer)?);
+        self.buffer.clear();
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Blob, BlobError> {
+        self.store()?;
+        let blob = Blob{hash: hex::encode(std::mem::take(&mut self.hasher).finalize()), size: self.size};
+        self.blobs.commit(&blob, &self.chunks)?;
+        self.chunks.clear();
+        Ok(blob)
+    }
+}
+impl Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(CHUNK - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        if self.buffer.len() == CHUNK {self.store()?;}
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {Ok(())}
+}
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        if self.chunks.is_empty() {return;}
+        if let Err(e) = Blobs::release(&self.blobs.connection.lock().unwrap(), &self.chunks) {log::error!("Could not discard blob chunks: {e}");}
+    }
+}
+
+/// Reads a blob one chunk at a time.
+pub struct BlobReader {
+    blobs: Arc<Blobs>,
+    blob: Blob,
+    chunks: Vec<String>,
+    position: u64,
+    loaded: Option<(usize, Vec<u8>)>,
+}
+impl BlobReader {
+    /// Opens a blob held locally, one that is not starts being fetched from the [`BlobSource`] and fails with
+    /// [`BlobError::Pending`] until it arrived.
+    pub(crate) fn open(blobs: Arc<Blobs>, blob: &Blob) -> Result<Self, BlobError> {
+        let chunks = match blobs.chunks(blob) {
+            Err(BlobError::Missing(_)) => return Err(blobs.fetch(blob)),
+            chunks => chunks?,
+        };
+        Ok(BlobReader{blobs, blob: blob.clone(), chunks, position: 0, loaded: None})
+    }
+
+    pub fn blob(&self) -> &Blob {&self.blob}
+}
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.blob.size {return Ok(0);}
+        let index = (self.position / CHUNK as u64) as usize;
+        if self.loaded.as_ref().is_none_or(|(i, _)| *i != index) {
+            let hash = self.chunks.get(index).ok_or_else(|| BlobError::Corrupt(format!("Missing chunk {index} of {}", self.blob.hash)))?;
+            self.loaded = Some((index, self.blobs.chunk(hash)?));
+        }
+        let (_, data) = self.loaded.as_ref().unwrap();
+        let offset = (self.position % CHUNK as u64) as usize;
+        let n = buf.len().min(data.len().saturating_sub(offset));
+        buf[..n].copy_from_slice(&data[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+impl Seek for BlobReader {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.blob.size.checked_add_signed(n),
+            SeekFrom::Current(n) => self.position.checked_add_signed(n),
+        };
+        self.position = target.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Seek before the start of the blob"))?;
+        Ok(self.position)
+    }
+}
+
+/// A blob referenced by an instance, nothing is read until it is opened.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    blobs: Arc<Blobs>,
+    pub blob: Blob,
+    /// Where in the instance it is referenced.
+    pub path: String,
+}
+impl Attachment {
+    pub(crate) fn new(blobs: Arc<Blobs>, blob: Blob, path: String) -> Self {Attachment{blobs, blob, path}}
+
+    /// Whether the bytes are held locally, opening a blob that is not starts fetching it.
+    pub fn available(&self) -> bool {self.blobs.contains(&self.blob).unwrap_or(false)}
+
+    pub fn open(&self) -> Result<BlobReader, BlobError> {BlobReader::open(self.blobs.clone(), &self.blob)}
+
+    pub fn read(&self) -> Result<Vec<u8>, BlobError> {
+        // The size comes from whoever wrote the instance, the bytes read are bounded by the chunks held instead.
+        let mut bytes = Vec::new();
+        self.open()?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,288 @@
+//! Local, append-only history of changes to contract instances.
+//!
+//! After every tick the runtime records one entry per reactant submitted from this device and one per instance
+//! that changed otherwise, by a reactant of another signer or an instance arriving in full. The manager does not
+//! report who or what changed an instance it received, so those entries only carry the path the change lies
+//! under.
+//!
+//! Entries store the changes since the previous entry of the instance, every [`CHECKPOINT`]th entry stores the
+//! full state instead. `state_at` replays the changes since the nearest checkpoint.
+//! ```ignore
+//! let page = ctx.history(&Room::id(), &room, &History::default().signer(bob).path("/messages").limit(20))?;
+//! let before = ctx.state_at(&Room::id(), &room, page.last().unwrap().seq - 1)?;
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Name, Id, Substance, from, into};
+use super::lang::{self, split, display};
+use super::undo::{Change, diff};
+
+/// Entries between two full copies of an instance state.
+pub const CHECKPOINT: u64 = 64;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Database(rusqlite::Error),
+    Corrupt(String),
+}
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HistoryError::Database(e) => write!(f, "History database error: {e}"),
+            HistoryError::Corrupt(e) => write!(f, "History entry is corrupt: {e}"),
+        }
+    }
+}
+impl std::error::Error for HistoryError {}
+impl From<rusqlite::Error> for HistoryError {fn from(e: rusqlite::Error) -> Self {HistoryError::Database(e)}}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// The instance state changed in the tick the entry was recorded in.
+    Applied,
+    /// A local reactant that left the instance as it was, it was denied, rejected or did nothing.
+    Refused,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub seq: u64,
+    pub contract: Id,
+    pub instance: Id,
+    /// `None` for changes whose reactant is not known.
+    pub signer: Option<Name>,
+    pub timestamp: u64,
+    pub path: Option<String>,
+    pub reactant: Option<Id>,
+    /// The serialized reactant.
+    pub body: Option<Value>,
+    pub result: Outcome,
+}
+
+/// Metadata of a reactant submitted through `Context::send`, carried alongside its request.
+#[derive(Debug, Clone)]
+pub(crate) struct Origin {
+    /// Identifies the submission so it can be found again once applied.
+    pub token: u64,
+    pub contract: Id,
+    pub instance: Id,
+    pub path: String,
+    pub reactant: Id,
+    pub body: Value,
+}
+
+/// Filter and page over the history of one instance, newest entries first.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    signer: Option<Option<Name>>,
+    path: Option<String>,
+    before: Option<u64>,
+    limit: Option<usize>,
+}
+impl History {
+    pub fn signer(mut self, signer: Name) -> Self {self.signer = Some(Some(signer)); self}
+    /// Only entries with no known signer.
+    pub fn remote(mut self) -> Self {self.signer = Some(None); self}
+    /// Only entries at or below `path`, which may contain `*` segments like a route.
+    pub fn path(mut self, path: &str) -> Self {self.path = Some(path.to_string()); self}
+    /// Continues paging after the oldest entry of the previous page.
+    pub fn before(mut self, seq: u64) -> Self {self.before = Some(seq); self}
+    pub fn limit(mut self, limit: usize) -> Self {self.limit = Some(limit); self}
+
+    fn covers(&self, entry: &Entry) -> bool {
+        let Some(pattern) = &self.path else {return true;};
+        let Some(path) = &entry.path else {return false;};
+        let path = split(path);
+        let depth = split(pattern).len();
+        path.len() >= depth && lang::matches(pattern, &path[..depth])
+    }
+
+    pub(crate) fn fetch(&self, cache: &Connection, contract: &Id, instance: &Id) -> Result<Vec<Entry>, HistoryError> {
+        let mut statement = cache.prepare(
+            "SELECT seq, signer, timestamp, path, reactant, body, result FROM History
+             WHERE contract = ?1 AND instance = ?2 AND seq < ?3 ORDER BY seq DESC"
+        )?;
+        let before = self.before.map(|b| b as i64).unwrap_or(i64::MAX);
+        let rows = statement.query_map(params![key(contract), key(instance), before], |r| Ok((
+            r.get::<_, i64>(0)?, r.get::<_, Option<String>>(1)?, r.get::<_, i64>(2)?, r.get::<_, Option<String>>(3)?,
+            r.get::<_, Option<String>>(4)?, r.get::<_, Option<String>>(5)?, r.get::<_, String>(6)?,
+        )))?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (seq, signer, timestamp, path, reactant, body, result) = row?;
+            let entry = Entry{
+                seq: seq as u64,
+                contract: *contract,
+                instance: *instance,
+                signer: signer.map(|s| decode(&s)).transpose()?,
+                timestamp: timestamp as u64,
+                path,
+                reactant: reactant.map(|r| decode(&r)).transpose()?,
+                body: body.map(|b| decode(&b)).transpose()?,
+                result: decode(&result)?,
+            };
+            if self.signer.as_ref().is_some_and(|s| *s != entry.signer) || !self.covers(&entry) {continue;}
+            entries.push(entry);
+            if self.limit.is_some_and(|l| entries.len() >= l) {break;}
+        }
+        Ok(entries)
+    }
+}
+
+fn key<T: Serialize>(value: &T) -> String {serde_json::to_string(value).unwrap()}
+
+fn decode<T: for<'a> Deserialize<'a>>(value: &str) -> Result<T, HistoryError> {
+    serde_json::from_str(value).map_err(|e| HistoryError::Corrupt(e.to_string()))
+}
+
+pub(crate) fn init(cache: &Connection) -> Result<(), rusqlite::Error> {
+    cache.execute("CREATE TABLE if not exists History(
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        contract TEXT NOT NULL,
+        instance TEXT NOT NULL,
+        signer TEXT,
+        timestamp INTEGER NOT NULL,
+        path TEXT,
+        reactant TEXT,
+        body TEXT,
+        result TEXT NOT NULL,
+        checkpoint INTEGER NOT NULL,
+        state BLOB NOT NULL,
+        token INTEGER
+    );", [])?;
+    cache.execute("CREATE INDEX if not exists HistoryInstance ON History(contract, instance, seq);", [])?;
+    Ok(())
+}
+
+/// An entry about to be appended.
+struct Row<'a> {
+    signer: Option<&'a Name>,
+    timestamp: u64,
+    path: Option<String>,
+    reactant: Option<Id>,
+    body: Option<&'a Value>,
+    result: Outcome,
+    token: Option<u64>,
+}
+
+/// The history of one instance as far as it is recorded, with the entries since its last checkpoint.
+struct Tail<'a> {
+    cache: &'a Connection,
+    contract: &'a Id,
+    instance: &'a Id,
+    state: Option<Value>,
+    since: u64,
+}
+impl Tail<'_> {
+    fn append(&mut self, row: Row, state: &Value) -> Result<(), HistoryError> {
+        let changes = self.state.as_ref().map(|before| diff(Some(state), Some(before)));
+        let checkpoint = match &changes {
+            Some(changes) => self.since + 1 >= CHECKPOINT || changes.iter().any(|c| c.path().is_empty()),
+            None => true,
+        };
+        let stored = match checkpoint {
+            true => serde_json::to_vec(&into(state).map_err(|e| HistoryError::Corrupt(e.to_string()))?).unwrap(),
+            false => serde_json::to_vec(&changes.unwrap_or_default()).unwrap(),
+        };
+        self.cache.execute(
+            "INSERT INTO History(contract, instance, signer, timestamp, path, reactant, body, result, checkpoint, state, token)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                key(self.contract), key(self.instance), row.signer.map(key), row.timestamp as i64, row.path,
+                row.reactant.as_ref().map(key), row.body.map(key), key(&row.result), checkpoint, stored, row.token.map(|t| t as i64)
+            ]
+        )?;
+        self.since = if checkpoint {0} else {self.since + 1};
+        self.state = Some(state.clone());
+        Ok(())
+    }
+}
+
+/// Appends the entries for one tick given the instances it changed.
+pub(crate) fn record(
+    cache: &Connection,
+    name: &Name,
+    origins: &[Origin],
+    changed: &BTreeSet<(Id, Id)>,
+    after: &BTreeMap<Id, BTreeMap<Id, Substance>>,
+) -> Result<(), HistoryError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let transaction = cache.unchecked_transaction()?;
+    let instances = changed.iter().copied().chain(origins.iter().map(|o| (o.contract, o.instance))).collect::<BTreeSet<_>>();
+    for (contract, instance) in &instances {
+        let Some(substance) = after.get(contract).and_then(|i| i.get(instance)) else {continue;};
+        let state = from::<Value>(substance.clone()).map_err(|e| HistoryError::Corrupt(e.to_string()))?;
+        let (previous, since) = replay(&transaction, contract, instance, None)?.unzip();
+        let mut tail = Tail{cache: &transaction, contract, instance, state: previous, since: since.unwrap_or_default()};
+        let changed = changed.contains(&(*contract, *instance));
+        let local = origins.iter().filter(|o| o.contract == *contract && o.instance == *instance).collect::<Vec<_>>();
+        for origin in &local {
+            tail.append(Row{
+                signer: Some(name), timestamp: now, path: Some(origin.path.clone()), reactant: Some(origin.reactant),
+                body: Some(&origin.body), result: if changed {Outcome::Applied} else {Outcome::Refused}, token: Some(origin.token)
+            }, &state)?;
+        }
+        if local.is_empty() && tail.state.as_ref() != Some(&state) {
+            let path = tail.state.as_ref().map(|before| display(&prefix(&diff(Some(&state), Some(before)))));
+            tail.append(Row{signer: None, timestamp: now, path, reactant: None, body: None, result: Outcome::Applied, token: None}, &state)?;
+        }
+    }
+    Ok(transaction.commit()?)
+}
+
+/// The longest path every change lies under.
+fn prefix(changes: &[Change]) -> Vec<String> {
+    let mut paths = changes.iter().map(Change::path);
+    let first = paths.next().unwrap_or_default().to_vec();
+    paths.fold(first, |prefix, path| prefix.iter().zip(path).take_while(|(a, b)| a == b).map(|(a, _)| a.clone()).collect())
+}
+
+/// Rebuilds the instance state after entry `seq`, or after the newest entry when `None`, from the nearest
+/// checkpoint. Returns it with the number of entries replayed on top of the checkpoint.
+fn replay(cache: &Connection, contract: &Id, instance: &Id, seq: Option<u64>) -> Result<Option<(Value, u64)>, HistoryError> {
+    let seq = seq.map(|s| s as i64).unwrap_or(i64::MAX);
+    let mut statement = cache.prepare(
+        "SELECT checkpoint, state FROM History WHERE contract = ?1 AND instance = ?2 AND seq <= ?3 AND seq >= (
+            SELECT MAX(seq) FROM History WHERE contract = ?1 AND instance = ?2 AND seq <= ?3 AND checkpoint = 1
+        ) ORDER BY seq"
+    )?;
+    let rows = statement.query_map(params![key(contract), key(instance), seq], |r| Ok((r.get::<_, bool>(0)?, r.get::<_, Vec<u8>>(1)?)))?;
+    let corrupt = |e: &dyn std::fmt::Display| HistoryError::Corrupt(e.to_string());
+    let mut state: Option<(Value, u64)> = None;
+    for row in rows {
+        match (row?, &mut state) {
+            ((true, bytes), _) => {
+                let substance = serde_json::from_slice::<Substance>(&bytes).map_err(|e| corrupt(&e))?;
+                state = Some((from::<Value>(substance).map_err(|e| corrupt(&e))?, 0));
+            },
+            ((false, bytes), Some((value, since))) => {
+                for change in serde_json::from_slice::<Vec<Change>>(&bytes).map_err(|e| corrupt(&e))? {
+                    change.apply(value).map_err(|e| corrupt(&e))?;
+                }
+                *since += 1;
+            },
+            ((false, _), None) => return Err(HistoryError::Corrupt("Changes recorded before any checkpoint".to_string())),
+        }
+    }
+    Ok(state)
+}
+
+/// The instance state right after entry `seq`, or after the newest entry before it.
+pub(crate) fn state_at(cache: &Connection, contract: &Id, instance: &Id, seq: u64) -> Result<Option<Substance>, HistoryError> {
+    replay(cache, contract, instance, Some(seq))?.map(|(value, _)| into(&value).map_err(|e| HistoryError::Corrupt(e.to_string()))).transpose()
+}
+
+/// The entry recorded for a local submission, `None` until the runtime has processed it.
+pub(crate) fn find(cache: &Connection, token: u64) -> Result<Option<(u64, Outcome)>, HistoryError> {
+    cache.query_row(
+        "SELECT seq, result FROM History WHERE token = ?1", params![token as i64],
+        |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?))
+    ).optional()?.map(|(seq, result)| Ok((seq as u64, decode(&result)?))).transpose()
+}
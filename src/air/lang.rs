@@ -0,0 +1,244 @@
+//! Runtime for contracts written with [`contract!`](super::contract).
+//!
+//! ```ignore
+//! contract! {
+//!     ChatRoom {name: String, author: Name, admins: Vec<Name>, messages: Vec<Message>}
+//!     Message {body: String, author: Name, timestamp: u64, comments: Vec<Comment>}
+//!     Comment {body: String, author: Name, timestamp: u64}
+//!
+//!     init(String) {name: self, author, admins: [], messages: []}
+//!
+//!     routes {
+//!         "/name": [ChangeName],
+//!         "/messages": [SendMessage],
+//!         "/messages/*": [EditMessage],
+//!         "/messages/*/comments": [SendComment],
+//!     }
+//!
+//!     access {
+//!         "/name": owner(/author),
+//!         "/messages": anyone,
+//!         "/messages/*" [EditMessage]: owner(./author) | member(/admins),
+//!     }
+//!
+//!     reactants {
+//!         ChangeName(String) {if /author == author {write self to /name}}
+//!         SendMessage(String) {push Message{body: self, author, timestamp, comments: []} to ./}
+//!         EditMessage(String) {write self to ./body}
+//!         SendComment(String) {push Comment{body: self, author, timestamp} to ./}
+//!     }
+//! }
+//! ```
+//! The first struct is the contract, the rest describe its nested values. Every path in a route or reactant is
+//! checked against these structs at compile time, an unknown field or a `push` to something that is not a list
+//! is a compile error. `init` compiles to a struct literal, a value that does not fit its field fails to compile
+//! rather than at runtime. Each reactant becomes a struct holding the concrete path it targets and its argument,
+//! `SendComment::at("/messages/3/comments", body)`, or `ChangeName::new(name)` when it has a single fixed route.
+//!
+//! Statements are `write e to p`, `push e to p`, `insert e into p`, `remove e from p`, `delete p`, `read p`,
+//! `read p = e {..}`, `if c {..} else {..}`, `ensure c` and `reject "reason"`. A reactant either applies in full
+//! or leaves the state untouched and returns [`Rejected`].
+//!
+//! The optional `access` section compiles to the contract's [`Policy`](super::policy::Policy), rules are
+//! `anyone`, `nobody`, `owner(path)`, `member(path)` and `role(path, Writer)` combined with `|` and `&`, optionally
+//! scoped to a list of reactants. Air checks it against every path a reactant changed, paths no rule covers are
+//! denied, so a contract that lets anyone post declares it with `"/messages": anyone`.
+//!
+//! The built-in [`Revert`](super::Revert), [`Splice`](super::Splice) and [`Membership`](super::Membership)
+//! reactants are only registered for contracts that list them, `builtins [Revert, Splice]`.
+
+use std::cmp::Ordering;
+
+use serde::{Serialize, Deserialize};
+
+pub use serde;
+pub use serde_json::{Value, Map};
+
+pub type Path = Vec<String>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rejected {
+    /// The reactant was sent to a path none of its routes match.
+    Route(String),
+    /// A path read or written by the reactant does not exist in the instance.
+    Missing(String),
+    /// A value did not have the shape the statement or contract expected.
+    Type(String),
+    /// An `ensure` did not hold.
+    Condition(String),
+    /// The reactant hit an explicit `reject`.
+    Reason(String),
+}
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Rejected::Route(p) => write!(f, "No route matches {p}"),
+            Rejected::Missing(p) => write!(f, "Nothing at {p}"),
+            Rejected::Type(e) => write!(f, "Type mismatch: {e}"),
+            Rejected::Condition(c) => write!(f, "Condition failed: {c}"),
+            Rejected::Reason(r) => write!(f, "Rejected: {r}"),
+        }
+    }
+}
+impl std::error::Error for Rejected {}
+
+pub(crate) fn display(path: &[String]) -> String {format!("/{}", path.join("/"))}
+
+pub(crate) fn split(path: &str) -> Path {path.split('/').filter(|s| !s.is_empty()).map(str::to_string).collect()}
+
+/// True when `at` matches the route `pattern`, `*` matches any single segment.
+pub fn matches(pattern: &str, at: &[String]) -> bool {
+    let pattern = split(pattern);
+    pattern.len() == at.len() && pattern.iter().zip(at).all(|(p, s)| p == "*" || p == s)
+}
+
+/// Checks `at` against the route patterns and returns its segments.
+pub fn route(at: &str, patterns: &[&str]) -> Result<Path, Rejected> {
+    let at = split(at);
+    if patterns.iter().any(|p| matches(p, &at)) {Ok(at)} else {Err(Rejected::Route(display(&at)))}
+}
+
+/// Joins `segments` onto the root when `ups` is `None`, otherwise onto `at` after climbing `ups` levels.
+pub fn resolve(at: &[String], ups: Option<usize>, segments: Vec<String>) -> Result<Path, Rejected> {
+    let base = match ups {
+        None => &[][..],
+        Some(ups) => &at[..at.len().checked_sub(ups).ok_or_else(|| Rejected::Missing(display(at)))?],
+    };
+    Ok(base.iter().cloned().chain(segments).collect())
+}
+
+/// Turns an argument used as a path segment, `./self.name`, into a key or index.
+pub fn segment(value: &Value) -> Result<String, Rejected> {
+    match value {
+        Value::String(s) if !s.is_empty() && !s.contains('/') => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        v => Err(Rejected::Type(format!("{v} cannot be used as a path segment")))
+    }
+}
+
+pub(crate) fn step<'a>(value: &'a Value, segment: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None
+    }
+}
+
+fn step_mut<'a>(value: &'a mut Value, segment: &str) -> Option<&'a mut Value> {
+    match value {
+        Value::Object(map) => map.get_mut(segment),
+        Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?),
+        _ => None
+    }
+}
+
+pub fn read<'a>(state: &'a Value, path: &[String]) -> Result<&'a Value, Rejected> {
+    path.iter().try_fold(state, |v, s| step(v, s)).ok_or_else(|| Rejected::Missing(display(path)))
+}
+
+pub fn exists(state: &Value, path: &[String]) -> bool {read(state, path).is_ok()}
+
+fn read_mut<'a>(state: &'a mut Value, path: &[String]) -> Result<&'a mut Value, Rejected> {
+    path.iter().try_fold(state, |v, s| step_mut(v, s)).ok_or_else(|| Rejected::Missing(display(path)))
+}
+
+fn list<'a>(state: &'a mut Value, path: &[String]) -> Result<&'a mut Vec<Value>, Rejected> {
+    match read_mut(state, path)? {
+        Value::Array(items) => Ok(items),
+        _ => Err(Rejected::Type(format!("{} is not a list", display(path))))
+    }
+}
+
+/// Replaces the value at `path`, creating the last segment when its parent is a map.
+pub fn write(state: &mut Value, path: &[String], value: Value) -> Result<(), Rejected> {
+    let Some((last, parent)) = path.split_last() else {*state = value; return Ok(());};
+    match read_mut(state, parent)? {
+        Value::Object(map) => {map.insert(last.clone(), value);},
+        Value::Array(items) => match last.parse::<usize>() {
+            Ok(i) if i < items.len() => items[i] = value,
+            Ok(i) if i == items.len() => items.push(value),
+            _ => return Err(Rejected::Missing(display(path)))
+        },
+        _ => return Err(Rejected::Type(format!("{} has no children", display(parent))))
+    }
+    Ok(())
+}
+
+pub fn push(state: &mut Value, path: &[String], value: Value) -> Result<(), Rejected> {
+    list(state, path)?.push(value);
+    Ok(())
+}
+
+/// Appends `value` unless the list already holds it.
+pub fn insert(state: &mut Value, path: &[String], value: Value) -> Result<(), Rejected> {
+    let items = list(state, path)?;
+    if !items.contains(&value) {items.push(value);}
+    Ok(())
+}
+
+/// Removes every occurrence of `value` from the list.
+pub fn remove(state: &mut Value, path: &[String], value: Value) -> Result<(), Rejected> {
+    list(state, path)?.retain(|v| *v != value);
+    Ok(())
+}
+
+pub fn delete(state: &mut Value, path: &[String]) -> Result<(), Rejected> {
+    let Some((last, parent)) = path.split_last() else {return Err(Rejected::Type("The root cannot be deleted".to_string()));};
+    let removed = match read_mut(state, parent)? {
+        Value::Object(map) => map.remove(last).is_some(),
+        Value::Array(items) => match last.parse::<usize>() {
+            Ok(i) if i < items.len() => {items.remove(i); true},
+            _ => false
+        },
+        _ => false
+    };
+    if removed {Ok(())} else {Err(Rejected::Missing(display(path)))}
+}
+
+/// Reads a field or tuple index of a reactant argument.
+pub fn field(value: &Value, name: &str) -> Result<Value, Rejected> {
+    step(value, name).cloned().ok_or_else(|| Rejected::Type(format!("Argument has no field {name}")))
+}
+
+/// `item in container`, list membership or a key of a map.
+pub fn contains(container: &Value, item: &Value) -> bool {
+    match (container, item) {
+        (Value::Array(items), item) => items.contains(item),
+        (Value::Object(map), Value::String(key)) => map.contains_key(key),
+        (Value::String(s), Value::String(sub)) => s.contains(sub.as_str()),
+        _ => false
+    }
+}
+
+/// Orders numbers, strings, booleans and nulls, integers are compared exactly across the whole `i64` and
+/// `u64` range.
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (integer(a), integer(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?)
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        _ => None
+    }
+}
+
+fn integer(n: &serde_json::Number) -> Option<i128> {n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from))}
+
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => false,
+        Value::Array(items) => !items.is_empty(),
+        _ => true
+    }
+}
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Rejected> {
+    serde_json::to_value(value).map_err(|e| Rejected::Type(e.to_string()))
+}
+
+pub fn from_value<T: for<'a> Deserialize<'a>>(value: Value) -> Result<T, Rejected> {
+    serde_json::from_value(value).map_err(|e| Rejected::Type(e.to_string()))
+}
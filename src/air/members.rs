@@ -0,0 +1,184 @@
+//! Instance membership with roles and invitations.
+//!
+//! A contract keeps its members in a [`Members`] value and points a `role` rule at it:
+//! ```ignore
+//! contract! {
+//!     Group {name: String, members: Members, messages: Vec<Message>}
+//!     init(String) {name: self, members: [author]}
+//!     builtins [Membership]
+//!     access {"/messages": role(/members, Writer), "/name": role(/members, Admin), "/members" [Membership]: anyone}
+//! }
+//! ctx.invite::<Group>(&group, "/members", bob, Role::Writer)?;
+//! ```
+//! `Members` start out with their owners, `[author]` in a `contract!` init or [`Members::owner`] in a hand
+//! written one, an instance nobody owns can never gain members. Members are changed with the [`Membership`]
+//! reactant, which a `contract!` enables with `builtins [Membership]`, and which enforces the roles itself:
+//! only admins invite, revoke and change roles, and only for members below their own role.
+//!
+//! Revoking removes a member from `Members` and so from every rule that checks it, from then on every reactant
+//! they send to a path those rules cover is refused. Air has no way to take back a share, the instance keeps
+//! being delivered to everyone it was shared with, so contracts whose state a revoked member must not read keep
+//! it out of the instance.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use serde::{Serialize, Deserialize};
+
+use super::{Reactant, Name, Id};
+use super::policy::Governed;
+use super::lang::{self, Rejected};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Role {Reader, Writer, Admin, Owner}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    Invited{by: Name, at: u64},
+    Active{since: u64},
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    pub name: Name,
+    pub role: Role,
+    pub status: Status,
+}
+impl Member {
+    pub fn is_active(&self) -> bool {matches!(self.status, Status::Active{..})}
+}
+
+/// Who belongs to an instance, keyed by the member's encoded name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Members(BTreeMap<String, Member>);
+impl Members {
+    fn key(name: &Name) -> String {serde_json::to_string(name).unwrap()}
+
+    /// Members with `name` as their only, active, owner.
+    pub fn owner(name: Name, since: u64) -> Self {
+        Members(BTreeMap::from([(Self::key(&name), Member{name, role: Role::Owner, status: Status::Active{since}})]))
+    }
+
+    pub fn get(&self, name: &Name) -> Option<&Member> {self.0.get(&Self::key(name))}
+    pub fn all(&self) -> impl Iterator<Item = &Member> {self.0.values()}
+    pub fn active(&self) -> impl Iterator<Item = &Member> {self.all().filter(|m| m.is_active())}
+    pub fn invited(&self) -> impl Iterator<Item = &Member> {self.all().filter(|m| !m.is_active())}
+
+    /// The role of an active member.
+    pub fn role(&self, name: &Name) -> Option<Role> {self.get(name).filter(|m| m.is_active()).map(|m| m.role)}
+
+    fn owners(&self) -> usize {self.active().filter(|m| m.role == Role::Owner).count()}
+
+    /// How `self` turned into `after`.
+    pub fn changes(&self, after: &Members) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (key, member) in &after.0 {
+            match (self.0.get(key), &member.status) {
+                (None, Status::Invited{by, ..}) => changes.push(Change::Invited{name: member.name.clone(), role: member.role, by: by.clone()}),
+                (None, Status::Active{..}) | (Some(Member{status: Status::Invited{..}, ..}), Status::Active{..}) =>
+                    changes.push(Change::Joined{name: member.name.clone(), role: member.role}),
+                (Some(before), _) if before.role != member.role =>
+                    changes.push(Change::RoleChanged{name: member.name.clone(), from: before.role, to: member.role}),
+                _ => {}
+            }
+        }
+        changes.extend(self.0.iter().filter(|(k, _)| !after.0.contains_key(*k)).map(|(_, m)| Change::Removed{name: m.name.clone(), was: m.status.clone()}));
+        changes
+    }
+
+    /// Applies `action` for `signer`, enforcing the roles.
+    fn apply(&mut self, action: Action, signer: &Name, timestamp: u64) -> Result<(), Rejected> {
+        let deny = |reason: &str| Err(Rejected::Reason(reason.to_string()));
+        let own = self.role(signer);
+        match action {
+            Action::Invite{name, role} => {
+                if !own.is_some_and(|own| own >= Role::Admin && (role < own || own == Role::Owner)) {return deny("Only admins can invite, below their own role");}
+                if self.get(&name).is_some() {return deny("Already a member or invited");}
+                self.0.insert(Self::key(&name), Member{name, role, status: Status::Invited{by: signer.clone(), at: timestamp}});
+            },
+            Action::Accept => match self.0.get_mut(&Self::key(signer)) {
+                Some(member) if !member.is_active() => member.status = Status::Active{since: timestamp},
+                Some(_) => return deny("Already a member"),
+                None => return deny("Not invited"),
+            },
+            Action::Decline => match self.get(signer) {
+                Some(member) if !member.is_active() => {self.0.remove(&Self::key(signer));},
+                _ => return deny("Not invited"),
+            },
+            Action::Revoke(name) => {
+                let Some(target) = self.get(&name) else {return deny("Not a member")};
+                if !own.is_some_and(|own| own >= Role::Admin && (target.role < own || own == Role::Owner)) {return deny("Only admins can revoke, below their own role");}
+                if target.role == Role::Owner && target.is_active() && self.owners() == 1 {return deny("The last owner cannot be removed");}
+                self.0.remove(&Self::key(&name));
+            },
+            Action::Leave => {
+                if own.is_none() {return deny("Not a member");}
+                if own == Some(Role::Owner) && self.owners() == 1 {return deny("The last owner cannot leave");}
+                self.0.remove(&Self::key(signer));
+            },
+            Action::SetRole{name, role} => {
+                let Some(target) = self.get(&name) else {return deny("Not a member")};
+                if !own.is_some_and(|own| own == Role::Owner || (own >= Role::Admin && target.role < own && role < own)) {
+                    return deny("Only admins can change roles, below their own role");
+                }
+                if target.role == Role::Owner && role != Role::Owner && target.is_active() && self.owners() == 1 {return deny("The last owner cannot be demoted");}
+                self.0.get_mut(&Self::key(&name)).unwrap().role = role;
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Every name an active owner since 0, the creation of the instance, for `members: [author]` in an init.
+impl FromIterator<Name> for Members {
+    fn from_iter<I: IntoIterator<Item = Name>>(names: I) -> Self {
+        Members(names.into_iter().map(|name| (Self::key(&name), Member{name, role: Role::Owner, status: Status::Active{since: 0}})).collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+    Invited{name: Name, role: Role, by: Name},
+    Joined{name: Name, role: Role},
+    RoleChanged{name: Name, from: Role, to: Role},
+    /// Revoked, left or declined, `was` tells whether they had joined.
+    Removed{name: Name, was: Status},
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Invite{name: Name, role: Role},
+    Accept,
+    Decline,
+    Revoke(Name),
+    Leave,
+    SetRole{name: Name, role: Role},
+}
+
+/// Changes the [`Members`] at `at`, held to the contract's policy on top of the roles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Membership<C> {
+    pub at: String,
+    pub action: Action,
+    #[serde(skip)]
+    contract: PhantomData<fn() -> C>,
+}
+impl<C> Membership<C> {
+    pub fn new(at: impl Into<String>, action: Action) -> Self {Membership{at: at.into(), action, contract: PhantomData}}
+}
+impl<C: Governed + Serialize + for<'a> Deserialize<'a>> Reactant<C> for Membership<C> {
+    type Result = Result<(), Rejected>;
+
+    fn id() -> Id {Id::hash("Membership")}
+
+    fn apply(self, state: &mut C, signer: Name, timestamp: u64) -> Self::Result {
+        let at = lang::split(&self.at);
+        let mut root = lang::to_value(&*state)?;
+        let mut members = lang::from_value::<Members>(lang::read(&root, &at)?.clone())?;
+        members.apply(self.action, &signer, timestamp)?;
+        lang::write(&mut root, &at, lang::to_value(&members)?)?;
+        *state = lang::from_value(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+//! Schema versions for contract state.
+//!
+//! ```ignore
+//! impl Versioned for Room {
+//!     const VERSION: u32 = 1;
+//!     fn migrations() -> Migrations {
+//!         Migrations::default().add(0, |mut room| {
+//!             room["topic"] = Value::String(String::new());
+//!             Ok(room)
+//!         })
+//!     }
+//! }
+//!
+//! Contracts::default().versioned::<Room>()
+//! ```
+//! A migration registered with `add(n, ..)` turns state written at version `n` into state for version `n + 1`.
+//! The state of a versioned contract records the version it was written at under [`KEY`], state without it was
+//! written before the contract was versioned and is at version 0. The version travels with the state, so an
+//! instance received from a peer running another version of the application is read at the version it was
+//! written at.
+//!
+//! The manager decodes state through the contract air registers, which migrates it first, so reactants apply to
+//! instances loaded from the cache or received at an older version alike and the state they leave behind is
+//! written at the current version. Every read, [`Context::load`](super::Context::load) included, sees the current
+//! version without the marker.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{Contract, Substance, Id, from, into};
+
+/// Where the state of a versioned contract records its version.
+pub const KEY: &str = "$version";
+
+/// The current version and migrations of every versioned contract, registered along with the contract.
+static SCHEMAS: RwLock<BTreeMap<Id, (u32, Migrations)>> = RwLock::new(BTreeMap::new());
+
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Substance(super::Error),
+    /// The state was written by a newer version than this application knows.
+    Newer{contract: Id, version: u32, current: u32},
+    /// A migration failed or the migrated state does not deserialize.
+    Failed{contract: Id, from: u32, to: u32, reason: String},
+}
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MigrationError::Substance(e) => write!(f, "Could not read substance: {e:?}"),
+            MigrationError::Newer{contract, version, current} =>
+                write!(f, "State of contract {contract:?} has version {version}, newer than {current}"),
+            MigrationError::Failed{contract, from, to, reason} =>
+                write!(f, "Could not migrate contract {contract:?} from version {from} to {to}: {reason}"),
+        }
+    }
+}
+impl std::error::Error for MigrationError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Migrations(BTreeMap<u32, Migration>);
+impl Migrations {
+    pub fn add(mut self, from: u32, migration: Migration) -> Self {
+        self.0.insert(from, migration);
+        self
+    }
+
+    pub fn upgrade(&self, value: Value, from: u32, to: u32) -> Result<Value, String> {
+        (from..to).try_fold(value, |value, version| match self.0.get(&version) {
+            Some(migration) => migration(value).map_err(|e| format!("migration {version} -> {} failed: {e}", version+1)),
+            None => Err(format!("no migration registered from version {version}"))
+        })
+    }
+
+    pub(crate) fn migrate(&self, contract: Id, value: Value, from: u32, to: u32) -> Result<Value, MigrationError> {
+        if from > to {return Err(MigrationError::Newer{contract, version: from, current: to});}
+        self.upgrade(value, from, to).map_err(|reason| MigrationError::Failed{contract, from, to, reason})
+    }
+}
+
+pub trait Versioned: Contract + for<'a> Deserialize<'a> {
+    const VERSION: u32;
+
+    fn migrations() -> Migrations {Migrations::default()}
+
+    /// Reads state written at version `from`.
+    fn upgrade(substance: Substance, from: u32) -> Result<Self, MigrationError> where Self: Sized {
+        let value = super::from::<Value>(substance).map_err(MigrationError::Substance)?;
+        let value = Self::migrations().migrate(Self::id(), value, from, Self::VERSION)?;
+        Self::deserialize(value).map_err(|e| MigrationError::Failed{contract: Self::id(), from, to: Self::VERSION, reason: e.to_string()})
+    }
+}
+
+pub(crate) fn register(contract: Id, version: u32, migrations: Migrations) {
+    SCHEMAS.write().unwrap().insert(contract, (version, migrations));
+}
+
+/// Marks `value` as written at the current version of `contract`, state of unversioned contracts is kept as it is.
+pub(crate) fn stamp(contract: &Id, mut value: Value) -> Value {
+    if let Some((version, _)) = SCHEMAS.read().unwrap().get(contract) && let Value::Object(map) = &mut value {
+        map.insert(KEY.to_string(), Value::from(*version));
+    }
+    value
+}
+
+/// Migrates `value` from the version it was written at to the current version of `contract` and drops the marker.
+pub(crate) fn upgrade(contract: &Id, mut value: Value) -> Result<Value, MigrationError> {
+    let schemas = SCHEMAS.read().unwrap();
+    let Some((current, migrations)) = schemas.get(contract) else {return Ok(value);};
+    let version = match &mut value {
+        Value::Object(map) => match map.remove(KEY) {
+            Some(version) => version.as_u64().and_then(|v| u32::try_from(v).ok()).ok_or_else(|| MigrationError::Failed{
+                contract: *contract, from: 0, to: *current, reason: format!("Invalid version {version}")
+            })?,
+            None => 0,
+        },
+        _ => 0,
+    };
+    migrations.migrate(*contract, value, version, *current)
+}
+
+/// Every instance of a versioned contract in `state` at the current version, instances that cannot be migrated
+/// are kept as they are.
+pub(crate) fn current(mut state: BTreeMap<Id, BTreeMap<Id, Substance>>) -> BTreeMap<Id, BTreeMap<Id, Substance>> {
+    let versioned = SCHEMAS.read().unwrap().keys().copied().collect::<Vec<_>>();
+    for contract in versioned {
+        for (instance, substance) in state.get_mut(&contract).into_iter().flatten() {
+            let upgraded = from::<Value>(substance.clone()).map_err(MigrationError::Substance)
+                .and_then(|value| upgrade(&contract, value))
+                .and_then(|value| into(&value).map_err(MigrationError::Substance));
+            match upgraded {
+                Ok(upgraded) => *substance = upgraded,
+                Err(e) => log::error!("Could not migrate instance {instance:?}: {e}"),
+            }
+        }
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+    use super::super::{Contracts, Reactant, Reactants, Name, Manager, Secret, Governed, Guard, Policy, Rule};
+    use super::super::policy::{Governing, Guarded};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Room {title: String, topic: String}
+    impl Contract for Room {
+        type Init = String;
+        fn id() -> Id {Id::hash("Room")}
+        fn init(title: String, _signer: Name, _timestamp: u64) -> Self {Room{title, topic: String::new()}}
+        fn reactants() -> Reactants<Self> {Reactants::default().add::<SetTopic>()}
+    }
+    impl Governed for Room {
+        fn policy() -> Policy<Self> {Policy::default().otherwise(Rule::Anyone)}
+        fn guard(guard: Guard<Self>) -> Guard<Self> {guard.add::<SetTopic>()}
+    }
+    impl Versioned for Room {
+        const VERSION: u32 = 1;
+        fn migrations() -> Migrations {
+            Migrations::default().add(0, |mut room| {
+                room["topic"] = Value::String(String::new());
+                Ok(room)
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct SetTopic(String);
+    impl Reactant<Room> for SetTopic {
+        type Result = ();
+        fn id() -> Id {Id::hash("SetTopic")}
+        fn apply(self, state: &mut Room, _signer: Name, _timestamp: u64) {state.topic = self.0;}
+    }
+
+    #[test]
+    fn reactants_apply_to_migrated_instances() {
+        let _ = Contracts::default().versioned::<Room>();
+        let signer = Manager::new(Secret::new()).request_builder().name();
+
+        // Written before the contract was versioned, it has no topic yet.
+        let mut room = serde_json::from_value::<Governing<Room>>(serde_json::json!({"title": "Lobby"})).unwrap();
+        Guarded(SetTopic("Welcome".to_string())).apply(&mut room, signer, 0).unwrap();
+        let stored = serde_json::to_value(&room).unwrap();
+        assert_eq!(stored, serde_json::json!({"title": "Lobby", "topic": "Welcome", KEY: 1}));
+
+        let read = current(BTreeMap::from([(Room::id(), BTreeMap::from([(Id::hash("lobby"), into(&stored).unwrap())]))]));
+        assert_eq!(from::<Value>(read[&Room::id()][&Id::hash("lobby")].clone()).unwrap(), serde_json::json!({"title": "Lobby", "topic": "Welcome"}));
+        assert!(serde_json::from_value::<Governing<Room>>(serde_json::json!({"title": "Lobby", "topic": "", KEY: 2})).is_err());
+    }
+}
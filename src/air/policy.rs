@@ -0,0 +1,263 @@
+//! Path level access rules for contracts.
+//!
+//! ```ignore
+//! impl Governed for FileSystem {
+//!     fn policy() -> Policy<Self> {
+//!         Policy::default()
+//!             .reactant::<AddAdmin>("/admins", Rule::owner("/author"))
+//!             .reactant::<RemoveAdmin>("/admins", Rule::owner("/author"))
+//!             .route("/system/*", Rule::owner("/author").or(Rule::member("/admins")))
+//!     }
+//!
+//!     fn guard(guard: Guard<Self>) -> Guard<Self> {
+//!         guard.add::<AddAdmin>().add::<RemoveAdmin>().add::<WriteFile>()
+//!     }
+//! }
+//! ```
+//! Rule paths are absolute, `/author`, or relative to the path the rule applies at, `./author` or `../owner`.
+//! Every rule whose route matches the changed path, or a prefix of it, and whose reactant filter includes the
+//! reactant, must allow the signer. Paths no rule covers fall back to [`Policy::otherwise`], which denies
+//! everyone unless the contract says otherwise.
+//!
+//! Air registers every contract with the reactants listed in [`Governed::guard`] in place of
+//! [`Contract::reactants`]. Each of them is applied to a copy of the state, and every path it changed is checked
+//! against the policy as it stood before, so a reactant is held to the policy whether it was written with
+//! `contract!` or by hand and whichever peer sent it. A denied reactant leaves the state untouched.
+//! [`Context::send_checked`](super::Context::send_checked) evaluates the policy against the local state before
+//! submitting.
+
+use std::marker::PhantomData;
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use super::{Contract, Reactant, Reactants, Name, Id};
+use super::lang::{self, display};
+use super::undo::diff;
+use super::members::{Members, Role};
+use super::migration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rule {
+    Anyone,
+    Nobody,
+    /// The signer is the `Name` stored at the path.
+    Owner(String),
+    /// The signer is an item of the list, or a key of the map, stored at the path.
+    MemberOf(String),
+    /// The signer is an active member with at least the role in the [`Members`] stored at the path.
+    Role(String, Role),
+    Any(Vec<Rule>),
+    All(Vec<Rule>),
+}
+impl Rule {
+    pub fn owner(path: &str) -> Self {Rule::Owner(path.to_string())}
+    pub fn member(path: &str) -> Self {Rule::MemberOf(path.to_string())}
+    pub fn role(path: &str, role: Role) -> Self {Rule::Role(path.to_string(), role)}
+
+    pub fn or(self, other: Rule) -> Self {
+        match self {
+            Rule::Any(mut rules) => {rules.push(other); Rule::Any(rules)},
+            rule => Rule::Any(vec![rule, other])
+        }
+    }
+
+    pub fn and(self, other: Rule) -> Self {
+        match self {
+            Rule::All(mut rules) => {rules.push(other); Rule::All(rules)},
+            rule => Rule::All(vec![rule, other])
+        }
+    }
+
+    fn allows(&self, state: &Value, at: &[String], signer: &Value) -> bool {
+        match self {
+            Rule::Anyone => true,
+            Rule::Nobody => false,
+            Rule::Owner(path) => locate(state, at, path) == Some(signer),
+            Rule::MemberOf(path) => match locate(state, at, path) {
+                Some(Value::Array(items)) => items.contains(signer),
+                Some(Value::Object(map)) => signer.as_str().is_some_and(|s| map.contains_key(s)),
+                _ => false
+            },
+            Rule::Role(path, role) => locate(state, at, path)
+                .and_then(|m| serde_json::from_value::<Members>(m.clone()).ok())
+                .zip(serde_json::from_value::<Name>(signer.clone()).ok())
+                .and_then(|(members, signer)| members.role(&signer))
+                .is_some_and(|r| r >= *role),
+            Rule::Any(rules) => rules.iter().any(|r| r.allows(state, at, signer)),
+            Rule::All(rules) => rules.iter().all(|r| r.allows(state, at, signer)),
+        }
+    }
+}
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let join = |f: &mut std::fmt::Formatter, rules: &[Rule], op: &str| {
+            write!(f, "(")?;
+            rules.iter().enumerate().try_for_each(|(i, r)| if i == 0 {write!(f, "{r}")} else {write!(f, " {op} {r}")})?;
+            write!(f, ")")
+        };
+        match self {
+            Rule::Anyone => write!(f, "anyone"),
+            Rule::Nobody => write!(f, "nobody"),
+            Rule::Owner(p) => write!(f, "owner({p})"),
+            Rule::MemberOf(p) => write!(f, "member({p})"),
+            Rule::Role(p, r) => write!(f, "role({p}, {r:?})"),
+            Rule::Any(rules) => join(f, rules, "|"),
+            Rule::All(rules) => join(f, rules, "&"),
+        }
+    }
+}
+
+fn locate<'a>(state: &'a Value, at: &[String], path: &str) -> Option<&'a Value> {
+    let mut base = if path.starts_with('/') {Vec::new()} else {at.to_vec()};
+    let mut segments = Vec::new();
+    for segment in path.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        if segment == ".." {
+            if segments.pop().is_none() {base.pop()?;}
+        } else {segments.push(segment.to_string());}
+    }
+    base.extend(segments);
+    lang::read(state, &base).ok()
+}
+
+/// A reactant was refused because the signer did not satisfy a rule covering its target path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Denied {
+    pub signer: Name,
+    pub path: String,
+    pub reactant: Id,
+    pub rule: Rule,
+}
+impl std::fmt::Display for Denied {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?} may not act on {}, requires {}", self.signer, self.path, self.rule)
+    }
+}
+impl std::error::Error for Denied {}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    route: String,
+    reactants: Option<Vec<Id>>,
+    rule: Rule,
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy<C>(Vec<Entry>, Rule, PhantomData<fn() -> C>);
+impl<C> Default for Policy<C> {
+    fn default() -> Self {Policy(Vec::new(), Rule::Nobody, PhantomData)}
+}
+impl<C: Contract> Policy<C> {
+    /// Applies `rule` to every reactant sent to a path matching `route`.
+    pub fn route(mut self, route: &str, rule: Rule) -> Self {
+        self.0.push(Entry{route: route.to_string(), reactants: None, rule});
+        self
+    }
+
+    /// Applies `rule` only to `R` when it is sent to a path matching `route`.
+    pub fn reactant<R: Reactant<C>>(self, route: &str, rule: Rule) -> Self {self.reactants(route, &[R::id()], rule)}
+
+    pub fn reactants(mut self, route: &str, reactants: &[Id], rule: Rule) -> Self {
+        self.0.push(Entry{route: route.to_string(), reactants: Some(reactants.to_vec()), rule});
+        self
+    }
+
+    /// Applies `rule` to paths no other rule covers, [`Rule::Nobody`] by default.
+    pub fn otherwise(mut self, rule: Rule) -> Self {self.1 = rule; self}
+
+    fn covering(&self, reactant: Id) -> impl Iterator<Item = &Entry> {
+        self.0.iter().filter(move |e| e.reactants.as_ref().is_none_or(|r| r.contains(&reactant)))
+    }
+
+    fn denied(&self, signer: &Name, path: &[String], reactant: Id, rule: &Rule) -> Denied {
+        Denied{signer: signer.clone(), path: display(path), reactant, rule: rule.clone()}
+    }
+
+    /// Evaluates every rule covering `at` for `reactant` against the instance state.
+    pub fn check(&self, state: &Value, at: &[String], reactant: Id, signer: &Name) -> Result<(), Denied> {
+        let value = serde_json::to_value(signer).unwrap_or_default();
+        let mut covered = false;
+        for entry in self.covering(reactant).filter(|e| lang::matches(&e.route, at)) {
+            covered = true;
+            if !entry.rule.allows(state, at, &value) {return Err(self.denied(signer, at, reactant, &entry.rule));}
+        }
+        if !covered && !self.1.allows(state, at, &value) {return Err(self.denied(signer, at, reactant, &self.1));}
+        Ok(())
+    }
+
+    /// Like `check`, but for a change anywhere below the routes, every rule whose route matches a prefix of
+    /// `path` is evaluated at that prefix.
+    pub fn check_within(&self, state: &Value, path: &[String], reactant: Id, signer: &Name) -> Result<(), Denied> {
+        let value = serde_json::to_value(signer).unwrap_or_default();
+        let mut covered = false;
+        for entry in self.covering(reactant) {
+            let depth = lang::split(&entry.route).len();
+            if depth > path.len() || !lang::matches(&entry.route, &path[..depth]) {continue;}
+            covered = true;
+            if !entry.rule.allows(state, &path[..depth], &value) {return Err(self.denied(signer, path, reactant, &entry.rule));}
+        }
+        if !covered && !self.1.allows(state, path, &value) {return Err(self.denied(signer, path, reactant, &self.1));}
+        Ok(())
+    }
+}
+
+pub trait Governed: Contract {
+    fn policy() -> Policy<Self> where Self: Sized;
+
+    /// The reactants air applies to the contract, each held to the policy.
+    fn guard(guard: Guard<Self>) -> Guard<Self> where Self: Sized;
+}
+
+/// The reactants of a governed contract as air registers them.
+pub struct Guard<C>(Reactants<Governing<C>>);
+impl<C> Default for Guard<C> {
+    fn default() -> Self {Guard(Reactants::default())}
+}
+impl<C: Governed> Guard<C> {
+    pub fn add<R: Reactant<C>>(self) -> Self {Guard(self.0.add::<Guarded<R>>())}
+}
+
+/// The contract air registers in place of `C`, it has the same id and state. State of a versioned contract is
+/// written with its version and migrated as it is read, see [`migration`](super::migration).
+#[derive(Debug, Clone)]
+pub(crate) struct Governing<C>(pub(crate) C);
+impl<C: Governed> Serialize for Governing<C> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = serde_json::to_value(&self.0).map_err(serde::ser::Error::custom)?;
+        migration::stamp(&C::id(), value).serialize(serializer)
+    }
+}
+impl<'de, C: Governed> Deserialize<'de> for Governing<C> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = migration::upgrade(&C::id(), Value::deserialize(deserializer)?).map_err(serde::de::Error::custom)?;
+        C::deserialize(value).map(Governing).map_err(serde::de::Error::custom)
+    }
+}
+impl<C: Governed> Contract for Governing<C> {
+    type Init = C::Init;
+
+    fn id() -> Id {C::id()}
+    fn init(init: Self::Init, signer: Name, timestamp: u64) -> Self {Governing(C::init(init, signer, timestamp))}
+    fn reactants() -> Reactants<Self> {C::guard(Guard::default()).0}
+}
+
+/// Applies `R` and keeps the result only when the policy allows every path it changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Guarded<R>(pub(crate) R);
+impl<C: Governed, R: Reactant<C>> Reactant<Governing<C>> for Guarded<R> {
+    type Result = Result<R::Result, Denied>;
+
+    fn id() -> Id {R::id()}
+
+    fn apply(self, state: &mut Governing<C>, signer: Name, timestamp: u64) -> Self::Result {
+        let before = state.0.clone();
+        let result = self.0.apply(&mut state.0, signer.clone(), timestamp);
+        let (old, new) = (serde_json::to_value(&before).unwrap_or_default(), serde_json::to_value(&state.0).unwrap_or_default());
+        let policy = C::policy();
+        match diff(Some(&old), Some(&new)).iter().try_for_each(|c| policy.check_within(&old, c.path(), R::id(), &signer)) {
+            Ok(()) => Ok(result),
+            Err(denied) => {state.0 = before; Err(denied)}
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! Path queries over contract state.
+//!
+//! A query is a path like the route syntax in `notes.rs`:
+//! ```text
+//! /messages/*/author
+//! /messages/*[timestamp >= 4958383][timestamp < 5995929]/body
+//! /**/author
+//! ```
+//! `*` matches every child of a map or list, `**` matches the current node and all of its descendants,
+//! and `[field op value]` keeps only the nodes where `field` (a `.` separated path relative to the node)
+//! compares true against a JSON literal. Ordering and pagination are applied to the final matches.
+
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::lang::{compare, step};
+
+#[derive(Debug)]
+pub enum QueryError {
+    Parse(String),
+    Substance(super::Error),
+    Store(super::store::StoreError),
+    Type(PathBuf, serde_json::Error),
+}
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            QueryError::Parse(e) => write!(f, "Invalid query: {e}"),
+            QueryError::Substance(e) => write!(f, "Could not read substance: {e:?}"),
+            QueryError::Store(e) => write!(f, "{e}"),
+            QueryError::Type(p, e) => write!(f, "Value at {} has the wrong type: {e}", p.display()),
+        }
+    }
+}
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {Eq, Ne, Lt, Le, Gt, Ge}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {#[default] Ascending, Descending}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub field: Vec<String>,
+    pub op: Op,
+    pub value: Value,
+}
+impl Predicate {
+    fn matches(&self, node: &Value) -> bool {
+        let Some(field) = lookup(node, &self.field) else {return false;};
+        let ordering = compare(field, &self.value);
+        match self.op {
+            Op::Eq => ordering.map_or(field == &self.value, Ordering::is_eq),
+            Op::Ne => ordering.map_or(field != &self.value, Ordering::is_ne),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Any,
+    Descend,
+    Filter(Predicate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<T> {
+    pub path: PathBuf,
+    pub value: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    segments: Vec<Segment>,
+    order: Option<(Vec<String>, Order)>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl Query {
+    pub fn new(segments: Vec<Segment>) -> Self {Query{segments, ..Default::default()}}
+
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let mut segments = Vec::new();
+        for part in split(query, '/')? {
+            let (key, filters) = match unquoted(&part).find(|(_, c)| *c == '[') {
+                Some((i, _)) => part.split_at(i),
+                None => (part.as_str(), "")
+            };
+            match key.trim() {
+                "" if filters.is_empty() => continue,
+                "" | "." => {},
+                "*" => segments.push(Segment::Any),
+                "**" => segments.push(Segment::Descend),
+                key => segments.push(Segment::Key(key.to_string())),
+            }
+            let mut rest = filters;
+            while !rest.is_empty() {
+                let (end, _) = unquoted(rest).find(|(_, c)| *c == ']')
+                    .ok_or_else(|| QueryError::Parse(format!("Unclosed filter in {part}")))?;
+                segments.push(Segment::Filter(predicate(&rest[1..end])?));
+                rest = rest[end+1..].trim_start();
+                if !rest.is_empty() && !rest.starts_with('[') {
+                    return Err(QueryError::Parse(format!("Unexpected {rest} after filter")));
+                }
+            }
+        }
+        Ok(Query::new(segments))
+    }
+
+    /// Sorts matches by `field`, a `.` separated path relative to each match.
+    pub fn order_by(mut self, field: &str, order: Order) -> Self {
+        self.order = Some((fields(field), order));
+        self
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {self.offset = offset; self}
+    pub fn limit(mut self, limit: usize) -> Self {self.limit = Some(limit); self}
+
+    pub fn evaluate<'a>(&self, root: &'a Value) -> Vec<(PathBuf, &'a Value)> {
+        let mut nodes = vec![(PathBuf::from("/"), root)];
+        for segment in &self.segments {
+            nodes = match segment {
+                Segment::Key(key) => nodes.into_iter().filter_map(|(path, node)|
+                    step(node, key).map(|c| (path.join(key), c))
+                ).collect(),
+                Segment::Any => nodes.into_iter().flat_map(|(path, node)| children(path, node)).collect(),
+                Segment::Descend => nodes.into_iter().flat_map(|(path, node)| descendants(path, node)).collect(),
+                Segment::Filter(predicate) => nodes.into_iter().filter(|(_, node)| predicate.matches(node)).collect(),
+            };
+        }
+        if let Some((field, order)) = &self.order {
+            nodes.sort_by(|(_, a), (_, b)| {
+                let ordering = match (lookup(a, field), lookup(b, field)) {
+                    (Some(a), Some(b)) => compare(a, b).unwrap_or_else(|| rank(a).cmp(&rank(b))),
+                    (a, b) => a.is_some().cmp(&b.is_some()).reverse(),
+                };
+                if *order == Order::Descending {ordering.reverse()} else {ordering}
+            });
+        }
+        nodes.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect()
+    }
+
+    pub fn select<T: for<'a> Deserialize<'a>>(&self, root: &Value) -> Result<Vec<Match<T>>, QueryError> {
+        self.evaluate(root).into_iter().map(|(path, value)| Ok(Match{
+            value: T::deserialize(value).map_err(|e| QueryError::Type(path.clone(), e))?,
+            path
+        })).collect()
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = QueryError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {Query::parse(s)}
+}
+
+fn children(path: PathBuf, node: &Value) -> Vec<(PathBuf, &Value)> {
+    match node {
+        Value::Object(map) => map.iter().map(|(k, v)| (path.join(k), v)).collect(),
+        Value::Array(list) => list.iter().enumerate().map(|(i, v)| (path.join(i.to_string()), v)).collect(),
+        _ => vec![]
+    }
+}
+
+fn descendants(path: PathBuf, node: &Value) -> Vec<(PathBuf, &Value)> {
+    let mut nodes = vec![(path.clone(), node)];
+    for (path, child) in children(path, node) {
+        nodes.extend(descendants(path, child));
+    }
+    nodes
+}
+
+fn lookup<'a>(node: &'a Value, field: &[String]) -> Option<&'a Value> {
+    field.iter().try_fold(node, |node, key| step(node, key))
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+fn fields(field: &str) -> Vec<String> {
+    field.split('.').map(str::trim).filter(|f| !f.is_empty()).map(str::to_string).collect()
+}
+
+const OPERATORS: [(&str, Op); 7] = [("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt), ("=", Op::Eq)];
+
+/// Splits a filter at its first operator outside of a quoted string.
+fn predicate(filter: &str) -> Result<Predicate, QueryError> {
+    let (at, token, op) = unquoted(filter).find_map(|(i, _)|
+        OPERATORS.iter().find(|(token, _)| filter[i..].starts_with(token)).map(|(token, op)| (i, *token, *op))
+    ).ok_or_else(|| QueryError::Parse(format!("Filter [{filter}] has no comparison")))?;
+    let value = filter[at+token.len()..].trim();
+    Ok(Predicate{
+        field: fields(&filter[..at]),
+        op,
+        value: serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+    })
+}
+
+/// Tracks whether a character is inside a quoted string, `\"` does not end one.
+#[derive(Default)]
+struct Quotes {
+    quoted: bool,
+    escaped: bool,
+}
+impl Quotes {
+    /// Feeds the next character, true when it is outside of a quoted string and not a quote itself.
+    fn outside(&mut self, c: char) -> bool {
+        match (self.quoted, self.escaped, c) {
+            (true, true, _) => self.escaped = false,
+            (true, false, '\\') => self.escaped = true,
+            (_, _, '"') => {self.quoted = !self.quoted; return false;},
+            _ => {}
+        }
+        !self.quoted
+    }
+}
+
+/// The characters of `text` outside of quoted strings, with their byte offsets.
+fn unquoted(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quotes = Quotes::default();
+    text.char_indices().filter(move |(_, c)| quotes.outside(*c))
+}
+
+/// Splits on `separator` outside of filters and quoted strings.
+fn split(query: &str, separator: char) -> Result<Vec<String>, QueryError> {
+    let (mut parts, mut start, mut depth, mut quotes) = (Vec::new(), 0, 0, Quotes::default());
+    for (i, c) in query.char_indices() {
+        if !quotes.outside(c) {continue;}
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => return Err(QueryError::Parse(format!("Unbalanced query {query}"))),
+            ']' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(query[start..i].to_string());
+                start = i + c.len_utf8();
+            },
+            _ => {}
+        }
+    }
+    if depth != 0 || quotes.quoted {return Err(QueryError::Parse(format!("Unbalanced query {query}")));}
+    parts.push(query[start..].to_string());
+    Ok(parts)
+}
@@ -0,0 +1,211 @@
+//! In-process network of air peers for testing synchronisation.
+//!
+//! Every peer is a full [`Air`] runtime with its own secret, name and cache file. Every request a peer submits is
+//! relayed over a simulated network that delivers it to the other peers on a virtual clock, subject to latency,
+//! reordering, drops and partitions, and each peer ticks the requests it receives into its own manager. The caches
+//! of a simulation live in a temporary directory of their own that is removed on drop.
+//! ```ignore
+//! let mut sim = Simulation::new(contracts, 7).faults(Faults{latency: 1..5, drop_rate: 0.1, reorder: true});
+//! let (alice, bob) = (sim.peer()?, sim.peer()?);
+//! sim.spawn(bob, Box::new(ChatBot::default()));
+//! let room = sim.context(alice).create::<Room>("The Room".to_string())?;
+//! sim.context(alice).share::<Room>(room, sim.context(bob).name())?;
+//! sim.run(100).await;
+//! assert!(sim.converged(&Room::id(), &room));
+//! ```
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use super::{Air, Context, Contracts, Name, Id};
+use crate::runtime::Service;
+
+/// Numbers the simulations of this process so their directories never collide.
+static SIMULATIONS: AtomicU64 = AtomicU64::new(0);
+
+pub type PeerId = usize;
+
+/// Carries the requests of one runtime to the others.
+pub(crate) trait Transport: Send + Sync {
+    /// Sends `message` to every runtime it reaches.
+    fn broadcast(&self, message: Vec<u8>);
+    /// The messages that arrived since the last call with the name of their sender.
+    fn receive(&self) -> Vec<(Name, Vec<u8>)>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Faults {
+    /// Delivery delay in ticks, sampled uniformly for every message.
+    pub latency: Range<u64>,
+    /// Probability in `0.0..=1.0` that a message is lost.
+    pub drop_rate: f64,
+    /// Allow messages on the same link to overtake each other.
+    pub reorder: bool,
+}
+impl Default for Faults {
+    fn default() -> Self {Faults{latency: 0..1, drop_rate: 0.0, reorder: false}}
+}
+
+struct Envelope {
+    from: PeerId,
+    to: PeerId,
+    at: u64,
+    message: Vec<u8>,
+}
+
+/// The simulated network every peer's transport sends through.
+struct Network {
+    rng: StdRng,
+    faults: Faults,
+    clock: u64,
+    names: Vec<Name>,
+    in_flight: Vec<Envelope>,
+    partitions: Vec<BTreeSet<PeerId>>,
+}
+impl Network {
+    fn reachable(&self, from: PeerId, to: PeerId) -> bool {
+        self.partitions.is_empty() || self.partitions.iter().any(|g| g.contains(&from) && g.contains(&to))
+    }
+
+    fn broadcast(&mut self, from: PeerId, message: Vec<u8>) {
+        for to in 0..self.names.len() {self.send(from, to, message.clone());}
+    }
+
+    fn send(&mut self, from: PeerId, to: PeerId, message: Vec<u8>) {
+        if to == from || !self.reachable(from, to) || self.rng.random_bool(self.faults.drop_rate.clamp(0.0, 1.0)) {return;}
+        let latency = if self.faults.latency.is_empty() {0} else {self.rng.random_range(self.faults.latency.clone())};
+        let mut at = self.clock + latency;
+        if !self.faults.reorder {
+            at = self.in_flight.iter().filter(|e| e.to == to).map(|e| e.at).max().unwrap_or(at).max(at);
+        }
+        self.in_flight.push(Envelope{from, to, at, message});
+    }
+
+    /// Takes the messages due for `to`, those sent across a partition that formed meanwhile are lost.
+    fn receive(&mut self, to: PeerId) -> Vec<(Name, Vec<u8>)> {
+        let (due, pending) = std::mem::take(&mut self.in_flight).into_iter().partition::<Vec<_>, _>(|e| e.to == to && e.at <= self.clock);
+        self.in_flight = pending;
+        due.into_iter().filter(|e| self.reachable(e.from, e.to)).map(|e| (self.names[e.from].clone(), e.message)).collect()
+    }
+}
+
+/// The in-memory transport of one peer.
+struct Link(Arc<Mutex<Network>>, PeerId);
+impl Transport for Link {
+    fn broadcast(&self, message: Vec<u8>) {self.0.lock().unwrap().broadcast(self.1, message)}
+    fn receive(&self) -> Vec<(Name, Vec<u8>)> {self.0.lock().unwrap().receive(self.1)}
+}
+
+struct Peer {
+    air: Air,
+    context: Context,
+    services: Vec<Box<dyn Service>>,
+}
+
+pub struct Simulation {
+    contracts: Contracts,
+    directory: PathBuf,
+    network: Arc<Mutex<Network>>,
+    peers: Vec<Peer>,
+}
+
+impl Simulation {
+    pub fn new(contracts: Contracts, seed: u64) -> Self {
+        let directory = std::env::temp_dir().join(format!(
+            "air_simulation_{}_{}_{seed}", std::process::id(), SIMULATIONS.fetch_add(1, Ordering::Relaxed)
+        ));
+        Simulation{
+            contracts,
+            directory,
+            network: Arc::new(Mutex::new(Network{
+                rng: StdRng::seed_from_u64(seed),
+                faults: Faults::default(),
+                clock: 0,
+                names: Vec::new(),
+                in_flight: Vec::new(),
+                partitions: Vec::new(),
+            })),
+            peers: Vec::new(),
+        }
+    }
+
+    pub fn faults(self, faults: Faults) -> Self {self.set_faults(faults); self}
+    pub fn set_faults(&self, faults: Faults) {self.network.lock().unwrap().faults = faults;}
+
+    /// Starts a new peer with a fresh identity.
+    pub fn peer(&mut self) -> Result<PeerId, std::io::Error> {
+        let id = self.peers.len();
+        std::fs::create_dir_all(&self.directory)?;
+        let link = Arc::new(Link(self.network.clone(), id));
+        let (air, context) = Air::open(&self.directory.join(format!("peer_{id}.db")), self.contracts.clone(), link)
+            .map_err(std::io::Error::other)?;
+        self.network.lock().unwrap().names.push(context.name());
+        self.peers.push(Peer{air, context, services: Vec::new()});
+        Ok(id)
+    }
+
+    pub fn peers(&self) -> Range<PeerId> {0..self.peers.len()}
+    pub fn context(&self, peer: PeerId) -> &Context {&self.peers[peer].context}
+    pub fn clock(&self) -> u64 {self.network.lock().unwrap().clock}
+
+    /// Runs `service` against the peer once per tick, ignoring the requested sleep duration.
+    pub fn spawn(&mut self, peer: PeerId, service: Box<dyn Service>) {
+        self.peers[peer].services.push(service);
+    }
+
+    /// Splits the network, peers can only reach peers in the same group. Peers left out form their own group.
+    pub fn partition(&mut self, groups: &[&[PeerId]]) {
+        self.network.lock().unwrap().partitions = groups.iter().map(|g| g.iter().copied().collect()).collect();
+    }
+
+    pub fn heal(&mut self) {self.network.lock().unwrap().partitions.clear();}
+
+    /// Advances the virtual clock by one tick, running services and local requests on every peer, then ticks every
+    /// peer once more so it picks up the requests that are due.
+    pub async fn step(&mut self) {
+        self.network.lock().unwrap().clock += 1;
+        for peer in &mut self.peers {
+            for service in &mut peer.services {
+                let _ = service.run(&mut peer.context).await;
+            }
+            while let Some(submission) = peer.air.next() {
+                peer.air.apply(Some(submission)).await;
+            }
+        }
+        for peer in &mut self.peers {peer.air.apply(None).await;}
+    }
+
+    /// Steps until nothing is in flight or `max` ticks have passed, returns the number of ticks run.
+    pub async fn run(&mut self, max: u64) -> u64 {
+        for tick in 0..max {
+            self.step().await;
+            if self.in_flight() == 0 {return tick + 1;}
+        }
+        max
+    }
+
+    pub fn in_flight(&self) -> usize {self.network.lock().unwrap().in_flight.len()}
+
+    /// True when every peer holding the instance sees the same state and at least one peer holds it.
+    pub fn converged(&self, contract: &Id, instance: &Id) -> bool {
+        let states = self.peers.iter().filter_map(|p| p.air.store.digests().get(contract)?.get(instance).copied()).collect::<Vec<_>>();
+        !states.is_empty() && states.windows(2).all(|w| w[0] == w[1])
+    }
+
+    /// Peers that do not hold the instance yet, useful alongside `converged` when sharing.
+    pub fn missing(&self, contract: &Id, instance: &Id) -> Vec<PeerId> {
+        self.peers().filter(|p| !self.peers[*p].air.store.contains(contract, instance)).collect()
+    }
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        self.peers.clear();
+        let _ = std::fs::remove_dir_all(&self.directory);
+    }
+}
@@ -0,0 +1,200 @@
+//! Portable snapshots of the local air state.
+//!
+//! A snapshot file is a SQLCipher database holding a single row, the snapshot version and the JSON encoded
+//! [`Snapshot`]: every row of the `air_cache.db` cache, this includes the manager state with the identity's secret,
+//! all contract instances and any pending requests, along with the bytes of every [`Blob`](super::Blob) an
+//! instance refers to.
+//!
+//! The file is keyed with a key derived from the secret of the local identity. Only that identity can read a
+//! snapshot back, and nobody without its secret can make one it accepts, a snapshot of another identity or a
+//! damaged one does not open. Instances of contracts the application does not register are refused, the rest of
+//! the state replaces the local cache entirely.
+//!
+//! ```ignore
+//! context.export()?.write("backup.airsnap")?;
+//! let snapshot = context.read_snapshot("backup.airsnap")?;
+//! context.import(snapshot, false)?;
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest as _};
+
+use rusqlite::{Connection, OptionalExtension};
+
+use std::sync::Arc;
+
+use super::{Manager, Name, Id, Contracts, RequestBuilder, Secret};
+use super::store::{self, Digest};
+use super::migration;
+use super::blobs::{Blobs, BlobError, Chunked};
+use crate::vault::Vault;
+
+pub const VERSION: u32 = 2;
+
+/// Keys the snapshots of one identity, derived from its secret.
+#[derive(Clone)]
+pub(crate) struct Key([u8; 32]);
+impl Key {
+    pub(crate) fn new(secret: &Secret) -> Self {
+        Key(Sha256::new().chain_update(b"AIRSNAP\0").chain_update(serde_json::to_vec(secret).unwrap()).finalize().into())
+    }
+
+    fn open(&self, path: &Path) -> Result<Connection, SnapshotError> {
+        let connection = Connection::open(path)?;
+        connection.pragma_update(None, "key", Vault::pragma(&self.0))?;
+        Ok(connection)
+    }
+}
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {write!(f, "Key(..)")}
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Database(rusqlite::Error),
+    /// Not a snapshot file.
+    Format,
+    UnsupportedVersion(u32),
+    /// The file does not open with the key of the local identity, it belongs to another identity or is damaged.
+    Foreign,
+    /// The payload decoded but does not hold a valid manager state.
+    Corrupt(String),
+    Blob(BlobError),
+    /// Import was not forced and the snapshot disagrees with the local state.
+    Conflicts(Vec<Conflict>),
+}
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "Snapshot io error: {e}"),
+            SnapshotError::Database(e) => write!(f, "Snapshot database error: {e}"),
+            SnapshotError::Format => write!(f, "Not an air snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "Unsupported snapshot version {v}, expected {VERSION}"),
+            SnapshotError::Foreign => write!(f, "Snapshot does not belong to the local identity or is damaged"),
+            SnapshotError::Corrupt(e) => write!(f, "Snapshot is corrupt: {e}"),
+            SnapshotError::Blob(e) => write!(f, "{e}"),
+            SnapshotError::Conflicts(c) => write!(f, "Snapshot conflicts with local state: {c:?}"),
+        }
+    }
+}
+impl std::error::Error for SnapshotError {}
+impl From<std::io::Error> for SnapshotError {fn from(e: std::io::Error) -> Self {SnapshotError::Io(e)}}
+impl From<rusqlite::Error> for SnapshotError {fn from(e: rusqlite::Error) -> Self {SnapshotError::Database(e)}}
+impl From<BlobError> for SnapshotError {fn from(e: BlobError) -> Self {SnapshotError::Blob(e)}}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// The snapshot was taken by a different identity than the one running locally, its manager state would not
+    /// match the profile's secret.
+    Identity{local: Name, snapshot: Name},
+    /// The snapshot holds instances of a contract this application does not register.
+    UnknownContract(Id),
+    /// Both sides hold the instance but their states differ.
+    Diverged{contract: Id, instance: Id},
+    /// The instance only exists locally and would be dropped by the import.
+    LocalOnly{contract: Id, instance: Id},
+}
+impl Conflict {
+    /// Conflicts that an import can never resolve, even when forced.
+    pub fn is_fatal(&self) -> bool {matches!(self, Conflict::Identity{..} | Conflict::UnknownContract(_))}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: Name,
+    pub created: u64,
+    entries: BTreeMap<String, Vec<u8>>,
+    /// Referenced blobs with their chunks, checked against their hash on import.
+    #[serde(default)]
+    blobs: Vec<Chunked>,
+    #[serde(skip)]
+    key: Option<Key>,
+}
+
+impl Snapshot {
+    pub(crate) fn export(builder: &RequestBuilder, cache: &Connection, blobs: &Blobs, key: &Key) -> Result<Self, SnapshotError> {
+        let mut statement = cache.prepare("SELECT key, value FROM Cache")?;
+        let entries = statement.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?)))?
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        Ok(Snapshot{
+            name: builder.name(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            entries,
+            blobs: blobs.export()?,
+            key: Some(key.clone()),
+        })
+    }
+
+    /// Writes the snapshot encrypted for the identity that exported it, replacing any file at `path`.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let key = self.key.as_ref().ok_or(SnapshotError::Foreign)?;
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let connection = key.open(path.as_ref())?;
+        connection.execute("CREATE TABLE Snapshot(version INTEGER NOT NULL, payload BLOB NOT NULL);", [])?;
+        connection.execute("INSERT INTO Snapshot(version, payload) VALUES (?1, ?2)", rusqlite::params![VERSION, serde_json::to_vec(self).unwrap()])?;
+        Ok(())
+    }
+
+    pub(crate) fn read(path: &Path, key: &Key) -> Result<Self, SnapshotError> {
+        if !path.exists() {return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());}
+        let connection = key.open(path)?;
+        connection.query_row("SELECT count(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0)).map_err(|_| SnapshotError::Foreign)?;
+        let (version, payload) = connection.query_row("SELECT version, payload FROM Snapshot", [], |r| Ok((r.get::<_, u32>(0)?, r.get::<_, Vec<u8>>(1)?)))
+            .optional().map_err(|_| SnapshotError::Format)?.ok_or(SnapshotError::Format)?;
+        if version != VERSION {return Err(SnapshotError::UnsupportedVersion(version));}
+        let mut snapshot: Snapshot = serde_json::from_slice(&payload).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+        snapshot.key = Some(key.clone());
+        Ok(snapshot)
+    }
+
+    pub(crate) fn manager(&self) -> Result<Manager, SnapshotError> {
+        let manager = self.entries.get("manager").ok_or_else(|| SnapshotError::Corrupt("Missing manager state".to_string()))?;
+        let manager: Manager = serde_json::from_slice(manager).map_err(|e| SnapshotError::Corrupt(e.to_string()))?;
+        if manager.request_builder().name() != self.name {
+            return Err(SnapshotError::Corrupt("Manager state does not belong to the snapshot identity".to_string()));
+        }
+        Ok(manager)
+    }
+
+    /// Compares the snapshot against the local identity, registered contracts and instances.
+    pub(crate) fn conflicts(&self, name: Name, contracts: &Contracts, local: &BTreeMap<Id, BTreeMap<Id, Digest>>) -> Result<Vec<Conflict>, SnapshotError> {
+        let incoming = migration::current(self.manager()?.get());
+        let mut conflicts = Vec::new();
+        if name != self.name {conflicts.push(Conflict::Identity{local: name, snapshot: self.name.clone()});}
+        conflicts.extend(incoming.keys().filter(|id| !contracts.contains(id)).copied().map(Conflict::UnknownContract));
+        let digests = incoming.iter().map(|(contract, instances)|
+            (*contract, instances.iter().map(|(instance, substance)| (*instance, store::digest(substance))).collect::<BTreeMap<_, _>>())
+        ).collect::<BTreeMap<_, _>>();
+        for (contract, instances) in local {
+            let theirs = digests.get(contract);
+            let ids = instances.keys().chain(theirs.into_iter().flat_map(|i| i.keys())).collect::<BTreeSet<_>>();
+            for instance in ids {
+                match (instances.get(instance), theirs.and_then(|t| t.get(instance))) {
+                    (Some(ours), Some(theirs)) if ours != theirs => conflicts.push(Conflict::Diverged{contract: *contract, instance: *instance}),
+                    (Some(_), None) => conflicts.push(Conflict::LocalOnly{contract: *contract, instance: *instance}),
+                    _ => {}
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
+    /// Replaces every row of the cache with those of the snapshot and adds its blobs.
+    pub(crate) fn restore(self, cache: &Connection, blobs: &Arc<Blobs>) -> Result<(), SnapshotError> {
+        blobs.import(self.blobs)?;
+        let transaction = cache.unchecked_transaction()?;
+        transaction.execute("DELETE FROM Cache", [])?;
+        for (key, value) in self.entries {
+            transaction.execute("INSERT INTO Cache(key, value) VALUES (?1, ?2)", rusqlite::params![key, value])?;
+        }
+        Ok(transaction.commit()?)
+    }
+}
@@ -0,0 +1,365 @@
+//! Instance states kept on disk and loaded a subtree at a time.
+//!
+//! After every tick each instance whose state changed is written to the `Subtrees` table of the local cache. A list or map
+//! whose encoding is larger than [`SPLIT`] bytes is not stored whole, its row only records the length or keys and
+//! every item gets a row of its own, recursively. Reads walk the rows along the requested path, so reading
+//! `/messages/4812/body` touches four small rows no matter how long the chat is.
+//!
+//! Rows that were read stay resident until the memory budget is exceeded, the least recently used are evicted
+//! first. Large collections are best read through a [`Lazy`] handle, which loads one item at a time:
+//! ```ignore
+//! let messages = ctx.lazy::<ChatRoom, Message>(&room, "/messages");
+//! let latest = messages.range(messages.len()?.saturating_sub(50)..messages.len()?)?;
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use arc_swap::ArcSwap;
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use sha2::{Sha256, Digest as _};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{Id, Substance, from};
+use super::lang::{self, display};
+
+/// Lists and maps whose encoding is larger than this many bytes are stored one item per row.
+pub const SPLIT: usize = 4096;
+
+/// Bytes of rows kept in memory before the least recently used are evicted.
+pub const DEFAULT_BUDGET: usize = 32 * 1024 * 1024;
+
+pub(crate) type Digest = [u8; 32];
+
+pub(crate) fn fingerprint(state: &Value) -> Digest {Sha256::digest(serde_json::to_vec(state).unwrap()).into()}
+
+/// The [`fingerprint`] of the state a substance decodes to.
+pub(crate) fn digest(substance: &Substance) -> Digest {
+    from::<Value>(substance.clone()).map(|v| fingerprint(&v)).unwrap_or_else(|_| Sha256::digest(serde_json::to_vec(substance).unwrap()).into())
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Database(rusqlite::Error),
+    Corrupt(String),
+}
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StoreError::Database(e) => write!(f, "Instance store database error: {e}"),
+            StoreError::Corrupt(e) => write!(f, "Instance store row is corrupt: {e}"),
+        }
+    }
+}
+impl std::error::Error for StoreError {}
+impl From<rusqlite::Error> for StoreError {fn from(e: rusqlite::Error) -> Self {StoreError::Database(e)}}
+
+/// One row of the `Subtrees` table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Node {
+    /// The whole value at the path.
+    Leaf(Value),
+    /// A list whose items are stored at `path/0` to `path/len-1`.
+    List(usize),
+    /// A map whose values are stored at `path/key`.
+    Map(Vec<String>),
+}
+impl Node {
+    fn has(&self, segment: &str) -> bool {
+        match self {
+            Node::Leaf(_) => false,
+            Node::List(len) => segment.parse::<usize>().is_ok_and(|i| i < *len),
+            Node::Map(keys) => keys.iter().any(|k| k == segment),
+        }
+    }
+}
+
+/// Splits `value` into the rows it is stored as, keyed by path.
+fn rows(value: &Value, path: &mut Vec<String>, out: &mut BTreeMap<String, Vec<u8>>) {
+    let encoded = serde_json::to_vec(value).unwrap();
+    let node = match value {
+        Value::Array(items) if encoded.len() > SPLIT => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(i.to_string());
+                rows(item, path, out);
+                path.pop();
+            }
+            Node::List(items.len())
+        },
+        Value::Object(map) if encoded.len() > SPLIT => {
+            for (key, item) in map {
+                path.push(key.clone());
+                rows(item, path, out);
+                path.pop();
+            }
+            Node::Map(map.keys().cloned().collect())
+        },
+        value => Node::Leaf(value.clone()),
+    };
+    out.insert(display(path), serde_json::to_vec(&node).unwrap());
+}
+
+type Key = (Id, Id, String);
+
+/// Rows held in memory, evicted least recently used first once `bytes` exceeds `budget`.
+#[derive(Debug)]
+struct Resident {
+    nodes: BTreeMap<Key, (Arc<Node>, usize, u64)>,
+    order: BTreeMap<u64, Key>,
+    bytes: usize,
+    budget: usize,
+    clock: u64,
+}
+impl Resident {
+    fn get(&mut self, key: &Key) -> Option<Arc<Node>> {
+        let (node, _, used) = self.nodes.get_mut(key)?;
+        self.order.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.order.insert(self.clock, key.clone());
+        Some(node.clone())
+    }
+
+    fn insert(&mut self, key: Key, node: Arc<Node>, size: usize) {
+        self.remove(&key);
+        self.clock += 1;
+        self.nodes.insert(key.clone(), (node, size, self.clock));
+        self.order.insert(self.clock, key);
+        self.bytes += size;
+        self.evict();
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some((_, size, used)) = self.nodes.remove(key) {
+            self.order.remove(&used);
+            self.bytes -= size;
+        }
+    }
+
+    fn forget(&mut self, contract: &Id, instance: &Id) {
+        let keys = self.nodes.keys().filter(|(c, i, _)| c == contract && i == instance).cloned().collect::<Vec<_>>();
+        keys.iter().for_each(|k| self.remove(k));
+    }
+
+    fn evict(&mut self) {
+        while self.bytes > self.budget {
+            let Some((_, key)) = self.order.pop_first() else {break;};
+            if let Some((_, size, _)) = self.nodes.remove(&key) {self.bytes -= size;}
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Store {
+    connection: Mutex<Connection>,
+    resident: Mutex<Resident>,
+    /// Every known instance with the digest of its encoded state.
+    digests: ArcSwap<BTreeMap<Id, BTreeMap<Id, Digest>>>,
+}
+impl Store {
+    pub fn new(connection: Connection, budget: usize) -> Result<Self, rusqlite::Error> {
+        connection.execute("CREATE TABLE if not exists Subtrees(
+            contract TEXT NOT NULL,
+            instance TEXT NOT NULL,
+            path TEXT NOT NULL,
+            digest BLOB NOT NULL,
+            node BLOB NOT NULL,
+            PRIMARY KEY(contract, instance, path)
+        );", [])?;
+        Ok(Store{
+            connection: Mutex::new(connection),
+            resident: Mutex::new(Resident{nodes: BTreeMap::new(), order: BTreeMap::new(), bytes: 0, budget, clock: 0}),
+            digests: ArcSwap::from_pointee(BTreeMap::new()),
+        })
+    }
+
+    pub fn set_budget(&self, budget: usize) {
+        let mut resident = self.resident.lock().unwrap();
+        resident.budget = budget;
+        resident.evict();
+    }
+
+    /// Bytes of rows currently held in memory.
+    pub fn resident(&self) -> usize {self.resident.lock().unwrap().bytes}
+
+    pub fn digests(&self) -> Arc<BTreeMap<Id, BTreeMap<Id, Digest>>> {self.digests.load_full()}
+
+    pub fn contains(&self, contract: &Id, instance: &Id) -> bool {
+        self.digests.load().get(contract).is_some_and(|i| i.contains_key(instance))
+    }
+
+    /// Writes the instances whose state differs from what was last written and drops those that are gone. Every instance is fingerprinted, whether a reactant, a received state or an
+    /// import changed it. Returns the changed instances.
+    pub fn sync(&self, state: &BTreeMap<Id, BTreeMap<Id, Substance>>) -> Result<BTreeSet<(Id, Id)>, StoreError> {
+        let previous = self.digests.load_full();
+        let mut digests = BTreeMap::new();
+        let mut changed = BTreeSet::new();
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for (contract, instances) in state {
+            let known = digests.entry(*contract).or_insert_with(BTreeMap::new);
+            for (instance, substance) in instances {
+                let last = previous.get(contract).and_then(|i| i.get(instance)).copied();
+                let value = from::<Value>(substance.clone()).map_err(|e| StoreError::Corrupt(format!("{e:?}")))?;
+                let digest = fingerprint(&value);
+                known.insert(*instance, digest);
+                if last == Some(digest) {continue;}
+                self.write(&transaction, contract, instance, &value)?;
+                changed.insert((*contract, *instance));
+            }
+        }
+        for (contract, instances) in previous.iter() {
+            for instance in instances.keys().filter(|i| !digests.get(contract).is_some_and(|d| d.contains_key(*i))) {
+                transaction.execute("DELETE FROM Subtrees WHERE contract = ?1 AND instance = ?2", params![key(contract), key(instance)])?;
+                self.resident.lock().unwrap().forget(contract, instance);
+                changed.insert((*contract, *instance));
+            }
+        }
+        transaction.commit()?;
+        self.digests.store(Arc::new(digests));
+        Ok(changed)
+    }
+
+    /// Replaces the rows of one instance, only touching the rows whose content differs.
+    fn write(&self, connection: &Connection, contract: &Id, instance: &Id, value: &Value) -> Result<(), StoreError> {
+        let mut new = BTreeMap::new();
+        rows(value, &mut Vec::new(), &mut new);
+        let mut statement = connection.prepare("SELECT path, digest FROM Subtrees WHERE contract = ?1 AND instance = ?2")?;
+        let old = statement.query_map(params![key(contract), key(instance)], |r| Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?)))?
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        let mut resident = self.resident.lock().unwrap();
+        for path in old.keys().filter(|p| !new.contains_key(*p)) {
+            connection.execute("DELETE FROM Subtrees WHERE contract = ?1 AND instance = ?2 AND path = ?3", params![key(contract), key(instance), path])?;
+            resident.remove(&(*contract, *instance, path.clone()));
+        }
+        for (path, node) in new {
+            let digest = Sha256::digest(&node).to_vec();
+            if old.get(&path) == Some(&digest) {continue;}
+            connection.execute(
+                "INSERT INTO Subtrees(contract, instance, path, digest, node) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT DO UPDATE SET digest=excluded.digest, node=excluded.node",
+                params![key(contract), key(instance), path, digest, node]
+            )?;
+            resident.remove(&(*contract, *instance, path));
+        }
+        Ok(())
+    }
+
+    /// Runs `f` in one read transaction, so a read never mixes rows from before and after a sync.
+    fn reading<T>(&self, f: impl FnOnce(&Connection) -> Result<T, StoreError>) -> Result<T, StoreError> {
+        let connection = self.connection.lock().unwrap();
+        let transaction = connection.unchecked_transaction()?;
+        let result = f(&transaction)?;
+        transaction.commit()?;
+        Ok(result)
+    }
+
+    fn node(&self, connection: &Connection, contract: &Id, instance: &Id, path: &[String]) -> Result<Option<Arc<Node>>, StoreError> {
+        let k = (*contract, *instance, display(path));
+        if let Some(node) = self.resident.lock().unwrap().get(&k) {return Ok(Some(node));}
+        let row = connection.query_row(
+            "SELECT node FROM Subtrees WHERE contract = ?1 AND instance = ?2 AND path = ?3",
+            params![key(contract), key(instance), &k.2], |r| r.get::<_, Vec<u8>>(0)
+        ).optional()?;
+        let Some(row) = row else {return Ok(None);};
+        let node = Arc::new(serde_json::from_slice::<Node>(&row).map_err(|e| StoreError::Corrupt(e.to_string()))?);
+        self.resident.lock().unwrap().insert(k, node.clone(), row.len());
+        Ok(Some(node))
+    }
+
+    /// Walks down to the row holding `path`, returning it with the depth of that row.
+    fn locate(&self, connection: &Connection, contract: &Id, instance: &Id, path: &[String]) -> Result<Option<(Arc<Node>, usize)>, StoreError> {
+        let mut depth = 0;
+        loop {
+            let Some(node) = self.node(connection, contract, instance, &path[..depth])? else {return Ok(None);};
+            if depth == path.len() || matches!(*node, Node::Leaf(_)) {return Ok(Some((node, depth)));}
+            if !node.has(&path[depth]) {return Ok(None);}
+            depth += 1;
+        }
+    }
+
+    /// Loads the value at `path` of an instance, `None` when either does not exist.
+    pub fn read(&self, contract: &Id, instance: &Id, path: &[String]) -> Result<Option<Value>, StoreError> {
+        self.reading(|connection| match self.locate(connection, contract, instance, path)? {
+            Some((node, depth)) => match &*node {
+                Node::Leaf(value) => Ok(lang::read(value, &path[depth..]).ok().cloned()),
+                _ => self.assemble(connection, contract, instance, &mut path[..depth].to_vec(), &node).map(Some),
+            },
+            None => Ok(None)
+        })
+    }
+
+    fn assemble(&self, connection: &Connection, contract: &Id, instance: &Id, path: &mut Vec<String>, node: &Node) -> Result<Value, StoreError> {
+        let child = |path: &mut Vec<String>, segment: String| -> Result<Value, StoreError> {
+            path.push(segment);
+            let node = self.node(connection, contract, instance, path)?.ok_or_else(|| StoreError::Corrupt(format!("Missing row {}", display(path))))?;
+            let value = self.assemble(connection, contract, instance, path, &node);
+            path.pop();
+            value
+        };
+        match node {
+            Node::Leaf(value) => Ok(value.clone()),
+            Node::List(len) => Ok(Value::Array((0..*len).map(|i| child(path, i.to_string())).collect::<Result<_, _>>()?)),
+            Node::Map(keys) => Ok(Value::Object(keys.iter().map(|k| Ok((k.clone(), child(path, k.clone())?))).collect::<Result<_, StoreError>>()?)),
+        }
+    }
+
+    /// The keys of the list or map at `path`, indexes for a list, without loading its items.
+    pub fn keys(&self, contract: &Id, instance: &Id, path: &[String]) -> Result<Option<Vec<String>>, StoreError> {
+        Ok(self.reading(|connection| self.locate(connection, contract, instance, path))?.and_then(|(node, depth)| match &*node {
+            Node::List(len) => Some((0..*len).map(|i| i.to_string()).collect()),
+            Node::Map(keys) => Some(keys.clone()),
+            Node::Leaf(value) => match lang::read(value, &path[depth..]).ok()? {
+                Value::Array(items) => Some((0..items.len()).map(|i| i.to_string()).collect()),
+                Value::Object(map) => Some(map.keys().cloned().collect()),
+                _ => None
+            }
+        }))
+    }
+}
+
+fn key<T: Serialize>(value: &T) -> String {serde_json::to_string(value).unwrap()}
+
+/// A list or map inside an instance that is read item by item instead of being loaded whole.
+#[derive(Debug, Clone)]
+pub struct Lazy<T> {
+    store: Arc<Store>,
+    contract: Id,
+    instance: Id,
+    path: Vec<String>,
+    item: PhantomData<fn() -> T>,
+}
+impl<T: for<'a> Deserialize<'a>> Lazy<T> {
+    pub(crate) fn new(store: Arc<Store>, contract: Id, instance: Id, path: &str) -> Self {
+        Lazy{store, contract, instance, path: lang::split(path), item: PhantomData}
+    }
+
+    /// The keys of a map or the indexes of a list, empty when nothing is at the path.
+    pub fn keys(&self) -> Result<Vec<String>, StoreError> {
+        Ok(self.store.keys(&self.contract, &self.instance, &self.path)?.unwrap_or_default())
+    }
+
+    pub fn len(&self) -> Result<usize, StoreError> {self.keys().map(|k| k.len())}
+    pub fn is_empty(&self) -> Result<bool, StoreError> {self.len().map(|l| l == 0)}
+
+    pub fn get(&self, key: &str) -> Result<Option<T>, StoreError> {
+        let path = self.path.iter().cloned().chain([key.to_string()]).collect::<Vec<_>>();
+        self.store.read(&self.contract, &self.instance, &path)?
+            .map(|v| serde_json::from_value(v).map_err(|e| StoreError::Corrupt(e.to_string()))).transpose()
+    }
+
+    /// The items of a list in `range`, stopping at the end of the list.
+    pub fn range(&self, range: Range<usize>) -> Result<Vec<T>, StoreError> {
+        range.map_while(|i| self.get(&i.to_string()).transpose()).collect()
+    }
+
+    /// Loads the items one at a time in key order.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<T, StoreError>> + '_, StoreError> {
+        Ok(self.keys()?.into_iter().filter_map(|k| self.get(&k).transpose()))
+    }
+}
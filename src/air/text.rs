@@ -0,0 +1,242 @@
+//! Collaborative plain text.
+//!
+//! [`Text`] is a replicated growable array, every character carries the [`Stamp`] it was inserted with and
+//! deleted characters stay behind as tombstones. Edits reference stamps instead of indexes, so an edit made
+//! against an older copy still lands where its author meant it to, and any two copies that have applied the
+//! same edits hold the same text no matter in which order the edits arrived. Edits that arrive before the
+//! characters they reference are held back until those show up. Stamps are drawn per replica rather than per
+//! signer, so one user editing from two devices never stamps two characters alike.
+//! ```ignore
+//! contract! {
+//!     Notes {author: Name, body: Text}
+//!     init(Text) {author, body: self}
+//!     builtins [Splice]
+//!     access {"/body": owner(/author)}
+//! }
+//! ctx.edit_text::<Notes>(&notes, "/body", 6..11, "there")?;
+//! ```
+//! Contracts written with `contract!` enable [`Splice`] with `builtins [Splice]`, like any reactant it is held to
+//! the `access` rules covering the text.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use serde::{Serialize, Deserialize};
+
+use super::{Reactant, Name, Id};
+use super::policy::Governed;
+use super::lang::{self, Rejected};
+
+/// Orders insertions, a later stamp wins the position right after the character both were inserted after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Stamp {
+    pub clock: u64,
+    pub site: Id,
+}
+impl Stamp {
+    fn offset(self, n: usize) -> Self {Stamp{clock: self.clock + n as u64, site: self.site}}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Item {
+    stamp: Stamp,
+    value: char,
+    deleted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Edit {
+    /// Inserts `text` right after the character stamped `after`, or at the start, stamping its characters
+    /// `at`, `at + 1`, ...
+    Insert{after: Option<Stamp>, at: Stamp, text: String},
+    Delete(Vec<Stamp>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Text {
+    items: Vec<Item>,
+    clock: u64,
+    /// Edits waiting for a character they reference.
+    pending: Vec<Edit>,
+}
+impl Text {
+    /// Characters that are not deleted.
+    pub fn len(&self) -> usize {self.items.iter().filter(|i| !i.deleted).count()}
+    pub fn is_empty(&self) -> bool {self.len() == 0}
+
+    fn position(&self, stamp: Stamp) -> Option<usize> {self.items.iter().position(|i| i.stamp == stamp)}
+
+    fn visible(&self) -> impl Iterator<Item = &Item> {self.items.iter().filter(|i| !i.deleted)}
+
+    /// Whether `edit` was applied to this copy or is held back by it.
+    pub(crate) fn has(&self, edit: &Edit) -> bool {
+        self.pending.contains(edit) || match edit {
+            Edit::Insert{at, text, ..} => (0..text.chars().count()).all(|n| self.position(at.offset(n)).is_some()),
+            Edit::Delete(stamps) => stamps.iter().all(|s| self.position(*s).is_some_and(|i| self.items[i].deleted)),
+        }
+    }
+
+    /// The edit inserting `text` before the character at `index`, clamped to the end, stamped by the replica
+    /// `site`. Apply it before building the next edit from the same copy, edits built from the same state by the
+    /// same replica share stamps.
+    pub fn insert(&self, index: usize, text: &str, site: Id) -> Edit {
+        let after = index.checked_sub(1).and_then(|i| self.visible().nth(i).or_else(|| self.visible().last())).map(|i| i.stamp);
+        Edit::Insert{after, at: Stamp{clock: self.clock + 1, site}, text: text.to_string()}
+    }
+
+    /// The edit deleting the characters in `range`.
+    pub fn delete(&self, range: Range<usize>) -> Edit {
+        Edit::Delete(self.visible().skip(range.start).take(range.len()).map(|i| i.stamp).collect())
+    }
+
+    /// Replaces the characters in `range` with `text`, like `Update(u32..u32, String)` on a file.
+    pub fn splice(&self, range: Range<usize>, text: &str, site: Id) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        if !range.is_empty() {edits.push(self.delete(range.clone()));}
+        if !text.is_empty() {edits.push(self.insert(range.start, text, site));}
+        edits
+    }
+
+    /// Applies `edit` and any held back edits it unblocks.
+    pub fn apply(&mut self, edit: Edit) {
+        let mut queue = vec![edit];
+        while let Some(edit) = queue.pop() {
+            match self.integrate(edit) {
+                Some(blocked) => self.pending.push(blocked),
+                None => queue.append(&mut self.pending),
+            }
+        }
+    }
+
+    /// Returns what could not be applied yet.
+    fn integrate(&mut self, edit: Edit) -> Option<Edit> {
+        match edit {
+            Edit::Insert{after, at, text} => {
+                let mut index = match after.map(|a| self.position(a)) {
+                    None => 0,
+                    Some(Some(i)) => i + 1,
+                    Some(None) => return Some(Edit::Insert{after, at, text}),
+                };
+                for (n, value) in text.chars().enumerate() {
+                    let stamp = at.offset(n);
+                    if let Some(existing) = self.position(stamp) {index = existing + 1; continue;}
+                    while self.items.get(index).is_some_and(|i| i.stamp > stamp) {index += 1;}
+                    self.items.insert(index, Item{stamp, value, deleted: false});
+                    self.clock = self.clock.max(stamp.clock);
+                    index += 1;
+                }
+                None
+            },
+            Edit::Delete(stamps) => {
+                let missing = stamps.into_iter().filter(|s| match self.position(*s) {
+                    Some(i) => {self.items[i].deleted = true; false},
+                    None => true,
+                }).collect::<Vec<_>>();
+                (!missing.is_empty()).then_some(Edit::Delete(missing))
+            }
+        }
+    }
+}
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        let mut value = Text::default();
+        value.apply(Edit::Insert{after: None, at: Stamp{clock: 1, site: Id::hash("")}, text: text.to_string()});
+        value
+    }
+}
+impl From<String> for Text {
+    fn from(text: String) -> Self {Text::from(text.as_str())}
+}
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.visible().try_for_each(|i| write!(f, "{}", i.value))
+    }
+}
+
+/// Edits sent for the [`Text`] at a path of an instance, by contract, instance and path.
+pub(crate) type Sent = BTreeMap<(Id, Id, String), Vec<Edit>>;
+
+/// Applies edits to the [`Text`] at `at`. It is held to the contract's policy like any other reactant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Splice<C> {
+    pub at: String,
+    pub edits: Vec<Edit>,
+    #[serde(skip)]
+    contract: PhantomData<fn() -> C>,
+}
+impl<C> Splice<C> {
+    pub fn new(at: impl Into<String>, edits: Vec<Edit>) -> Self {Splice{at: at.into(), edits, contract: PhantomData}}
+}
+impl<C: Governed + Serialize + for<'a> Deserialize<'a>> Reactant<C> for Splice<C> {
+    type Result = Result<(), Rejected>;
+
+    fn id() -> Id {Id::hash("Splice")}
+
+    fn apply(self, state: &mut C, _signer: Name, _timestamp: u64) -> Self::Result {
+        let at = lang::split(&self.at);
+        let mut root = lang::to_value(&*state)?;
+        let mut text = lang::from_value::<Text>(lang::read(&root, &at)?.clone())?;
+        self.edits.into_iter().for_each(|e| text.apply(e));
+        lang::write(&mut root, &at, lang::to_value(&text)?)?;
+        *state = lang::from_value(root)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use super::super::{Air, Contract, Contracts, Reactants, Substance, Transport, Guard, Policy, Rule, into};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Notes {body: Text}
+    impl Contract for Notes {
+        type Init = String;
+        fn id() -> Id {Id::hash("Notes")}
+        fn init(init: String, _signer: Name, _timestamp: u64) -> Self {Notes{body: Text::from(init)}}
+        fn reactants() -> Reactants<Self> {Reactants::default().add::<Splice<Self>>()}
+    }
+    impl Governed for Notes {
+        fn policy() -> Policy<Self> {Policy::default().otherwise(Rule::Anyone)}
+        fn guard(guard: Guard<Self>) -> Guard<Self> {guard.add::<Splice<Self>>()}
+    }
+
+    struct Offline;
+    impl Transport for Offline {
+        fn broadcast(&self, _message: Vec<u8>) {}
+        fn receive(&self) -> Vec<(Name, Vec<u8>)> {vec![]}
+    }
+
+    fn stored(notes: Id, body: &Text) -> BTreeMap<Id, BTreeMap<Id, Substance>> {
+        BTreeMap::from([(Notes::id(), BTreeMap::from([(notes, into(&Notes{body: body.clone()}).unwrap())]))])
+    }
+
+    #[test]
+    fn quick_edits_build_on_each_other() {
+        let directory = std::env::temp_dir().join(format!("air_text_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (mut air, ctx) = Air::open(&directory.join("cache.db"), Contracts::default().add::<Notes>(), Arc::new(Offline)).unwrap();
+        let notes = Id::hash("notes");
+        let mut text = Text::from("hello");
+        ctx.store.sync(&stored(notes, &text)).unwrap();
+
+        // None of these reach the store before the next one is made.
+        assert!(ctx.edit_text::<Notes>(&notes, "/body", 5..5, " there").unwrap());
+        assert!(ctx.edit_text::<Notes>(&notes, "/body", 11..11, "!").unwrap());
+        assert!(ctx.edit_text::<Notes>(&notes, "/body", 0..1, "H").unwrap());
+        while let Some(submission) = air.next() {
+            let splice = serde_json::from_value::<Splice<Notes>>(submission.origin.unwrap().body).unwrap();
+            splice.edits.into_iter().for_each(|e| text.apply(e));
+        }
+        assert_eq!(text.to_string(), "Hello there!");
+
+        ctx.store.sync(&stored(notes, &text)).unwrap();
+        assert!(ctx.edit_text::<Notes>(&notes, "/body", 12..12, "?").unwrap());
+        assert_eq!(ctx.edits.lock().unwrap().values().map(Vec::len).sum::<usize>(), 1, "edits the store shows are forgotten");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+//! Undo and redo for reactants sent from this device.
+//!
+//! `Context::send_undoable` remembers the submission, once the runtime has applied it the local history holds the
+//! instance state before and after. `undo()` turns that difference into a [`Revert`] that puts back what the
+//! reactant changed, `redo()` does the same for the revert. A contract opts in by adding `Revert<Self>` to its
+//! [`Guard`](super::Guard), or with `builtins [Revert]` in `contract!`. Reactants that know their own inverse
+//! implement [`Invertible`] and are sent with `Context::send_invertible` instead.
+//!
+//! Before anything is sent the current state is compared with the state the step left behind. If another
+//! signer has since changed any of it the step is skipped and the next one is tried. The revert carries what it
+//! expects at every path it changes and repeats the comparison when it is applied, so a change arriving in between
+//! makes it fail rather than clobber it. Whether it applies depends on the instance state alone, every peer
+//! reaches the same verdict.
+
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use super::{Contract, Reactant, Name, Id, Context, RequestError};
+use super::policy::Governed;
+use super::history::HistoryError;
+use super::lang::{self, Rejected};
+
+#[derive(Debug)]
+pub enum UndoError {
+    /// The step has not been applied by the runtime yet, it stays on the stack.
+    Pending,
+    History(HistoryError),
+    Air(RequestError),
+}
+impl std::fmt::Display for UndoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UndoError::Pending => write!(f, "The last change has not been applied yet"),
+            UndoError::History(e) => write!(f, "{e}"),
+            UndoError::Air(e) => write!(f, "Could not send the compensating request: {e}"),
+        }
+    }
+}
+impl std::error::Error for UndoError {}
+impl From<HistoryError> for UndoError {fn from(e: HistoryError) -> Self {UndoError::History(e)}}
+impl From<RequestError> for UndoError {fn from(e: RequestError) -> Self {UndoError::Air(e)}}
+
+/// One part of the difference between two states, holding what to put back and what is expected to be there.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    /// Put `value` at `path`, or remove it when `None`, if the path still holds `expect`.
+    Set{path: Vec<String>, value: Option<Value>, expect: Option<Value>},
+    /// Remove the items `expect` from the list at `path` starting at index `at`.
+    Trim{path: Vec<String>, at: usize, expect: Vec<Value>},
+    /// Insert `items` into the list at `path` at index `at`, if the list holds exactly `at` items.
+    Splice{path: Vec<String>, at: usize, items: Vec<Value>},
+}
+impl Change {
+    pub fn path(&self) -> &[String] {
+        match self {Change::Set{path, ..} | Change::Trim{path, ..} | Change::Splice{path, ..} => path}
+    }
+
+    /// True when `state` still looks the way the change expects.
+    pub fn holds(&self, state: &Value) -> bool {
+        match self {
+            Change::Set{path, expect, ..} => lang::read(state, path).ok() == expect.as_ref(),
+            Change::Trim{path, at, expect} => matches!(lang::read(state, path), Ok(Value::Array(items)) if items.get(*at..at+expect.len()) == Some(expect)),
+            Change::Splice{path, at, ..} => matches!(lang::read(state, path), Ok(Value::Array(items)) if items.len() == *at),
+        }
+    }
+
+    pub(crate) fn apply(self, state: &mut Value) -> Result<(), Rejected> {
+        match self {
+            Change::Set{path, value: Some(value), ..} => lang::write(state, &path, value),
+            Change::Set{path, value: None, ..} => lang::delete(state, &path),
+            Change::Trim{path, at, expect} => {
+                let mut items = lang::from_value::<Vec<Value>>(lang::read(state, &path)?.clone())?;
+                items.drain(at..at+expect.len());
+                lang::write(state, &path, Value::Array(items))
+            },
+            Change::Splice{path, at, items} => {
+                let mut list = lang::from_value::<Vec<Value>>(lang::read(state, &path)?.clone())?;
+                list.splice(at..at, items);
+                lang::write(state, &path, Value::Array(list))
+            }
+        }
+    }
+}
+
+/// The changes that turn `expected` back into `target`.
+pub fn diff(target: Option<&Value>, expected: Option<&Value>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    walk(&mut Vec::new(), target, expected, &mut changes);
+    changes
+}
+
+fn walk(path: &mut Vec<String>, target: Option<&Value>, expected: Option<&Value>, changes: &mut Vec<Change>) {
+    match (target, expected) {
+        (t, e) if t == e => {},
+        (Some(Value::Object(t)), Some(Value::Object(e))) => {
+            let keys = t.keys().chain(e.keys().filter(|k| !t.contains_key(*k))).cloned().collect::<Vec<_>>();
+            for key in keys {
+                path.push(key.clone());
+                walk(path, t.get(&key), e.get(&key), changes);
+                path.pop();
+            }
+        },
+        (Some(Value::Array(t)), Some(Value::Array(e))) if t.len() == e.len() => {
+            for (i, (t, e)) in t.iter().zip(e).enumerate() {
+                path.push(i.to_string());
+                walk(path, Some(t), Some(e), changes);
+                path.pop();
+            }
+        },
+        (Some(Value::Array(t)), Some(Value::Array(e))) if e.starts_with(t) =>
+            changes.push(Change::Trim{path: path.clone(), at: t.len(), expect: e[t.len()..].to_vec()}),
+        (Some(Value::Array(t)), Some(Value::Array(e))) if t.starts_with(e) =>
+            changes.push(Change::Splice{path: path.clone(), at: e.len(), items: t[e.len()..].to_vec()}),
+        (t, e) => changes.push(Change::Set{path: path.clone(), value: t.cloned(), expect: e.cloned()}),
+    }
+}
+
+/// Puts back a set of changes when every changed path still holds what the change expects. Like every reactant
+/// it is held to the policy rules covering the changed paths that are not scoped to other reactants, so it writes
+/// nothing its signer could not write directly. Add `Revert` to a rule's reactant list to constrain it further.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Revert<C> {
+    pub changes: Vec<Change>,
+    #[serde(skip)]
+    contract: PhantomData<fn() -> C>,
+}
+impl<C> Revert<C> {
+    pub fn new(changes: Vec<Change>) -> Self {Revert{changes, contract: PhantomData}}
+}
+impl<C: Governed + Serialize + for<'a> Deserialize<'a>> Reactant<C> for Revert<C> {
+    type Result = Result<(), Rejected>;
+
+    fn id() -> Id {Id::hash("Revert")}
+
+    fn apply(self, state: &mut C, _signer: Name, _timestamp: u64) -> Self::Result {
+        let mut root = lang::to_value(&*state)?;
+        for change in &self.changes {
+            if !change.holds(&root) {
+                return Err(Rejected::Condition(format!("{} changed since", lang::display(change.path()))));
+            }
+        }
+        for change in self.changes {change.apply(&mut root)?;}
+        *state = lang::from_value(root)?;
+        Ok(())
+    }
+}
+
+/// A reactant that can compute the reactant undoing it from the state it is applied to.
+pub trait Invertible<C: Contract>: Reactant<C> + Serialize + Clone + Send + Sync + 'static {
+    type Inverse: Reactant<C> + Serialize + Clone + Send + Sync + 'static;
+    fn inverse(&self, before: &C) -> Option<Self::Inverse>;
+}
+
+type Resend = Arc<dyn Fn(&Context) -> Result<u64, RequestError> + Send + Sync>;
+type SendRevert = fn(&Context, Id, &Path, Vec<Change>) -> Result<u64, RequestError>;
+
+#[derive(Clone)]
+pub(crate) enum Action {
+    /// Sends the revert of a history entry.
+    Revert(SendRevert),
+    /// Sends `backward` to undo, after which `forward` redoes it.
+    Custom{forward: Resend, backward: Resend},
+}
+
+#[derive(Clone)]
+pub(crate) struct Step {
+    pub contract: Id,
+    pub instance: Id,
+    pub path: PathBuf,
+    pub token: u64,
+    pub action: Action,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Stack {
+    pub undo: Vec<Step>,
+    pub redo: Vec<Step>,
+}
+impl std::fmt::Debug for Stack {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Stack{{undo: {}, redo: {}}}", self.undo.len(), self.redo.len())
+    }
+}
+
+pub(crate) fn send_revert<C: Governed + Serialize + for<'a> Deserialize<'a>>(ctx: &Context, iid: Id, path: &Path, changes: Vec<Change>) -> Result<u64, RequestError> {
+    ctx.dispatch(iid, path, Revert::<C>::new(changes))
+}
+
+pub(crate) fn resend<C: Contract, R: Reactant<C> + Serialize + Clone + Send + Sync + 'static>(iid: Id, path: PathBuf, reactant: R) -> Resend {
+    Arc::new(move |ctx: &Context| ctx.dispatch(iid, &path, reactant.clone()))
+}
@@ -9,14 +9,18 @@ mod safe_area;
 mod notifications;
 mod app_support;
 
-pub use clipboard::Clipboard;
-pub use camera::Camera;
+pub use clipboard::{Clipboard, ClipboardFormat, ClipboardContent};
+pub use camera::{
+    Camera, CameraError, CameraDevice, CameraFormat, Facing, SyntheticSource, CameraSettings, CameraCapabilities,
+    SettingRange, CustomExposure, ExposureMode, FocusMode, WhiteBalanceMode, WhiteBalanceGains, Resolution, SceneMode,
+    ColorFilter, CapturedPhoto, PhotoMetadata, PhotoFormat, Exposure,
+};
 pub use share::Share;
 pub use cloud::CloudStorage;
 pub use photo_picker::PhotoPicker;
 pub use safe_area::SafeAreaInsets;
 pub use haptics::Haptics;
-pub use notifications::Notifications;
+pub use notifications::{Notifications, Notification, NotificationAction, NotificationError, Urgency, Repeat, Scheduled, Activation};
 pub use logger::Logger;
 
 use crate::window::Input;
@@ -65,9 +69,21 @@ impl Context {
         if let Some(frame) = self.camera.tick() {
             events.push(Input::CameraFrame(frame));
         }
+        for photo in self.camera.photos() {
+            events.push(Input::CapturedPhoto(photo));
+        }
+        for camera::Code{text, corners} in self.camera.codes() {
+            events.push(Input::CodeDetected{text, corners});
+        }
         if let Some(photo) = self.photo_picker.tick() {
             events.push(Input::Photo(photo));
         }
+        if let Some(formats) = self.clipboard.tick() {
+            events.push(Input::ClipboardChanged(formats));
+        }
+        for Activation{id, action, reply_text} in self.notifications.tick() {
+            events.push(Input::NotificationActivated{id, action, reply_text});
+        }
         events
     }
 }
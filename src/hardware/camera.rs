@@ -18,328 +18,657 @@ mod windows;
 #[cfg(target_os = "windows")]
 use windows::OsCamera;
 
+mod synthetic;
+use synthetic::SyntheticCamera;
+pub use synthetic::SyntheticSource;
+
+mod software;
+
+mod photo;
+pub use photo::{CapturedPhoto, PhotoMetadata, PhotoFormat, Exposure};
+
+mod scanner;
+pub(crate) use scanner::Code;
+use scanner::Scanner;
+
 use image::RgbaImage;
-use std::sync::Arc;
+use image::metadata::Orientation;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CameraError {
+    InitializationFailed,
+    PermissionDenied,
+    FailedToGetFrame,
+    /// The frames of a synthetic camera could not be read.
+    Source(String),
+    /// No device has this id.
+    UnknownDevice(String),
+    /// Photos are only taken while a [`Handle`] is held.
+    NotStarted,
+    /// A photo could not be encoded.
+    Encoding(String),
+}
+impl std::fmt::Display for CameraError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CameraError::InitializationFailed => write!(f, "Camera could not be initialized"),
+            CameraError::PermissionDenied => write!(f, "Camera permission denied"),
+            CameraError::FailedToGetFrame => write!(f, "Camera frame unavailable"),
+            CameraError::Source(e) => write!(f, "Synthetic camera source error: {e}"),
+            CameraError::UnknownDevice(id) => write!(f, "No camera device {id}"),
+            CameraError::NotStarted => write!(f, "Camera not started"),
+            CameraError::Encoding(e) => write!(f, "Photo could not be encoded: {e}"),
+        }
+    }
+}
+impl std::error::Error for CameraError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Facing {Front, Back, External}
+
+/// A resolution a device streams at, with the frame rates it supports there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraFormat {
+    pub width: u32,
+    pub height: u32,
+    pub min_fps: f32,
+    pub max_fps: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraDevice {
+    /// Stable for as long as the device is connected, pass it to [`Camera::open`] or [`Camera::switch`].
+    pub id: String,
+    pub name: String,
+    pub facing: Facing,
+    pub formats: Vec<CameraFormat>,
+    pub capabilities: CameraCapabilities,
+}
 
 #[derive(Clone, Default, Debug)]
 pub struct Handle(Arc<bool>);
 
-pub struct Camera(OsCamera, Handle);
+enum Source {
+    Os(OsCamera),
+    Synthetic(SyntheticCamera),
+}
+impl Source {
+    fn start(&mut self) {
+        match self {
+            Source::Os(camera) => camera.start(),
+            Source::Synthetic(camera) => camera.start(),
+        }
+    }
+
+    fn stop(&mut self) {
+        match self {
+            Source::Os(camera) => camera.stop(),
+            Source::Synthetic(camera) => camera.stop(),
+        }
+    }
+
+    fn frame(&mut self) -> Option<RgbaImage> {
+        match self {
+            Source::Os(camera) => camera.frame(),
+            Source::Synthetic(camera) => camera.frame(),
+        }
+    }
+
+    fn devices(&self) -> Vec<CameraDevice> {
+        match self {
+            Source::Os(camera) => camera.devices(),
+            Source::Synthetic(camera) => vec![camera.device()],
+        }
+    }
+
+    /// The device in use.
+    fn current(&self) -> Option<CameraDevice> {
+        match self {
+            Source::Os(camera) => camera.current(),
+            Source::Synthetic(camera) => Some(camera.device()),
+        }
+    }
+
+    /// Asks for a photo, returns false when the source has no still capture.
+    fn capture(&mut self) -> bool {
+        match self {
+            Source::Os(camera) => camera.capture(),
+            Source::Synthetic(_) => false,
+        }
+    }
+
+    fn photos(&mut self) -> Vec<CapturedPhoto> {
+        match self {
+            Source::Os(camera) => camera.photos(),
+            Source::Synthetic(_) => Vec::new(),
+        }
+    }
+
+    fn select(&mut self, id: &str) {
+        match self {
+            Source::Os(camera) => camera.select(id),
+            Source::Synthetic(_) => {}
+        }
+    }
+
+    fn capabilities(&self) -> CameraCapabilities {
+        match self {
+            Source::Os(camera) => camera.capabilities(),
+            Source::Synthetic(camera) => camera.device().capabilities,
+        }
+    }
+
+    fn apply(&mut self, settings: &CameraSettings) {
+        match self {
+            Source::Os(camera) => camera.apply(settings),
+            Source::Synthetic(_) => {}
+        }
+    }
+}
+
+/// Streams frames as [`Input::CameraFrame`](crate::window::Input) while a [`Handle`] from `start` is held.
+///
+/// Setting `MAVERICK_CAMERA` replaces the device with a [`SyntheticSource`], which is how the camera is used
+/// on Linux and Windows, where no device is supported: `pattern` or `pattern:640x480` for a test pattern, or
+/// the path of a directory of images or of an animated image. `MAVERICK_CAMERA_FPS` sets its frame rate.
+pub struct Camera {
+    source: Source,
+    handle: Handle,
+    settings: Arc<Mutex<CameraSettings>>,
+    /// Of the device in use, read once it streams.
+    capabilities: Option<CameraCapabilities>,
+    running: bool,
+    /// Photos asked for since the last tick.
+    requested: usize,
+    /// Photos to take from the next frame, for sources without still capture.
+    stills: usize,
+    photos: Vec<CapturedPhoto>,
+    scanner: Option<Scanner>,
+}
 impl Camera {
-    pub fn new() -> Self {Camera(OsCamera::new(), Handle::default())}
+    fn with(source: Source) -> Self {
+        Camera{
+            source, handle: Handle::default(), settings: Arc::default(), capabilities: None, running: false,
+            requested: 0, stills: 0, photos: Vec::new(), scanner: None,
+        }
+    }
 
-    pub fn start(&mut self) -> Handle {self.1.clone()}
+    pub fn new() -> Self {
+        let source = match SyntheticSource::configured() {
+            Some((source, fps)) => match SyntheticCamera::new(source, fps) {
+                Ok(camera) => Source::Synthetic(camera),
+                Err(e) => {
+                    log::error!("{e}");
+                    Source::Os(OsCamera::new())
+                }
+            },
+            None => Source::Os(OsCamera::new()),
+        };
+        Self::with(source)
+    }
+
+    /// A camera streaming from the device `id` instead of the platform's default.
+    pub fn open(id: &str) -> Result<Self, CameraError> {
+        let mut camera = Self::new();
+        camera.switch(id)?;
+        Ok(camera)
+    }
+
+    /// A camera playing `source` at `fps` frames per second, whatever the configuration.
+    pub fn synthetic(source: SyntheticSource, fps: f32) -> Result<Self, CameraError> {
+        Ok(Self::with(Source::Synthetic(SyntheticCamera::new(source, fps)?)))
+    }
+
+    /// The devices this camera can stream from. A synthetic camera is its only device.
+    pub fn devices(&self) -> Vec<CameraDevice> {self.source.devices()}
+
+    /// Streams from the device `id` from now on, a stream in progress carries on from the new device with the
+    /// same settings.
+    pub fn switch(&mut self, id: &str) -> Result<(), CameraError> {
+        if !self.devices().iter().any(|d| d.id == id) {return Err(CameraError::UnknownDevice(id.to_string()));}
+        self.source.select(id);
+        self.capabilities = None;
+        self.settings.lock().unwrap().is_updated = true;
+        Ok(())
+    }
+
+    /// What the device in use supports.
+    pub fn capabilities(&self) -> CameraCapabilities {
+        self.capabilities.clone().unwrap_or_else(|| self.source.capabilities())
+    }
+
+    /// The settings applied to every frame, changes take effect on the next one.
+    pub fn settings(&self) -> Arc<Mutex<CameraSettings>> {self.settings.clone()}
+
+    pub fn start(&mut self) -> Handle {self.handle.clone()}
+
+    /// Takes a photo at the device's full resolution, delivered as
+    /// [`Input::CapturedPhoto`](crate::window::Input) once it is ready. Without still capture, as with a synthetic
+    /// camera, the next frame is taken instead. Settings apply as they do to frames.
+    pub fn capture_photo(&mut self) -> Result<(), CameraError> {
+        if Arc::strong_count(&self.handle.0) == 1 {return Err(CameraError::NotStarted);}
+        self.requested += 1;
+        Ok(())
+    }
+
+    pub(crate) fn photos(&mut self) -> Vec<CapturedPhoto> {std::mem::take(&mut self.photos)}
+
+    /// Looks for QR codes, EAN-13, UPC-A, EAN-8 and Code 128 barcodes in frames while enabled, reported as
+    /// [`Input::CodeDetected`](crate::window::Input) when they come into sight.
+    pub fn scan(&mut self, enabled: bool) {
+        if enabled != self.scanner.is_some() {self.scanner = enabled.then(Scanner::new);}
+    }
+
+    pub(crate) fn codes(&mut self) -> Vec<Code> {self.scanner.as_ref().map(|s| s.codes()).unwrap_or_default()}
 
     pub(crate) fn tick(&mut self) -> Option<RgbaImage> {
-        let count = Arc::strong_count(&self.1.0);
+        let count = Arc::strong_count(&self.handle.0);
         if count > 1 {
-            self.0.start();
-            self.0.frame()
+            self.source.start();
+            if !self.running {
+                // Devices forget their configuration when they stop.
+                self.running = true;
+                self.settings.lock().unwrap().is_updated = true;
+            }
+            let capabilities = self.capabilities.get_or_insert_with(|| self.source.capabilities());
+            let settings = {
+                let mut shared = self.settings.lock().unwrap();
+                let settings = shared.clone();
+                shared.is_updated = false;
+                settings
+            };
+            if settings.is_updated {self.source.apply(&settings);}
+
+            for _ in 0..std::mem::take(&mut self.requested) {
+                if !self.source.capture() {self.stills += 1;}
+            }
+            let mut photos = self.source.photos().into_iter().map(|mut photo| {
+                photo.image = software::adjust(photo.image, &settings, capabilities);
+                photo
+            }).collect::<Vec<_>>();
+            let frame = self.source.frame().map(|frame| software::adjust(frame, &settings, capabilities));
+            if let Some(frame) = &frame && self.stills > 0 {
+                let metadata = PhotoMetadata{
+                    timestamp: SystemTime::now(), orientation: Orientation::NoTransforms, device: None, exposure: None,
+                };
+                for _ in 0..std::mem::take(&mut self.stills) {
+                    photos.push(CapturedPhoto{image: frame.clone(), metadata: metadata.clone()});
+                }
+            }
+            if !photos.is_empty() {
+                let device = self.source.current();
+                for mut photo in photos {
+                    if photo.metadata.device.is_none() {photo.metadata.device = device.clone();}
+                    self.photos.push(photo);
+                }
+            }
+            if let (Some(scanner), Some(frame)) = (&self.scanner, &frame) {scanner.analyze(frame);}
+            frame
         } else if count == 1 {
-            self.0.stop();
+            self.source.stop();
+            self.running = false;
+            self.requested = 0;
+            self.stills = 0;
             None
         } else {None}
     }
 }
 impl Default for Camera {fn default() -> Self {Self::new()}}
 
-//  #[derive(Debug, Clone)]
-//  pub struct CameraSettings {
-//      pub exposure_mode: ExposureMode,
-//      pub custom_exposure: Option<CustomExposure>,
-//      pub exposure_compensation: Option<f32>,
-//      pub exposure_stacking: bool,
-
-//      pub focus_mode: FocusMode,
-//      pub focus_distance: Option<f32>,
-//      pub focus_point_of_interest: Option<(f32, f32)>,
-
-//      pub white_balance_mode: WhiteBalanceMode,
-//      pub white_balance_gains: Option<WhiteBalanceGains>,
-
-//      pub zoom_factor: Option<f32>,
-
-//      pub frame_rate: Option<f32>,
-//      pub resolution: Option<Resolution>,
-//      pub hdr_enabled: bool,
-//      pub stabilization_enabled: bool,
-
-//      pub low_light_boost: Option<bool>,
-//      pub scene_mode_hint: Option<SceneMode>,
-
-//      pub brightness: Option<f32>,
-//      pub contrast: Option<f32>,
-//      pub saturation: Option<f32>,
-//      pub sharpness: Option<f32>,
-//      pub hue: Option<f32>,
-//      pub noise_reduction: Option<f32>,
-//      pub gamma: Option<f32>,
-//      pub color_filter: Option<ColorFilter>,
-
-//      pub is_updated: bool,
-//  }
-
-//  #[derive(Debug, Clone, Copy)]
-//  pub struct CustomExposure {
-//      pub duration: f32,
-//      pub iso: f32,
-//  }
-
-//  impl Default for CustomExposure {
-//      fn default() -> Self {
-//          Self {
-//              iso: 0.0,
-//              duration: 0.0,
-//          }
-//      }
-//  }
-
-//  #[derive(Debug, Clone, Copy, PartialEq)]
-//  pub enum ExposureMode {
-//      Auto,
-//      Continuous,
-//      Custom,
-//  }
-
-//  #[derive(Debug, Clone, Copy, PartialEq)]
-//  pub enum FocusMode {
-//      Auto,
-//      Continuous,
-//      Locked,
-//      Manual,
-//  }
-
-//  #[derive(Debug, Clone, Copy, PartialEq)]
-//  pub enum WhiteBalanceMode {
-//      Auto,
-//      Locked,
-//      Custom,
-//  }
-
-//  #[derive(Debug, Clone, Copy, PartialEq)]
-//  pub struct WhiteBalanceGains {
-//      pub red: f32,
-//      pub green: f32,
-//      pub blue: f32,
-//  }
-
-//  impl WhiteBalanceGains {
-//      pub fn from(red: f32, green: f32, blue: f32) -> Self {
-//          WhiteBalanceGains { red, green, blue }
-//      }
-//  }
-
-//  impl Default for WhiteBalanceGains {
-//      fn default() -> Self {
-//          Self {
-//              red: 0.0,
-//              green: 0.0,
-//              blue: 0.0,
-//          }
-//      }
-//  }
-
-//  #[derive(Debug, Clone, Copy)]
-//  pub struct Resolution {
-//      pub width: u32,
-//      pub height: u32,
-//  }
-
-//  #[derive(Debug, Clone, Copy)]
-//  pub enum SceneMode {
-//      Standard,
-//      Portrait,
-//      Night,
-//      Action,
-//      Backlit,
-//      Macro,
-//  }
-
-//  #[derive(Debug, Clone, Copy)]
-//  pub enum ColorFilter {
-//      None,
-//      Sepia,
-//      Mono,
-//      Vibrant,
-//      Cool,
-//      Warm,
-//  }
-
-//  impl Default for CameraSettings {
-//      fn default() -> Self {
-//          Self {
-//              exposure_mode: ExposureMode::Continuous,
-//              custom_exposure: None,
-//              exposure_compensation: Some(0.0),
-//              exposure_stacking: false,
-//              focus_mode: FocusMode::Auto,
-//              focus_distance: Some(0.5),
-//              focus_point_of_interest: Some((0.5, 0.5)),
-//              white_balance_mode: WhiteBalanceMode::Auto,
-//              white_balance_gains: None,
-//              zoom_factor: Some(1.0),
-//              frame_rate: Some(30.0),
-//              resolution: Some(Resolution {
-//                  width: 1920,
-//                  height: 1080,
-//              }),
-//              hdr_enabled: false,
-//              stabilization_enabled: true,
-//              low_light_boost: Some(false),
-//              scene_mode_hint: Some(SceneMode::Standard),
-//              brightness: Some(0.5),
-//              contrast: Some(0.5),
-//              saturation: Some(0.5),
-//              sharpness: None,
-//              hue: Some(0.5),
-//              noise_reduction: None,
-//              gamma: Some(0.5),
-//              color_filter: None,
-//              is_updated: true,
-//          }
-//      }
-//  }
-
-//  impl CameraSettings {
-//      pub fn set_brightness(&mut self, value: f32) {
-//          self.brightness = Some(value.clamp(0.0, 1.0));
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_contrast(&mut self, value: f32) {
-//          self.contrast = Some(value.clamp(0.0, 1.0));
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_saturation(&mut self, value: f32) {
-//          self.saturation = Some(value.clamp(0.0, 1.0));
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_sharpness(&mut self, value: f32) {
-//          let v = value.clamp(0.0, 1.0);
-//          self.sharpness = if v < 0.1 { None } else { Some(v) };
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_hue(&mut self, value: f32) {
-//          self.hue = Some(value.clamp(0.0, 1.0));
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_noise_reduction(&mut self, value: f32) {
-//          let v = value.clamp(0.0, 1.0);
-//          self.noise_reduction = if v < 0.1 { None } else { Some(v) };
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_gamma(&mut self, value: f32) {
-//          self.gamma = Some(value.clamp(0.0, 1.0));
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_focus_mode(&mut self, mode: FocusMode) {
-//          if mode != FocusMode::Manual {
-//              self.focus_distance = Some(0.5)
-//          };
-//          self.focus_mode = mode;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_focus_distance(&mut self, value: f32) {
-//          if self.focus_mode == FocusMode::Manual {
-//              self.focus_distance = Some(value.clamp(0.0, 1.0));
-//              self.is_updated = true;
-//          }
-//      }
-
-//      pub fn set_exposure_compensation(&mut self, value: f32) {
-//          self.exposure_compensation = Some((value.clamp(0.0, 1.0) * 4.0) - 2.0);
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_custom_exposure(&mut self, duration_percentage: f32, iso_percentage: f32) {
-//          self.custom_exposure = Some(CustomExposure {
-//              duration: duration_percentage.clamp(0.0, 1.0),
-//              iso: iso_percentage.clamp(0.0, 1.0),
-//          });
-//          self.exposure_mode = ExposureMode::Custom;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_exposure_mode(&mut self, mode: ExposureMode) {
-//          if mode != ExposureMode::Custom {
-//              self.custom_exposure = None
-//          };
-//          self.exposure_mode = mode;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_white_balance_mode(&mut self, mode: WhiteBalanceMode) {
-//          if mode != WhiteBalanceMode::Custom {
-//              self.white_balance_gains = None
-//          };
-//          self.white_balance_mode = mode;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_white_balance_gains_red(&mut self, red: f32) {
-//          let g = self.white_balance_gains.unwrap_or_default();
-//          self.white_balance_gains =
-//              Some(WhiteBalanceGains::from(red.clamp(0.0, 1.0), g.green, g.blue));
-//          self.white_balance_mode = WhiteBalanceMode::Custom;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_white_balance_gains_green(&mut self, green: f32) {
-//          let g = self.white_balance_gains.unwrap_or_default();
-//          self.white_balance_gains = Some(WhiteBalanceGains::from(
-//              g.red,
-//              green.clamp(0.0, 1.0),
-//              g.blue,
-//          ));
-//          self.white_balance_mode = WhiteBalanceMode::Custom;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_white_balance_gains_blue(&mut self, blue: f32) {
-//          let g = self.white_balance_gains.unwrap_or_default();
-//          self.white_balance_gains = Some(WhiteBalanceGains::from(
-//              g.red,
-//              g.green,
-//              blue.clamp(0.0, 1.0),
-//          ));
-//          self.white_balance_mode = WhiteBalanceMode::Custom;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_zoom_factor(&mut self, value: f32) {
-//          self.zoom_factor = Some(1.0 + value.clamp(0.0, 1.0) * 9.0);
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_hdr_enabled(&mut self, enabled: bool) {
-//          self.hdr_enabled = enabled;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_stabilization_enabled(&mut self, enabled: bool) {
-//          self.stabilization_enabled = enabled;
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_low_light_boost(&mut self, enabled: bool) {
-//          self.low_light_boost = Some(enabled);
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_scene_mode(&mut self, scene: SceneMode) {
-//          self.scene_mode_hint = Some(scene);
-//          self.is_updated = true;
-//      }
-
-//      pub fn set_focus_point_of_interest(&mut self, x: f32, y: f32) {
-//          self.focus_point_of_interest = Some((x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)));
-//          self.is_updated = true;
-//      }
-//  }
+/// What a [`Camera`] is asked to do, shared through [`Camera::settings`]. Values are fractions from 0 to 1 unless
+/// noted, with 0.5 leaving colour adjustments as they are. Change them with the setters, which mark the settings
+/// as updated so the next frame applies them. Brightness, contrast, saturation, sharpness, hue, noise reduction,
+/// gamma and the colour filter are applied in software on every platform, as are white balance gains on devices
+/// without custom white balance. Anything else a device does not support, as reported by its
+/// [`CameraCapabilities`], is ignored.
+#[derive(Debug, Clone)]
+pub struct CameraSettings {
+    pub exposure_mode: ExposureMode,
+    pub custom_exposure: Option<CustomExposure>,
+    /// In EV.
+    pub exposure_compensation: Option<f32>,
+    pub exposure_stacking: bool,
+
+    pub focus_mode: FocusMode,
+    pub focus_distance: Option<f32>,
+    pub focus_point_of_interest: Option<(f32, f32)>,
+
+    pub white_balance_mode: WhiteBalanceMode,
+    pub white_balance_gains: Option<WhiteBalanceGains>,
+
+    /// The magnification, from 1.
+    pub zoom_factor: Option<f32>,
+
+    /// In frames per second.
+    pub frame_rate: Option<f32>,
+    pub resolution: Option<Resolution>,
+    pub hdr_enabled: bool,
+    pub stabilization_enabled: bool,
+
+    pub low_light_boost: Option<bool>,
+    pub scene_mode_hint: Option<SceneMode>,
+
+    pub brightness: Option<f32>,
+    pub contrast: Option<f32>,
+    pub saturation: Option<f32>,
+    pub sharpness: Option<f32>,
+    pub hue: Option<f32>,
+    pub noise_reduction: Option<f32>,
+    pub gamma: Option<f32>,
+    pub color_filter: Option<ColorFilter>,
+
+    pub is_updated: bool,
+}
+
+/// Fractions of the exposure duration and ISO ranges of the device.
+#[derive(Debug, Clone, Copy)]
+pub struct CustomExposure {
+    pub duration: f32,
+    pub iso: f32,
+}
+
+impl Default for CustomExposure {
+    fn default() -> Self {
+        Self {
+            iso: 0.0,
+            duration: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExposureMode {
+    Auto,
+    Continuous,
+    Custom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FocusMode {
+    Auto,
+    Continuous,
+    Locked,
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WhiteBalanceMode {
+    Auto,
+    Locked,
+    Custom,
+}
+
+/// Fractions of the largest gain of the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WhiteBalanceGains {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl WhiteBalanceGains {
+    pub fn from(red: f32, green: f32, blue: f32) -> Self {
+        WhiteBalanceGains { red, green, blue }
+    }
+}
+
+impl Default for WhiteBalanceGains {
+    fn default() -> Self {
+        Self {
+            red: 0.0,
+            green: 0.0,
+            blue: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Not used by any platform yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneMode {
+    Standard,
+    Portrait,
+    Night,
+    Action,
+    Backlit,
+    Macro,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFilter {
+    None,
+    Sepia,
+    Mono,
+    Vibrant,
+    Cool,
+    Warm,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            exposure_mode: ExposureMode::Continuous,
+            custom_exposure: None,
+            exposure_compensation: Some(0.0),
+            exposure_stacking: false,
+            focus_mode: FocusMode::Auto,
+            focus_distance: Some(0.5),
+            focus_point_of_interest: Some((0.5, 0.5)),
+            white_balance_mode: WhiteBalanceMode::Auto,
+            white_balance_gains: None,
+            zoom_factor: Some(1.0),
+            frame_rate: Some(30.0),
+            resolution: Some(Resolution {
+                width: 1920,
+                height: 1080,
+            }),
+            hdr_enabled: false,
+            stabilization_enabled: true,
+            low_light_boost: Some(false),
+            scene_mode_hint: Some(SceneMode::Standard),
+            brightness: Some(0.5),
+            contrast: Some(0.5),
+            saturation: Some(0.5),
+            sharpness: None,
+            hue: Some(0.5),
+            noise_reduction: None,
+            gamma: Some(0.5),
+            color_filter: None,
+            is_updated: true,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn set_brightness(&mut self, value: f32) {
+        self.brightness = Some(value.clamp(0.0, 1.0));
+        self.is_updated = true;
+    }
+
+    pub fn set_contrast(&mut self, value: f32) {
+        self.contrast = Some(value.clamp(0.0, 1.0));
+        self.is_updated = true;
+    }
+
+    pub fn set_saturation(&mut self, value: f32) {
+        self.saturation = Some(value.clamp(0.0, 1.0));
+        self.is_updated = true;
+    }
+
+    pub fn set_sharpness(&mut self, value: f32) {
+        let v = value.clamp(0.0, 1.0);
+        self.sharpness = if v < 0.1 { None } else { Some(v) };
+        self.is_updated = true;
+    }
+
+    pub fn set_hue(&mut self, value: f32) {
+        self.hue = Some(value.clamp(0.0, 1.0));
+        self.is_updated = true;
+    }
+
+    pub fn set_noise_reduction(&mut self, value: f32) {
+        let v = value.clamp(0.0, 1.0);
+        self.noise_reduction = if v < 0.1 { None } else { Some(v) };
+        self.is_updated = true;
+    }
+
+    pub fn set_gamma(&mut self, value: f32) {
+        self.gamma = Some(value.clamp(0.0, 1.0));
+        self.is_updated = true;
+    }
+
+    pub fn set_focus_mode(&mut self, mode: FocusMode) {
+        if mode != FocusMode::Manual {
+            self.focus_distance = Some(0.5)
+        };
+        self.focus_mode = mode;
+        self.is_updated = true;
+    }
+
+    pub fn set_focus_distance(&mut self, value: f32) {
+        if self.focus_mode == FocusMode::Manual {
+            self.focus_distance = Some(value.clamp(0.0, 1.0));
+            self.is_updated = true;
+        }
+    }
+
+    pub fn set_exposure_compensation(&mut self, value: f32) {
+        self.exposure_compensation = Some((value.clamp(0.0, 1.0) * 4.0) - 2.0);
+        self.is_updated = true;
+    }
+
+    pub fn set_custom_exposure(&mut self, duration_percentage: f32, iso_percentage: f32) {
+        self.custom_exposure = Some(CustomExposure {
+            duration: duration_percentage.clamp(0.0, 1.0),
+            iso: iso_percentage.clamp(0.0, 1.0),
+        });
+        self.exposure_mode = ExposureMode::Custom;
+        self.is_updated = true;
+    }
+
+    pub fn set_exposure_mode(&mut self, mode: ExposureMode) {
+        if mode != ExposureMode::Custom {
+            self.custom_exposure = None
+        };
+        self.exposure_mode = mode;
+        self.is_updated = true;
+    }
+
+    pub fn set_white_balance_mode(&mut self, mode: WhiteBalanceMode) {
+        if mode != WhiteBalanceMode::Custom {
+            self.white_balance_gains = None
+        };
+        self.white_balance_mode = mode;
+        self.is_updated = true;
+    }
+
+    pub fn set_white_balance_gains_red(&mut self, red: f32) {
+        let g = self.white_balance_gains.unwrap_or_default();
+        self.white_balance_gains =
+            Some(WhiteBalanceGains::from(red.clamp(0.0, 1.0), g.green, g.blue));
+        self.white_balance_mode = WhiteBalanceMode::Custom;
+        self.is_updated = true;
+    }
+
+    pub fn set_white_balance_gains_green(&mut self, green: f32) {
+        let g = self.white_balance_gains.unwrap_or_default();
+        self.white_balance_gains = Some(WhiteBalanceGains::from(
+            g.red,
+            green.clamp(0.0, 1.0),
+            g.blue,
+        ));
+        self.white_balance_mode = WhiteBalanceMode::Custom;
+        self.is_updated = true;
+    }
+
+    pub fn set_white_balance_gains_blue(&mut self, blue: f32) {
+        let g = self.white_balance_gains.unwrap_or_default();
+        self.white_balance_gains = Some(WhiteBalanceGains::from(
+            g.red,
+            g.green,
+            blue.clamp(0.0, 1.0),
+        ));
+        self.white_balance_mode = WhiteBalanceMode::Custom;
+        self.is_updated = true;
+    }
+
+    pub fn set_zoom_factor(&mut self, value: f32) {
+        self.zoom_factor = Some(1.0 + value.clamp(0.0, 1.0) * 9.0);
+        self.is_updated = true;
+    }
+
+    pub fn set_hdr_enabled(&mut self, enabled: bool) {
+        self.hdr_enabled = enabled;
+        self.is_updated = true;
+    }
+
+    pub fn set_stabilization_enabled(&mut self, enabled: bool) {
+        self.stabilization_enabled = enabled;
+        self.is_updated = true;
+    }
+
+    pub fn set_low_light_boost(&mut self, enabled: bool) {
+        self.low_light_boost = Some(enabled);
+        self.is_updated = true;
+    }
+
+    pub fn set_scene_mode(&mut self, scene: SceneMode) {
+        self.scene_mode_hint = Some(scene);
+        self.is_updated = true;
+    }
+
+    pub fn set_focus_point_of_interest(&mut self, x: f32, y: f32) {
+        self.focus_point_of_interest = Some((x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)));
+        self.is_updated = true;
+    }
+
+    pub fn set_frame_rate(&mut self, fps: f32) {
+        self.frame_rate = Some(fps.max(1.0));
+        self.is_updated = true;
+    }
+
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.resolution = Some(Resolution { width, height });
+        self.is_updated = true;
+    }
+
+    pub fn set_color_filter(&mut self, filter: ColorFilter) {
+        self.color_filter = Some(filter);
+        self.is_updated = true;
+    }
+}
+
+/// The lowest and highest value a setting takes on a device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SettingRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// The settings a device applies itself, in the units of [`CameraSettings`]. Settings missing here are ignored,
+/// apart from the colour adjustments done in software.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraCapabilities {
+    pub exposure_modes: Vec<ExposureMode>,
+    pub exposure_compensation: Option<SettingRange>,
+    /// The ISO that [`CustomExposure::iso`] spans.
+    pub iso: Option<SettingRange>,
+    /// The exposure duration in seconds that [`CustomExposure::duration`] spans.
+    pub exposure_duration: Option<SettingRange>,
+    pub focus_modes: Vec<FocusMode>,
+    pub focus_point_of_interest: bool,
+    pub white_balance_modes: Vec<WhiteBalanceMode>,
+    pub zoom: Option<SettingRange>,
+    pub frame_rate: Option<SettingRange>,
+    pub resolutions: Vec<Resolution>,
+    pub hdr: bool,
+    pub stabilization: bool,
+    pub low_light_boost: bool,
+}
@@ -1,596 +1,876 @@
-use image::{Rgba, RgbaImage};
-use jni::objects::JClass;
-use jni::sys::jobject;
-use jni::{
-    objects::{GlobalRef, JByteBuffer, JObject, JObjectArray, JString, JValue},
-    JNIEnv, JavaVM,
+use image::RgbaImage;
+use image::metadata::Orientation;
+use jni::objects::{GlobalRef, JByteBuffer, JClass, JIntArray, JObject, JObjectArray, JString, JValue};
+use jni::{JNIEnv, JavaVM, NativeMethod};
+
+use crate::hardware::{CameraSettings, CameraDevice, CameraFormat, Facing};
+use crate::hardware::camera::{
+    CameraCapabilities, CapturedPhoto, Exposure, ExposureMode, FocusMode, PhotoMetadata, SettingRange, WhiteBalanceMode,
 };
-use ndk_context;
-
-use crate::hardware::{CameraError, CameraSettings};
 
 use std::error::Error;
-use std::thread;
-use std::time::Duration;
-use std::fs;
-use std::path::Path;
+use std::ffi::c_void;
 use std::sync::{Arc, Mutex};
-
-#[derive(Clone, Debug)]
-pub struct OsCamera {
-    java_vm: Arc<JavaVM>,
-    app_context: GlobalRef,
-    camera_manager: GlobalRef,
-    camera_helper_class_loader: Option<GlobalRef>,
-    camera_helper_instance: Option<GlobalRef>,
-    settings: Option<Arc<Mutex<CameraSettings>>>,
+use std::time::{Duration, SystemTime};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Built from the `CameraHelper` class, which opens the device and streams YUV frames into an `ImageReader`.
+static DEX: &[u8] = include_bytes!("android/classes.dex");
+
+/// Built from `android/StillCallback.java`.
+static STILLS: &[u8] = include_bytes!("android/stills.dex");
+
+/// Filled by `StillCallback.onCaptureCompleted` on the helper's background thread, a process has one camera open.
+static SHOTS: Mutex<Vec<Shot>> = Mutex::new(Vec::new());
+
+// Values of `CameraMetadata`.
+const OFF: i32 = 0;
+const ON: i32 = 1;
+const AUTO: i32 = 1;
+const AE_ON: i32 = 1;
+const AE_LOW_LIGHT_BOOST: i32 = 6;
+const AF_AUTO: i32 = 1;
+const AF_CONTINUOUS_VIDEO: i32 = 3;
+const AF_TRIGGER_START: i32 = 1;
+const USE_SCENE_MODE: i32 = 2;
+const SCENE_HDR: i32 = 18;
+
+/// The signature of `CameraCaptureSession.capture` and `setRepeatingRequest`.
+const SUBMIT: &str =
+    "(Landroid/hardware/camera2/CaptureRequest;Landroid/hardware/camera2/CameraCaptureSession$CaptureCallback;Landroid/os/Handler;)I";
+
+/// How a still was exposed, from its capture result.
+#[derive(Debug, Clone, Copy)]
+struct Shot {
+    /// Of the sensor, which the image of the still carries too.
+    timestamp: i64,
+    /// In nanoseconds, `None` when the device does not report it.
+    duration: Option<i64>,
+    iso: Option<i64>,
+    /// In steps of the device's exposure compensation.
+    bias: i64,
 }
 
-impl OsCamera {
-    pub fn new_standard() -> Result<Self, CameraError> {
-        Self::new_internal(false)
-    }
-
-    pub fn new_custom() -> Result<Self, CameraError> {
-        Self::new_internal(true)
+extern "system" fn completed(mut env: JNIEnv, _this: JObject, _session: JObject, _request: JObject, result: JObject) {
+    match shot(&mut env, &result) {
+        Ok(shot) => SHOTS.lock().unwrap().push(shot),
+        Err(e) => log::error!("Could not read a capture result: {e}"),
     }
+}
 
-    fn new_internal(custom: bool) -> Result<Self, CameraError> {
-        let jvm = Arc::new(unsafe { 
-            JavaVM::from_raw(ndk_context::android_context().vm().cast())
-                .map_err(|_| CameraError::InitializationFailed)?
-        });
-
-        let (global_context, global_camera_manager) = {
-            let mut env = jvm.attach_current_thread()
-                .map_err(|_| CameraError::InitializationFailed)?;
+fn shot(env: &mut JNIEnv, result: &JObject) -> Result<Shot> {
+    let mut get = |key: &str| -> Result<Option<i64>> {
+        let key = env.get_static_field(
+            "android/hardware/camera2/CaptureResult",
+            key,
+            "Landroid/hardware/camera2/CaptureResult$Key;",
+        )?.l()?;
+        let value = env.call_method(
+            result,
+            "get",
+            "(Landroid/hardware/camera2/CaptureResult$Key;)Ljava/lang/Object;",
+            &[JValue::Object(&key)],
+        )?.l()?;
+        if value.is_null() {return Ok(None);}
+        Ok(Some(env.call_method(&value, "longValue", "()J", &[])?.j()?))
+    };
+    Ok(Shot{
+        timestamp: get("SENSOR_TIMESTAMP")?.ok_or("Capture result without a timestamp")?,
+        duration: get("SENSOR_EXPOSURE_TIME")?,
+        iso: get("SENSOR_SENSITIVITY")?,
+        bias: get("CONTROL_AE_EXPOSURE_COMPENSATION")?.unwrap_or(0),
+    })
+}
 
-            let ctx_ptr = ndk_context::android_context().context();
-            if ctx_ptr.is_null() {
-                return Err(CameraError::InitializationFailed);
-            }
+/// Where the camera is between `start` and `stop`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed,
+    /// The permission was asked for and the user has not answered yet.
+    Asking,
+    Open,
+    /// Opening failed, it is not tried again until the camera is stopped.
+    Failed,
+}
 
-            let context_obj = unsafe { JObject::from_raw(ctx_ptr as jobject) };
-            let global_context = env.new_global_ref(context_obj)
-                .map_err(|_| CameraError::InitializationFailed)?;
-            let global_camera_manager = Self::initialize_camera_manager_static(&mut env, &global_context)
-                .map_err(|_| CameraError::InitializationFailed)?;
+/// The JNI handles of the camera, `None` in `OsCamera` when the Java VM could not be reached.
+#[derive(Debug)]
+struct Java {
+    vm: Arc<JavaVM>,
+    context: GlobalRef,
+    manager: GlobalRef,
+    /// Loaded on the first start.
+    helper: Option<GlobalRef>,
+    /// A `StillCallback`, loaded on the first photo.
+    callback: Option<GlobalRef>,
+}
 
-            (global_context, global_camera_manager)
-        };
+/// The JPEG output of photos, added to the helper's capture session at the largest size the device offers.
+#[derive(Debug)]
+struct Stills {
+    reader: GlobalRef,
+    /// The helper's session without the reader, until the session with it is configured.
+    replaced: Option<GlobalRef>,
+    /// Of the exposure compensation, in EV.
+    step: f32,
+    orientation: Orientation,
+    /// Decoded photos waiting for their capture result, by timestamp.
+    images: Vec<(i64, RgbaImage)>,
+}
 
-        let settings = if custom {
-            Some(Arc::new(Mutex::new(CameraSettings::default())))
-        } else {
-            None
-        };
+/// Streams from a camera2 device through the `CameraHelper` of the embedded dex. Nothing blocks: the
+/// permission is asked for on start and the device opens once it is granted, frames arrive once the capture
+/// session is configured. Photos are full resolution JPEG stills with the exposure of their capture result.
+#[derive(Debug)]
+pub struct OsCamera {
+    java: Option<Java>,
+    state: State,
+    /// The camera id to open, the first one when `None`.
+    device: Option<String>,
+    /// The last settings asked for.
+    settings: Option<CameraSettings>,
+    /// Whether the capture session streams with `settings`.
+    applied: bool,
+    /// Photos asked for and not yet requested from the device.
+    requested: usize,
+    /// Set up on the first photo after the camera opens.
+    stills: Option<Stills>,
+}
 
-        let mut camera = Self {
-            java_vm: jvm.clone(),
-            app_context: global_context,
-            camera_manager: global_camera_manager,
-            camera_helper_class_loader: None,
-            camera_helper_instance: None,
-            settings,
-        };
-        
-        camera.start().map_err(|_| CameraError::InitializationFailed)?;
-        
-        Ok(camera)
+impl OsCamera {
+    pub fn new() -> Self {
+        let java = Self::connect().map_err(|e| log::error!("Camera unavailable: {e}")).ok();
+        OsCamera{java, state: State::Closed, device: None, settings: None, applied: false, requested: 0, stills: None}
     }
 
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let jvm = Arc::new(unsafe { JavaVM::from_raw(ndk_context::android_context().vm().cast())? });
-
-        let (global_context, global_camera_manager) = {
-            let mut env = jvm.attach_current_thread()?;
-
-            let ctx_ptr = ndk_context::android_context().context();
-            if ctx_ptr.is_null() {
-                return Err("Failed to get Android context".into());
-            }
-
-            let context_obj = unsafe { JObject::from_raw(ctx_ptr as jobject) };
-            let global_context = env.new_global_ref(context_obj)?;
-            let global_camera_manager =
-                Self::initialize_camera_manager_static(&mut env, &global_context)?;
-
-            (global_context, global_camera_manager)
+    fn connect() -> Result<Java> {
+        let vm = Arc::new(unsafe {JavaVM::from_raw(ndk_context::android_context().vm().cast())?});
+        let (context, manager) = {
+            let mut env = vm.attach_current_thread()?;
+            let context = ndk_context::android_context().context();
+            if context.is_null() {return Err("No Android context".into());}
+            let context = env.new_global_ref(unsafe {JObject::from_raw(context.cast())})?;
+            let service = env.get_static_field("android/content/Context", "CAMERA_SERVICE", "Ljava/lang/String;")?.l()?;
+            let manager = env.call_method(
+                context.as_obj(), "getSystemService", "(Ljava/lang/String;)Ljava/lang/Object;", &[JValue::Object(&service)],
+            )?.l()?;
+            (context, env.new_global_ref(manager)?)
         };
-
-        let mut camera = Self {
-            java_vm: jvm.clone(),
-            app_context: global_context,
-            camera_manager: global_camera_manager,
-            camera_helper_class_loader: None,
-            camera_helper_instance: None,
-            settings: None,
-        };
-        
-        camera.start().expect("Failed to start camera");
-        
-        Ok(camera)
-    }
-
-    // Add settings method
-    pub fn settings(&mut self) -> Option<Arc<Mutex<CameraSettings>>> {
-        self.settings.clone()
+        Ok(Java{vm, context, manager, helper: None, callback: None})
     }
 
-    fn initialize_camera_manager_static(
-        env: &mut JNIEnv,
-        context: &GlobalRef,
-    ) -> Result<GlobalRef, Box<dyn Error>> {
-        let camera_service = env
-            .get_static_field("android/content/Context", "CAMERA_SERVICE", "Ljava/lang/String;")?
-            .l()?;
-
-        let manager = env
-            .call_method(
-                context.as_obj(),
-                "getSystemService",
-                "(Ljava/lang/String;)Ljava/lang/Object;",
-                &[JValue::Object(&camera_service)],
-            )?
-            .l()?;
-
-        Ok(env.new_global_ref(manager)?)
-    }
-
-    fn get_embedded_dex_bytes(&self) -> &'static [u8] {
-        static DEX_BYTES: &[u8] = include_bytes!(".././camera/android/classes.dex");
-        println!("Using embedded dex bytes: {} bytes", DEX_BYTES.len());
-        DEX_BYTES
+    /// Opens the device once the permission is granted, asking for it the first time.
+    pub fn start(&mut self) {
+        if matches!(self.state, State::Open | State::Failed) {return;}
+        match self.open() {
+            Ok(true) => self.state = State::Open,
+            Ok(false) => self.state = State::Asking,
+            Err(e) => {
+                log::error!("Could not open the camera: {e}");
+                self.state = State::Failed;
+            }
+        }
     }
 
-    // Alternative: Load from runtime file path (if you still need this option)
-    fn load_dex_from_file(&self, dex_file_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
-        println!("Loading dex file from: {}", dex_file_path);
+    /// Returns false while waiting for the permission.
+    fn open(&mut self) -> Result<bool> {
+        let java = self.java.as_mut().ok_or("Camera unavailable")?;
+        let vm = java.vm.clone();
+        let mut env = vm.attach_current_thread()?;
+        let helper = match java.helper.clone() {
+            Some(helper) => helper,
+            None => {
+                let class = Self::class(&mut env, &java.context, DEX, "com.orangeme.camera.CameraHelper")?;
+                let helper = env.new_object(&class, "(Landroid/content/Context;)V", &[JValue::Object(java.context.as_obj())])?;
+                java.helper.insert(env.new_global_ref(helper)?).clone()
+            },
+        };
 
-        if !Path::new(dex_file_path).exists() {
-            return Err(format!("Dex file not found: {}", dex_file_path).into());
+        if !env.call_method(helper.as_obj(), "hasCameraPermission", "()Z", &[])?.z()? {
+            return match self.state {
+                State::Asking if !env.call_method(helper.as_obj(), "isWaitingForPermission", "()Z", &[])?.z()? => {
+                    Err("Camera permission denied".into())
+                },
+                State::Asking => Ok(false),
+                _ => {
+                    env.call_method(helper.as_obj(), "requestCameraPermission", "()V", &[])?;
+                    Ok(false)
+                }
+            };
         }
 
-        let dex_bytes = fs::read(dex_file_path)?;
-        println!("Successfully loaded {} bytes from dex file", dex_bytes.len());
-
-        Ok(dex_bytes)
+        let id = match &self.device {
+            Some(id) => env.new_string(id)?,
+            None => {
+                let ids = JObjectArray::from(env.call_method(helper.as_obj(), "getCameraIdList", "()[Ljava/lang/String;", &[])?.l()?);
+                if env.get_array_length(&ids)? == 0 {return Err("No cameras available".into());}
+                JString::from(env.get_object_array_element(&ids, 0)?)
+            }
+        };
+        env.call_method(helper.as_obj(), "openCamera", "(Ljava/lang/String;)V", &[JValue::Object(&id)])?;
+        Ok(true)
     }
 
-    unsafe fn dex_loader_from_bytes(&mut self, dex_bytes: &[u8]) -> Result<(), Box<dyn Error>> {
-        let mut env = self.java_vm.attach_current_thread()?;
-        println!("Starting dex_loader_from_bytes with {} bytes", dex_bytes.len());
-
-        let byte_buffer = env.new_direct_byte_buffer(dex_bytes.as_ptr() as *mut u8, dex_bytes.len())?;
-        println!("Created direct ByteBuffer: {:?}", byte_buffer);
-
-        let context_class = env.get_object_class(&self.app_context)?;
-        let get_class_loader_method = env.get_method_id(context_class, "getClassLoader", "()Ljava/lang/ClassLoader;")?;
-        let parent_class_loader = env.call_method_unchecked(
-            &self.app_context,
-            get_class_loader_method,
-            jni::signature::ReturnType::Object,
-            &[],
-        )?.l()?;
-        println!("Parent class loader obtained: {:?}", parent_class_loader);
-
-        let in_memory_dex_class_loader_class = env.find_class("dalvik/system/InMemoryDexClassLoader")?;
-        println!("InMemoryDexClassLoader class found: {:?}", in_memory_dex_class_loader_class);
-
-        let constructor_id = env.get_method_id(
-            &in_memory_dex_class_loader_class,
-            "<init>",
+    /// Loads the class `name` from an embedded dex.
+    fn class<'a>(env: &mut JNIEnv<'a>, context: &GlobalRef, dex: &'static [u8], name: &str) -> Result<JClass<'a>> {
+        let buffer = unsafe {env.new_direct_byte_buffer(dex.as_ptr() as *mut u8, dex.len())?};
+        let parent = env.call_method(context.as_obj(), "getClassLoader", "()Ljava/lang/ClassLoader;", &[])?.l()?;
+        let loader = env.new_object(
+            "dalvik/system/InMemoryDexClassLoader",
             "(Ljava/nio/ByteBuffer;Ljava/lang/ClassLoader;)V",
+            &[JValue::Object(&buffer), JValue::Object(&parent)],
         )?;
-        println!("InMemoryDexClassLoader constructor ID retrieved: {:?}", constructor_id);
-
-        let dex_class_loader_obj = env.new_object_unchecked(
-            in_memory_dex_class_loader_class,
-            constructor_id,
-            &[
-                JValue::Object(&byte_buffer).as_jni(),
-                JValue::Object(&parent_class_loader).as_jni(),
-            ],
-        )?;
-        println!("InMemoryDexClassLoader instantiated: {:?}", dex_class_loader_obj);
-
-        self.camera_helper_class_loader = Some(env.new_global_ref(dex_class_loader_obj)?);
-
-        let thread = env.call_static_method("java/lang/Thread", "currentThread", "()Ljava/lang/Thread;", &[])?.l()?;
-        env.call_method(
-            thread,
-            "setContextClassLoader",
-            "(Ljava/lang/ClassLoader;)V",
-            &[JValue::Object(self.camera_helper_class_loader.as_ref().unwrap().as_obj())],
-        )?;
-        println!("Context class loader set.");
-
-        let class_name = env.new_string("com.orangeme.camera.CameraHelper")?;
-        let camera_helper_class = env.call_method(
-            self.camera_helper_class_loader.as_ref().unwrap().as_obj(),
-            "loadClass",
-            "(Ljava/lang/String;)Ljava/lang/Class;",
-            &[JValue::Object(&class_name)],
-        )?.l()?;
-        println!("CameraHelper class loaded: {:?}", camera_helper_class);
-
-        let camera_helper_class_jclass = JClass::from(camera_helper_class);
-        let camera_helper_constructor = env.get_method_id(
-            &camera_helper_class_jclass,
-            "<init>",
-            "(Landroid/content/Context;)V",
-        )?;
-
-        let camera_helper_obj = env.new_object_unchecked(
-            camera_helper_class_jclass,
-            camera_helper_constructor,
-            &[JValue::Object(&self.app_context).as_jni()],
-        )?;
-        println!("CameraHelper instance created: {:?}", camera_helper_obj);
+        let name = env.new_string(name)?;
+        Ok(JClass::from(env.call_method(&loader, "loadClass", "(Ljava/lang/String;)Ljava/lang/Class;", &[JValue::Object(&name)])?.l()?))
+    }
 
-        self.camera_helper_instance = Some(env.new_global_ref(camera_helper_obj)?);
+    pub fn stop(&mut self) {
+        if self.state == State::Open && let Err(e) = self.close() {log::error!("Could not close the camera: {e}");}
+        self.state = State::Closed;
+        self.applied = false;
+        self.requested = 0;
+        self.stills = None;
+        SHOTS.lock().unwrap().clear();
+    }
 
+    fn close(&self) -> Result<()> {
+        let Some(Java{vm, helper: Some(helper), ..}) = &self.java else {return Ok(());};
+        let mut env = vm.attach_current_thread()?;
+        env.call_method(helper.as_obj(), "closeCamera", "()V", &[])?;
+        if let Some(stills) = &self.stills {env.call_method(stills.reader.as_obj(), "close", "()V", &[])?;}
         Ok(())
     }
 
-    unsafe fn load_embedded_dex(&mut self) -> Result<(), Box<dyn Error>> {
-        println!("Loading embedded dex bytes");
-        let dex_bytes = self.get_embedded_dex_bytes();
-        self.dex_loader_from_bytes(dex_bytes)
+    /// The newest frame, once the capture session is configured.
+    pub fn frame(&mut self) -> Option<RgbaImage> {
+        if self.state != State::Open {return None;}
+        self.latest().unwrap_or_else(|e| {log::error!("Could not read a camera frame: {e}"); None})
     }
 
-    fn has_camera_permission(&self) -> Result<bool, Box<dyn Error>> {
-        let mut env = self.java_vm.attach_current_thread()?;
-        let camera_helper = self.camera_helper_instance.as_ref().ok_or("CameraHelper not initialized")?;
-
-        let has_permission = env.call_method(
-            camera_helper.as_obj(),
-            "hasCameraPermission",
-            "()Z",
-            &[],
-        )?.z()?;
-
-        Ok(has_permission)
+    fn latest(&mut self) -> Result<Option<RgbaImage>> {
+        let Some(Java{vm, helper: Some(helper), ..}) = &self.java else {return Ok(None);};
+        let mut env = vm.attach_current_thread()?;
+        if !env.call_method(helper.as_obj(), "isSessionReady", "()Z", &[])?.z()? {return Ok(None);}
+        if !self.applied && let Some(settings) = &self.settings {self.applied = self.request(settings)?;}
+        let image = env.call_method(helper.as_obj(), "acquireLatestImage", "()Landroid/media/Image;", &[])?.l()?;
+        if image.is_null() {return Ok(None);}
+        let frame = rgba(&mut env, &image);
+        env.call_method(&image, "close", "()V", &[])?;
+        frame.map(Some)
     }
 
-    fn request_camera_permission(&self) -> Result<(), Box<dyn Error>> {
-        let mut env = self.java_vm.attach_current_thread()?;
-        let camera_helper = self.camera_helper_instance.as_ref().ok_or("CameraHelper not initialized")?;
-
-        env.call_method(
-            camera_helper.as_obj(),
-            "requestCameraPermission",
-            "()V",
-            &[],
-        )?;
-
-        Ok(())
+    pub fn devices(&self) -> Vec<CameraDevice> {
+        self.devices_impl().unwrap_or_else(|e| {
+            log::error!("Failed to list cameras: {e}");
+            Vec::new()
+        })
     }
 
-    fn is_waiting_for_permission(&self) -> Result<bool, Box<dyn Error>> {
-        let mut env = self.java_vm.attach_current_thread()?;
-        let camera_helper = self.camera_helper_instance.as_ref().ok_or("CameraHelper not initialized")?;
-
-        let waiting = env.call_method(
-            camera_helper.as_obj(),
-            "isWaitingForPermission",
-            "()Z",
+    fn devices_impl(&self) -> Result<Vec<CameraDevice>> {
+        let Some(java) = &self.java else {return Ok(Vec::new());};
+        let mut env = java.vm.attach_current_thread()?;
+        let ids = JObjectArray::from(env.call_method(
+            java.manager.as_obj(),
+            "getCameraIdList",
+            "()[Ljava/lang/String;",
             &[],
-        )?.z()?;
+        )?.l()?);
 
-        Ok(waiting)
+        let mut devices = Vec::new();
+        for i in 0..env.get_array_length(&ids)? {
+            let id = JString::from(env.get_object_array_element(&ids, i)?);
+            devices.push(Self::describe(&mut env, &java.manager, &id)?);
+        }
+        Ok(devices)
     }
 
-    fn wait_for_permission(&self, timeout_seconds: u64) -> Result<bool, Box<dyn Error>> {
-        println!("Waiting for camera permission (timeout: {}s)", timeout_seconds);
+    /// The device `id` as the manager reports it.
+    fn describe(env: &mut JNIEnv, manager: &GlobalRef, id_obj: &JString) -> Result<CameraDevice> {
+        let id: String = env.get_string(id_obj)?.into();
+        let yuv = env.get_static_field("android/graphics/ImageFormat", "YUV_420_888", "I")?.i()?;
+        let characteristics = Self::characteristics(env, manager, id_obj)?;
+
+        let facing = Self::characteristic(env, &characteristics, "LENS_FACING")?;
+        let facing = match env.call_method(&facing, "intValue", "()I", &[])?.i()? {
+            0 => Facing::Front,
+            1 => Facing::Back,
+            _ => Facing::External,
+        };
 
-        let start_time = std::time::Instant::now();
-        let timeout = Duration::from_secs(timeout_seconds);
+        let ranges = JObjectArray::from(Self::characteristic(env, &characteristics, "CONTROL_AE_AVAILABLE_TARGET_FPS_RANGES")?);
+        let (mut min_fps, mut max_fps) = (f32::INFINITY, 0.0f32);
+        for r in 0..env.get_array_length(&ranges)? {
+            let range = env.get_object_array_element(&ranges, r)?;
+            min_fps = min_fps.min(Self::bound(env, &range, "getLower")? as f32);
+            max_fps = max_fps.max(Self::bound(env, &range, "getUpper")? as f32);
+        }
 
-        // First check if we already have permission
-        if self.has_camera_permission()? {
-            println!("Camera permission already granted");
-            return Ok(true);
+        let map = Self::characteristic(env, &characteristics, "SCALER_STREAM_CONFIGURATION_MAP")?;
+        let sizes = JObjectArray::from(env.call_method(&map, "getOutputSizes", "(I)[Landroid/util/Size;", &[JValue::Int(yuv)])?.l()?);
+        let mut formats = Vec::new();
+        for s in 0..env.get_array_length(&sizes)? {
+            let size = env.get_object_array_element(&sizes, s)?;
+            let width = env.call_method(&size, "getWidth", "()I", &[])?.i()? as u32;
+            let height = env.call_method(&size, "getHeight", "()I", &[])?.i()? as u32;
+            let duration = env.call_method(
+                &map,
+                "getOutputMinFrameDuration",
+                "(ILandroid/util/Size;)J",
+                &[JValue::Int(yuv), JValue::Object(&size)],
+            )?.j()?;
+            let limit = if duration > 0 {1_000_000_000.0 / duration as f32} else {max_fps};
+            formats.push(CameraFormat{width, height, min_fps: min_fps.min(limit), max_fps: max_fps.min(limit)});
         }
 
-        // Request permission if we don't have it
-        self.request_camera_permission()?;
+        let mut capabilities = Self::capabilities_of(env, &characteristics)?;
+        capabilities.frame_rate = (max_fps > 0.0).then_some(SettingRange{min: min_fps, max: max_fps});
 
-        // Wait for permission to be granted or denied
-        while start_time.elapsed() < timeout {
-            if self.has_camera_permission()? {
-                println!("Camera permission granted!");
-                return Ok(true);
-            }
+        let name = match facing {
+            Facing::Front => format!("Front camera {}", id),
+            Facing::Back => format!("Back camera {}", id),
+            Facing::External => format!("External camera {}", id),
+        };
+        Ok(CameraDevice{id, name, facing, formats, capabilities})
+    }
 
-            if !self.is_waiting_for_permission()? {
-                println!("No longer waiting for permission - likely denied");
-                return Ok(false);
-            }
+    /// The helper's `ImageReader` has a fixed size, so no resolutions are offered.
+    fn capabilities_of(env: &mut JNIEnv, characteristics: &JObject) -> Result<CameraCapabilities> {
+        let ae = Self::ints(env, characteristics, "CONTROL_AE_AVAILABLE_MODES")?;
+        let af = Self::ints(env, characteristics, "CONTROL_AF_AVAILABLE_MODES")?;
+        let awb = Self::ints(env, characteristics, "CONTROL_AWB_AVAILABLE_MODES")?;
+        let scenes = Self::ints(env, characteristics, "CONTROL_AVAILABLE_SCENE_MODES")?;
+        let stabilization = Self::ints(env, characteristics, "CONTROL_AVAILABLE_VIDEO_STABILIZATION_MODES")?;
+        let optical = Self::ints(env, characteristics, "LENS_INFO_AVAILABLE_OPTICAL_STABILIZATION")?;
+
+        let mut capabilities = CameraCapabilities{
+            exposure_modes: [(ExposureMode::Continuous, AE_ON), (ExposureMode::Custom, OFF)]
+                .into_iter().filter(|(_, m)| ae.contains(m)).map(|(mode, _)| mode).collect(),
+            focus_modes: [
+                (FocusMode::Auto, AF_AUTO),
+                (FocusMode::Continuous, AF_CONTINUOUS_VIDEO),
+                (FocusMode::Locked, AF_AUTO),
+            ].into_iter().filter(|(_, m)| af.contains(m)).map(|(mode, _)| mode).collect(),
+            focus_point_of_interest: Self::number(env, characteristics, "CONTROL_MAX_REGIONS_AF")?.is_some_and(|n| n > 0.0),
+            white_balance_modes: [(WhiteBalanceMode::Auto, AUTO)]
+                .into_iter().filter(|(_, m)| awb.contains(m)).map(|(mode, _)| mode).collect(),
+            zoom: Self::number(env, characteristics, "SCALER_AVAILABLE_MAX_DIGITAL_ZOOM")?
+                .map(|max| SettingRange{min: 1.0, max: max as f32}),
+            hdr: scenes.contains(&SCENE_HDR),
+            stabilization: stabilization.contains(&ON) || optical.contains(&ON),
+            low_light_boost: ae.contains(&AE_LOW_LIGHT_BOOST),
+            ..Default::default()
+        };
 
-            println!("Still waiting for camera permission...");
-            thread::sleep(Duration::from_millis(500));
+        if Self::number(env, characteristics, "LENS_INFO_MINIMUM_FOCUS_DISTANCE")?.is_some_and(|d| d > 0.0) && af.contains(&OFF) {
+            capabilities.focus_modes.push(FocusMode::Manual);
         }
-
-        println!("Timeout waiting for camera permission");
-        Ok(false)
+        if Self::flag(env, characteristics, "CONTROL_AWB_LOCK_AVAILABLE")? {
+            capabilities.white_balance_modes.push(WhiteBalanceMode::Locked);
+        }
+        if let (Some(range), Some(step)) = (
+            Self::optional(env, characteristics, "CONTROL_AE_COMPENSATION_RANGE")?,
+            Self::number(env, characteristics, "CONTROL_AE_COMPENSATION_STEP")?,
+        ) {
+            let (min, max) = (Self::bound(env, &range, "getLower")?, Self::bound(env, &range, "getUpper")?);
+            if max > min {capabilities.exposure_compensation = Some(SettingRange{min: (min * step) as f32, max: (max * step) as f32});}
+        }
+        if capabilities.exposure_modes.contains(&ExposureMode::Custom) && let (Some(iso), Some(duration)) = (
+            Self::optional(env, characteristics, "SENSOR_INFO_SENSITIVITY_RANGE")?,
+            Self::optional(env, characteristics, "SENSOR_INFO_EXPOSURE_TIME_RANGE")?,
+        ) {
+            capabilities.iso = Some(SettingRange{
+                min: Self::bound(env, &iso, "getLower")? as f32,
+                max: Self::bound(env, &iso, "getUpper")? as f32,
+            });
+            capabilities.exposure_duration = Some(SettingRange{
+                min: (Self::bound(env, &duration, "getLower")? / 1e9) as f32,
+                max: (Self::bound(env, &duration, "getUpper")? / 1e9) as f32,
+            });
+        }
+        Ok(capabilities)
     }
 
-    fn open_camera_with_dex_file(&mut self, dex_file_path: &str) -> Result<(), Box<dyn Error>> {
-        println!("Opening camera with dex file: {}", dex_file_path);
+    fn characteristics<'a>(env: &mut JNIEnv<'a>, manager: &GlobalRef, id: &JString) -> Result<JObject<'a>> {
+        Ok(env.call_method(
+            manager.as_obj(),
+            "getCameraCharacteristics",
+            "(Ljava/lang/String;)Landroid/hardware/camera2/CameraCharacteristics;",
+            &[JValue::Object(id)],
+        )?.l()?)
+    }
 
-        let dex_bytes = self.load_dex_from_file(dex_file_path)?;
-        unsafe {
-            self.dex_loader_from_bytes(&dex_bytes)?;
-        }
+    fn characteristic<'a>(env: &mut JNIEnv<'a>, characteristics: &JObject, key: &str) -> Result<JObject<'a>> {
+        Self::optional(env, characteristics, key)?.ok_or_else(|| "Camera characteristic unavailable".into())
+    }
 
-        self.open_camera_internal()
+    /// `None` when the device does not report `key`, or the platform is too old to know it.
+    fn optional<'a>(env: &mut JNIEnv<'a>, characteristics: &JObject, key: &str) -> Result<Option<JObject<'a>>> {
+        let key = match env.get_static_field(
+            "android/hardware/camera2/CameraCharacteristics",
+            key,
+            "Landroid/hardware/camera2/CameraCharacteristics$Key;",
+        ) {
+            Ok(key) => key.l()?,
+            Err(jni::errors::Error::JavaException) => {
+                env.exception_clear()?;
+                return Ok(None);
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let value = env.call_method(
+            characteristics,
+            "get",
+            "(Landroid/hardware/camera2/CameraCharacteristics$Key;)Ljava/lang/Object;",
+            &[JValue::Object(&key)],
+        )?.l()?;
+        Ok((!value.is_null()).then_some(value))
     }
 
-    pub fn start(&mut self) -> Result<(), Box<dyn Error>> {
-        println!("Opening camera with embedded dex");
+    /// An `int[]` characteristic, empty when it is not reported.
+    fn ints(env: &mut JNIEnv, characteristics: &JObject, key: &str) -> Result<Vec<i32>> {
+        let Some(array) = Self::optional(env, characteristics, key)? else {return Ok(Vec::new());};
+        let array = JIntArray::from(array);
+        let mut values = vec![0; env.get_array_length(&array)? as usize];
+        env.get_int_array_region(&array, 0, &mut values)?;
+        Ok(values)
+    }
 
-        unsafe {
-            self.load_embedded_dex()?;
-        }
+    /// A characteristic that is a `Number`, such as an `Integer`, `Float` or `Rational`.
+    fn number(env: &mut JNIEnv, characteristics: &JObject, key: &str) -> Result<Option<f64>> {
+        let Some(value) = Self::optional(env, characteristics, key)? else {return Ok(None);};
+        Ok(Some(env.call_method(&value, "doubleValue", "()D", &[])?.d()?))
+    }
 
-        self.open_camera_internal()
+    fn flag(env: &mut JNIEnv, characteristics: &JObject, key: &str) -> Result<bool> {
+        let Some(value) = Self::optional(env, characteristics, key)? else {return Ok(false);};
+        Ok(env.call_method(&value, "booleanValue", "()Z", &[])?.z()?)
     }
 
-    fn open_camera_internal(&mut self) -> Result<(), Box<dyn Error>> {
-        println!("Checking camera permission before opening camera");
+    /// Reads a bound of a `Range` of numbers.
+    fn bound(env: &mut JNIEnv, range: &JObject, method: &str) -> Result<f64> {
+        let value = env.call_method(range, method, "()Ljava/lang/Comparable;", &[])?.l()?;
+        Ok(env.call_method(&value, "doubleValue", "()D", &[])?.d()?)
+    }
 
-        if !self.has_camera_permission()? {
-            println!("Camera permission not granted, requesting...");
-            if !self.wait_for_permission(30)? {
-                return Err("Camera permission not granted within timeout".into());
-            }
-        }
+    pub fn capabilities(&self) -> CameraCapabilities {
+        self.current().map(|device| device.capabilities).unwrap_or_default()
+    }
 
-        let mut env = self.java_vm.attach_current_thread()?;
-        let camera_helper = self.camera_helper_instance.as_ref().ok_or("CameraHelper not initialized")?;
+    /// Takes effect once the capture session is configured, as the repeating request of the session.
+    pub fn apply(&mut self, settings: &CameraSettings) {
+        self.settings = Some(settings.clone());
+        self.applied = false;
+    }
 
-        let camera_id_list_obj = env.call_method(
-            camera_helper.as_obj(),
-            "getCameraIdList",
-            "()[Ljava/lang/String;",
-            &[],
+    /// Replaces the helper's repeating request with one carrying `settings`, false while there is no session.
+    fn request(&self, settings: &CameraSettings) -> Result<bool> {
+        let Some(Java{vm, manager, helper: Some(helper), ..}) = &self.java else {return Ok(false);};
+        let mut env = vm.attach_current_thread()?;
+        let device = env.call_method(helper.as_obj(), "getCameraDevice", "()Landroid/hardware/camera2/CameraDevice;", &[])?.l()?;
+        let session = env.call_method(
+            helper.as_obj(), "getCaptureSession", "()Landroid/hardware/camera2/CameraCaptureSession;", &[],
         )?.l()?;
-
-        let camera_id_array = JObjectArray::from(camera_id_list_obj);
-        let length = env.get_array_length(&camera_id_array)?;
-        if length == 0 {
-            return Err("No cameras available".into());
+        let reader = env.call_method(helper.as_obj(), "getImageReader", "()Landroid/media/ImageReader;", &[])?.l()?;
+        if device.is_null() || session.is_null() || reader.is_null() {return Ok(false);}
+
+        let id = JString::from(env.call_method(&device, "getId", "()Ljava/lang/String;", &[])?.l()?);
+        let characteristics = Self::characteristics(&mut env, manager, &id)?;
+        let capabilities = Self::describe(&mut env, manager, &id)?.capabilities;
+        let surface = env.call_method(&reader, "getSurface", "()Landroid/view/Surface;", &[])?.l()?;
+        let builder = Self::build(&mut env, &device, &characteristics, &capabilities, "TEMPLATE_PREVIEW", &surface, settings)?;
+
+        let repeating = env.call_method(&builder, "build", "()Landroid/hardware/camera2/CaptureRequest;", &[])?.l()?;
+        env.call_method(&session, "setRepeatingRequest", SUBMIT, &[
+            JValue::Object(&repeating), JValue::Object(&JObject::null()), JValue::Object(&JObject::null()),
+        ])?;
+        if capabilities.focus_modes.contains(&settings.focus_mode) && settings.focus_mode == FocusMode::Auto {
+            // Focuses once, the repeating request then keeps the lens where it stopped.
+            set(&mut env, &builder, "CONTROL_AF_TRIGGER", JValue::Int(AF_TRIGGER_START))?;
+            let once = env.call_method(&builder, "build", "()Landroid/hardware/camera2/CaptureRequest;", &[])?.l()?;
+            env.call_method(&session, "capture", SUBMIT, &[
+                JValue::Object(&once), JValue::Object(&JObject::null()), JValue::Object(&JObject::null()),
+            ])?;
         }
-
-        let first_camera_id = env.get_object_array_element(&camera_id_array, 0)?;
-        let camera_id_str: String = env.get_string(&JString::from(first_camera_id))?.into();
-        let camera_id_jstr = env.new_string(&camera_id_str)?;
-
-        println!("Opening camera with ID: {}", camera_id_str);
-
-        env.call_method(
-            camera_helper.as_obj(),
-            "openCamera",
-            "(Ljava/lang/String;)V",
-            &[JValue::Object(&camera_id_jstr)],
-        )?;
-
-        println!("Camera open request sent successfully");
-        Ok(())
+        Ok(true)
     }
 
-    fn wait_for_camera_ready(&self, timeout_seconds: u64) -> Result<bool, Box<dyn Error>> {
-        println!("Waiting for camera session to be ready (timeout: {}s)", timeout_seconds);
+    /// A `CaptureRequest.Builder` of `template` into `surface` with what `settings` asks for and the device supports.
+    fn build<'a>(
+        env: &mut JNIEnv<'a>,
+        device: &JObject,
+        characteristics: &JObject,
+        capabilities: &CameraCapabilities,
+        template: &str,
+        surface: &JObject,
+        settings: &CameraSettings,
+    ) -> Result<JObject<'a>> {
+        let template = env.get_static_field("android/hardware/camera2/CameraDevice", template, "I")?.i()?;
+        let builder = env.call_method(
+            device, "createCaptureRequest", "(I)Landroid/hardware/camera2/CaptureRequest$Builder;", &[JValue::Int(template)],
+        )?.l()?;
+        env.call_method(&builder, "addTarget", "(Landroid/view/Surface;)V", &[JValue::Object(surface)])?;
+
+        let boost = settings.low_light_boost == Some(true) && capabilities.low_light_boost;
+        if capabilities.exposure_modes.contains(&settings.exposure_mode) {
+            match settings.exposure_mode {
+                ExposureMode::Custom => if let (Some(custom), Some(iso), Some(duration)) =
+                    (settings.custom_exposure, capabilities.iso, capabilities.exposure_duration) {
+                    let seconds = duration.min + (duration.max - duration.min) * custom.duration.clamp(0.0, 1.0);
+                    let iso = iso.min + (iso.max - iso.min) * custom.iso.clamp(0.0, 1.0);
+                    set(env, &builder, "CONTROL_AE_MODE", JValue::Int(OFF))?;
+                    set(env, &builder, "SENSOR_EXPOSURE_TIME", JValue::Long((seconds as f64 * 1e9) as i64))?;
+                    set(env, &builder, "SENSOR_SENSITIVITY", JValue::Int(iso as i32))?;
+                },
+                _ => set(env, &builder, "CONTROL_AE_MODE", JValue::Int(if boost {AE_LOW_LIGHT_BOOST} else {AE_ON}))?,
+            }
+        }
+        if let (Some(bias), Some(range), Some(step)) = (
+            settings.exposure_compensation,
+            capabilities.exposure_compensation,
+            Self::number(env, characteristics, "CONTROL_AE_COMPENSATION_STEP")?,
+        ) {
+            let steps = (bias.clamp(range.min, range.max) as f64 / step).round() as i32;
+            set(env, &builder, "CONTROL_AE_EXPOSURE_COMPENSATION", JValue::Int(steps))?;
+        }
 
-        let start_time = std::time::Instant::now();
-        let timeout = Duration::from_secs(timeout_seconds);
+        if capabilities.focus_point_of_interest && let Some((x, y)) = settings.focus_point_of_interest
+            && let Some(area) = Self::optional(env, characteristics, "SENSOR_INFO_ACTIVE_ARRAY_SIZE")? {
+            let width = env.call_method(&area, "width", "()I", &[])?.i()?;
+            let height = env.call_method(&area, "height", "()I", &[])?.i()?;
+            // A tenth of the sensor around the point, at the largest weight.
+            let (w, h) = (width / 10, height / 10);
+            let left = ((x * width as f32) as i32 - w / 2).clamp(0, width - w);
+            let top = ((y * height as f32) as i32 - h / 2).clamp(0, height - h);
+            let region = env.new_object(
+                "android/hardware/camera2/params/MeteringRectangle",
+                "(IIIII)V",
+                &[JValue::Int(left), JValue::Int(top), JValue::Int(w), JValue::Int(h), JValue::Int(1000)],
+            )?;
+            let regions = env.new_object_array(1, "android/hardware/camera2/params/MeteringRectangle", &region)?;
+            set(env, &builder, "CONTROL_AF_REGIONS", JValue::Object(&regions))?;
+        }
+        if capabilities.focus_modes.contains(&settings.focus_mode) {
+            match settings.focus_mode {
+                FocusMode::Auto | FocusMode::Locked => set(env, &builder, "CONTROL_AF_MODE", JValue::Int(AF_AUTO))?,
+                FocusMode::Continuous => set(env, &builder, "CONTROL_AF_MODE", JValue::Int(AF_CONTINUOUS_VIDEO))?,
+                FocusMode::Manual => if let Some(nearest) =
+                    Self::number(env, characteristics, "LENS_INFO_MINIMUM_FOCUS_DISTANCE")? {
+                    // In diopters, from infinity at 0 to the nearest distance, where a lens position of 0 is nearest.
+                    let diopters = nearest as f32 * (1.0 - settings.focus_distance.unwrap_or(0.5).clamp(0.0, 1.0));
+                    set(env, &builder, "CONTROL_AF_MODE", JValue::Int(OFF))?;
+                    set(env, &builder, "LENS_FOCUS_DISTANCE", JValue::Float(diopters))?;
+                },
+            }
+        }
 
-        while start_time.elapsed() < timeout {
-            match self.is_camera_ready() {
-                Ok(true) => {
-                    println!("Camera session is ready!");
-                    return Ok(true);
-                }
-                Ok(false) => {
-                    println!("Camera session not ready yet, waiting...");
-                    thread::sleep(Duration::from_millis(100));
-                }
-                Err(e) => {
-                    println!("Error checking camera ready status: {}", e);
-                    return Err(e);
-                }
+        if capabilities.white_balance_modes.contains(&settings.white_balance_mode) {
+            match settings.white_balance_mode {
+                WhiteBalanceMode::Auto => set(env, &builder, "CONTROL_AWB_MODE", JValue::Int(AUTO))?,
+                WhiteBalanceMode::Locked => set(env, &builder, "CONTROL_AWB_LOCK", JValue::Bool(1))?,
+                WhiteBalanceMode::Custom => {}
             }
         }
 
-        println!("Timeout waiting for camera session to be ready");
-        Ok(false)
-    }
+        if let (Some(zoom), Some(range)) = (settings.zoom_factor, capabilities.zoom)
+            && let Some(area) = Self::optional(env, characteristics, "SENSOR_INFO_ACTIVE_ARRAY_SIZE")? {
+            let zoom = zoom.clamp(range.min, range.max);
+            let width = env.call_method(&area, "width", "()I", &[])?.i()?;
+            let height = env.call_method(&area, "height", "()I", &[])?.i()?;
+            let (w, h) = ((width as f32 / zoom) as i32, (height as f32 / zoom) as i32);
+            let crop = env.new_object(
+                "android/graphics/Rect",
+                "(IIII)V",
+                &[JValue::Int((width - w) / 2), JValue::Int((height - h) / 2), JValue::Int((width + w) / 2), JValue::Int((height + h) / 2)],
+            )?;
+            set(env, &builder, "SCALER_CROP_REGION", JValue::Object(&crop))?;
+        }
 
-    fn get_image_dimensions(&self, image: &JObject) -> Result<(i32, i32), Box<dyn Error>> {
-        let mut env_guard = self.java_vm.attach_current_thread()?;
-        let env = &mut *env_guard;
+        if let (Some(fps), Some(range)) = (settings.frame_rate, capabilities.frame_rate) {
+            let fps = fps.clamp(range.min, range.max).round() as i32;
+            let bound = env.call_static_method("java/lang/Integer", "valueOf", "(I)Ljava/lang/Integer;", &[JValue::Int(fps)])?.l()?;
+            let range = env.new_object(
+                "android/util/Range",
+                "(Ljava/lang/Comparable;Ljava/lang/Comparable;)V",
+                &[JValue::Object(&bound), JValue::Object(&bound)],
+            )?;
+            set(env, &builder, "CONTROL_AE_TARGET_FPS_RANGE", JValue::Object(&range))?;
+        }
 
-        let width = env.call_method(image, "getWidth", "()I", &[])?.i()?;
-        let height = env.call_method(image, "getHeight", "()I", &[])?.i()?;
+        if capabilities.hdr && settings.hdr_enabled {
+            set(env, &builder, "CONTROL_MODE", JValue::Int(USE_SCENE_MODE))?;
+            set(env, &builder, "CONTROL_SCENE_MODE", JValue::Int(SCENE_HDR))?;
+        }
+        if capabilities.stabilization {
+            let mode = if settings.stabilization_enabled {ON} else {OFF};
+            if Self::ints(env, characteristics, "CONTROL_AVAILABLE_VIDEO_STABILIZATION_MODES")?.contains(&ON) {
+                set(env, &builder, "CONTROL_VIDEO_STABILIZATION_MODE", JValue::Int(mode))?;
+            } else {
+                set(env, &builder, "LENS_OPTICAL_STABILIZATION_MODE", JValue::Int(mode))?;
+            }
+        }
 
-        println!("Image dimensions - Width: {}, Height: {}", width, height);
-        Ok((width, height))
+        Ok(builder)
     }
 
-    fn convert_yuv_to_rgba(
-        &self,
-        image: &JObject,
-        width: i32,
-        height: i32,
-    ) -> Result<Vec<u8>, Box<dyn Error>> {
-        println!("Starting convert_yuv_to_rgba");
-        let mut env = self.java_vm.attach_current_thread()?;
-
-        let planes: JObjectArray = env
-            .call_method(image, "getPlanes", "()[Landroid/media/Image$Plane;", &[])?
-            .l()?
-            .into();
-
-        let plane_count = env.get_array_length(&planes)?;
-        println!("Number of planes: {}", plane_count);
-
-        if plane_count < 3 {
-            return Err("Image does not have the expected YUV planes".into());
+    pub fn current(&self) -> Option<CameraDevice> {
+        let devices = self.devices();
+        match &self.device {
+            Some(id) => devices.into_iter().find(|d| &d.id == id),
+            None => devices.into_iter().next(),
         }
+    }
 
-        let mut extract = |idx| -> Result<(Vec<u8>, i32, i32), Box<dyn Error>> {
-            println!("Extracting plane index: {}", idx);
-            let plane = env.get_object_array_element(&planes, idx)?;
-            let buffer = env.call_method(&plane, "getBuffer", "()Ljava/nio/ByteBuffer;", &[])?.l()?;
-            let byte_buffer = JByteBuffer::from(buffer);
-
-            let len = env.get_direct_buffer_capacity(&byte_buffer)?;
-            let ptr = env.get_direct_buffer_address(&byte_buffer)?;
-            let data = unsafe { std::slice::from_raw_parts(ptr, len).to_vec() };
+    /// Photos are taken once the capture session also feeds a JPEG reader, the first one takes a little longer.
+    pub fn capture(&mut self) -> bool {
+        if self.state != State::Open {return false;}
+        self.requested += 1;
+        true
+    }
 
-            let row_stride = env.call_method(&plane, "getRowStride", "()I", &[])?.i()?;
-            let pixel_stride = env.call_method(&plane, "getPixelStride", "()I", &[])?.i()?;
+    pub fn photos(&mut self) -> Vec<CapturedPhoto> {
+        if self.state != State::Open {return Vec::new();}
+        self.stills().unwrap_or_else(|e| {
+            log::error!("Could not take a photo: {e}");
+            self.requested = 0;
+            Vec::new()
+        })
+    }
 
-            println!(
-                "Plane {}: len = {}, row_stride = {}, pixel_stride = {}",
-                idx, len, row_stride, pixel_stride
-            );
+    /// Requests the photos asked for and collects those that are done.
+    fn stills(&mut self) -> Result<Vec<CapturedPhoto>> {
+        let Some(java) = &mut self.java else {return Ok(Vec::new());};
+        let Some(helper) = java.helper.clone() else {return Ok(Vec::new());};
+        let vm = java.vm.clone();
+        let mut env = vm.attach_current_thread()?;
+        if !env.call_method(helper.as_obj(), "isSessionReady", "()Z", &[])?.z()? {return Ok(Vec::new());}
+        let session = env.call_method(
+            helper.as_obj(), "getCaptureSession", "()Landroid/hardware/camera2/CameraCaptureSession;", &[],
+        )?.l()?;
 
-            Ok((data, row_stride, pixel_stride))
+        let Some(stills) = &mut self.stills else {
+            if self.requested > 0 {self.stills = Some(Self::prepare(java, &mut env, &helper, &session)?);}
+            return Ok(Vec::new());
         };
+        if let Some(replaced) = &stills.replaced {
+            if env.is_same_object(replaced, &session)? {return Ok(Vec::new());}
+            stills.replaced = None;
+            // The helper started its own repeating request on the new session.
+            self.applied = false;
+        }
 
-        let (y, y_rs, y_ps) = extract(0)?;
-        let (u, u_rs, u_ps) = extract(1)?;
-        let (v, v_rs, v_ps) = extract(2)?;
-
-        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
-
-        for row in 0..height {
-            for col in 0..width {
-                let yi = (row * y_rs + col * y_ps) as usize;
-                let ui = ((row / 2) * u_rs + (col / 2) * u_ps) as usize;
-                let vi = ((row / 2) * v_rs + (col / 2) * v_ps) as usize;
-
-                let y_val = y.get(yi).copied().unwrap_or(0) as i32;
-                let u_val = u.get(ui).copied().unwrap_or(128) as i32;
-                let v_val = v.get(vi).copied().unwrap_or(128) as i32;
-
-                let c = y_val - 16;
-                let d = u_val - 128;
-                let e = v_val - 128;
-
-                let r = ((298 * c + 409 * e + 128) >> 8).clamp(0, 255) as u8;
-                let g = ((298 * c - 100 * d - 208 * e + 128) >> 8).clamp(0, 255) as u8;
-                let b = ((298 * c + 516 * d + 128) >> 8).clamp(0, 255) as u8;
+        let settings = self.settings.clone().unwrap_or_default();
+        for _ in 0..std::mem::take(&mut self.requested) {
+            Self::shoot(java, &mut env, &helper, &session, stills, &settings)?;
+        }
 
-                rgba.extend_from_slice(&[r, g, b, 255]);
-            }
+        loop {
+            let image = env.call_method(stills.reader.as_obj(), "acquireNextImage", "()Landroid/media/Image;", &[])?.l()?;
+            if image.is_null() {break;}
+            let timestamp = env.call_method(&image, "getTimestamp", "()J", &[])?.j()?;
+            let decoded = jpeg(&mut env, &image);
+            env.call_method(&image, "close", "()V", &[])?;
+            stills.images.push((timestamp, decoded?));
         }
 
-        println!("Finished convert_yuv_to_rgba");
-        Ok(rgba)
+        let mut shots = SHOTS.lock().unwrap();
+        let mut photos = Vec::new();
+        let mut i = 0;
+        while i < stills.images.len() {
+            let Some(s) = shots.iter().position(|shot| shot.timestamp == stills.images[i].0) else {
+                i += 1;
+                continue;
+            };
+            let shot = shots.swap_remove(s);
+            let (_, image) = stills.images.swap_remove(i);
+            let exposure = shot.duration.zip(shot.iso).map(|(duration, iso)| Exposure{
+                duration: Duration::from_nanos(duration.max(0) as u64),
+                iso: iso as f32,
+                bias: shot.bias as f32 * stills.step,
+            });
+            photos.push(CapturedPhoto{
+                image,
+                metadata: PhotoMetadata{timestamp: SystemTime::now(), orientation: stills.orientation, device: None, exposure},
+            });
+        }
+        Ok(photos)
     }
 
-    pub fn frame(&self) -> Result<RgbaImage, CameraError> {
-        println!("Starting get_latest_frame");
-
-        // Ensure we have permission before trying to get frame
-        if !self.has_camera_permission().map_err(|_| CameraError::PermissionDenied)? {
-            return Err(CameraError::PermissionDenied);
+    /// Recreates the helper's session with a JPEG reader of the largest size next to the helper's own. The
+    /// helper's session callback, `CameraHelper$2` in its dex, takes the new session over as it did the first.
+    fn prepare(java: &mut Java, env: &mut JNIEnv, helper: &GlobalRef, session: &JObject) -> Result<Stills> {
+        if java.callback.is_none() {
+            let class = Self::class(env, &java.context, STILLS, "com.orangeme.camera.StillCallback")?;
+            env.register_native_methods(&class, &[NativeMethod{
+                name: "onCaptureCompleted".into(),
+                sig: "(Landroid/hardware/camera2/CameraCaptureSession;Landroid/hardware/camera2/CaptureRequest;Landroid/hardware/camera2/TotalCaptureResult;)V".into(),
+                fn_ptr: completed as *mut c_void,
+            }])?;
+            let callback = env.new_object(&class, "()V", &[])?;
+            java.callback = Some(env.new_global_ref(callback)?);
         }
 
-        if !self.wait_for_camera_ready(10).map_err(|_| CameraError::PermissionDenied)? {
-            return Err(CameraError::PermissionDenied);
+        let device = env.call_method(helper.as_obj(), "getCameraDevice", "()Landroid/hardware/camera2/CameraDevice;", &[])?.l()?;
+        let preview = env.call_method(helper.as_obj(), "getImageReader", "()Landroid/media/ImageReader;", &[])?.l()?;
+        if device.is_null() || preview.is_null() {return Err("The camera closed".into());}
+        let id = JString::from(env.call_method(&device, "getId", "()Ljava/lang/String;", &[])?.l()?);
+        let characteristics = Self::characteristics(env, &java.manager, &id)?;
+
+        let format = env.get_static_field("android/graphics/ImageFormat", "JPEG", "I")?.i()?;
+        let map = Self::characteristic(env, &characteristics, "SCALER_STREAM_CONFIGURATION_MAP")?;
+        let sizes = JObjectArray::from(env.call_method(&map, "getOutputSizes", "(I)[Landroid/util/Size;", &[JValue::Int(format)])?.l()?);
+        let (mut width, mut height) = (0, 0);
+        for s in 0..env.get_array_length(&sizes)? {
+            let size = env.get_object_array_element(&sizes, s)?;
+            let w = env.call_method(&size, "getWidth", "()I", &[])?.i()?;
+            let h = env.call_method(&size, "getHeight", "()I", &[])?.i()?;
+            if w as i64 * h as i64 > width as i64 * height as i64 {(width, height) = (w, h);}
         }
+        if width == 0 {return Err("The camera has no JPEG output".into());}
+        let reader = env.call_static_method(
+            "android/media/ImageReader",
+            "newInstance",
+            "(IIII)Landroid/media/ImageReader;",
+            &[JValue::Int(width), JValue::Int(height), JValue::Int(format), JValue::Int(2)],
+        )?.l()?;
 
-
-        let mut env = self.java_vm.attach_current_thread().map_err(|_| CameraError::PermissionDenied)?;
-        println!("Attached to Java thread.");
-
-        let camera_helper = self.camera_helper_instance.as_ref().ok_or(CameraError::PermissionDenied)?;
-        println!("CameraHelper instance retrieved: {:?}", camera_helper);
-
-        let session_ready = env.call_method(
-            camera_helper.as_obj(),
-            "isSessionReady",
-            "()Z",
-            &[],
-        ).map_err(|_| CameraError::PermissionDenied)?
-        .z()
-        .map_err(|_| CameraError::PermissionDenied)?;
-        println!("Session ready status: {}", session_ready);
-
-        if !session_ready {
-            return Err(CameraError::PermissionDenied);
+        let surfaces = env.new_object("java/util/ArrayList", "()V", &[])?;
+        for output in [&preview, &reader] {
+            let surface = env.call_method(output, "getSurface", "()Landroid/view/Surface;", &[])?.l()?;
+            env.call_method(&surfaces, "add", "(Ljava/lang/Object;)Z", &[JValue::Object(&surface)])?;
         }
+        let class = env.get_object_class(helper.as_obj())?;
+        let loader = env.call_method(&class, "getClassLoader", "()Ljava/lang/ClassLoader;", &[])?.l()?;
+        let name = env.new_string("com.orangeme.camera.CameraHelper$2")?;
+        let class = JClass::from(env.call_method(&loader, "loadClass", "(Ljava/lang/String;)Ljava/lang/Class;", &[JValue::Object(&name)])?.l()?);
+        let callback = env.new_object(&class, "(Lcom/orangeme/camera/CameraHelper;)V", &[JValue::Object(helper.as_obj())])?;
+        let handler = env.get_field(helper.as_obj(), "backgroundHandler", "Landroid/os/Handler;")?.l()?;
+        env.call_method(
+            &device,
+            "createCaptureSession",
+            "(Ljava/util/List;Landroid/hardware/camera2/CameraCaptureSession$StateCallback;Landroid/os/Handler;)V",
+            &[JValue::Object(&surfaces), JValue::Object(&callback), JValue::Object(&handler)],
+        )?;
 
-        let image_obj = env.call_method(
-            camera_helper.as_obj(),
-            "acquireLatestImage",
-            "()Landroid/media/Image;",
-            &[],
-        ).map_err(|_| CameraError::PermissionDenied)?
-        .l()
-        .map_err(|_| CameraError::PermissionDenied)?;
-        println!("Acquired image object: {:?}", image_obj);
-
-        if image_obj.is_null() {
-            println!("No image available.");
-            return Err(CameraError::PermissionDenied);
-        }
+        let step = Self::number(env, &characteristics, "CONTROL_AE_COMPENSATION_STEP")?.unwrap_or(0.0) as f32;
+        let orientation = match Self::number(env, &characteristics, "SENSOR_ORIENTATION")?.unwrap_or(0.0) as i32 {
+            90 => Orientation::Rotate90,
+            180 => Orientation::Rotate180,
+            270 => Orientation::Rotate270,
+            _ => Orientation::NoTransforms,
+        };
+        Ok(Stills{
+            reader: env.new_global_ref(reader)?,
+            replaced: Some(env.new_global_ref(session)?),
+            step,
+            orientation,
+            images: Vec::new(),
+        })
+    }
 
-        println!("Image acquired successfully: {:?}", image_obj);
+    /// Requests a still with `settings` into the JPEG reader, its result goes to the `StillCallback`.
+    fn shoot(java: &Java, env: &mut JNIEnv, helper: &GlobalRef, session: &JObject, stills: &Stills, settings: &CameraSettings) -> Result<()> {
+        let callback = java.callback.as_ref().ok_or("No capture callback")?;
+        let device = env.call_method(helper.as_obj(), "getCameraDevice", "()Landroid/hardware/camera2/CameraDevice;", &[])?.l()?;
+        if device.is_null() {return Err("The camera closed".into());}
+        let id = JString::from(env.call_method(&device, "getId", "()Ljava/lang/String;", &[])?.l()?);
+        let characteristics = Self::characteristics(env, &java.manager, &id)?;
+        let capabilities = Self::describe(env, &java.manager, &id)?.capabilities;
+        let surface = env.call_method(stills.reader.as_obj(), "getSurface", "()Landroid/view/Surface;", &[])?.l()?;
+        let builder = Self::build(env, &device, &characteristics, &capabilities, "TEMPLATE_STILL_CAPTURE", &surface, settings)?;
+        let request = env.call_method(&builder, "build", "()Landroid/hardware/camera2/CaptureRequest;", &[])?.l()?;
+        let handler = env.get_field(helper.as_obj(), "backgroundHandler", "Landroid/os/Handler;")?.l()?;
+        env.call_method(session, "capture", SUBMIT, &[
+            JValue::Object(&request), JValue::Object(callback.as_obj()), JValue::Object(&handler),
+        ])?;
+        Ok(())
+    }
 
-        let (w, h) = self.get_image_dimensions(&image_obj).map_err(|_| CameraError::PermissionDenied)?;;
-        println!("Image dimensions retrieved: Width = {}, Height = {}", w, h);
+    /// Opens the camera `id` from now on, reopening the camera if it is open.
+    pub fn select(&mut self, id: &str) {
+        self.device = Some(id.to_string());
+        if self.state == State::Open {
+            self.stop();
+            self.start();
+        }
+    }
+}
 
-        let data = self.convert_yuv_to_rgba(&image_obj, w, h).map_err(|_| CameraError::PermissionDenied)?;;
-        println!("YUV to RGBA conversion completed. Data length: {}", data.len());
+/// Sets `key` of a `CaptureRequest.Builder`, boxing primitive values.
+fn set(env: &mut JNIEnv, builder: &JObject, key: &str, value: JValue) -> Result<()> {
+    let value = match value {
+        JValue::Object(object) => env.new_local_ref(object)?,
+        JValue::Int(_) => env.call_static_method("java/lang/Integer", "valueOf", "(I)Ljava/lang/Integer;", &[value])?.l()?,
+        JValue::Long(_) => env.call_static_method("java/lang/Long", "valueOf", "(J)Ljava/lang/Long;", &[value])?.l()?,
+        JValue::Float(_) => env.call_static_method("java/lang/Float", "valueOf", "(F)Ljava/lang/Float;", &[value])?.l()?,
+        JValue::Bool(_) => env.call_static_method("java/lang/Boolean", "valueOf", "(Z)Ljava/lang/Boolean;", &[value])?.l()?,
+        _ => return Err("Unsupported capture request value".into()),
+    };
+    let key = env.get_static_field(
+        "android/hardware/camera2/CaptureRequest",
+        key,
+        "Landroid/hardware/camera2/CaptureRequest$Key;",
+    )?.l()?;
+    env.call_method(
+        builder,
+        "set",
+        "(Landroid/hardware/camera2/CaptureRequest$Key;Ljava/lang/Object;)V",
+        &[JValue::Object(&key), JValue::Object(&value)],
+    )?;
+    Ok(())
+}
 
-        let mut img = RgbaImage::new(w as u32, h as u32);
-        println!("Created new RgbaImage with dimensions: {}x{}", w, h);
+/// Decodes a JPEG `android.media.Image`.
+fn jpeg(env: &mut JNIEnv, image: &JObject) -> Result<RgbaImage> {
+    let planes = JObjectArray::from(env.call_method(image, "getPlanes", "()[Landroid/media/Image$Plane;", &[])?.l()?);
+    let plane = env.get_object_array_element(&planes, 0)?;
+    let buffer = JByteBuffer::from(env.call_method(&plane, "getBuffer", "()Ljava/nio/ByteBuffer;", &[])?.l()?);
+    let len = env.call_method(&buffer, "remaining", "()I", &[])?.i()? as usize;
+    let ptr = env.get_direct_buffer_address(&buffer)?;
+    let bytes = unsafe {std::slice::from_raw_parts(ptr, len)};
+    Ok(image::load_from_memory_with_format(bytes, image::ImageFormat::Jpeg)?.into_rgba8())
+}
 
-        for (i, px) in data.chunks_exact(4).enumerate() {
-            let x = (i % w as usize) as u32;
-            let y = (i / w as usize) as u32;
-            img.put_pixel(x, y, Rgba([px[0], px[1], px[2], px[3]]));
-        }
-        println!("Populated RgbaImage with pixel data.");
-
-        env.call_method(&image_obj, "close", "()V", &[]).map_err(|_| CameraError::PermissionDenied)?;;
-        println!("Closed image to free resources.");
-
-        println!("Successfully converted image to RGBA");
-        Ok(img)
-    }
-
-    pub fn is_camera_ready(&self) -> Result<bool, Box<dyn Error>> {
-        if let Some(camera_helper) = &self.camera_helper_instance {
-            let mut env = self.java_vm.attach_current_thread()?;
-            let ready = env.call_method(
-                camera_helper.as_obj(),
-                "isSessionReady",
-                "()Z",
-                &[],
-            )?.z()?;
-            Ok(ready)
-        } else {
-            Ok(false)
-        }
+/// Converts a YUV_420_888 `android.media.Image`.
+fn rgba(env: &mut JNIEnv, image: &JObject) -> Result<RgbaImage> {
+    let width = env.call_method(image, "getWidth", "()I", &[])?.i()?;
+    let height = env.call_method(image, "getHeight", "()I", &[])?.i()?;
+    let planes = JObjectArray::from(env.call_method(image, "getPlanes", "()[Landroid/media/Image$Plane;", &[])?.l()?);
+    if env.get_array_length(&planes)? < 3 {
+        return Err("Image does not have the expected YUV planes".into());
     }
 
-    pub fn close_camera(&self) -> Result<(), Box<dyn Error>> {
-        if let Some(camera_helper) = &self.camera_helper_instance {
-            let mut env = self.java_vm.attach_current_thread()?;
-            env.call_method(
-                camera_helper.as_obj(),
-                "closeCamera",
-                "()V",
-                &[],
-            )?;
-            println!("Camera closed successfully");
+    let mut extract = |idx| -> Result<(Vec<u8>, i32, i32)> {
+        let plane = env.get_object_array_element(&planes, idx)?;
+        let buffer = JByteBuffer::from(env.call_method(&plane, "getBuffer", "()Ljava/nio/ByteBuffer;", &[])?.l()?);
+        let len = env.get_direct_buffer_capacity(&buffer)?;
+        let ptr = env.get_direct_buffer_address(&buffer)?;
+        let data = unsafe {std::slice::from_raw_parts(ptr, len)}.to_vec();
+        let row_stride = env.call_method(&plane, "getRowStride", "()I", &[])?.i()?;
+        let pixel_stride = env.call_method(&plane, "getPixelStride", "()I", &[])?.i()?;
+        Ok((data, row_stride, pixel_stride))
+    };
+
+    let (y, y_rs, y_ps) = extract(0)?;
+    let (u, u_rs, u_ps) = extract(1)?;
+    let (v, v_rs, v_ps) = extract(2)?;
+
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for row in 0..height {
+        for col in 0..width {
+            let yi = (row * y_rs + col * y_ps) as usize;
+            let ui = ((row / 2) * u_rs + (col / 2) * u_ps) as usize;
+            let vi = ((row / 2) * v_rs + (col / 2) * v_ps) as usize;
+
+            let c = y.get(yi).copied().unwrap_or(0) as i32 - 16;
+            let d = u.get(ui).copied().unwrap_or(128) as i32 - 128;
+            let e = v.get(vi).copied().unwrap_or(128) as i32 - 128;
+
+            let r = ((298 * c + 409 * e + 128) >> 8).clamp(0, 255) as u8;
+            let g = ((298 * c - 100 * d - 208 * e + 128) >> 8).clamp(0, 255) as u8;
+            let b = ((298 * c + 516 * d + 128) >> 8).clamp(0, 255) as u8;
+            rgba.extend_from_slice(&[r, g, b, 255]);
         }
-        Ok(())
     }
-}
\ No newline at end of file
+    RgbaImage::from_raw(width as u32, height as u32, rgba).ok_or_else(|| "Image has no pixels".into())
+}
@@ -0,0 +1,11 @@
+package com.orangeme.camera;
+
+import android.hardware.camera2.CameraCaptureSession;
+import android.hardware.camera2.CaptureRequest;
+import android.hardware.camera2.TotalCaptureResult;
+
+/** Forwards the results of still captures to the native side, the source of stills.dex next to it. */
+public final class StillCallback extends CameraCaptureSession.CaptureCallback {
+    @Override
+    public native void onCaptureCompleted(CameraCaptureSession session, CaptureRequest request, TotalCaptureResult result);
+}
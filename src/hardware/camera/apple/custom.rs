@@ -13,7 +13,6 @@ use objc2::runtime::ProtocolObject;
 use objc2_core_media::CMSampleBuffer;
 use objc2_av_foundation::*;
 use objc2_core_video::*;
-use imageproc::filter;
 
 use crate::hardware::{CameraSettings, CameraError};
 use crate::hardware::camera::{ExposureMode, FocusMode, WhiteBalanceMode};
@@ -243,14 +242,9 @@ define_class!(
         ) {
             *self.ivars().ready.lock().unwrap() = true;
             
+            // Colour adjustments are applied by `Camera` in software.
             if let Some(raw_image) = self.process_sample_buffer(sample_buffer) {
-                let settings = self.settings().lock().unwrap().clone();
-
-                let mut processed_image = ImageProcessor::apply_image_settings(raw_image, &settings);
-
-                { processed_image = self.rotate_90_cw(&processed_image); }
-
-                *self.ivars().last_raw_frame.lock().unwrap() = Some(processed_image);
+                *self.ivars().last_raw_frame.lock().unwrap() = Some(self.rotate_90_cw(&raw_image));
             }
         }
     }
@@ -428,85 +422,6 @@ impl ImageProcessor {
         RgbaImage::from_raw(width as u32, height as u32, rgba_data)
     }
 
-    pub fn apply_image_settings(mut img: RgbaImage, settings: &CameraSettings) -> RgbaImage {
-        let bval = settings.brightness.map(|b| (b - 0.5) * 2.0 * 255.0);
-        let contrast = settings.contrast.map(|c| 1.0 + (c - 0.5) * 2.0).unwrap_or(1.0);
-        let sat = settings.saturation.map(|s| 1.0 + (s - 0.5) * 2.0).unwrap_or(1.0);
-        let hue_shift = settings.hue.map(|h| (h - 0.5) * 360.0).unwrap_or(0.0);
-
-        let need_hsv = (sat - 1.0).abs() > f32::EPSILON || hue_shift.abs() > f32::EPSILON;
-
-        for px in img.pixels_mut() {
-            let (mut r, mut g, mut b) = (px[0] as f32, px[1] as f32, px[2] as f32);
-
-            if let Some(v) = bval { r += v; g += v; b += v; }
-
-            r = ((r - 128.0) * contrast + 128.0).clamp(0.0, 255.0);
-            g = ((g - 128.0) * contrast + 128.0).clamp(0.0, 255.0);
-            b = ((b - 128.0) * contrast + 128.0).clamp(0.0, 255.0);
-
-            #[cfg(target_os = "macos")]
-            if settings.white_balance_mode == WhiteBalanceMode::Custom && let Some(gains) = &settings.white_balance_gains {
-                r *= gains.red; g *= gains.green; b *= gains.blue;
-            }
-
-            if need_hsv {
-                let (mut h, mut s, v) = Self::rgb_to_hsv(r, g, b);
-                s *= sat; s = s.clamp(0.0, 1.0);
-                h = (h + hue_shift) % 360.0; if h < 0.0 { h += 360.0; }
-                let (r2, g2, b2) = Self::hsv_to_rgb(h, s, v);
-
-                px[0] = r2.clamp(0.0, 255.0) as u8;
-                px[1] = g2.clamp(0.0, 255.0) as u8;
-                px[2] = b2.clamp(0.0, 255.0) as u8;
-            } else {
-                px[0] = r.clamp(0.0, 255.0) as u8;
-                px[1] = g.clamp(0.0, 255.0) as u8;
-                px[2] = b.clamp(0.0, 255.0) as u8;
-            }
-        }
-
-        if let Some(amount) = settings.noise_reduction && amount > 0.0 {
-            let kernel = [
-                1.0/9.0, 1.0/9.0, 1.0/9.0,
-                1.0/9.0, 1.0/9.0, 1.0/9.0,
-                1.0/9.0, 1.0/9.0, 1.0/9.0,
-            ];
-            img = filter::filter3x3(&img, &kernel);
-        }
-
-        if let Some(strength) = settings.sharpness && strength > 0.0 {
-            let kernel = [
-                0.0, -0.5, 0.0,
-                -0.5, 3.0, -0.5,
-                0.0, -0.5, 0.0,
-            ];
-            img = filter::filter3x3(&img, &kernel);
-        }
-
-        img
-    }
-
-    fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32,f32,f32) {
-        let r = r/255.0; let g = g/255.0; let b = b/255.0;
-        let max = r.max(g).max(b); let min = r.min(g).min(b); let d = max - min;
-        let h = if d==0.0 {0.0} else if max==r {(60.0*((g-b)/d))%360.0} else if max==g {60.0*((b-r)/d+2.0)} else {60.0*((r-g)/d+4.0)};
-        let s = if max==0.0 {0.0} else {d/max}; (h,s,max)
-    }
-
-    fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (f32,f32,f32) {
-        let c = v*s; let x = c*(1.0 - ((h/60.0)%2.0 - 1.0).abs()); let m = v-c;
-        let (r1,g1,b1) = match h {
-            h if h<60.0 => (c,x,0.0),
-            h if h<120.0 => (x,c,0.0),
-            h if h<180.0 => (0.0,c,x),
-            h if h<240.0 => (0.0,x,c),
-            h if h<300.0 => (x,0.0,c),
-            _ => (c,0.0,x)
-        };
-        ((r1+m)*255.0,(g1+m)*255.0,(b1+m)*255.0)
-    }
-
     fn demosaic_bilinear(bayer_data: &[u16], width: usize, height: usize, pattern: BayerPattern) -> Vec<u8> {
         let mut rgb_data = vec![0u8; width * height * 4];
         
@@ -1,19 +1,28 @@
 use std::slice::from_raw_parts;
 use std::cell::RefCell;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 use image::RgbaImage;
 use image::Rgba;
+use image::metadata::Orientation;
 
 use objc2::__framework_prelude::NSObject;
 use objc2::rc::Retained;
-use objc2::runtime::NSObjectProtocol;
-use objc2::{define_class, AllocAnyThread, DeclaredClass};
-use objc2_core_media::CMSampleBuffer;
+use objc2::runtime::{AnyObject, NSObjectProtocol};
+use objc2::{define_class, msg_send, AllocAnyThread, ClassType, DeclaredClass};
+use objc2_core_media::{CMSampleBuffer, CMTime, CMTimeFlags, CMVideoFormatDescription};
+use objc2_core_foundation::CGPoint;
 use objc2_av_foundation::*;
 use objc2_core_video::*;
-use objc2_foundation::{NSArray, NSDictionary, NSNumber, NSString};
+use objc2_foundation::{NSArray, NSDictionary, NSError, NSNumber, NSString};
 use dispatch2::DispatchQueue;
 use objc2::runtime::ProtocolObject;
 
+use super::super::{
+    CameraCapabilities, CameraDevice, CameraFormat, CameraSettings, CapturedPhoto, Exposure, ExposureMode, Facing,
+    FocusMode, PhotoMetadata, Resolution, SettingRange, WhiteBalanceMode,
+};
+
 impl StandardProcessor {
     pub fn new() -> Retained<Self> {
         let this = Self::alloc();
@@ -41,50 +50,116 @@ define_class!(
             sample_buffer: &CMSampleBuffer,
             _connection: &AVCaptureConnection,
         ) {
-            let pixel_buffer = unsafe { CMSampleBuffer::image_buffer(sample_buffer) };
-            if pixel_buffer.is_none() { return; }
+            let Some(pixel_buffer) = (unsafe { CMSampleBuffer::image_buffer(sample_buffer) }) else {return;};
+            if let Some(image) = rgba(&pixel_buffer, true) {self.ivars().0.replace(Some(image));}
+        }
+    }
+);
 
-            let pixel_buffer = pixel_buffer.unwrap();
-            let height = CVPixelBufferGetHeight(&pixel_buffer);
-            let width = CVPixelBufferGetWidth(&pixel_buffer);
-            let bytes_per_row = CVPixelBufferGetBytesPerRow(&pixel_buffer);
-            let size = bytes_per_row * height;
+/// Copies a BGRA pixel buffer, flipped horizontally when `mirror` is set.
+fn rgba(pixel_buffer: &CVPixelBuffer, mirror: bool) -> Option<RgbaImage> {
+    let height = CVPixelBufferGetHeight(pixel_buffer);
+    let width = CVPixelBufferGetWidth(pixel_buffer);
+    let bytes_per_row = CVPixelBufferGetBytesPerRow(pixel_buffer);
+    let size = bytes_per_row * height;
 
-            use objc2_core_video::{CVPixelBufferLockBaseAddress, CVPixelBufferUnlockBaseAddress};
+    let lock_result = unsafe { CVPixelBufferLockBaseAddress(pixel_buffer, CVPixelBufferLockFlags(0)) };
+    if lock_result != 0 { return None; }
 
-            let lock_result = unsafe { CVPixelBufferLockBaseAddress(&pixel_buffer, CVPixelBufferLockFlags(0)) };
-            if lock_result != 0 { return; }
+    let base_address = CVPixelBufferGetBaseAddress(pixel_buffer) as *const u8;
+    if base_address.is_null() || size > isize::MAX as usize {
+        unsafe { CVPixelBufferUnlockBaseAddress(pixel_buffer, CVPixelBufferLockFlags(0)); }
+        return None;
+    }
 
+    let slice = unsafe { from_raw_parts(base_address, size) };
+    let mut image = RgbaImage::new(width as u32, height as u32);
 
-            let base_address = CVPixelBufferGetBaseAddress(&pixel_buffer) as *const u8;
-            if base_address.is_null() || size > isize::MAX as usize {
-                unsafe { CVPixelBufferUnlockBaseAddress(&pixel_buffer, CVPixelBufferLockFlags(0)); }
-                return;
-            }
+    for y in 0..height {
+        let row_start = y * bytes_per_row;
+        for x in 0..width {
+            let src_index = row_start + x * 4;
+            if src_index + 3 >= slice.len() { continue; }
 
-            let slice = unsafe { from_raw_parts(base_address, size) };
-            let mut image = RgbaImage::new(width as u32, height as u32);
+            let r = slice[src_index + 2];
+            let g = slice[src_index + 1];
+            let b = slice[src_index];
+            let a = slice[src_index + 3];
 
-            for y in 0..height {
-                let row_start = y * bytes_per_row;
-                for x in 0..width {
-                    let src_index = row_start + x * 4;
-                    if src_index + 3 >= slice.len() { continue; }
+            let dest_x = if mirror {width - 1 - x} else {x};
 
-                    let r = slice[src_index + 2];
-                    let g = slice[src_index + 1];
-                    let b = slice[src_index];
-                    let a = slice[src_index + 3];
+            image.put_pixel(dest_x as u32, y as u32, Rgba([r, g, b, a]));
+        }
+    }
 
-                    let dest_x = width - 1 - x; 
-                    let dest_y = y;
+    unsafe { CVPixelBufferUnlockBaseAddress(pixel_buffer, CVPixelBufferLockFlags(0)); }
+    Some(image)
+}
 
-                    image.put_pixel(dest_x as u32, dest_y as u32, Rgba([r, g, b, a]));
-                }
-            }
-            self.ivars().0.replace(Some(image));
+/// Asks for BGRA pixel buffers, which `rgba` reads.
+fn bgra() -> Retained<NSDictionary<NSString, AnyObject>> {
+    let pixel_format_value = NSNumber::new_u32(kCVPixelFormatType_32BGRA);
+    let pixel_format_key: &NSString = unsafe { &*(kCVPixelBufferPixelFormatTypeKey as *const _ as *const NSString) };
+    NSDictionary::from_slices(&[pixel_format_key], &[pixel_format_value.as_ref()])
+}
+
+/// The value of `key` in `dictionary` when it is a `T`.
+unsafe fn value<T: ClassType>(dictionary: &NSDictionary<NSString, AnyObject>, key: &str) -> Option<Retained<T>> {
+    let object = dictionary.objectForKey(&NSString::from_str(key))?;
+    let is: bool = unsafe { msg_send![&*object, isKindOfClass: T::class()] };
+    is.then(|| unsafe { Retained::cast_unchecked(object) })
+}
 
-            unsafe { CVPixelBufferUnlockBaseAddress(&pixel_buffer, CVPixelBufferLockFlags(0)); }
+impl StillProcessor {
+    pub fn new() -> Retained<Self> {
+        let this = Self::alloc();
+        let this = this.set_ivars(Stills::default());
+        unsafe { objc2::msg_send![super(this), init] }
+    }
+}
+
+/// Photos delivered on the capture queue, waiting for the next tick.
+#[derive(Debug, Default)]
+pub struct Stills(Mutex<Vec<CapturedPhoto>>);
+
+define_class!(
+    #[unsafe(super = NSObject)]
+    #[ivars = Stills]
+    #[derive(Debug)]
+    pub struct StillProcessor;
+
+    unsafe impl NSObjectProtocol for StillProcessor {}
+
+    unsafe impl AVCapturePhotoCaptureDelegate for StillProcessor {
+        #[unsafe(method(captureOutput:didFinishProcessingPhoto:error:))]
+        fn captureOutput_didFinishProcessingPhoto_error(
+            &self,
+            _output: &AVCapturePhotoOutput,
+            photo: &AVCapturePhoto,
+            error: Option<&NSError>,
+        ) {
+            if let Some(error) = error {
+                log::error!("Photo capture failed: {}", error.localizedDescription());
+                return;
+            }
+            let Some(image) = (unsafe { photo.pixelBuffer() }).and_then(|buffer| rgba(&buffer, false)) else {return;};
+            let metadata = unsafe { photo.metadata() };
+            let orientation = unsafe { value::<NSNumber>(&metadata, "Orientation") }
+                .and_then(|o| Orientation::from_exif(o.as_u8()))
+                .unwrap_or(Orientation::NoTransforms);
+            let exposure = unsafe { value::<NSDictionary<NSString, AnyObject>>(&metadata, "{Exif}") }.and_then(|exif| unsafe {
+                let duration = value::<NSNumber>(&exif, "ExposureTime")?.as_f64();
+                let iso = value::<NSArray>(&exif, "ISOSpeedRatings")?.firstObject()?;
+                let is: bool = msg_send![&*iso, isKindOfClass: NSNumber::class()];
+                if !is {return None;}
+                let iso = Retained::cast_unchecked::<NSNumber>(iso).as_f32();
+                let bias = value::<NSNumber>(&exif, "ExposureBiasValue").map(|b| b.as_f32()).unwrap_or(0.0);
+                Some(Exposure{duration: Duration::from_secs_f64(duration.max(0.0)), iso, bias})
+            });
+            self.ivars().0.lock().unwrap().push(CapturedPhoto{
+                image,
+                metadata: PhotoMetadata{timestamp: SystemTime::now(), orientation, device: None, exposure},
+            });
         }
     }
 );
@@ -93,6 +168,10 @@ define_class!(
 pub struct StandardOsCamera {
     session: Retained<AVCaptureSession>,
     processor: Retained<StandardProcessor>,
+    output: Retained<AVCapturePhotoOutput>,
+    stills: Retained<StillProcessor>,
+    /// The unique id of the device to use, the first back camera when `None`.
+    device: Option<String>,
 }
 
 impl StandardOsCamera {
@@ -101,39 +180,298 @@ impl StandardOsCamera {
             StandardOsCamera {
                 session: AVCaptureSession::new(),
                 processor: StandardProcessor::new(),
+                output: AVCapturePhotoOutput::new(),
+                stills: StillProcessor::new(),
+                device: None,
             }
         }
     }
 
-    pub fn start(&self) {
+    unsafe fn discover(position: AVCaptureDevicePosition) -> Retained<NSArray<AVCaptureDevice>> {
         unsafe {
-            if self.session.isRunning() {return;}
-
             let device_types = NSArray::from_slice(&[
                 AVCaptureDeviceTypeBuiltInTripleCamera,
                 AVCaptureDeviceTypeBuiltInDualWideCamera,
                 AVCaptureDeviceTypeBuiltInDualCamera,
                 AVCaptureDeviceTypeBuiltInWideAngleCamera,
+                AVCaptureDeviceTypeExternal,
             ]);
 
-            let discovery_session = AVCaptureDeviceDiscoverySession::discoverySessionWithDeviceTypes_mediaType_position(
+            AVCaptureDeviceDiscoverySession::discoverySessionWithDeviceTypes_mediaType_position(
                 &device_types,
                 AVMediaTypeVideo,
-                AVCaptureDevicePosition::Back,
-            );
+                position,
+            ).devices()
+        }
+    }
 
-            let devices = discovery_session.devices();
-            let device = devices.into_iter().next().expect("No camera device found");
+    /// The selected device, or the first back camera.
+    fn device(&self) -> Option<Retained<AVCaptureDevice>> {
+        unsafe {
+            match &self.device {
+                Some(id) => AVCaptureDevice::deviceWithUniqueID(&NSString::from_str(id)),
+                None => Self::discover(AVCaptureDevicePosition::Back).iter().next()
+                    .or_else(|| Self::discover(AVCaptureDevicePosition::Unspecified).iter().next()),
+            }
+        }
+    }
 
+    pub fn devices(&self) -> Vec<CameraDevice> {
+        unsafe {
+            Self::discover(AVCaptureDevicePosition::Unspecified).iter().map(|device| Self::describe(&device)).collect()
+        }
+    }
 
-            let _ = device.lockForConfiguration();
+    pub fn current(&self) -> Option<CameraDevice> {
+        self.device().map(|device| unsafe { Self::describe(&device) })
+    }
+
+    unsafe fn describe(device: &AVCaptureDevice) -> CameraDevice {
+        unsafe {
+            CameraDevice{
+                id: device.uniqueID().to_string(),
+                name: device.localizedName().to_string(),
+                facing: match device.position() {
+                    AVCaptureDevicePosition::Front => Facing::Front,
+                    AVCaptureDevicePosition::Back => Facing::Back,
+                    _ => Facing::External,
+                },
+                formats: device.formats().iter().map(|format| {
+                    let dimensions = CMVideoFormatDescription::dimensions(&format.formatDescription());
+                    let (min_fps, max_fps) = Self::frame_rates(&format);
+                    CameraFormat{width: dimensions.width as u32, height: dimensions.height as u32, min_fps, max_fps}
+                }).collect(),
+                capabilities: Self::capabilities_of(device),
+            }
+        }
+    }
+
+    unsafe fn frame_rates(format: &AVCaptureDeviceFormat) -> (f32, f32) {
+        let ranges = unsafe { format.videoSupportedFrameRateRanges() };
+        (
+            ranges.iter().map(|r| unsafe { r.minFrameRate() } as f32).fold(f32::INFINITY, f32::min),
+            ranges.iter().map(|r| unsafe { r.maxFrameRate() } as f32).fold(0.0, f32::max),
+        )
+    }
+
+    pub fn capabilities(&self) -> CameraCapabilities {
+        self.device().map(|device| unsafe { Self::capabilities_of(&device) }).unwrap_or_default()
+    }
+
+    /// Several controls are only available on iOS.
+    unsafe fn capabilities_of(device: &AVCaptureDevice) -> CameraCapabilities {
+        unsafe {
+            let format = device.activeFormat();
+            let (min_fps, max_fps) = Self::frame_rates(&format);
+            let mut resolutions = device.formats().iter().map(|format| {
+                let dimensions = CMVideoFormatDescription::dimensions(&format.formatDescription());
+                Resolution{width: dimensions.width as u32, height: dimensions.height as u32}
+            }).collect::<Vec<_>>();
+            resolutions.sort_by_key(|r| (r.width, r.height));
+            resolutions.dedup();
+
+            #[cfg_attr(not(target_os = "ios"), allow(unused_mut))]
+            let mut capabilities = CameraCapabilities{
+                exposure_modes: [
+                    (ExposureMode::Auto, AVCaptureExposureMode::AutoExpose),
+                    (ExposureMode::Continuous, AVCaptureExposureMode::ContinuousAutoExposure),
+                    (ExposureMode::Custom, AVCaptureExposureMode::Custom),
+                ].into_iter().filter(|(_, m)| device.isExposureModeSupported(*m)).map(|(mode, _)| mode).collect(),
+                focus_modes: [
+                    (FocusMode::Auto, AVCaptureFocusMode::AutoFocus),
+                    (FocusMode::Continuous, AVCaptureFocusMode::ContinuousAutoFocus),
+                    (FocusMode::Locked, AVCaptureFocusMode::Locked),
+                ].into_iter().filter(|(_, m)| device.isFocusModeSupported(*m)).map(|(mode, _)| mode).collect(),
+                focus_point_of_interest: device.isFocusPointOfInterestSupported(),
+                white_balance_modes: [
+                    (WhiteBalanceMode::Auto, AVCaptureWhiteBalanceMode::AutoWhiteBalance),
+                    (WhiteBalanceMode::Locked, AVCaptureWhiteBalanceMode::Locked),
+                ].into_iter().filter(|(_, m)| device.isWhiteBalanceModeSupported(*m)).map(|(mode, _)| mode).collect(),
+                frame_rate: (max_fps > 0.0).then_some(SettingRange{min: min_fps, max: max_fps}),
+                resolutions,
+                ..Default::default()
+            };
+
+            #[cfg(target_os = "ios")]
+            {
+                let seconds = |t: CMTime| t.value as f32 / t.timescale as f32;
+                if capabilities.exposure_modes.contains(&ExposureMode::Custom) {
+                    capabilities.iso = Some(SettingRange{min: format.minISO(), max: format.maxISO()});
+                    capabilities.exposure_duration = Some(SettingRange{
+                        min: seconds(format.minExposureDuration()),
+                        max: seconds(format.maxExposureDuration()),
+                    });
+                }
+                capabilities.exposure_compensation = Some(SettingRange{
+                    min: device.minExposureTargetBias(),
+                    max: device.maxExposureTargetBias(),
+                });
+                if device.isLockingFocusWithCustomLensPositionSupported() {capabilities.focus_modes.push(FocusMode::Manual);}
+                if device.isLockingWhiteBalanceWithCustomDeviceGainsSupported() {
+                    capabilities.white_balance_modes.push(WhiteBalanceMode::Custom);
+                }
+                capabilities.zoom = Some(SettingRange{
+                    min: device.minAvailableVideoZoomFactor() as f32,
+                    max: device.maxAvailableVideoZoomFactor() as f32,
+                });
+                capabilities.hdr = format.isVideoHDRSupported();
+                capabilities.stabilization = format.isVideoStabilizationModeSupported(AVCaptureVideoStabilizationMode::Auto);
+                capabilities.low_light_boost = device.isLowLightBoostSupported();
+            }
+
+            capabilities
+        }
+    }
+
+    /// Applies what the device supports of `settings`.
+    pub fn apply(&mut self, settings: &CameraSettings) {
+        let Some(device) = self.device() else {return;};
+        unsafe {
+            let capabilities = Self::capabilities_of(&device);
+            if device.lockForConfiguration().is_err() {
+                log::error!("Could not configure the camera");
+                return;
+            }
+
+            if let Some(resolution) = settings.resolution {
+                let format = device.formats().iter().find(|format| {
+                    let dimensions = CMVideoFormatDescription::dimensions(&format.formatDescription());
+                    let (min_fps, max_fps) = Self::frame_rates(format);
+                    dimensions.width as u32 == resolution.width && dimensions.height as u32 == resolution.height
+                        && settings.frame_rate.is_none_or(|fps| (min_fps..=max_fps).contains(&fps))
+                });
+                if let Some(format) = format {device.setActiveFormat(&format);}
+            }
+
+            if let (Some(fps), Some(range)) = (settings.frame_rate, capabilities.frame_rate) {
+                let duration = CMTime{
+                    value: 1000,
+                    timescale: (fps.clamp(range.min, range.max) * 1000.0) as i32,
+                    flags: CMTimeFlags::Valid,
+                    epoch: 0,
+                };
+                device.setActiveVideoMinFrameDuration(duration);
+                device.setActiveVideoMaxFrameDuration(duration);
+            }
+
+            if capabilities.exposure_modes.contains(&settings.exposure_mode) {
+                match settings.exposure_mode {
+                    ExposureMode::Auto => device.setExposureMode(AVCaptureExposureMode::AutoExpose),
+                    ExposureMode::Continuous => device.setExposureMode(AVCaptureExposureMode::ContinuousAutoExposure),
+                    #[cfg(target_os = "ios")]
+                    ExposureMode::Custom => if let (Some(custom), Some(iso), Some(duration)) =
+                        (settings.custom_exposure, capabilities.iso, capabilities.exposure_duration) {
+                        let seconds = duration.min + (duration.max - duration.min) * custom.duration.clamp(0.0, 1.0);
+                        let duration = CMTime{
+                            value: (seconds as f64 * 1_000_000_000.0) as i64,
+                            timescale: 1_000_000_000,
+                            flags: CMTimeFlags::Valid,
+                            epoch: 0,
+                        };
+                        let iso = iso.min + (iso.max - iso.min) * custom.iso.clamp(0.0, 1.0);
+                        device.setExposureModeCustomWithDuration_ISO_completionHandler(duration, iso, None);
+                    },
+                    #[cfg(not(target_os = "ios"))]
+                    ExposureMode::Custom => {}
+                }
+            }
+            #[cfg(target_os = "ios")]
+            if let (Some(bias), Some(range)) = (settings.exposure_compensation, capabilities.exposure_compensation) {
+                device.setExposureTargetBias_completionHandler(bias.clamp(range.min, range.max), None);
+            }
+
+            if capabilities.focus_point_of_interest && let Some((x, y)) = settings.focus_point_of_interest {
+                device.setFocusPointOfInterest(CGPoint{x: x as f64, y: y as f64});
+            }
+            if capabilities.focus_modes.contains(&settings.focus_mode) {
+                match settings.focus_mode {
+                    FocusMode::Auto => device.setFocusMode(AVCaptureFocusMode::AutoFocus),
+                    FocusMode::Continuous => device.setFocusMode(AVCaptureFocusMode::ContinuousAutoFocus),
+                    FocusMode::Locked => device.setFocusMode(AVCaptureFocusMode::Locked),
+                    #[cfg(target_os = "ios")]
+                    FocusMode::Manual => device.setFocusModeLockedWithLensPosition_completionHandler(
+                        settings.focus_distance.unwrap_or(0.5).clamp(0.0, 1.0), None,
+                    ),
+                    #[cfg(not(target_os = "ios"))]
+                    FocusMode::Manual => {}
+                }
+            }
+
+            if capabilities.white_balance_modes.contains(&settings.white_balance_mode) {
+                match settings.white_balance_mode {
+                    WhiteBalanceMode::Auto => device.setWhiteBalanceMode(AVCaptureWhiteBalanceMode::AutoWhiteBalance),
+                    WhiteBalanceMode::Locked => device.setWhiteBalanceMode(AVCaptureWhiteBalanceMode::Locked),
+                    #[cfg(target_os = "ios")]
+                    WhiteBalanceMode::Custom => if let Some(gains) = settings.white_balance_gains {
+                        let max_gain = device.maxWhiteBalanceGain();
+                        let gains = AVCaptureWhiteBalanceGains{
+                            redGain: 1.0 + (max_gain - 1.0) * gains.red.clamp(0.0, 1.0),
+                            greenGain: 1.0 + (max_gain - 1.0) * gains.green.clamp(0.0, 1.0),
+                            blueGain: 1.0 + (max_gain - 1.0) * gains.blue.clamp(0.0, 1.0),
+                        };
+                        device.setWhiteBalanceModeLockedWithDeviceWhiteBalanceGains_completionHandler(gains, None);
+                    },
+                    #[cfg(not(target_os = "ios"))]
+                    WhiteBalanceMode::Custom => {}
+                }
+            }
+
+            #[cfg(target_os = "ios")]
+            {
+                if let (Some(zoom), Some(range)) = (settings.zoom_factor, capabilities.zoom) {
+                    device.setVideoZoomFactor(zoom.clamp(range.min, range.max) as f64);
+                }
+                if capabilities.hdr {
+                    device.setAutomaticallyAdjustsVideoHDREnabled(false);
+                    device.setVideoHDREnabled(settings.hdr_enabled);
+                }
+                if capabilities.low_light_boost {
+                    device.setAutomaticallyEnablesLowLightBoostWhenAvailable(settings.low_light_boost.unwrap_or(false));
+                }
+            }
 
-            for preset in [AVCaptureSessionPreset3840x2160, AVCaptureSessionPresetPhoto, AVCaptureSessionPresetHigh, AVCaptureSessionPresetMedium] {
-                if self.session.canSetSessionPreset(preset) {
-                    self.session.setSessionPreset(preset);
-                    break;
+            device.unlockForConfiguration();
+
+            #[cfg(target_os = "ios")]
+            if capabilities.stabilization {
+                let mode = match settings.stabilization_enabled {
+                    true => AVCaptureVideoStabilizationMode::Auto,
+                    false => AVCaptureVideoStabilizationMode::Off,
+                };
+                for output in self.session.outputs().iter() {
+                    if let Some(connection) = output.connectionWithMediaType(AVMediaTypeVideo.unwrap()) {
+                        connection.setPreferredVideoStabilizationMode(mode);
+                    }
                 }
             }
+        }
+    }
+
+    /// Uses the device `id` from now on, moving a running session over to it.
+    pub fn select(&mut self, id: &str) {
+        self.device = Some(id.to_string());
+        unsafe {
+            let running = self.session.isRunning();
+            if running {self.session.stopRunning();}
+            self.session.beginConfiguration();
+            for input in self.session.inputs().iter() {
+                self.session.removeInput(&input);
+            }
+            self.session.commitConfiguration();
+            if running {self.start();}
+        }
+    }
+
+    pub fn start(&self) {
+        unsafe {
+            if self.session.isRunning() {return;}
+
+            let Some(device) = self.device() else {
+                log::error!("No camera device found");
+                return;
+            };
+
+            let _ = device.lockForConfiguration();
 
             if device.isFocusModeSupported(AVCaptureFocusMode::ContinuousAutoFocus) {
                 device.setFocusMode(AVCaptureFocusMode::ContinuousAutoFocus);
@@ -147,7 +485,12 @@ impl StandardOsCamera {
                 .map_err(|e| format!("Failed to create AVCaptureDeviceInput: {:?}", e)).unwrap();
 
             self.session.beginConfiguration();
-            self.session.setSessionPreset(AVCaptureSessionPresetMedium);
+            // Full resolution photos with the video output at a lower one.
+            let preset = match self.session.canSetSessionPreset(AVCaptureSessionPresetPhoto) {
+                true => AVCaptureSessionPresetPhoto,
+                false => AVCaptureSessionPresetMedium,
+            };
+            self.session.setSessionPreset(preset);
 
             if self.session.inputs().is_empty() && self.session.canAddInput(&input) {
                 self.session.addInput(&input);
@@ -155,17 +498,7 @@ impl StandardOsCamera {
 
             if self.session.outputs().is_empty() {
                 let output = AVCaptureVideoDataOutput::new();
-
-                let pixel_format_value = NSNumber::new_u32(kCVPixelFormatType_32BGRA);
-                let pixel_format_key: &NSString =
-                    &*(kCVPixelBufferPixelFormatTypeKey as *const _ as *const NSString);
-
-                let video_settings = NSDictionary::from_slices(
-                    &[pixel_format_key],
-                    &[pixel_format_value.as_ref()],
-                );
-
-                output.setVideoSettings(Some(&video_settings));
+                output.setVideoSettings(Some(&bgra()));
 
                 let queue = DispatchQueue::new("CameraQueue", None);
                 output.setSampleBufferDelegate_queue(
@@ -176,6 +509,9 @@ impl StandardOsCamera {
                 if self.session.canAddOutput(&output) {
                     self.session.addOutput(&output);
                 }
+                if self.session.canAddOutput(&self.output) {
+                    self.session.addOutput(&self.output);
+                }
             }
 
             self.session.commitConfiguration();
@@ -191,4 +527,16 @@ impl StandardOsCamera {
         self.processor.ivars().0.take()
     }
 
+    /// Returns false when the photo output could not be added to the session.
+    pub fn capture(&mut self) -> bool {
+        unsafe {
+            if self.output.connectionWithMediaType(AVMediaTypeVideo.unwrap()).is_none() {return false;}
+            let settings = AVCapturePhotoSettings::photoSettingsWithFormat(Some(&bgra()));
+            self.output.capturePhotoWithSettings_delegate(&settings, ProtocolObject::from_ref(&*self.stills));
+        }
+        true
+    }
+
+    pub fn photos(&mut self) -> Vec<CapturedPhoto> {std::mem::take(&mut *self.stills.ivars().0.lock().unwrap())}
+
 }
@@ -1,26 +1,38 @@
-use super::{CameraError, CameraSettings};
 use image::RgbaImage;
-use std::sync::{Arc, Mutex};
 
-#[derive(Debug, Clone)]
-pub struct OsCamera;
+use super::{CameraCapabilities, CameraDevice, CameraSettings, CapturedPhoto};
+
+/// No camera device is supported on Linux yet, frames come from a synthetic camera set up with `MAVERICK_CAMERA`.
+#[derive(Debug, Clone, Default)]
+pub struct OsCamera {
+    warned: bool,
+}
 
 impl OsCamera {
-    pub fn new_standard() -> Result<Self, CameraError> {
-        Err(CameraError::InitializationFailed)
-    }
+    pub fn new() -> Self {Self::default()}
 
-    pub fn new_custom() -> Result<Self, CameraError> {
-        Err(CameraError::InitializationFailed)
+    pub fn start(&mut self) {
+        if !self.warned {
+            log::warn!("No camera device on Linux, set MAVERICK_CAMERA to use a synthetic camera");
+            self.warned = true;
+        }
     }
 
-    pub fn frame(&self) -> Result<RgbaImage, CameraError> {
-        Err(CameraError::FailedToGetFrame)
-    }
+    pub fn stop(&mut self) {}
 
-    pub fn settings(&mut self) -> Option<Arc<Mutex<CameraSettings>>> {
-        None
-    }
+    pub fn frame(&mut self) -> Option<RgbaImage> {None}
+
+    pub fn devices(&self) -> Vec<CameraDevice> {Vec::new()}
+
+    pub fn current(&self) -> Option<CameraDevice> {None}
+
+    pub fn capture(&mut self) -> bool {false}
+
+    pub fn photos(&mut self) -> Vec<CapturedPhoto> {Vec::new()}
+
+    pub fn select(&mut self, _id: &str) {}
+
+    pub fn capabilities(&self) -> CameraCapabilities {CameraCapabilities::default()}
 
-    pub fn stop(self) {}
+    pub fn apply(&mut self, _settings: &CameraSettings) {}
 }
@@ -0,0 +1,188 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ExtendedColorType, ImageEncoder, RgbaImage};
+
+use super::{CameraDevice, CameraError};
+
+/// How a photo was exposed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub duration: Duration,
+    pub iso: f32,
+    /// In EV.
+    pub bias: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhotoMetadata {
+    pub timestamp: SystemTime,
+    /// How the image has to be turned to be upright.
+    pub orientation: Orientation,
+    /// The device that took it, when known.
+    pub device: Option<CameraDevice>,
+    /// Only reported by devices with still capture.
+    pub exposure: Option<Exposure>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhotoFormat {
+    /// With a quality from 1 to 100.
+    Jpeg(u8),
+    Png,
+}
+
+/// A photo taken by [`Camera::capture_photo`](super::Camera::capture_photo). The image is as the device
+/// delivered it, `upright` applies the orientation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPhoto {
+    pub image: RgbaImage,
+    pub metadata: PhotoMetadata,
+}
+
+impl CapturedPhoto {
+    pub fn upright(&self) -> RgbaImage {
+        let mut image = DynamicImage::ImageRgba8(self.image.clone());
+        image.apply_orientation(self.metadata.orientation);
+        image.into_rgba8()
+    }
+
+    /// Encodes the image with the metadata as EXIF. JPEG drops the alpha channel.
+    pub fn encode(&self, format: PhotoFormat) -> Result<Vec<u8>, CameraError> {
+        let (width, height) = self.image.dimensions();
+        let exif = self.exif();
+        let mut bytes = Vec::new();
+        let encoded = match format {
+            PhotoFormat::Jpeg(quality) => {
+                let mut encoder = JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100));
+                encoder.set_exif_metadata(exif).map_err(|e| CameraError::Encoding(e.to_string()))?;
+                let rgb = DynamicImage::ImageRgba8(self.image.clone()).into_rgb8();
+                encoder.write_image(rgb.as_raw(), width, height, ExtendedColorType::Rgb8)
+            },
+            PhotoFormat::Png => {
+                let mut encoder = PngEncoder::new(&mut bytes);
+                encoder.set_exif_metadata(exif).map_err(|e| CameraError::Encoding(e.to_string()))?;
+                encoder.write_image(self.image.as_raw(), width, height, ExtendedColorType::Rgba8)
+            },
+        };
+        encoded.map_err(|e| CameraError::Encoding(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    /// The metadata as a little endian TIFF structure, the payload of a JPEG APP1 segment or a PNG eXIf chunk.
+    /// Times are in UTC.
+    pub fn exif(&self) -> Vec<u8> {
+        let metadata = &self.metadata;
+        let time = date_time(metadata.timestamp);
+        let mut primary = Vec::new();
+        if let Some(device) = &metadata.device {primary.push((MODEL, Value::Ascii(device.name.clone())));}
+        primary.push((ORIENTATION, Value::Short(metadata.orientation.to_exif() as u16)));
+        primary.push((DATE_TIME, Value::Ascii(time.clone())));
+        primary.push((EXIF_IFD, Value::Long(0)));
+
+        let mut exif = Vec::new();
+        if let Some(exposure) = metadata.exposure {
+            let seconds = exposure.duration.as_secs_f64();
+            exif.push((EXPOSURE_TIME, Value::Rational(match seconds {
+                s if s > 0.0 && s < 1.0 => (1, (1.0 / s).round() as u32),
+                s => ((s * 1000.0).round() as u32, 1000),
+            })));
+            exif.push((ISO, Value::Short(exposure.iso.round().clamp(0.0, u16::MAX as f32) as u16)));
+        }
+        exif.push((DATE_TIME_ORIGINAL, Value::Ascii(time)));
+        exif.push((OFFSET_TIME_ORIGINAL, Value::Ascii("+00:00".to_string())));
+        if let Some(exposure) = metadata.exposure {
+            exif.push((EXPOSURE_BIAS, Value::SRational(((exposure.bias * 100.0).round() as i32, 100))));
+        }
+        exif.push((PIXEL_X_DIMENSION, Value::Long(self.image.width())));
+        exif.push((PIXEL_Y_DIMENSION, Value::Long(self.image.height())));
+
+        let mut bytes = b"II*\0\x08\0\0\0".to_vec();
+        let pointer = ifd(&mut bytes, &primary) + 12 * (primary.len() - 1) + 8;
+        let offset = (bytes.len() as u32).to_le_bytes();
+        bytes[pointer..pointer + 4].copy_from_slice(&offset);
+        ifd(&mut bytes, &exif);
+        bytes
+    }
+}
+
+const MODEL: u16 = 0x0110;
+const ORIENTATION: u16 = 0x0112;
+const DATE_TIME: u16 = 0x0132;
+const EXIF_IFD: u16 = 0x8769;
+const EXPOSURE_TIME: u16 = 0x829a;
+const ISO: u16 = 0x8827;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const EXPOSURE_BIAS: u16 = 0x9204;
+const PIXEL_X_DIMENSION: u16 = 0xa002;
+const PIXEL_Y_DIMENSION: u16 = 0xa003;
+
+enum Value {
+    Ascii(String),
+    Short(u16),
+    Long(u32),
+    Rational((u32, u32)),
+    SRational((i32, i32)),
+}
+impl Value {
+    /// The TIFF type, the count and the bytes.
+    fn encode(&self) -> (u16, u32, Vec<u8>) {
+        match self {
+            Value::Ascii(text) => {
+                let mut bytes = text.replace('\0', "").into_bytes();
+                bytes.push(0);
+                (2, bytes.len() as u32, bytes)
+            },
+            Value::Short(value) => (3, 1, value.to_le_bytes().to_vec()),
+            Value::Long(value) => (4, 1, value.to_le_bytes().to_vec()),
+            Value::Rational((n, d)) => (5, 1, [n.to_le_bytes(), d.to_le_bytes()].concat()),
+            Value::SRational((n, d)) => (10, 1, [n.to_le_bytes(), d.to_le_bytes()].concat()),
+        }
+    }
+}
+
+/// Appends an IFD with `entries`, in tag order, and no next IFD. Returns where its entries start.
+fn ifd(bytes: &mut Vec<u8>, entries: &[(u16, Value)]) -> usize {
+    let start = bytes.len() + 2;
+    let mut data = start + 12 * entries.len() + 4;
+    let mut values = Vec::new();
+    bytes.extend((entries.len() as u16).to_le_bytes());
+    for (tag, value) in entries {
+        let (kind, count, mut value) = value.encode();
+        bytes.extend(tag.to_le_bytes());
+        bytes.extend(kind.to_le_bytes());
+        bytes.extend(count.to_le_bytes());
+        if value.len() <= 4 {
+            value.resize(4, 0);
+            bytes.extend(value);
+        } else {
+            bytes.extend((data as u32).to_le_bytes());
+            if value.len() % 2 == 1 {value.push(0);}
+            data += value.len();
+            values.extend(value);
+        }
+    }
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(values);
+    start
+}
+
+/// `time` as EXIF writes it, `YYYY:MM:DD HH:MM:SS` in UTC.
+fn date_time(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+    // Days to a civil date, from Howard Hinnant's date algorithms.
+    let z = days as i64 + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}:{month:02}:{day:02} {:02}:{:02}:{:02}", rest / 3600, rest % 3600 / 60, rest % 60)
+}
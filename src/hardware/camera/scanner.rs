@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use image::{GrayImage, Luma, RgbaImage};
+use imageproc::integral_image::{integral_image, sum_image_pixels};
+
+mod barcode;
+mod qr;
+
+/// How long a code has to be out of sight before it is reported again.
+const FORGET: Duration = Duration::from_secs(2);
+
+/// A QR code or barcode found in a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Code {
+    pub text: String,
+    /// Clockwise from the top left of the code, in frame pixels.
+    pub corners: [(f32, f32); 4],
+}
+
+/// A run of pixels along a line: where it starts, its length and whether it is dark.
+type Run = (u32, u32, bool);
+
+fn runs(pixels: impl Iterator<Item = bool>) -> Vec<Run> {
+    let mut runs: Vec<Run> = Vec::new();
+    for (i, dark) in pixels.enumerate() {
+        match runs.last_mut() {
+            Some(run) if run.2 == dark => run.1 += 1,
+            _ => runs.push((i as u32, 1, dark)),
+        }
+    }
+    runs
+}
+
+/// Pixels in blocks of this size share a threshold.
+const BLOCK: u32 = 8;
+/// Blocks with a smaller spread than this between their darkest and lightest pixels have nothing printed in them.
+const CONTRAST: u8 = 24;
+
+/// Darker than halfway between the darkest and lightest pixels nearby is dark, 0, so lighting does not have to
+/// be even and blurred edges stay where they are. Where nothing nearby is printed, darker than the mean is.
+fn binarize(frame: &RgbaImage) -> GrayImage {
+    let gray = image::imageops::grayscale(frame);
+    let (width, height) = gray.dimensions();
+    let (columns, rows) = (width.div_ceil(BLOCK), height.div_ceil(BLOCK));
+    let midpoints = (0..rows * columns).map(|i| {
+        let (bx, by) = (i % columns * BLOCK, i / columns * BLOCK);
+        let pixels = (by..(by + BLOCK).min(height)).flat_map(|y| (bx..(bx + BLOCK).min(width)).map(move |x| (x, y)));
+        let (low, high) = pixels.fold((255, 0), |(low, high), (x, y)| {
+            let value = gray.get_pixel(x, y)[0];
+            (value.min(low), value.max(high))
+        });
+        (high - low >= CONTRAST).then_some((low as u32 + high as u32) / 2)
+    }).collect::<Vec<_>>();
+    let thresholds = (0..rows * columns).map(|i| {
+        let (column, row) = (i % columns, i / columns);
+        let near = (row.saturating_sub(2)..(row + 3).min(rows))
+            .flat_map(|r| (column.saturating_sub(2)..(column + 3).min(columns)).map(move |c| r * columns + c))
+            .filter_map(|j| midpoints[j as usize]).collect::<Vec<_>>();
+        (!near.is_empty()).then(|| near.iter().sum::<u32>() / near.len() as u32)
+    }).collect::<Vec<_>>();
+    let radius = (width.min(height) / 16).max(4);
+    let integral = integral_image::<_, u32>(&gray);
+    GrayImage::from_fn(width, height, |x, y| {
+        let value = gray.get_pixel(x, y)[0] as u32;
+        let dark = match thresholds[(y / BLOCK * columns + x / BLOCK) as usize] {
+            Some(threshold) => value < threshold,
+            None => {
+                let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+                let (x1, y1) = ((x + radius).min(width - 1), (y + radius).min(height - 1));
+                let mean = sum_image_pixels(&integral, x0, y0, x1, y1)[0] / ((x1 - x0 + 1) * (y1 - y0 + 1));
+                value * 16 < mean * 15
+            },
+        };
+        Luma([if dark {0} else {255}])
+    })
+}
+
+fn detect(frame: &RgbaImage) -> Vec<Code> {
+    let image = binarize(frame);
+    let mut codes = qr::detect(&image);
+    codes.extend(barcode::detect(&image));
+    codes
+}
+
+/// Reads codes from frames on a thread of its own, frames arriving while it is busy are skipped. A code is
+/// reported when it comes into sight, not for every frame it is in.
+pub(crate) struct Scanner {
+    frames: SyncSender<RgbaImage>,
+    /// Set while the thread waits for a frame, so one is only copied when it will be read.
+    waiting: Arc<AtomicBool>,
+    found: Arc<Mutex<Vec<Code>>>,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        let (frames, receiver) = sync_channel::<RgbaImage>(1);
+        let waiting = Arc::new(AtomicBool::new(false));
+        let found = Arc::new(Mutex::new(Vec::new()));
+        let (ready, shared) = (waiting.clone(), found.clone());
+        std::thread::spawn(move || {
+            let mut seen = HashMap::<String, Instant>::new();
+            loop {
+                ready.store(true, Ordering::Release);
+                let Ok(frame) = receiver.recv() else {break};
+                let now = Instant::now();
+                for code in detect(&frame) {
+                    if seen.insert(code.text.clone(), now).is_none_or(|last| now - last > FORGET) {
+                        shared.lock().unwrap().push(code);
+                    }
+                }
+                seen.retain(|_, last| now - *last <= FORGET);
+            }
+        });
+        Scanner{frames, waiting, found}
+    }
+
+    pub fn analyze(&self, frame: &RgbaImage) {
+        if self.waiting.swap(false, Ordering::AcqRel) {let _ = self.frames.try_send(frame.clone());}
+    }
+
+    pub fn codes(&self) -> Vec<Code> {std::mem::take(&mut *self.found.lock().unwrap())}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use image::imageops::{flip_horizontal, overlay, rotate90, rotate180};
+    use imageproc::filter::gaussian_blur_f32;
+    use imageproc::geometric_transformations::{warp, Interpolation, Projection};
+    use qrcode::{Color, EcLevel, QrCode, Version};
+
+    const LIGHT: Rgba<u8> = Rgba([235, 235, 235, 255]);
+
+    /// `code` with a quiet zone of four modules, each `module` pixels wide.
+    fn render(code: &QrCode, module: u32) -> RgbaImage {
+        let width = code.width() as i64;
+        let colors = code.to_colors();
+        let size = (width as u32 + 8) * module;
+        RgbaImage::from_fn(size, size, |x, y| {
+            let (x, y) = ((x / module) as i64 - 4, (y / module) as i64 - 4);
+            let inside = (0..width).contains(&x) && (0..width).contains(&y);
+            if inside && colors[(y * width + x) as usize] == Color::Dark {Rgba([20, 20, 20, 255])} else {LIGHT}
+        })
+    }
+
+    /// `image` moved by `projection` onto a frame half as large again.
+    fn place(image: &RgbaImage, projection: Projection) -> RgbaImage {
+        let mut frame = RgbaImage::from_pixel(image.width() * 3 / 2, image.height() * 3 / 2, LIGHT);
+        overlay(&mut frame, image, 0, 0);
+        warp(&frame, &projection, Interpolation::Bilinear, LIGHT)
+    }
+
+    /// `image` turned by `angle` about its centre, in the middle of a frame half as large again.
+    fn turn(image: &RgbaImage, angle: f32) -> RgbaImage {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        place(image, Projection::translate(width * 0.75, height * 0.75) * Projection::rotate(angle) * Projection::translate(-width / 2.0, -height / 2.0))
+    }
+
+    fn texts(frame: &RgbaImage) -> Vec<String> {detect(frame).into_iter().map(|code| code.text).collect()}
+
+    fn text(version: i16) -> String {(0..version * 3).map(|i| (b'a' + ((i * 7 + version) % 26) as u8) as char).collect()}
+
+    #[test]
+    fn qr_versions() {
+        for version in 1..=15 {
+            for level in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H] {
+                let text = text(version);
+                let code = QrCode::with_version(&text, Version::Normal(version), level).unwrap();
+                let frame = place(&render(&code, 3), Projection::translate(20.0, 20.0));
+                assert_eq!(texts(&frame), [text], "version {version} at {level:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn qr_blurred() {
+        for version in [2, 7, 10, 13, 15] {
+            for module in [3, 4, 6] {
+                let text = text(version);
+                let code = QrCode::with_version(&text, Version::Normal(version), EcLevel::M).unwrap();
+                let frame = gaussian_blur_f32(&place(&render(&code, module), Projection::translate(20.0, 20.0)), 1.0);
+                assert_eq!(texts(&frame), [text], "version {version} with {module} pixel modules");
+            }
+        }
+    }
+
+    #[test]
+    fn qr_modes() {
+        for text in ["01234567890123456789", "HELLO WORLD $%*+-./:", "maverick://pair?id=42", "héllo wörld ✓"] {
+            let frame = place(&render(&QrCode::new(text).unwrap(), 4), Projection::translate(20.0, 20.0));
+            assert_eq!(texts(&frame), [text]);
+        }
+    }
+
+    #[test]
+    fn qr_turned() {
+        let text = text(5);
+        let code = render(&QrCode::with_version(&text, Version::Normal(5), EcLevel::M).unwrap(), 4);
+        for degrees in [10.0f32, 30.0, 45.0, 90.0, 135.0, 200.0, 290.0] {
+            assert_eq!(texts(&turn(&code, degrees.to_radians())), [text.as_str()], "turned by {degrees} degrees");
+        }
+    }
+
+    #[test]
+    fn qr_perspective() {
+        let text = text(15);
+        let code = render(&QrCode::with_version(&text, Version::Normal(15), EcLevel::M).unwrap(), 5);
+        let s = code.width() as f32;
+        let corners = [
+            [(10.0, 4.0), (s + 30.0, 20.0), (s + 14.0, s + 2.0), (24.0, s + 36.0)],
+            [(30.0, 30.0), (s, 10.0), (s + 40.0, s + 40.0), (0.0, s + 10.0)],
+            // Tilted away at the bottom right, the estimate from the finder patterns is two sizes off.
+            [(0.0, 0.0), (s * 0.7, s * 0.1), (s * 0.7, s * 0.7), (s * 0.1, s * 0.7)],
+        ];
+        for to in corners {
+            let projection = Projection::from_control_points([(0.0, 0.0), (s, 0.0), (s, s), (0.0, s)], to).unwrap();
+            assert_eq!(texts(&place(&code, projection)), [text.as_str()], "corners at {to:?}");
+        }
+    }
+
+    #[test]
+    fn qr_mirrored() {
+        let text = text(3);
+        let code = render(&QrCode::with_version(&text, Version::Normal(3), EcLevel::Q).unwrap(), 4);
+        assert_eq!(texts(&flip_horizontal(&turn(&code, 0.3))), [text]);
+    }
+
+    #[test]
+    fn qr_two_codes() {
+        let (first, second) = (render(&QrCode::new("first").unwrap(), 4), render(&QrCode::new("second one").unwrap(), 4));
+        let mut frame = RgbaImage::from_pixel(first.width() + second.width() + 60, first.height().max(second.height()) + 40, LIGHT);
+        overlay(&mut frame, &first, 20, 20);
+        overlay(&mut frame, &second, first.width() as i64 + 40, 20);
+        let mut texts = texts(&frame);
+        texts.sort();
+        assert_eq!(texts, ["first", "second one"]);
+    }
+
+    #[test]
+    fn barcodes() {
+        let fixtures = [
+            (include_bytes!("scanner/fixtures/ean13.png").as_slice(), "4006381333931"),
+            (include_bytes!("scanner/fixtures/upca.png"), "036000291452"),
+            (include_bytes!("scanner/fixtures/ean8.png"), "96385074"),
+            (include_bytes!("scanner/fixtures/code128b.png"), "Maverick-128"),
+            (include_bytes!("scanner/fixtures/code128c.png"), "12345678"),
+        ];
+        for (png, text) in fixtures {
+            let bars = image::load_from_memory(png).unwrap().into_rgba8();
+            let mut frame = RgbaImage::from_pixel(bars.width() + 40, bars.height() + 40, LIGHT);
+            overlay(&mut frame, &bars, 20, 20);
+            for frame in [rotate90(&frame), rotate180(&frame), turn(&frame, 0.08), frame] {
+                assert_eq!(texts(&frame), [text]);
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_in_noise() {
+        let mut state = 7u32;
+        let noise = RgbaImage::from_fn(320, 240, |_, _| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let value = (state >> 24) as u8;
+            Rgba([value, value, value, 255])
+        });
+        assert!(detect(&noise).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use image::GrayImage;
+
+use super::{Code, Run, runs};
+
+/// Rows, and columns, read across the image.
+const LINES: u32 = 48;
+/// The widths of the EAN and UPC digits, light first on the left half and dark first on the right.
+const DIGITS: [[u32; 4]; 10] = [
+    [3, 2, 1, 1], [2, 2, 2, 1], [2, 1, 2, 2], [1, 4, 1, 1], [1, 1, 3, 2],
+    [1, 2, 3, 1], [1, 1, 1, 4], [1, 3, 1, 2], [1, 2, 1, 3], [3, 1, 1, 2],
+];
+/// Which of the left digits of an EAN-13 are mirrored, by its first digit, leftmost in the highest bit.
+const PARITIES: [u8; 10] = [0x00, 0x0b, 0x0d, 0x0e, 0x13, 0x19, 0x1c, 0x15, 0x16, 0x1a];
+/// The widths of the Code 128 symbols by value, dark first. 103 to 105 are the starts.
+const CODE_128: [[u32; 6]; 106] = [
+    [2, 1, 2, 2, 2, 2], [2, 2, 2, 1, 2, 2], [2, 2, 2, 2, 2, 1], [1, 2, 1, 2, 2, 3], [1, 2, 1, 3, 2, 2],
+    [1, 3, 1, 2, 2, 2], [1, 2, 2, 2, 1, 3], [1, 2, 2, 3, 1, 2], [1, 3, 2, 2, 1, 2], [2, 2, 1, 2, 1, 3],
+    [2, 2, 1, 3, 1, 2], [2, 3, 1, 2, 1, 2], [1, 1, 2, 2, 3, 2], [1, 2, 2, 1, 3, 2], [1, 2, 2, 2, 3, 1],
+    [1, 1, 3, 2, 2, 2], [1, 2, 3, 1, 2, 2], [1, 2, 3, 2, 2, 1], [2, 2, 3, 2, 1, 1], [2, 2, 1, 1, 3, 2],
+    [2, 2, 1, 2, 3, 1], [2, 1, 3, 2, 1, 2], [2, 2, 3, 1, 1, 2], [3, 1, 2, 1, 3, 1], [3, 1, 1, 2, 2, 2],
+    [3, 2, 1, 1, 2, 2], [3, 2, 1, 2, 2, 1], [3, 1, 2, 2, 1, 2], [3, 2, 2, 1, 1, 2], [3, 2, 2, 2, 1, 1],
+    [2, 1, 2, 1, 2, 3], [2, 1, 2, 3, 2, 1], [2, 3, 2, 1, 2, 1], [1, 1, 1, 3, 2, 3], [1, 3, 1, 1, 2, 3],
+    [1, 3, 1, 3, 2, 1], [1, 1, 2, 3, 1, 3], [1, 3, 2, 1, 1, 3], [1, 3, 2, 3, 1, 1], [2, 1, 1, 3, 1, 3],
+    [2, 3, 1, 1, 1, 3], [2, 3, 1, 3, 1, 1], [1, 1, 2, 1, 3, 3], [1, 1, 2, 3, 3, 1], [1, 3, 2, 1, 3, 1],
+    [1, 1, 3, 1, 2, 3], [1, 1, 3, 3, 2, 1], [1, 3, 3, 1, 2, 1], [3, 1, 3, 1, 2, 1], [2, 1, 1, 3, 3, 1],
+    [2, 3, 1, 1, 3, 1], [2, 1, 3, 1, 1, 3], [2, 1, 3, 3, 1, 1], [2, 1, 3, 1, 3, 1], [3, 1, 1, 1, 2, 3],
+    [3, 1, 1, 3, 2, 1], [3, 3, 1, 1, 2, 1], [3, 1, 2, 1, 1, 3], [3, 1, 2, 3, 1, 1], [3, 3, 2, 1, 1, 1],
+    [3, 1, 4, 1, 1, 1], [2, 2, 1, 4, 1, 1], [4, 3, 1, 1, 1, 1], [1, 1, 1, 2, 2, 4], [1, 1, 1, 4, 2, 2],
+    [1, 2, 1, 1, 2, 4], [1, 2, 1, 4, 2, 1], [1, 4, 1, 1, 2, 2], [1, 4, 1, 2, 2, 1], [1, 1, 2, 2, 1, 4],
+    [1, 1, 2, 4, 1, 2], [1, 2, 2, 1, 1, 4], [1, 2, 2, 4, 1, 1], [1, 4, 2, 1, 1, 2], [1, 4, 2, 2, 1, 1],
+    [2, 4, 1, 2, 1, 1], [2, 2, 1, 1, 1, 4], [4, 1, 3, 1, 1, 1], [2, 4, 1, 1, 1, 2], [1, 3, 4, 1, 1, 1],
+    [1, 1, 1, 2, 4, 2], [1, 2, 1, 1, 4, 2], [1, 2, 1, 2, 4, 1], [1, 1, 4, 2, 1, 2], [1, 2, 4, 1, 1, 2],
+    [1, 2, 4, 2, 1, 1], [4, 1, 1, 2, 1, 2], [4, 2, 1, 1, 1, 2], [4, 2, 1, 2, 1, 1], [2, 1, 2, 1, 4, 1],
+    [2, 1, 4, 1, 2, 1], [4, 1, 2, 1, 2, 1], [1, 1, 1, 1, 4, 3], [1, 1, 1, 3, 4, 1], [1, 3, 1, 1, 4, 1],
+    [1, 1, 4, 1, 1, 3], [1, 1, 4, 3, 1, 1], [4, 1, 1, 1, 1, 3], [4, 1, 1, 3, 1, 1], [1, 1, 3, 1, 4, 1],
+    [1, 1, 4, 1, 3, 1], [3, 1, 1, 1, 4, 1], [4, 1, 1, 1, 3, 1], [2, 1, 1, 4, 1, 2], [2, 1, 1, 2, 1, 4],
+    [2, 1, 1, 2, 3, 2],
+];
+const STOP: [u32; 7] = [2, 3, 3, 1, 1, 1, 2];
+
+/// How far `counts` are from the proportions of `pattern`, on average per module. `None` when a single run is
+/// off by more than `individual` modules.
+fn variance(counts: &[u32], pattern: &[u32], individual: f32) -> Option<f32> {
+    let total = counts.iter().sum::<u32>() as f32;
+    let modules = pattern.iter().sum::<u32>() as f32;
+    let unit = total / modules;
+    let mut sum = 0.0;
+    for (c, p) in counts.iter().zip(pattern) {
+        let off = (*c as f32 - *p as f32 * unit).abs() / unit;
+        if off > individual {return None;}
+        sum += off;
+    }
+    Some(sum / modules)
+}
+
+/// The value of the pattern in `patterns` closest to `counts`, when it is within `average`.
+fn closest<const N: usize>(counts: &[u32], patterns: &[[u32; N]], average: f32) -> Option<(usize, f32)> {
+    patterns.iter().enumerate()
+        .filter_map(|(i, p)| variance(counts, p, 0.7).map(|v| (i, v)))
+        .filter(|(_, v)| *v < average)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Reads the EAN-13, UPC-A, EAN-8 and Code 128 barcodes crossing rows or columns of `image`, binarized with 0
+/// for dark.
+pub fn detect(image: &GrayImage) -> Vec<Code> {
+    let (width, height) = image.dimensions();
+    // By text, the extent along the lines and the first and last line it was read on.
+    let mut found = HashMap::<String, (bool, u32, u32, u32, u32)>::new();
+    for vertical in [false, true] {
+        let (length, lines) = if vertical {(height, width)} else {(width, height)};
+        let step = (lines / LINES).max(1);
+        for line in (step / 2..lines).step_by(step as usize) {
+            let row = runs((0..length).map(|i| {
+                let (x, y) = if vertical {(line, i)} else {(i, line)};
+                image.get_pixel(x, y)[0] == 0
+            }));
+            for (text, start, end) in scan(&row, length) {
+                let extent = found.entry(text).or_insert((vertical, start, end, line, line));
+                if extent.0 != vertical {continue;}
+                extent.1 = extent.1.min(start);
+                extent.2 = extent.2.max(end);
+                extent.3 = extent.3.min(line);
+                extent.4 = extent.4.max(line);
+            }
+        }
+    }
+    // Noise reads as a barcode now and then, but not on two lines.
+    found.into_iter().filter(|(_, extent)| extent.4 > extent.3).map(|(text, (vertical, start, end, first, last))| {
+        let (start, end, first, last) = (start as f32, end as f32, first as f32, last as f32 + 1.0);
+        let corners = match vertical {
+            false => [(start, first), (end, first), (end, last), (start, last)],
+            true => [(first, start), (last, start), (last, end), (first, end)],
+        };
+        Code{text, corners}
+    }).collect()
+}
+
+/// The barcodes along `row`, read both ways, with where they start and end.
+fn scan(row: &[Run], length: u32) -> Vec<(String, u32, u32)> {
+    let reversed = row.iter().rev().map(|(start, len, dark)| (length - start - len, *len, *dark)).collect::<Vec<_>>();
+    let mut codes = Vec::new();
+    for runs in [row, &reversed] {
+        let mut i = 1;
+        while i < runs.len() {
+            let read = match runs[i].2 && !runs[i - 1].2 {
+                true => ean(runs, i).or_else(|| code_128(runs, i)),
+                false => None,
+            };
+            match read {
+                Some((text, last)) => {
+                    let (first, last_run) = (runs[i], runs[last]);
+                    codes.push((text, first.0.min(last_run.0), (first.0 + first.1).max(last_run.0 + last_run.1)));
+                    i = last + 1;
+                },
+                None => i += 1,
+            }
+        }
+    }
+    codes
+}
+
+fn lengths(runs: &[Run]) -> Vec<u32> {runs.iter().map(|r| r.1).collect()}
+
+/// A light run at `i` at least `modules` wide, or the end of the line.
+fn quiet(runs: &[Run], i: usize, unit: f32, modules: f32) -> bool {
+    runs.get(i).is_none_or(|r| !r.2 && r.1 as f32 >= unit * modules)
+}
+
+/// Reads an EAN-13, UPC-A or EAN-8 starting with the guard at `i`. Returns its digits and its last run.
+fn ean(runs: &[Run], i: usize) -> Option<(String, usize)> {
+    let guard = lengths(runs.get(i..i + 3)?);
+    if variance(&guard, &[1, 1, 1], 0.7)? > 0.48 {return None;}
+    let unit = guard.iter().sum::<u32>() as f32 / 3.0;
+    if !quiet(runs, i - 1, unit, 3.0) {return None;}
+    let mirrored = DIGITS.map(|mut p| {p.reverse(); p});
+    [6, 4].into_iter().find_map(|half| {
+        let end = i + 3 + half * 8 + 5 + 3;
+        let counts = lengths(runs.get(i..end)?);
+        let mut digits = Vec::new();
+        let mut parity = 0u8;
+        for d in 0..half {
+            let counts = &counts[3 + d * 4..7 + d * 4];
+            let (digit, mirror) = match (closest(counts, &DIGITS, 0.48), closest(counts, &mirrored, 0.48)) {
+                (Some(l), Some(g)) => if l.1 <= g.1 {(l.0, false)} else {(g.0, true)},
+                (Some(l), None) => (l.0, false),
+                (None, Some(g)) => (g.0, true),
+                (None, None) => return None,
+            };
+            if half == 4 && mirror {return None;}
+            parity = parity << 1 | mirror as u8;
+            digits.push(digit as u8);
+        }
+        let middle = 3 + half * 4;
+        if variance(&counts[middle..middle + 5], &[1, 1, 1, 1, 1], 0.7)? > 0.48 {return None;}
+        for d in 0..half {
+            let start = middle + 5 + d * 4;
+            digits.push(closest(&counts[start..start + 4], &DIGITS, 0.48)?.0 as u8);
+        }
+        if variance(&counts[end - i - 3..], &[1, 1, 1], 0.7)? > 0.48 || !quiet(runs, end, unit, 3.0) {return None;}
+        if half == 6 {digits.insert(0, PARITIES.iter().position(|p| *p == parity)? as u8);}
+        let sum = digits[..digits.len() - 1].iter().rev().enumerate()
+            .map(|(k, d)| *d as u32 * if k % 2 == 0 {3} else {1}).sum::<u32>();
+        if (10 - sum % 10) % 10 != *digits.last()? as u32 {return None;}
+        // A UPC-A is an EAN-13 starting with 0.
+        let digits = if half == 6 && digits[0] == 0 {&digits[1..]} else {&digits[..]};
+        Some((digits.iter().map(|d| (b'0' + d) as char).collect(), end - 1))
+    })
+}
+
+/// Reads a Code 128 starting with the start symbol at `i`. Returns its text and its last run.
+fn code_128(runs: &[Run], i: usize) -> Option<(String, usize)> {
+    let counts = lengths(runs.get(i..i + 6)?);
+    let (start, _) = closest(&counts, &CODE_128[103..], 0.25)?;
+    let unit = counts.iter().sum::<u32>() as f32 / 11.0;
+    if !quiet(runs, i - 1, unit, 5.0) {return None;}
+    let mut values = vec![103 + start];
+    let mut position = i + 6;
+    loop {
+        if let Some(stop) = runs.get(position..position + 7)
+            && variance(&lengths(stop), &STOP, 0.7).is_some_and(|v| v < 0.25) {break;}
+        let (value, _) = closest(&lengths(runs.get(position..position + 6)?), &CODE_128[..103], 0.25)?;
+        values.push(value);
+        position += 6;
+    }
+    if values.len() < 3 || !quiet(runs, position + 7, unit, 5.0) {return None;}
+    let check = values.pop()?;
+    let sum = values.iter().enumerate().map(|(k, v)| v * k.max(1)).sum::<usize>();
+    if sum % 103 != check {return None;}
+    Some((code_128_text(&values)?, position + 6))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CodeSet {A, B, C}
+
+fn code_128_text(values: &[usize]) -> Option<String> {
+    let mut set = [CodeSet::A, CodeSet::B, CodeSet::C][values[0] - 103];
+    let mut shift = false;
+    let mut text = String::new();
+    for value in &values[1..] {
+        let current = match (shift, set) {
+            (true, CodeSet::A) => CodeSet::B,
+            (true, CodeSet::B) => CodeSet::A,
+            (_, set) => set,
+        };
+        shift = false;
+        match (current, value) {
+            (CodeSet::C, 0..=99) => text.push_str(&format!("{value:02}")),
+            (CodeSet::A, 0..=63) | (CodeSet::B, 0..=95) => text.push((32 + *value as u8) as char),
+            (CodeSet::A, 64..=95) => text.push((*value as u8 - 64) as char),
+            (CodeSet::A | CodeSet::B, 98) => shift = true,
+            (CodeSet::A | CodeSet::B, 99) => set = CodeSet::C,
+            (CodeSet::A | CodeSet::C, 100) => set = CodeSet::B,
+            (CodeSet::B | CodeSet::C, 101) => set = CodeSet::A,
+            // The function codes and FNC4 carry no text.
+            (_, 96..=102) => {},
+            _ => return None,
+        }
+    }
+    Some(text)
+}
@@ -0,0 +1,249 @@
+use image::GrayImage;
+use imageproc::geometric_transformations::Projection;
+
+mod decode;
+mod reed_solomon;
+
+use super::{Code, runs};
+
+/// Candidate finder patterns beyond this many are ignored, the strongest are kept.
+const MAX_FINDERS: usize = 12;
+
+/// A finder pattern: its centre, the size of a module there and how many rows it was found on.
+#[derive(Debug, Clone, Copy)]
+struct Finder {
+    x: f32,
+    y: f32,
+    module: f32,
+    count: u32,
+}
+
+/// Reads the QR codes in `image`, binarized with 0 for dark.
+pub fn detect(image: &GrayImage) -> Vec<Code> {
+    let finders = finders(image);
+    let mut used = vec![false; finders.len()];
+    let mut codes = Vec::new();
+    for a in 0..finders.len() {
+        for b in a + 1..finders.len() {
+            for c in b + 1..finders.len() {
+                if used[a] || used[b] || used[c] {continue;}
+                if let Some(code) = read(image, [finders[a], finders[b], finders[c]]) {
+                    used[a] = true;
+                    used[b] = true;
+                    used[c] = true;
+                    codes.push(code);
+                }
+            }
+        }
+    }
+    codes
+}
+
+fn dark(image: &GrayImage, x: i32, y: i32) -> Option<bool> {
+    let (width, height) = image.dimensions();
+    (x >= 0 && y >= 0 && (x as u32) < width && (y as u32) < height).then(|| image.get_pixel(x as u32, y as u32)[0] == 0)
+}
+
+/// The size of a module when `counts` are in the proportions of `pattern`, each within half a module.
+fn matches(counts: &[u32], pattern: &[u32]) -> Option<f32> {
+    let total = counts.iter().sum::<u32>() as f32;
+    let module = total / pattern.iter().sum::<u32>() as f32;
+    if module < 1.0 {return None;}
+    counts.iter().zip(pattern).all(|(c, p)| (*c as f32 - *p as f32 * module).abs() < *p as f32 * module / 2.0).then_some(module)
+}
+
+/// The five runs, dark first, crossing the dark run at (`x`, `y`) along (`dx`, `dy`), the middle three no longer
+/// than `limit`. Returns the centre of the middle run along the line with the runs.
+fn cross(image: &GrayImage, x: f32, y: f32, (dx, dy): (i32, i32), limit: u32) -> Option<(f32, [u32; 5])> {
+    let (x, y) = (x as i32, y as i32);
+    let at = |k: i32| dark(image, x + k * dx, y + k * dy);
+    if at(0) != Some(true) {return None;}
+    let mut counts = [0u32; 5];
+    let mut k = 0;
+    while at(k) == Some(true) {k += 1;}
+    let end = k;
+    for (i, shade) in [(3, false), (4, true)] {
+        while at(k) == Some(shade) && counts[i] <= limit {counts[i] += 1; k += 1;}
+    }
+    k = -1;
+    while at(k) == Some(true) {k -= 1;}
+    let start = k + 1;
+    for (i, shade) in [(1, false), (0, true)] {
+        while at(k) == Some(shade) && counts[i] <= limit {counts[i] += 1; k -= 1;}
+    }
+    counts[2] = (end - start) as u32;
+    // The outer dark runs may run into neighbouring modules.
+    if counts.contains(&0) || counts[1..4].iter().any(|c| *c > limit) {return None;}
+    let base = if dx != 0 {x} else {y};
+    Some((base as f32 + (start + end) as f32 / 2.0, counts))
+}
+
+/// Finder patterns are dark, light, dark, light, dark in the proportions 1:1:3:1:1 across both ways.
+fn finders(image: &GrayImage) -> Vec<Finder> {
+    let (width, height) = image.dimensions();
+    let mut finders: Vec<Finder> = Vec::new();
+    for y in 0..height {
+        let row = runs((0..width).map(|x| image.get_pixel(x, y)[0] == 0));
+        for window in row.windows(5) {
+            if !window[0].2 {continue;}
+            let counts = window.iter().map(|r| r.1).collect::<Vec<_>>();
+            if matches(&counts, &[1, 1, 3, 1, 1]).is_none() {continue;}
+            let total = counts.iter().sum::<u32>();
+            let x = window[2].0 as f32 + window[2].1 as f32 / 2.0;
+            let Some((cy, vertical)) = cross(image, x, y as f32, (0, 1), total) else {continue;};
+            let Some(vertical) = matches(&vertical, &[1, 1, 3, 1, 1]) else {continue;};
+            let Some((cx, horizontal)) = cross(image, x, cy, (1, 0), total) else {continue;};
+            let Some(horizontal) = matches(&horizontal, &[1, 1, 3, 1, 1]) else {continue;};
+            let module = (vertical + horizontal) / 2.0;
+            let found = finders.iter_mut().find(|f| {
+                (f.x - cx).abs() <= f.module.max(module) && (f.y - cy).abs() <= f.module.max(module)
+                    && (0.5..2.0).contains(&(f.module / module))
+            });
+            match found {
+                Some(f) => {
+                    let n = f.count as f32;
+                    f.x = (f.x * n + cx) / (n + 1.0);
+                    f.y = (f.y * n + cy) / (n + 1.0);
+                    f.module = (f.module * n + module) / (n + 1.0);
+                    f.count += 1;
+                },
+                None => finders.push(Finder{x: cx, y: cy, module, count: 1}),
+            }
+        }
+    }
+    finders.retain(|f| f.count >= 2);
+    finders.sort_by_key(|f| std::cmp::Reverse(f.count));
+    finders.truncate(MAX_FINDERS);
+    finders
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {(a.0 - b.0).hypot(a.1 - b.1)}
+
+/// Reads the code with the three finder patterns `finders`, if they make one.
+fn read(image: &GrayImage, finders: [Finder; 3]) -> Option<Code> {
+    let modules = finders.map(|f| f.module);
+    let module = modules.iter().sum::<f32>() / 3.0;
+    if modules.iter().cloned().fold(0.0, f32::max) > modules.iter().cloned().fold(f32::INFINITY, f32::min) * 1.5 {return None;}
+
+    // The top left pattern is opposite the longest side.
+    let points = finders.map(|f| (f.x, f.y));
+    let sides = [distance(points[1], points[2]), distance(points[0], points[2]), distance(points[0], points[1])];
+    let corner = (0..3).max_by(|a, b| sides[*a].total_cmp(&sides[*b]))?;
+    let top_left = points[corner];
+    let (p, q) = (points[(corner + 1) % 3], points[(corner + 2) % 3]);
+    let (legs, hypotenuse) = ((distance(top_left, p), distance(top_left, q)), sides[corner]);
+    if legs.0 < legs.1 * 0.6 || legs.1 < legs.0 * 0.6 {return None;}
+    let expected = (legs.0 * legs.0 + legs.1 * legs.1).sqrt();
+    if (hypotenuse - expected).abs() > expected * 0.2 {return None;}
+    let clockwise = (p.0 - top_left.0) * (q.1 - top_left.1) - (p.1 - top_left.1) * (q.0 - top_left.0) > 0.0;
+    let ((top_right, bottom_left), legs) = if clockwise {((p, q), legs)} else {((q, p), (legs.1, legs.0))};
+
+    // Modules found across the image are stretched when the symbol is turned, so measure along its sides.
+    let measure = |a, b| span(image, a, b).zip(span(image, b, a)).map(|(x, y)| (x + y) / 14.0).unwrap_or(module);
+    let (across, down) = (measure(top_left, top_right), measure(top_left, bottom_left));
+    let module = (across + down) / 2.0;
+    let estimate = ((legs.0 / across + legs.1 / down) / 2.0).round() as i32 + 7;
+    // Under perspective the finder patterns differ in size, which can put the estimate a size or more off.
+    let mut sizes = (estimate - 4..=estimate + 4).filter(|s| s % 4 == 1 && (21..=177).contains(s)).collect::<Vec<_>>();
+    sizes.sort_by_key(|s| (s - estimate).abs());
+    // A mirrored image, as from a front camera, reads as the transposed symbol.
+    for (top_right, bottom_left) in [(top_right, bottom_left), (bottom_left, top_right)] {
+        for size in &sizes {
+            if let Some(code) = sample(image, [top_left, top_right, bottom_left], module, *size as usize) {return Some(code);}
+        }
+    }
+    None
+}
+
+/// The width of the finder pattern centred on `from` along the line towards `to`, to the outside of its dark
+/// ring both ways.
+fn span(image: &GrayImage, from: (f32, f32), to: (f32, f32)) -> Option<f32> {
+    let length = distance(from, to);
+    let (dx, dy) = ((to.0 - from.0) / length, (to.1 - from.1) / length);
+    let mut width = 0.0;
+    for sign in [1.0, -1.0] {
+        let (mut changes, mut last, mut t) = (0, true, 0.0);
+        while changes < 3 {
+            t += 0.25;
+            if t > length / 2.0 {return None;}
+            let here = dark(image, (from.0 + sign * dx * t).floor() as i32, (from.1 + sign * dy * t).floor() as i32)?;
+            if here != last {
+                changes += 1;
+                last = here;
+            }
+        }
+        width += t - 0.125;
+    }
+    Some(width)
+}
+
+/// Samples a symbol of `size` modules from the centres of its finder patterns, corrected for perspective with
+/// its bottom right alignment pattern when it has one and that can be found.
+fn sample(image: &GrayImage, [top_left, top_right, bottom_left]: [(f32, f32); 3], module: f32, size: usize) -> Option<Code> {
+    let s = size as f32;
+    let mut projections = Vec::new();
+    if size > 21 {
+        let t = (s - 10.0) / (s - 7.0);
+        let estimate = (
+            top_left.0 + (top_right.0 - top_left.0 + bottom_left.0 - top_left.0) * t,
+            top_left.1 + (top_right.1 - top_left.1 + bottom_left.1 - top_left.1) * t,
+        );
+        for alignment in alignments(image, estimate, module) {
+            projections.push(Projection::from_control_points(
+                [(3.5, 3.5), (s - 3.5, 3.5), (s - 6.5, s - 6.5), (3.5, s - 3.5)],
+                [top_left, top_right, alignment, bottom_left],
+            ));
+        }
+    }
+    let bottom_right = (top_right.0 + bottom_left.0 - top_left.0, top_right.1 + bottom_left.1 - top_left.1);
+    projections.push(Projection::from_control_points(
+        [(3.5, 3.5), (s - 3.5, 3.5), (s - 3.5, s - 3.5), (3.5, s - 3.5)],
+        [top_left, top_right, bottom_right, bottom_left],
+    ));
+
+    for projection in projections.into_iter().flatten() {
+        let grid = (0..size).map(|y| (0..size).map(|x| {
+            let (px, py) = projection * (x as f32 + 0.5, y as f32 + 0.5);
+            dark(image, px.floor() as i32, py.floor() as i32).unwrap_or(false)
+        }).collect()).collect::<Vec<Vec<bool>>>();
+        if let Some(text) = decode::decode(&grid) {
+            let corners = [(0.0, 0.0), (s, 0.0), (s, s), (0.0, s)].map(|p| projection * p);
+            return Some(Code{text, corners});
+        }
+    }
+    None
+}
+
+/// Candidates beyond this many for the bottom right alignment pattern are not tried, the nearest are kept.
+const MAX_ALIGNMENTS: usize = 4;
+
+/// Looks for the centres of alignment patterns, a dark module ringed by light then dark ones, near `estimate`,
+/// nearest first. Data modules can look like one, so more than one may be worth trying.
+fn alignments(image: &GrayImage, estimate: (f32, f32), module: f32) -> Vec<(f32, f32)> {
+    let (width, height) = image.dimensions();
+    let limit = (module * 2.0).ceil() as u32;
+    let mut found: Vec<(f32, f32)> = Vec::new();
+    for reach in [4.0, 8.0, 16.0] {
+        let reach = reach * module;
+        let (x0, x1) = ((estimate.0 - reach).max(0.0) as u32, ((estimate.0 + reach) as u32).min(width));
+        let (y0, y1) = ((estimate.1 - reach).max(0.0) as u32, ((estimate.1 + reach) as u32).min(height));
+        for y in y0..y1 {
+            let row = runs((x0..x1).map(|x| image.get_pixel(x, y)[0] == 0));
+            for window in row.windows(3) {
+                if window[0].2 {continue;}
+                let counts = window.iter().map(|r| r.1).collect::<Vec<_>>();
+                if matches(&counts, &[1, 1, 1]).is_none_or(|m| !(0.5..2.0).contains(&(m / module))) {continue;}
+                let x = x0 as f32 + window[1].0 as f32 + window[1].1 as f32 / 2.0;
+                let Some((cy, vertical)) = cross(image, x, y as f32, (0, 1), limit) else {continue;};
+                if matches(&vertical[1..4], &[1, 1, 1]).is_none() {continue;}
+                let Some((cx, horizontal)) = cross(image, x, cy, (1, 0), limit) else {continue;};
+                if matches(&horizontal[1..4], &[1, 1, 1]).is_none() {continue;}
+                if found.iter().all(|f| distance(*f, (cx, cy)) > module) {found.push((cx, cy));}
+            }
+        }
+        if found.len() >= MAX_ALIGNMENTS {break;}
+    }
+    found.sort_by(|a, b| distance(*a, estimate).total_cmp(&distance(*b, estimate)));
+    found.truncate(MAX_ALIGNMENTS);
+    found
+}
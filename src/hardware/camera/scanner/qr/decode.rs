@@ -0,0 +1,221 @@
+use super::reed_solomon;
+
+/// Error correction codewords per block, by level in the order L, M, Q, H, then version.
+const ECC_PER_BLOCK: [[u8; 41]; 4] = [
+    [0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28],
+    [0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+    [0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30],
+];
+/// Error correction blocks, laid out like `ECC_PER_BLOCK`.
+const BLOCKS: [[u8; 41]; 4] = [
+    [0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+    [0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+    [0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],
+    [0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],
+];
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Reads the text of a sampled symbol, `grid[y][x]` being true for dark modules.
+pub fn decode(grid: &[Vec<bool>]) -> Option<String> {
+    let size = grid.len();
+    if !(21..=177).contains(&size) || size % 4 != 1 {return None;}
+    let version = (size - 17) / 4;
+    let (level, mask) = format(grid)?;
+
+    let function = function_modules(version, size);
+    let mut bits = Vec::new();
+    let mut right = size as i32 - 1;
+    while right >= 1 {
+        if right == 6 {right = 5;}
+        for vertical in 0..size {
+            for j in 0..2 {
+                let x = (right - j) as usize;
+                let upward = (right + 1) & 2 == 0;
+                let y = if upward {size - 1 - vertical} else {vertical};
+                if !function[y][x] {bits.push(grid[y][x] ^ masked(mask, x, y));}
+            }
+        }
+        right -= 2;
+    }
+    let codewords = bits.chunks_exact(8).map(|byte| byte.iter().fold(0u8, |acc, b| acc << 1 | *b as u8)).collect::<Vec<_>>();
+
+    let data = deinterleave(&codewords, version, level)?;
+    segments(&data, version)
+}
+
+/// The error correction level, as an index into the tables, and the mask, from whichever copy of the format
+/// information reads best.
+fn format(grid: &[Vec<bool>]) -> Option<(usize, u8)> {
+    let size = grid.len();
+    let bit = |x: usize, y: usize| grid[y][x] as u32;
+    let mut first = 0;
+    for x in 0..6 {first = first << 1 | bit(x, 8);}
+    for (x, y) in [(7, 8), (8, 8), (8, 7)] {first = first << 1 | bit(x, y);}
+    for y in (0..6).rev() {first = first << 1 | bit(8, y);}
+    let mut second = 0;
+    for y in (size - 7..size).rev() {second = second << 1 | bit(8, y);}
+    for x in size - 8..size {second = second << 1 | bit(x, 8);}
+
+    let (distance, data) = (0..32u32).map(|data| {
+        let mut remainder = data << 10;
+        for i in (0..5).rev() {
+            if remainder & (1 << (i + 10)) != 0 {remainder ^= 0x537 << i;}
+        }
+        let code = (data << 10 | remainder) ^ 0x5412;
+        ((code ^ first).count_ones().min((code ^ second).count_ones()), data)
+    }).min()?;
+    if distance > 3 {return None;}
+    // The level bits are 01 for L, 00 for M, 11 for Q and 10 for H.
+    let level = [1, 0, 3, 2][(data >> 3) as usize];
+    Some((level, (data & 7) as u8))
+}
+
+fn masked(mask: u8, x: usize, y: usize) -> bool {
+    match mask {
+        0 => (x + y).is_multiple_of(2),
+        1 => y.is_multiple_of(2),
+        2 => x.is_multiple_of(3),
+        3 => (x + y).is_multiple_of(3),
+        4 => (x / 3 + y / 2).is_multiple_of(2),
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3).is_multiple_of(2),
+        _ => ((x + y) % 2 + x * y % 3).is_multiple_of(2),
+    }
+}
+
+pub fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {return Vec::new();}
+    let size = version * 4 + 17;
+    let count = version / 7 + 2;
+    let step = if version == 32 {26} else {(version * 4 + count * 2 + 1) / (count * 2 - 2) * 2};
+    let mut positions = (0..count - 1).map(|i| size - 7 - i * step).collect::<Vec<_>>();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Marks the finder, timing and alignment patterns, and the format and version information.
+fn function_modules(version: usize, size: usize) -> Vec<Vec<bool>> {
+    let mut function = vec![vec![false; size]; size];
+    let mut mark = |x0: usize, y0: usize, width: usize, height: usize| {
+        for row in function.iter_mut().skip(y0).take(height) {
+            for module in row.iter_mut().skip(x0).take(width) {*module = true;}
+        }
+    };
+    mark(0, 0, 9, 9);
+    mark(size - 8, 0, 8, 9);
+    mark(0, size - 8, 9, 8);
+    mark(6, 0, 1, size);
+    mark(0, 6, size, 1);
+    let positions = alignment_positions(version);
+    let last = positions.len().saturating_sub(1);
+    for (i, x) in positions.iter().enumerate() {
+        for (j, y) in positions.iter().enumerate() {
+            // Not where the finder patterns are.
+            if i.min(j) == 0 && [0, last].contains(&i.max(j)) {continue;}
+            mark(x - 2, y - 2, 5, 5);
+        }
+    }
+    if version >= 7 {
+        mark(size - 11, 0, 3, 6);
+        mark(0, size - 11, 6, 3);
+    }
+    function
+}
+
+/// Splits the interleaved codewords into blocks, corrects them and joins their data.
+fn deinterleave(codewords: &[u8], version: usize, level: usize) -> Option<Vec<u8>> {
+    let ecc = ECC_PER_BLOCK[level][version] as usize;
+    let count = BLOCKS[level][version] as usize;
+    let total = codewords.len();
+    let short = count - total % count;
+    let short_length = total / count;
+    let mut blocks = (0..count).map(|i| Vec::with_capacity(short_length + (i >= short) as usize)).collect::<Vec<Vec<u8>>>();
+    let mut codewords = codewords.iter();
+    // Data codewords go round the blocks, short blocks have one less, then the error correction codewords do.
+    for i in 0..short_length - ecc + 1 {
+        for (b, block) in blocks.iter_mut().enumerate() {
+            if i < short_length - ecc || b >= short {block.push(*codewords.next()?);}
+        }
+    }
+    for _ in 0..ecc {
+        for block in blocks.iter_mut() {block.push(*codewords.next()?);}
+    }
+    let mut data = Vec::new();
+    for mut block in blocks {
+        if !reed_solomon::correct(&mut block, ecc) {return None;}
+        data.extend_from_slice(&block[..block.len() - ecc]);
+    }
+    Some(data)
+}
+
+struct Bits<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+impl Bits<'_> {
+    fn remaining(&self) -> usize {self.bytes.len() * 8 - self.position}
+
+    fn read(&mut self, count: usize) -> Option<u32> {
+        if count > self.remaining() {return None;}
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = self.bytes[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = value << 1 | bit as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Reads the segments of `data`. Byte segments are taken to be UTF-8, falling back to ISO-8859-1. Kanji is not
+/// supported.
+fn segments(data: &[u8], version: usize) -> Option<String> {
+    let mut bits = Bits{bytes: data, position: 0};
+    let class = match version {1..=9 => 0, 10..=26 => 1, _ => 2};
+    let mut text = Vec::new();
+    while bits.remaining() >= 4 {
+        match bits.read(4)? {
+            0b0000 => break,
+            0b0001 => {
+                let mut count = bits.read([10, 12, 14][class])?;
+                while count > 0 {
+                    let digits = count.min(3);
+                    let value = bits.read([4, 7, 10][digits as usize - 1])?;
+                    if value >= 10u32.pow(digits) {return None;}
+                    text.extend(format!("{value:0width$}", width = digits as usize).bytes());
+                    count -= digits;
+                }
+            },
+            0b0010 => {
+                let mut count = bits.read([9, 11, 13][class])?;
+                while count > 0 {
+                    if count >= 2 {
+                        let value = bits.read(11)? as usize;
+                        if value >= 45 * 45 {return None;}
+                        text.extend([ALPHANUMERIC[value / 45], ALPHANUMERIC[value % 45]]);
+                        count -= 2;
+                    } else {
+                        text.push(*ALPHANUMERIC.get(bits.read(6)? as usize)?);
+                        count -= 1;
+                    }
+                }
+            },
+            0b0100 => {
+                let count = bits.read([8, 16, 16][class])?;
+                for _ in 0..count {text.push(bits.read(8)? as u8);}
+            },
+            0b0111 => {
+                // The designator is one to three bytes long, marked by its leading bits.
+                let first = bits.read(8)?;
+                if first & 0x80 != 0 {bits.read(if first & 0x40 == 0 {8} else {16})?;}
+            },
+            0b0011 => {bits.read(16)?;},
+            0b0101 => {},
+            0b1001 => {bits.read(8)?;},
+            _ => return None,
+        }
+    }
+    Some(String::from_utf8(text).unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect()))
+}
@@ -0,0 +1,100 @@
+/// GF(256) with the QR code polynomial x^8 + x^4 + x^3 + x^2 + 1.
+struct Field {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+impl Field {
+    const fn new() -> Self {
+        let mut field = Field{exp: [0; 512], log: [0; 256]};
+        let mut value = 1u16;
+        let mut i = 0;
+        while i < 255 {
+            field.exp[i] = value as u8;
+            field.exp[i + 255] = value as u8;
+            field.log[value as usize] = i as u8;
+            value <<= 1;
+            if value & 0x100 != 0 {value ^= 0x11d;}
+            i += 1;
+        }
+        field
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {return 0;}
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u8, b: u8) -> u8 {
+        if a == 0 {return 0;}
+        self.exp[self.log[a as usize] as usize + 255 - self.log[b as usize] as usize]
+    }
+
+    /// α to the power `n`, which may be negative.
+    fn pow(&self, n: i32) -> u8 {self.exp[n.rem_euclid(255) as usize]}
+
+    /// Evaluates `poly`, lowest power first, at `x`.
+    fn eval(&self, poly: &[u8], x: u8) -> u8 {
+        poly.iter().rev().fold(0, |acc, c| self.mul(acc, x) ^ c)
+    }
+}
+
+const GF: Field = Field::new();
+
+/// Corrects `block`, data then `ecc` error correction codewords, in place. Returns false when there are more
+/// errors than can be corrected.
+pub fn correct(block: &mut [u8], ecc: usize) -> bool {
+    let n = block.len();
+    // The codewords are the coefficients of a polynomial, highest power first.
+    let syndromes = (0..ecc).map(|i| {
+        let x = GF.pow(i as i32);
+        block.iter().fold(0, |acc, c| GF.mul(acc, x) ^ c)
+    }).collect::<Vec<u8>>();
+    if syndromes.iter().all(|s| *s == 0) {return true;}
+
+    // Berlekamp-Massey finds the error locator.
+    let (mut locator, mut previous) = (vec![1u8], vec![1u8]);
+    let (mut errors, mut shift, mut last) = (0, 1, 1u8);
+    for k in 0..ecc {
+        let delta = (1..=errors).fold(syndromes[k], |d, i| d ^ GF.mul(*locator.get(i).unwrap_or(&0), syndromes[k - i]));
+        if delta == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = GF.div(delta, last);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, c) in previous.iter().enumerate() {next[i + shift] ^= GF.mul(scale, *c);}
+        if 2 * errors <= k {
+            previous = std::mem::replace(&mut locator, next);
+            errors = k + 1 - errors;
+            last = delta;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+    while locator.len() > 1 && locator.last() == Some(&0) {locator.pop();}
+    if errors * 2 > ecc || locator.len() - 1 != errors {return false;}
+
+    // The evaluator, syndromes times locator modulo x^ecc.
+    let mut evaluator = vec![0u8; ecc];
+    for (i, l) in locator.iter().enumerate() {
+        for (j, s) in syndromes.iter().enumerate().take(ecc.saturating_sub(i)) {evaluator[i + j] ^= GF.mul(*l, *s);}
+    }
+    // Its formal derivative only keeps odd powers.
+    let derivative = locator.iter().enumerate().skip(1).map(|(i, c)| if i % 2 == 1 {*c} else {0}).collect::<Vec<u8>>();
+
+    let mut found = 0;
+    for (position, codeword) in block.iter_mut().enumerate() {
+        let power = (n - 1 - position) as i32;
+        let inverse = GF.pow(-power);
+        if GF.eval(&locator, inverse) != 0 {continue;}
+        let denominator = GF.eval(&derivative, inverse);
+        if denominator == 0 {return false;}
+        let magnitude = GF.mul(GF.pow(power), GF.div(GF.eval(&evaluator, inverse), denominator));
+        *codeword ^= magnitude;
+        found += 1;
+    }
+    found == errors
+}
@@ -0,0 +1,114 @@
+use image::RgbaImage;
+use imageproc::filter;
+
+use super::{CameraCapabilities, CameraSettings, ColorFilter, WhiteBalanceMode};
+
+/// Applies the colour adjustments of `settings`, and white balance gains the device could not apply itself.
+pub fn adjust(mut img: RgbaImage, settings: &CameraSettings, capabilities: &CameraCapabilities) -> RgbaImage {
+    let bval = settings.brightness.map(|b| (b - 0.5) * 2.0 * 255.0).filter(|b| b.abs() > f32::EPSILON);
+    let contrast = settings.contrast.map(|c| 1.0 + (c - 0.5) * 2.0).unwrap_or(1.0);
+    let mut sat = settings.saturation.map(|s| 1.0 + (s - 0.5) * 2.0).unwrap_or(1.0);
+    let hue_shift = settings.hue.map(|h| (h - 0.5) * 360.0).unwrap_or(0.0);
+    let gamma = settings.gamma.map(|g| 2f32.powf((0.5 - g) * 2.0)).filter(|g| (g - 1.0).abs() > f32::EPSILON);
+    let filter = settings.color_filter.filter(|f| *f != ColorFilter::None);
+    if filter == Some(ColorFilter::Vibrant) {sat *= 1.3;}
+
+    let gains = match (settings.white_balance_mode, settings.white_balance_gains) {
+        (WhiteBalanceMode::Custom, Some(gains)) if !capabilities.white_balance_modes.contains(&WhiteBalanceMode::Custom) => {
+            let (r, g, b) = (1.0 + gains.red, 1.0 + gains.green, 1.0 + gains.blue);
+            let mean = (r + g + b) / 3.0;
+            Some((r / mean, g / mean, b / mean))
+        },
+        _ => None,
+    };
+
+    let need_hsv = (sat - 1.0).abs() > f32::EPSILON || hue_shift.abs() > f32::EPSILON;
+    let need_pixels = bval.is_some() || (contrast - 1.0).abs() > f32::EPSILON || need_hsv || gamma.is_some()
+        || filter.is_some() || gains.is_some();
+
+    if need_pixels {
+        for px in img.pixels_mut() {
+            let (mut r, mut g, mut b) = (px[0] as f32, px[1] as f32, px[2] as f32);
+
+            if let Some((gr, gg, gb)) = gains { r *= gr; g *= gg; b *= gb; }
+
+            if let Some(v) = bval { r += v; g += v; b += v; }
+
+            r = ((r - 128.0) * contrast + 128.0).clamp(0.0, 255.0);
+            g = ((g - 128.0) * contrast + 128.0).clamp(0.0, 255.0);
+            b = ((b - 128.0) * contrast + 128.0).clamp(0.0, 255.0);
+
+            if need_hsv {
+                let (mut h, mut s, v) = rgb_to_hsv(r, g, b);
+                s *= sat; s = s.clamp(0.0, 1.0);
+                h = (h + hue_shift) % 360.0; if h < 0.0 { h += 360.0; }
+                (r, g, b) = hsv_to_rgb(h, s, v);
+            }
+
+            if let Some(gamma) = gamma {
+                r = 255.0 * (r.clamp(0.0, 255.0) / 255.0).powf(gamma);
+                g = 255.0 * (g.clamp(0.0, 255.0) / 255.0).powf(gamma);
+                b = 255.0 * (b.clamp(0.0, 255.0) / 255.0).powf(gamma);
+            }
+
+            (r, g, b) = match filter {
+                Some(ColorFilter::Mono) => {let y = 0.299 * r + 0.587 * g + 0.114 * b; (y, y, y)},
+                Some(ColorFilter::Sepia) => (
+                    0.393 * r + 0.769 * g + 0.189 * b,
+                    0.349 * r + 0.686 * g + 0.168 * b,
+                    0.272 * r + 0.534 * g + 0.131 * b,
+                ),
+                Some(ColorFilter::Cool) => (r * 0.9, g, b * 1.1),
+                Some(ColorFilter::Warm) => (r * 1.1, g, b * 0.9),
+                _ => (r, g, b),
+            };
+
+            px[0] = r.clamp(0.0, 255.0) as u8;
+            px[1] = g.clamp(0.0, 255.0) as u8;
+            px[2] = b.clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    // Blends a box blur into the frame by the amount.
+    if let Some(amount) = settings.noise_reduction && amount > 0.0 {
+        let (centre, around) = (1.0 - amount + amount / 9.0, amount / 9.0);
+        let kernel = [
+            around, around, around,
+            around, centre, around,
+            around, around, around,
+        ];
+        img = filter::filter3x3(&img, &kernel);
+    }
+
+    // Subtracts the Laplacian scaled by the strength.
+    if let Some(strength) = settings.sharpness && strength > 0.0 {
+        let kernel = [
+            0.0, -strength, 0.0,
+            -strength, 1.0 + 4.0 * strength, -strength,
+            0.0, -strength, 0.0,
+        ];
+        img = filter::filter3x3(&img, &kernel);
+    }
+
+    img
+}
+
+fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32,f32,f32) {
+    let r = r/255.0; let g = g/255.0; let b = b/255.0;
+    let max = r.max(g).max(b); let min = r.min(g).min(b); let d = max - min;
+    let h = if d==0.0 {0.0} else if max==r {(60.0*((g-b)/d))%360.0} else if max==g {60.0*((b-r)/d+2.0)} else {60.0*((r-g)/d+4.0)};
+    let s = if max==0.0 {0.0} else {d/max}; (h,s,max)
+}
+
+fn hsv_to_rgb(h: f32, s: f32, v: f32) -> (f32,f32,f32) {
+    let c = v*s; let x = c*(1.0 - ((h/60.0)%2.0 - 1.0).abs()); let m = v-c;
+    let (r1,g1,b1) = match h {
+        h if h<60.0 => (c,x,0.0),
+        h if h<120.0 => (x,c,0.0),
+        h if h<180.0 => (0.0,c,x),
+        h if h<240.0 => (0.0,x,c),
+        h if h<300.0 => (x,0.0,c),
+        _ => (c,0.0,x)
+    };
+    ((r1+m)*255.0,(g1+m)*255.0,(b1+m)*255.0)
+}
@@ -0,0 +1,221 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, ImageFormat, ImageReader, Rgba, RgbaImage};
+
+use super::{CameraCapabilities, CameraDevice, CameraError, CameraFormat, Facing, Resolution, SettingRange};
+
+/// Selects a synthetic camera: `pattern`, `pattern:WIDTHxHEIGHT`, a directory of images or an image file.
+const SOURCE_VAR: &str = "MAVERICK_CAMERA";
+/// Frames per second of the synthetic camera, 30 when unset.
+const FPS_VAR: &str = "MAVERICK_CAMERA_FPS";
+const FPS: f32 = 30.0;
+const PATTERN: (u32, u32) = (1280, 720);
+/// How many bytes of decoded animation frames are kept, frames past it are left out.
+const BUDGET: usize = 256 << 20;
+
+impl From<std::io::Error> for CameraError {
+    fn from(e: std::io::Error) -> Self {CameraError::Source(e.to_string())}
+}
+impl From<image::ImageError> for CameraError {
+    fn from(e: image::ImageError) -> Self {CameraError::Source(e.to_string())}
+}
+
+/// Where a synthetic camera gets its frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntheticSource {
+    /// Every image in the directory, in file name order, looped.
+    Directory(PathBuf),
+    /// The frames of an animated GIF, PNG or WebP, looped. A still image is shown as the only frame.
+    Animation(PathBuf),
+    /// Scrolling colour bars with a square crossing the bottom.
+    TestPattern{width: u32, height: u32},
+}
+impl SyntheticSource {
+    /// The source and frame rate set by `MAVERICK_CAMERA` and `MAVERICK_CAMERA_FPS`. The working directory is
+    /// moved to application support at start, so relative paths are resolved against `PWD`.
+    pub(crate) fn configured() -> Option<(Self, f32)> {
+        let value = std::env::var(SOURCE_VAR).ok().filter(|v| !v.is_empty())?;
+        let fps = std::env::var(FPS_VAR).ok().and_then(|f| f.parse::<f32>().ok()).filter(|f| *f > 0.0).unwrap_or(FPS);
+        let source = match value.strip_prefix("pattern") {
+            Some("") => SyntheticSource::TestPattern{width: PATTERN.0, height: PATTERN.1},
+            Some(size) => {
+                let (width, height) = size.strip_prefix(':').and_then(|s| s.split_once('x'))
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .filter(|(w, h)| *w > 0 && *h > 0)
+                    .unwrap_or_else(|| {log::error!("Invalid {SOURCE_VAR} pattern size {size:?}"); PATTERN});
+                SyntheticSource::TestPattern{width, height}
+            },
+            None => {
+                let mut path = PathBuf::from(value);
+                if path.is_relative() && let Ok(pwd) = std::env::var("PWD") {path = Path::new(&pwd).join(path);}
+                if path.is_dir() {SyntheticSource::Directory(path)} else {SyntheticSource::Animation(path)}
+            }
+        };
+        Some((source, fps))
+    }
+
+    fn frames(&self) -> Result<Frames, CameraError> {
+        match self {
+            SyntheticSource::Directory(dir) => {
+                let mut paths = std::fs::read_dir(dir)?.filter_map(|e| e.ok().map(|e| e.path())).filter(|p| p.is_file()).collect::<Vec<_>>();
+                paths.sort();
+                let (paths, sizes) = paths.into_iter().filter_map(|path| match image::image_dimensions(&path) {
+                    Ok(size) => Some((path, size)),
+                    Err(e) => {log::warn!("Skipping {}: {e}", path.display()); None}
+                }).unzip::<_, _, Vec<_>, Vec<_>>();
+                if paths.is_empty() {return Err(CameraError::Source(format!("No images in {}", dir.display())));}
+                Ok(Frames::Files{paths, sizes, shown: None})
+            },
+            SyntheticSource::Animation(path) => {
+                let open = || File::open(path).map(BufReader::new);
+                let reader = ImageReader::open(path)?.with_guessed_format()?;
+                let frames = match reader.format() {
+                    Some(ImageFormat::Gif) => decode(GifDecoder::new(open()?)?.into_frames())?,
+                    Some(ImageFormat::Png) => {
+                        let decoder = PngDecoder::new(open()?)?;
+                        if decoder.is_apng()? {decode(decoder.apng()?.into_frames())?} else {Vec::new()}
+                    },
+                    Some(ImageFormat::WebP) => {
+                        let decoder = WebPDecoder::new(open()?)?;
+                        if decoder.has_animation() {decode(decoder.into_frames())?} else {Vec::new()}
+                    },
+                    _ => Vec::new(),
+                };
+                if frames.is_empty() {return Ok(Frames::Decoded(vec![reader.decode()?.into_rgba8()]));}
+                Ok(Frames::Decoded(frames))
+            },
+            SyntheticSource::TestPattern{..} => Ok(Frames::Pattern),
+        }
+    }
+}
+
+/// Decodes the frames of an animation until they take up `BUDGET`.
+fn decode(frames: image::Frames) -> Result<Vec<RgbaImage>, CameraError> {
+    let mut decoded = Vec::new();
+    let mut size = 0;
+    for frame in frames {
+        let frame = frame?.into_buffer();
+        size += frame.as_raw().len();
+        if size > BUDGET && !decoded.is_empty() {
+            log::warn!("Only the first {} frames of the animation fit in memory", decoded.len());
+            break;
+        }
+        decoded.push(frame);
+    }
+    Ok(decoded)
+}
+
+#[derive(Debug)]
+enum Frames {
+    /// Image files with their sizes, each decoded when it is shown.
+    Files{paths: Vec<PathBuf>, sizes: Vec<(u32, u32)>, shown: Option<(usize, RgbaImage)>},
+    Decoded(Vec<RgbaImage>),
+    Pattern,
+}
+
+/// Plays a `SyntheticSource` in real time. Like a device it only has a frame when a new one is due, and it
+/// starts over from the first frame each time it is started.
+#[derive(Debug)]
+pub struct SyntheticCamera {
+    source: SyntheticSource,
+    frames: Frames,
+    fps: f32,
+    started: Option<Instant>,
+    /// The number of the frame last handed out.
+    last: Option<u64>,
+}
+
+impl SyntheticCamera {
+    /// Checks the source up front, so a bad one fails here rather than while streaming. Images in a directory are
+    /// only decoded when they are shown, animations are decoded here.
+    pub fn new(source: SyntheticSource, fps: f32) -> Result<Self, CameraError> {
+        if fps.is_nan() || fps <= 0.0 {return Err(CameraError::Source(format!("Invalid frame rate {fps}")));}
+        let frames = source.frames()?;
+        Ok(SyntheticCamera{source, frames, fps, started: None, last: None})
+    }
+
+    pub fn start(&mut self) {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+            self.last = None;
+        }
+    }
+
+    pub fn stop(&mut self) {self.started = None;}
+
+    pub fn device(&self) -> CameraDevice {
+        let (name, mut sizes) = match (&self.source, &self.frames) {
+            (SyntheticSource::TestPattern{width, height}, _) => ("Test pattern".to_string(), vec![(*width, *height)]),
+            (SyntheticSource::Directory(path) | SyntheticSource::Animation(path), frames) => (
+                path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                match frames {
+                    Frames::Files{sizes, ..} => sizes.clone(),
+                    Frames::Decoded(frames) => frames.iter().map(|f| f.dimensions()).collect(),
+                    Frames::Pattern => Vec::new(),
+                },
+            ),
+        };
+        sizes.sort();
+        sizes.dedup();
+        CameraDevice{
+            id: "synthetic".to_string(),
+            name,
+            facing: Facing::External,
+            formats: sizes.iter().map(|&(width, height)| CameraFormat{width, height, min_fps: self.fps, max_fps: self.fps}).collect(),
+            capabilities: CameraCapabilities{
+                frame_rate: Some(SettingRange{min: self.fps, max: self.fps}),
+                resolutions: sizes.into_iter().map(|(width, height)| Resolution{width, height}).collect(),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn frame(&mut self) -> Option<RgbaImage> {
+        let number = (self.started?.elapsed().as_secs_f64() * self.fps as f64) as u64;
+        if self.last == Some(number) {return None;}
+        self.last = Some(number);
+        match (&self.source, &mut self.frames) {
+            (&SyntheticSource::TestPattern{width, height}, _) => Some(pattern(width, height, number)),
+            (_, Frames::Decoded(frames)) => Some(frames[(number % frames.len() as u64) as usize].clone()),
+            (_, Frames::Files{paths, shown, ..}) => {
+                let index = (number % paths.len() as u64) as usize;
+                if shown.as_ref().is_none_or(|(shown, _)| *shown != index) {
+                    match image::open(&paths[index]) {
+                        Ok(image) => *shown = Some((index, image.into_rgba8())),
+                        Err(e) => {log::warn!("Skipping {}: {e}", paths[index].display()); return None;}
+                    }
+                }
+                shown.as_ref().map(|(_, frame)| frame.clone())
+            },
+            (_, Frames::Pattern) => None,
+        }
+    }
+}
+
+const BARS: [[u8; 3]; 7] = [
+    [192, 192, 192], [192, 192, 0], [0, 192, 192], [0, 192, 0], [192, 0, 192], [192, 0, 0], [0, 0, 192],
+];
+
+fn pattern(width: u32, height: u32, number: u64) -> RgbaImage {
+    let band = height * 2 / 3;
+    let side = (height - band).min(width) / 2;
+    let offset = (number * 4 % width as u64) as u32;
+    let square = (number * 8 % (width - side + 1) as u64) as u32;
+    let top = band + (height - band - side) / 2;
+    RgbaImage::from_fn(width, height, |x, y| {
+        if y < band {
+            let [r, g, b] = BARS[((x + offset) % width * BARS.len() as u32 / width) as usize];
+            Rgba([r, g, b, 255])
+        } else if (square..square + side).contains(&x) && (top..top + side).contains(&y) {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([32, 32, 32, 255])
+        }
+    })
+}
@@ -1,58 +1,220 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+
 #[cfg(target_os = "ios")]
 mod ios;
 #[cfg(target_os = "ios")]
 use ios::OsClipboard;
 
-#[cfg(target_os = "macos")]
-mod macos;
-#[cfg(target_os = "macos")]
-use macos::OsClipboard;
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+mod desktop;
+#[cfg(any(target_os = "macos", target_os = "linux", target_os = "windows"))]
+use desktop::OsClipboard;
 
 #[cfg(target_os = "android")]
 mod android;
 #[cfg(target_os = "android")]
 use android::OsClipboard;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClipboardFormat {Text, Html, Image, Files}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipboardContent {
+    Text(String),
+    /// `alt` is offered as plain text to applications that do not read HTML.
+    Html{html: String, alt: Option<String>},
+    Image(RgbaImage),
+    Files(Vec<PathBuf>),
+}
+impl ClipboardContent {
+    pub fn format(&self) -> ClipboardFormat {
+        match self {
+            ClipboardContent::Text(_) => ClipboardFormat::Text,
+            ClipboardContent::Html{..} => ClipboardFormat::Html,
+            ClipboardContent::Image(_) => ClipboardFormat::Image,
+            ClipboardContent::Files(_) => ClipboardFormat::Files,
+        }
+    }
+}
+
+/// The format and fingerprint of contents we wrote, `None` for an empty clipboard.
+type Written = Option<(ClipboardFormat, u64)>;
+
+#[derive(Debug, Default)]
+struct Monitor {
+    watching: bool,
+    /// The change counter of the platform when it was last looked at.
+    changes: u64,
+    /// What we wrote last, so the change it causes is not reported. Android, X11 and Wayland report changes
+    /// asynchronously, our own write may show up a few frames later.
+    echo: Option<Written>,
+    /// When to clear the clipboard, if it still holds what we wrote.
+    clear: Option<(Instant, Written)>,
+}
+
+fn fingerprint(content: &ClipboardContent) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match content {
+        ClipboardContent::Text(text) => text.hash(&mut hasher),
+        ClipboardContent::Html{html, ..} => html.hash(&mut hasher),
+        ClipboardContent::Image(image) => (image.dimensions(), image.as_raw()).hash(&mut hasher),
+        ClipboardContent::Files(files) => files.hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+/// The system clipboard. Android does not expose images copied by other applications, reading
+/// [`ClipboardFormat::Image`] there always returns `None`.
+///
+/// Once [`watch`](Self::watch) is enabled, changes made by other applications are delivered as
+/// [`Input::ClipboardChanged`](crate::window::Input). Changes are noticed through the change count of the
+/// pasteboard on Apple platforms, the clipboard sequence number on Windows, the selection owner on X11 and
+/// Wayland and a primary clip listener on Android, the contents are only read once they changed.
 #[derive(Clone)]
-pub struct Clipboard(
-    #[cfg(any(target_os = "ios", target_os = "macos", target_os = "android"))]
-    OsClipboard
-);
+pub struct Clipboard {
+    #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android"))]
+    os: OsClipboard,
+    monitor: Arc<Mutex<Monitor>>,
+}
 
 impl Clipboard {
     pub(crate) fn new(
         #[cfg(target_os = "android")]
         vm: &jni::JavaVM
     ) -> Self {
-        Self(
-            #[cfg(target_os = "ios")]
-            OsClipboard::new(),
-            #[cfg(target_os = "macos")]
-            OsClipboard::new(),
+        Self{
+            #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows"))]
+            os: OsClipboard::new(),
             #[cfg(target_os = "android")]
-            OsClipboard::new(vm)
-        )
+            os: OsClipboard::new(vm),
+            monitor: Arc::new(Mutex::new(Monitor::default())),
+        }
     }
 
     pub fn get(&self) -> Option<String> {
-        #[cfg(any(target_os = "ios", target_os = "android"))]
-        {
-            Some(self.0.get_content())
+        match self.read(ClipboardFormat::Text)? {
+            ClipboardContent::Text(text) => Some(text),
+            _ => None
         }
+    }
+
+    pub fn set(&self, text: String) {self.write(ClipboardContent::Text(text))}
+
+    /// The formats the current contents can be read as. On desktops [`ClipboardFormat::Image`] is only listed
+    /// when no other format is, [`read`](Self::read) it to check for an image copied together with text.
+    pub fn formats(&self) -> Vec<ClipboardFormat> {
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android"))]
+        return self.os.formats();
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android")))]
+        Vec::new()
+    }
+
+    pub fn read(&self, format: ClipboardFormat) -> Option<ClipboardContent> {
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android"))]
+        return self.os.read(format);
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android")))]
+        {let _ = format; None}
+    }
+
+    /// Replaces the contents of the clipboard, this is not reported back as a change.
+    pub fn write(&self, content: ClipboardContent) {self.put(content, false);}
 
-        #[cfg(target_os = "macos")]
-        {
-            self.0.get_content()
+    /// Writes `content` and clears the clipboard after `clear_after`, unless something else was copied since.
+    /// Android is asked not to preview it.
+    pub fn write_sensitive(&self, content: ClipboardContent, clear_after: Duration) {
+        let written = self.put(content, true);
+        self.monitor.lock().unwrap().clear = Some((Instant::now() + clear_after, written));
+    }
+
+    fn put(&self, content: ClipboardContent, sensitive: bool) -> Written {
+        let written = Some((content.format(), fingerprint(&content)));
+        #[cfg(target_os = "android")]
+        match sensitive {
+            true => self.os.write_sensitive(content),
+            false => self.os.write(content),
+        }
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows"))]
+        {let _ = sensitive; self.os.write(content);}
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android")))]
+        let _ = (content, sensitive);
+        self.monitor.lock().unwrap().echo = Some(written);
+        written
+    }
+
+    pub fn clear(&self) {
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android"))]
+        self.os.clear();
+        self.monitor.lock().unwrap().echo = Some(None);
+    }
+
+    /// Starts or stops delivering [`Input::ClipboardChanged`](crate::window::Input), the contents at the time
+    /// watching starts are not reported.
+    pub fn watch(&self, enabled: bool) {
+        let changes = self.changes();
+        let mut monitor = self.monitor.lock().unwrap();
+        monitor.watching = enabled;
+        monitor.changes = changes;
+    }
+
+    fn changes(&self) -> u64 {
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android"))]
+        return self.os.changes();
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux", target_os = "windows", target_os = "android")))]
+        0
+    }
+
+    /// Whether the clipboard still holds `written`, only its format is read.
+    fn holds(&self, written: Written) -> bool {
+        match written {
+            None => self.formats().is_empty(),
+            Some((format, expected)) => self.read(format).is_some_and(|c| fingerprint(&c) == expected),
+        }
+    }
+
+    /// Clears expired sensitive contents and returns the formats of contents copied since the last change.
+    pub(crate) fn tick(&self) -> Option<Vec<ClipboardFormat>> {
+        let mut monitor = self.monitor.lock().unwrap();
+        if !monitor.watching && monitor.clear.is_none() {return None;}
+        if let Some((deadline, written)) = monitor.clear && deadline <= Instant::now() {
+            monitor.clear = None;
+            drop(monitor);
+            if self.holds(written) {self.clear();}
+            monitor = self.monitor.lock().unwrap();
         }
 
-        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "android")))]
-        panic!("Not Supported for Linux/Windows");
+        let changes = self.changes();
+        if !monitor.watching || monitor.changes == changes {return None;}
+        monitor.changes = changes;
+        let echo = monitor.echo.take();
+        drop(monitor);
+        if echo.is_some_and(|written| self.holds(written)) {return None;}
+        Some(self.formats())
     }
 
-    pub fn set(&self, text: String) {
-        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "android"))]
-        self.0.set_content(text);
-        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "android")))]
-        panic!("Not Supported for Linux/Windows");
+    pub fn get_image(&self) -> Option<RgbaImage> {
+        match self.read(ClipboardFormat::Image)? {
+            ClipboardContent::Image(image) => Some(image),
+            _ => None
+        }
+    }
+
+    pub fn get_html(&self) -> Option<String> {
+        match self.read(ClipboardFormat::Html)? {
+            ClipboardContent::Html{html, ..} => Some(html),
+            _ => None
+        }
+    }
+
+    pub fn get_files(&self) -> Option<Vec<PathBuf>> {
+        match self.read(ClipboardFormat::Files)? {
+            ClipboardContent::Files(files) => Some(files),
+            _ => None
+        }
     }
 }
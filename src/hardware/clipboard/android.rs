@@ -1,46 +1,123 @@
-use jni::objects::{GlobalRef, JObject};
-use jni::{JNIEnv, JavaVM};
+use jni::objects::{GlobalRef, JClass, JObject, JValue};
+use jni::{JNIEnv, JavaVM, NativeMethod};
+use std::ffi::c_void;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{ClipboardFormat, ClipboardContent};
+
+/// Built from `android/ClipboardWatcher.java`.
+static DEX: &[u8] = include_bytes!("android/classes.dex");
+
+/// Moved by `ClipboardWatcher.onPrimaryClipChanged`, a process has a single clipboard.
+static CHANGES: AtomicU64 = AtomicU64::new(0);
+
+extern "system" fn changed(_env: JNIEnv, _this: JObject) {
+    CHANGES.fetch_add(1, Ordering::AcqRel);
+}
 
 #[derive(Clone)]
 pub struct OsClipboard {
     vm: Arc<JavaVM>,
     context: GlobalRef,
+    /// The listener registered with the clipboard manager, `None` when registering failed.
+    _watcher: Option<GlobalRef>,
 }
 
 impl OsClipboard {
     pub fn new(vm: &JavaVM) -> Self {
         let vm = Arc::new(unsafe { JavaVM::from_raw(vm.get_java_vm_pointer()).unwrap() });
-        
+
         let context = {
             let mut env = vm.attach_current_thread().expect("Failed to attach thread");
             let context_obj = ndk_context::android_context().context().cast();
             let context_obj = unsafe { JObject::from_raw(context_obj) };
             env.new_global_ref(context_obj).expect("Failed to create global ref")
         };
-        
-        Self { vm, context }
-    }
 
-    pub fn get_content(&self) -> String {
-        self.get_content_impl().unwrap_or_default()
+        let mut clipboard = Self { vm, context, _watcher: None };
+        clipboard._watcher = clipboard.listen().map_err(|e| log::error!("Clipboard changes are not reported: {e}")).ok();
+        clipboard
     }
 
-    fn get_content_impl(&self) -> Result<String, Box<dyn std::error::Error>> {
+    /// Registers a `ClipboardWatcher` loaded from the embedded dex as primary clip listener.
+    fn listen(&self) -> Result<GlobalRef, Box<dyn std::error::Error>> {
         let mut env = self.vm.attach_current_thread()?;
-        let context = self.context.as_obj();
+        let buffer = unsafe { env.new_direct_byte_buffer(DEX.as_ptr() as *mut u8, DEX.len())? };
+        let parent = env.call_method(self.context.as_obj(), "getClassLoader", "()Ljava/lang/ClassLoader;", &[])?.l()?;
+        let loader = env.new_object(
+            "dalvik/system/InMemoryDexClassLoader",
+            "(Ljava/nio/ByteBuffer;Ljava/lang/ClassLoader;)V",
+            &[(&buffer).into(), (&parent).into()],
+        )?;
+        let name = env.new_string("com.orangeme.clipboard.ClipboardWatcher")?;
+        let class = JClass::from(env.call_method(&loader, "loadClass", "(Ljava/lang/String;)Ljava/lang/Class;", &[(&name).into()])?.l()?);
+        env.register_native_methods(&class, &[NativeMethod{
+            name: "onPrimaryClipChanged".into(),
+            sig: "()V".into(),
+            fn_ptr: changed as *mut c_void,
+        }])?;
+        let watcher = env.new_object(&class, "()V", &[])?;
+        let clipboard_manager = self.clipboard_manager(&mut env)?;
+        env.call_method(
+            clipboard_manager,
+            "addPrimaryClipChangedListener",
+            "(Landroid/content/ClipboardManager$OnPrimaryClipChangedListener;)V",
+            &[(&watcher).into()],
+        )?;
+        Ok(env.new_global_ref(watcher)?)
+    }
 
+    /// A counter that moves whenever the primary clip changes, without reading it.
+    pub fn changes(&self) -> u64 {
+        CHANGES.load(Ordering::Acquire)
+    }
+
+    fn clipboard_manager<'a>(&self, env: &mut JNIEnv<'a>) -> Result<JObject<'a>, Box<dyn std::error::Error>> {
         let clipboard_string = env.new_string("clipboard")?;
         let clipboard_service = env
             .call_method(
-                context,
+                self.context.as_obj(),
                 "getSystemService",
                 "(Ljava/lang/String;)Ljava/lang/Object;",
                 &[(&clipboard_string).into()],
             )?
             .l()?;
+        Ok(clipboard_service)
+    }
+
+    pub fn formats(&self) -> Vec<ClipboardFormat> {
+        self.formats_impl().unwrap_or_default()
+    }
+
+    fn formats_impl(&self) -> Result<Vec<ClipboardFormat>, Box<dyn std::error::Error>> {
+        let mut env = self.vm.attach_current_thread()?;
+        let clipboard_manager = self.clipboard_manager(&mut env)?;
+        let description = env
+            .call_method(clipboard_manager, "getPrimaryClipDescription", "()Landroid/content/ClipDescription;", &[])?
+            .l()?;
+        if description.is_null() {
+            return Ok(Vec::new());
+        }
 
-        let clipboard_manager = JObject::from(clipboard_service);
+        let mut formats = Vec::new();
+        for (mime, format) in [("text/*", ClipboardFormat::Text), ("text/html", ClipboardFormat::Html), ("text/uri-list", ClipboardFormat::Files)] {
+            let mime = env.new_string(mime)?;
+            if env.call_method(&description, "hasMimeType", "(Ljava/lang/String;)Z", &[(&mime).into()])?.z()? {
+                formats.push(format);
+            }
+        }
+        Ok(formats)
+    }
+
+    pub fn read(&self, format: ClipboardFormat) -> Option<ClipboardContent> {
+        self.read_impl(format).ok().flatten()
+    }
+
+    fn read_impl(&self, format: ClipboardFormat) -> Result<Option<ClipboardContent>, Box<dyn std::error::Error>> {
+        let mut env = self.vm.attach_current_thread()?;
+        let clipboard_manager = self.clipboard_manager(&mut env)?;
 
         let primary_clip = env
             .call_method(
@@ -52,73 +129,144 @@ impl OsClipboard {
             .l()?;
 
         if primary_clip.is_null() {
-            return Ok(String::new());
+            return Ok(None);
         }
 
         let item_count = env
             .call_method(&primary_clip, "getItemCount", "()I", &[])?
             .i()?;
-        if item_count == 0 {
-            return Ok(String::new());
+
+        let mut items = Vec::new();
+        for i in 0..item_count {
+            let clip_item = env
+                .call_method(
+                    &primary_clip,
+                    "getItemAt",
+                    "(I)Landroid/content/ClipData$Item;",
+                    &[i.into()],
+                )?
+                .l()?;
+            items.push(clip_item);
         }
+        let Some(first) = items.first() else {return Ok(None)};
 
-        let clip_item = env
-            .call_method(
-                primary_clip,
-                "getItemAt",
-                "(I)Landroid/content/ClipData$Item;",
-                &[0i32.into()],
-            )?
-            .l()?;
+        Ok(match format {
+            ClipboardFormat::Text => Self::string(&mut env, first, "getText", "()Ljava/lang/CharSequence;")?.map(ClipboardContent::Text),
+            ClipboardFormat::Html => match Self::string(&mut env, first, "getHtmlText", "()Ljava/lang/String;")? {
+                Some(html) => Some(ClipboardContent::Html{html, alt: Self::string(&mut env, first, "getText", "()Ljava/lang/CharSequence;")?}),
+                None => None,
+            },
+            ClipboardFormat::Image => None,
+            ClipboardFormat::Files => {
+                let mut files = Vec::new();
+                for item in &items {
+                    let uri = env.call_method(item, "getUri", "()Landroid/net/Uri;", &[])?.l()?;
+                    if uri.is_null() {continue;}
+                    let scheme = Self::string(&mut env, &uri, "getScheme", "()Ljava/lang/String;")?;
+                    if scheme.as_deref() != Some("file") {continue;}
+                    if let Some(path) = Self::string(&mut env, &uri, "getPath", "()Ljava/lang/String;")? {
+                        files.push(PathBuf::from(path));
+                    }
+                }
+                (!files.is_empty()).then_some(ClipboardContent::Files(files))
+            }
+        })
+    }
 
-        let text = env
-            .call_method(
-                clip_item,
-                "getText",
-                "()Ljava/lang/CharSequence;",
-                &[],
-            )?
-            .l()?;
-        if text.is_null() {
-            return Ok(String::new());
+    /// Calls a method returning a `CharSequence` or `String`, `None` when it returns null.
+    fn string(env: &mut JNIEnv, object: &JObject, method: &str, signature: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let value = env.call_method(object, method, signature, &[])?.l()?;
+        if value.is_null() {
+            return Ok(None);
         }
-
-        let java_string = env.call_method(text, "toString", "()Ljava/lang/String;", &[])?.l()?;
-        let rust_string = env.get_string(&java_string.into())?.into();
-        Ok(rust_string)
+        let java_string = env.call_method(value, "toString", "()Ljava/lang/String;", &[])?.l()?;
+        Ok(Some(env.get_string(&java_string.into())?.into()))
     }
 
-    pub fn set_content(&self, text: String) {
-        let _ = self.set_content_impl(text);
+    /// Needs API level 28.
+    pub fn clear(&self) {
+        if let Err(e) = self.clear_impl() {
+            log::error!("Could not clear the clipboard: {e}");
+        }
     }
 
-    fn set_content_impl(&self, text: String) -> Result<(), Box<dyn std::error::Error>> {
+    fn clear_impl(&self) -> Result<(), Box<dyn std::error::Error>> {
         let mut env = self.vm.attach_current_thread()?;
-        let context = self.context.as_obj();
+        let clipboard_manager = self.clipboard_manager(&mut env)?;
+        env.call_method(clipboard_manager, "clearPrimaryClip", "()V", &[])?;
+        Ok(())
+    }
 
-        let clipboard_string = env.new_string("clipboard")?;
-        let clipboard_service = env
-            .call_method(
-                context,
-                "getSystemService",
-                "(Ljava/lang/String;)Ljava/lang/Object;",
-                &[(&clipboard_string).into()],
-            )?
-            .l()?;
+    pub fn write(&self, content: ClipboardContent) {
+        if let Err(e) = self.write_impl(content, false) {
+            log::error!("Could not write to the clipboard: {e}");
+        }
+    }
 
-        let clipboard_manager = JObject::from(clipboard_service);
+    /// Marks the clip with `EXTRA_IS_SENSITIVE`, so the system does not preview it. Needs API level 24.
+    pub fn write_sensitive(&self, content: ClipboardContent) {
+        if let Err(e) = self.write_impl(content, true) {
+            log::error!("Could not write to the clipboard: {e}");
+        }
+    }
+
+    fn write_impl(&self, content: ClipboardContent, sensitive: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut env = self.vm.attach_current_thread()?;
+        let clipboard_manager = self.clipboard_manager(&mut env)?;
 
         let clip_data_class = env.find_class("android/content/ClipData")?;
-        let label = env.new_string("label")?;
-        let text_string = env.new_string(&text)?;
-        let clip_data = env
-            .call_static_method(
-                clip_data_class,
-                "newPlainText",
-                "(Ljava/lang/CharSequence;Ljava/lang/CharSequence;)Landroid/content/ClipData;",
-                &[(&JObject::from(label)).into(), (&JObject::from(text_string)).into()],
-            )?
-            .l()?;
+        let label = JObject::from(env.new_string("label")?);
+        let clip_data = match content {
+            ClipboardContent::Text(text) => {
+                let text_string = JObject::from(env.new_string(&text)?);
+                env.call_static_method(
+                    clip_data_class,
+                    "newPlainText",
+                    "(Ljava/lang/CharSequence;Ljava/lang/CharSequence;)Landroid/content/ClipData;",
+                    &[(&label).into(), (&text_string).into()],
+                )?.l()?
+            },
+            ClipboardContent::Html{html, alt} => {
+                let text_string = JObject::from(env.new_string(alt.as_deref().unwrap_or(&html))?);
+                let html_string = JObject::from(env.new_string(&html)?);
+                env.call_static_method(
+                    clip_data_class,
+                    "newHtmlText",
+                    "(Ljava/lang/CharSequence;Ljava/lang/CharSequence;Ljava/lang/String;)Landroid/content/ClipData;",
+                    &[(&label).into(), (&text_string).into(), (&html_string).into()],
+                )?.l()?
+            },
+            ClipboardContent::Image(_) => return Err("Android only shares images on the clipboard through a content provider".into()),
+            ClipboardContent::Files(files) => {
+                let mut uris = Vec::new();
+                for file in &files {
+                    let path = JObject::from(env.new_string(file.to_string_lossy())?);
+                    let java_file = env.new_object("java/io/File", "(Ljava/lang/String;)V", &[(&path).into()])?;
+                    let uri = env.call_static_method("android/net/Uri", "fromFile", "(Ljava/io/File;)Landroid/net/Uri;", &[(&java_file).into()])?.l()?;
+                    uris.push(uri);
+                }
+                let Some((first, rest)) = uris.split_first() else {return Ok(())};
+                let clip_data = env.call_static_method(
+                    clip_data_class,
+                    "newRawUri",
+                    "(Ljava/lang/CharSequence;Landroid/net/Uri;)Landroid/content/ClipData;",
+                    &[(&label).into(), first.into()],
+                )?.l()?;
+                for uri in rest {
+                    let item = env.new_object("android/content/ClipData$Item", "(Landroid/net/Uri;)V", &[JValue::Object(uri)])?;
+                    env.call_method(&clip_data, "addItem", "(Landroid/content/ClipData$Item;)V", &[(&item).into()])?;
+                }
+                clip_data
+            }
+        };
+
+        if sensitive {
+            let extras = env.new_object("android/os/PersistableBundle", "()V", &[])?;
+            let key = env.new_string("android.content.extra.IS_SENSITIVE")?;
+            env.call_method(&extras, "putBoolean", "(Ljava/lang/String;Z)V", &[(&key).into(), JValue::Bool(1)])?;
+            let description = env.call_method(&clip_data, "getDescription", "()Landroid/content/ClipDescription;", &[])?.l()?;
+            env.call_method(&description, "setExtras", "(Landroid/os/PersistableBundle;)V", &[(&extras).into()])?;
+        }
 
         env.call_method(
             clipboard_manager,
@@ -129,4 +277,4 @@ impl OsClipboard {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,9 @@
+package com.orangeme.clipboard;
+
+import android.content.ClipboardManager;
+
+/** Forwards clipboard changes to the native side, the source of classes.dex next to it. */
+public final class ClipboardWatcher implements ClipboardManager.OnPrimaryClipChangedListener {
+    @Override
+    public native void onPrimaryClipChanged();
+}
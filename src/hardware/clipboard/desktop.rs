@@ -0,0 +1,96 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+use arboard::{Clipboard, ImageData};
+use image::RgbaImage;
+
+#[cfg(target_os = "macos")]
+use objc2_app_kit::NSPasteboard;
+
+use super::{ClipboardFormat, ClipboardContent};
+
+#[cfg(target_os = "linux")]
+mod selection;
+#[cfg(target_os = "linux")]
+use selection::Selection;
+
+#[cfg(target_os = "windows")]
+#[link(name = "user32")]
+unsafe extern "system" {
+    fn GetClipboardSequenceNumber() -> u32;
+}
+
+/// On X11 and Wayland the contents we set are served by this process, so one clipboard is kept open for as long
+/// as the application runs.
+#[derive(Clone)]
+pub struct OsClipboard {
+    clipboard: Option<Arc<Mutex<Clipboard>>>,
+    #[cfg(target_os = "linux")]
+    selection: Selection,
+}
+
+impl OsClipboard {
+    pub fn new() -> Self {
+        let clipboard = Clipboard::new().map_err(|e| log::error!("Clipboard unavailable: {e}")).ok();
+        Self{
+            clipboard: clipboard.map(|c| Arc::new(Mutex::new(c))),
+            #[cfg(target_os = "linux")]
+            selection: Selection::watch(),
+        }
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Clipboard) -> Result<T, arboard::Error>) -> Option<T> {
+        let mut clipboard = self.clipboard.as_ref()?.lock().unwrap();
+        f(&mut clipboard).ok()
+    }
+
+    /// A counter that moves whenever the contents change, without reading them.
+    pub fn changes(&self) -> u64 {
+        #[cfg(target_os = "macos")]
+        return unsafe {NSPasteboard::generalPasteboard().changeCount()} as u64;
+        #[cfg(target_os = "windows")]
+        return unsafe {GetClipboardSequenceNumber()} as u64;
+        #[cfg(target_os = "linux")]
+        self.selection.changes()
+    }
+
+    /// X11, Wayland, AppKit and Win32 share no query for the offered formats, so the text formats are asked
+    /// for directly. Images have to be decoded to be found, that only happens when nothing else was copied.
+    pub fn formats(&self) -> Vec<ClipboardFormat> {
+        let mut formats = Vec::new();
+        if self.with(|c| c.get_text()).is_some_and(|t| !t.is_empty()) {formats.push(ClipboardFormat::Text);}
+        if self.with(|c| c.get().html()).is_some() {formats.push(ClipboardFormat::Html);}
+        if self.with(|c| c.get().file_list()).is_some_and(|f| !f.is_empty()) {formats.push(ClipboardFormat::Files);}
+        if formats.is_empty() && self.with(|c| c.get_image()).is_some() {formats.push(ClipboardFormat::Image);}
+        formats
+    }
+
+    pub fn read(&self, format: ClipboardFormat) -> Option<ClipboardContent> {
+        match format {
+            ClipboardFormat::Text => self.with(|c| c.get_text()).map(ClipboardContent::Text),
+            ClipboardFormat::Html => self.with(|c| c.get().html()).map(|html| ClipboardContent::Html{html, alt: None}),
+            ClipboardFormat::Image => self.with(|c| c.get_image()).and_then(|image| {
+                RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.into_owned())
+            }).map(ClipboardContent::Image),
+            ClipboardFormat::Files => self.with(|c| c.get().file_list()).filter(|f| !f.is_empty()).map(ClipboardContent::Files),
+        }
+    }
+
+    pub fn write(&self, content: ClipboardContent) {
+        let result = self.with(|c| match content {
+            ClipboardContent::Text(text) => c.set_text(text),
+            ClipboardContent::Html{html, alt} => c.set_html(html, alt),
+            ClipboardContent::Image(image) => c.set_image(ImageData{
+                width: image.width() as usize,
+                height: image.height() as usize,
+                bytes: Cow::Owned(image.into_raw()),
+            }),
+            ClipboardContent::Files(files) => c.set().file_list(&files),
+        });
+        if result.is_none() {log::error!("Could not write to the clipboard");}
+    }
+
+    pub fn clear(&self) {
+        if self.with(|c| c.clear()).is_none() {log::error!("Could not clear the clipboard");}
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle};
+use wayland_protocols_wlr::data_control::v1::client::zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1};
+use wayland_protocols_wlr::data_control::v1::client::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1;
+use wayland_protocols_wlr::data_control::v1::client::zwlr_data_control_offer_v1::ZwlrDataControlOfferV1;
+use x11rb::connection::Connection as _;
+use x11rb::protocol::Event;
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xproto::{ConnectionExt as _, CreateWindowAux, WindowClass};
+
+type Error = Box<dyn std::error::Error>;
+
+/// Counts how often the clipboard selection changed owner. X11 and Wayland announce a new owner to every
+/// client that asks, so the contents are never read to notice a change.
+#[derive(Clone, Default)]
+pub struct Selection(Arc<AtomicU64>);
+
+impl Selection {
+    pub fn watch() -> Self {
+        let selection = Self::default();
+        let changes = selection.0.clone();
+        std::thread::spawn(move || {
+            let result = match std::env::var_os("WAYLAND_DISPLAY") {
+                Some(_) => wayland(&changes).or_else(|e| {
+                    log::warn!("Watching the clipboard through Xwayland: {e}");
+                    x11(&changes)
+                }),
+                None => x11(&changes),
+            };
+            if let Err(e) = result {log::error!("Clipboard changes are not reported: {e}");}
+        });
+        selection
+    }
+
+    pub fn changes(&self) -> u64 {self.0.load(Ordering::Acquire)}
+}
+
+fn x11(changes: &AtomicU64) -> Result<(), Error> {
+    let (connection, screen) = x11rb::connect(None)?;
+    connection.xfixes_query_version(5, 0)?.reply()?;
+    let root = connection.setup().roots[screen].root;
+    let window = connection.generate_id()?;
+    connection.create_window(0, window, root, 0, 0, 1, 1, 0, WindowClass::INPUT_ONLY, 0, &CreateWindowAux::new())?;
+    let clipboard = connection.intern_atom(false, b"CLIPBOARD")?.reply()?.atom;
+    let mask = SelectionEventMask::SET_SELECTION_OWNER | SelectionEventMask::SELECTION_WINDOW_DESTROY | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+    connection.xfixes_select_selection_input(window, clipboard, mask)?;
+    connection.flush()?;
+    loop {
+        if let Event::XfixesSelectionNotify(_) = connection.wait_for_event()? {
+            changes.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
+
+fn wayland(changes: &Arc<AtomicU64>) -> Result<(), Error> {
+    let connection = Connection::connect_to_env()?;
+    let (globals, mut queue) = registry_queue_init::<Watcher>(&connection)?;
+    let handle = queue.handle();
+    let seat: WlSeat = globals.bind(&handle, 1..=1, ())?;
+    let manager: ZwlrDataControlManagerV1 = globals.bind(&handle, 1..=2, ())?;
+    let _device = manager.get_data_device(&seat, &handle, ());
+    let mut watcher = Watcher{changes: changes.clone(), offer: None, announced: false};
+    loop {queue.blocking_dispatch(&mut watcher)?;}
+}
+
+struct Watcher {
+    changes: Arc<AtomicU64>,
+    /// The offer of the current selection, destroyed once it is replaced.
+    offer: Option<ZwlrDataControlOfferV1>,
+    /// The compositor announces the selection already present when the device is created, which is no change.
+    announced: bool,
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for Watcher {
+    fn event(state: &mut Self, _: &ZwlrDataControlDeviceV1, event: zwlr_data_control_device_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_data_control_device_v1::Event::Selection{id} => {
+                if let Some(offer) = std::mem::replace(&mut state.offer, id) {offer.destroy();}
+                if state.announced {state.changes.fetch_add(1, Ordering::AcqRel);}
+                state.announced = true;
+            },
+            zwlr_data_control_device_v1::Event::PrimarySelection{id: Some(offer)} => offer.destroy(),
+            _ => {}
+        }
+    }
+
+    event_created_child!(Watcher, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ())
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for Watcher {
+    fn event(_: &mut Self, _: &ZwlrDataControlOfferV1, _: <ZwlrDataControlOfferV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<ZwlrDataControlManagerV1, ()> for Watcher {
+    fn event(_: &mut Self, _: &ZwlrDataControlManagerV1, _: <ZwlrDataControlManagerV1 as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlSeat, ()> for Watcher {
+    fn event(_: &mut Self, _: &WlSeat, _: <WlSeat as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Watcher {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
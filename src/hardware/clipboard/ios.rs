@@ -1,6 +1,19 @@
-use objc2_foundation::NSString;
+use std::ffi::c_void;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use objc2::rc::Retained;
+use objc2::runtime::AnyObject;
+use objc2::msg_send;
+use objc2_foundation::{NSArray, NSData, NSDictionary, NSString, NSURL};
 use objc2_ui_kit::UIPasteboard;
 
+use super::{ClipboardFormat, ClipboardContent};
+
+const TEXT: &str = "public.utf8-plain-text";
+const HTML: &str = "public.html";
+const PNG: &str = "public.png";
+
 #[derive(Clone)]
 pub struct OsClipboard;
 
@@ -9,18 +22,87 @@ impl OsClipboard {
         Self
     }
 
-    pub fn get_content(&self) -> String {
+    /// A counter that moves whenever the contents change, reading it does not trigger the paste prompt.
+    pub fn changes(&self) -> u64 {
+        unsafe {UIPasteboard::generalPasteboard().changeCount()} as u64
+    }
+
+    pub fn formats(&self) -> Vec<ClipboardFormat> {
+        unsafe {
+            let pasteboard = UIPasteboard::generalPasteboard();
+            let html = NSArray::from_retained_slice(&[NSString::from_str(HTML)]);
+            let mut formats = Vec::new();
+            if pasteboard.hasStrings() {formats.push(ClipboardFormat::Text);}
+            if pasteboard.containsPasteboardTypes(&html) {formats.push(ClipboardFormat::Html);}
+            if pasteboard.hasImages() {formats.push(ClipboardFormat::Image);}
+            if pasteboard.URLs().is_some_and(|urls| urls.iter().any(|u| u.isFileURL())) {formats.push(ClipboardFormat::Files);}
+            formats
+        }
+    }
+
+    fn data(pasteboard: &UIPasteboard, kind: &str) -> Option<Vec<u8>> {
+        let data: Option<Retained<NSData>> = unsafe {msg_send![pasteboard, dataForPasteboardType: &*NSString::from_str(kind)]};
+        let data = data?;
+        let length: usize = unsafe {msg_send![&*data, length]};
+        let bytes: *const c_void = unsafe {msg_send![&*data, bytes]};
+        Some(unsafe {std::slice::from_raw_parts(bytes as *const u8, length)}.to_vec())
+    }
+
+    pub fn read(&self, format: ClipboardFormat) -> Option<ClipboardContent> {
         unsafe {
             let pasteboard = UIPasteboard::generalPasteboard();
-            pasteboard.string().map(|s| s.to_string()).unwrap_or_default()
+            match format {
+                ClipboardFormat::Text => pasteboard.string().map(|s| ClipboardContent::Text(s.to_string())),
+                ClipboardFormat::Html => Self::data(&pasteboard, HTML).map(|html| ClipboardContent::Html{
+                    html: String::from_utf8_lossy(&html).to_string(),
+                    alt: pasteboard.string().map(|s| s.to_string()),
+                }),
+                ClipboardFormat::Image => Self::data(&pasteboard, PNG)
+                    .and_then(|png| image::load_from_memory(&png).ok())
+                    .map(|image| ClipboardContent::Image(image.to_rgba8())),
+                ClipboardFormat::Files => {
+                    let files = pasteboard.URLs()?.iter().filter(|u| u.isFileURL())
+                        .filter_map(|u| u.path()).map(|p| PathBuf::from(p.to_string())).collect::<Vec<_>>();
+                    (!files.is_empty()).then_some(ClipboardContent::Files(files))
+                }
+            }
         }
     }
 
-    pub fn set_content(&self, text: String) {
+    pub fn clear(&self) {
+        unsafe {UIPasteboard::generalPasteboard().setItems(&NSArray::new())}
+    }
+
+    pub fn write(&self, content: ClipboardContent) {
         unsafe {
             let pasteboard = UIPasteboard::generalPasteboard();
-            let ns_string = NSString::from_str(&text);
-            pasteboard.setString(Some(&ns_string));
+            match content {
+                ClipboardContent::Text(text) => pasteboard.setString(Some(&NSString::from_str(&text))),
+                ClipboardContent::Html{html, alt} => {
+                    let mut keys = vec![NSString::from_str(HTML)];
+                    let mut values = vec![NSString::from_str(&html)];
+                    if let Some(alt) = alt {
+                        keys.push(NSString::from_str(TEXT));
+                        values.push(NSString::from_str(&alt));
+                    }
+                    let item = NSDictionary::from_retained_objects(&keys.iter().map(|k| &**k).collect::<Vec<_>>(), &values);
+                    let item = Retained::cast_unchecked::<NSDictionary<NSString, AnyObject>>(item);
+                    pasteboard.setItems(&NSArray::from_retained_slice(&[item]));
+                },
+                ClipboardContent::Image(image) => {
+                    let mut png = Vec::new();
+                    if let Err(e) = image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png) {
+                        log::error!("Could not encode clipboard image: {e}");
+                        return;
+                    }
+                    let data = NSData::with_bytes(&png);
+                    let _: () = msg_send![&*pasteboard, setData: &*data, forPasteboardType: &*NSString::from_str(PNG)];
+                },
+                ClipboardContent::Files(files) => {
+                    let urls = files.iter().map(|f| NSURL::fileURLWithPath(&NSString::from_str(&f.to_string_lossy()))).collect::<Vec<_>>();
+                    pasteboard.setURLs(Some(&NSArray::from_retained_slice(&urls)));
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,35 +1,290 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use image::RgbaImage;
+use serde::{Serialize, Deserialize};
+
 #[cfg(any(target_os = "ios", target_os = "macos"))]
 mod apple;
 #[cfg(any(target_os = "ios", target_os = "macos"))]
 use apple::OsNotifications;
 
-#[derive(Clone)]
-pub struct Notifications(
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+use linux::OsNotifications;
+
+#[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+mod schedule;
+#[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+use schedule::Schedule;
+
+#[derive(Debug)]
+pub enum NotificationError {
+    /// No notification service is reachable.
+    Unavailable(String),
+    Rejected(String),
+    /// Schedules could not be read or saved.
+    Storage(String),
+    Unsupported,
+}
+impl std::fmt::Display for NotificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NotificationError::Unavailable(e) => write!(f, "Notification service unavailable: {e}"),
+            NotificationError::Rejected(e) => write!(f, "Notification rejected: {e}"),
+            NotificationError::Storage(e) => write!(f, "Notification schedule error: {e}"),
+            NotificationError::Unsupported => write!(f, "Notifications are not supported on this platform"),
+        }
+    }
+}
+impl std::error::Error for NotificationError {}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+/// A button shown on the notification, `id` identifies it when it is pressed. With a `reply` placeholder it
+/// takes text instead, which Linux servers only offer if they support inline replies, as KDE does.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NotificationAction {
+    pub id: String,
+    pub label: String,
+    pub reply: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub icon: Option<RgbaImage>,
+    pub urgency: Urgency,
+    /// How long it stays on screen, `None` leaves it to the platform.
+    pub timeout: Option<Duration>,
+    pub actions: Vec<NotificationAction>,
+}
+impl Notification {
+    pub fn new(title: &str, body: &str) -> Self {
+        Notification{title: title.to_string(), body: body.to_string(), ..Default::default()}
+    }
+
+    pub fn icon(mut self, icon: RgbaImage) -> Self {self.icon = Some(icon); self}
+    pub fn urgency(mut self, urgency: Urgency) -> Self {self.urgency = urgency; self}
+    pub fn timeout(mut self, timeout: Duration) -> Self {self.timeout = Some(timeout); self}
+    pub fn action(mut self, id: &str, label: &str) -> Self {
+        self.actions.push(NotificationAction{id: id.to_string(), label: label.to_string(), reply: None});
+        self
+    }
+    pub fn reply(mut self, id: &str, label: &str, placeholder: &str) -> Self {
+        self.actions.push(NotificationAction{id: id.to_string(), label: label.to_string(), reply: Some(placeholder.to_string())});
+        self
+    }
+}
+
+/// A notification was clicked, `action` is the id of the action used, if it was not the notification itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activation {
+    pub id: String,
+    pub action: Option<String>,
+    pub reply_text: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Activity {
+    pending: Vec<Activation>,
+    launch: Option<Activation>,
+    ticked: bool,
+}
+
+/// Where the platform backends report activations until the next tick.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Activations(Arc<Mutex<Activity>>);
+impl Activations {
+    #[cfg(target_os = "linux")]
+    pub fn push(&self, activation: Activation) {self.0.lock().unwrap().pending.push(activation);}
+
+    /// Reports an activation that may have launched the application, which it did if nothing ticked yet.
     #[cfg(any(target_os = "ios", target_os = "macos"))]
-    OsNotifications
-);
+    pub fn launched(&self, activation: Activation) {
+        let mut activity = self.0.lock().unwrap();
+        if !activity.ticked && activity.launch.is_none() {activity.launch = Some(activation.clone());}
+        activity.pending.push(activation);
+    }
+
+    fn take(&self) -> Vec<Activation> {
+        let mut activity = self.0.lock().unwrap();
+        activity.ticked = true;
+        std::mem::take(&mut activity.pending)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Repeat {
+    /// On Apple platforms the first repeat comes one interval after scheduling, not after `at`.
+    Every(Duration),
+    Daily,
+    Weekly,
+}
+impl Repeat {
+    pub fn interval(self) -> Duration {
+        match self {
+            Repeat::Every(interval) => interval.max(Duration::from_secs(1)),
+            Repeat::Daily => Duration::from_secs(24 * 60 * 60),
+            Repeat::Weekly => Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheduled {
+    pub id: String,
+    pub notification: Notification,
+    /// The next delivery.
+    pub at: SystemTime,
+    pub repeat: Option<Repeat>,
+}
+
+/// Local notifications. Apple platforms show the title, body, actions and a default sound, the icon, urgency and
+/// timeout are only used on Linux, where notifications go to the `org.freedesktop.Notifications` service of the
+/// session bus.
+///
+/// Every notification gets an id, which `cancel` and `replace` take. Schedules are kept in `./notifications.db`,
+/// Apple platforms deliver them natively, on Linux they are delivered by `hardware::Context::tick` and so only
+/// while the application runs, a schedule that came due while it was closed is delivered on the next start.
+///
+/// Clicks on a notification or its actions arrive as [`Input::NotificationActivated`](crate::window::Input).
+/// Showing with [`replace`](Self::replace) under an id of your own, such as a chat id, tells you what to open.
+#[derive(Clone)]
+pub struct Notifications {
+    #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+    os: OsNotifications,
+    #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+    schedule: Schedule,
+    activations: Activations,
+}
 
 impl Notifications {
     pub(crate) fn new() -> Self {
-        Self(
-            #[cfg(any(target_os = "ios", target_os = "macos"))]
-            OsNotifications::new()
-        )
+        let activations = Activations::default();
+        Self{
+            #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+            os: OsNotifications::new(activations.clone()),
+            #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+            schedule: Schedule::new(),
+            activations,
+        }
     }
 
+    /// The activation that launched the application, it is delivered as an input as well. Only Apple platforms
+    /// launch applications from notifications, and they report it just after start, by the first tick at the latest.
+    pub fn launch(&self) -> Option<Activation> {self.activations.0.lock().unwrap().launch.clone()}
+
     pub fn register(&self) {
-        #[cfg(any(target_os = "ios", target_os = "macos"))]
-        self.0.register();
-        
-        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        panic!("not supported os");
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+        self.os.register();
     }
 
     pub fn push(&self, title: &str, body: &str) {
+        if let Err(e) = self.show(Notification::new(title, body)) {log::error!("{e}");}
+    }
+
+    fn new_id() -> String {hex::encode(rand::random::<[u8; 8]>())}
+
+    /// Shows `notification` now, returning its id.
+    pub fn show(&self, notification: Notification) -> Result<String, NotificationError> {
+        let id = Self::new_id();
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+        {
+            self.os.show(&id, &notification)?;
+            Ok(id)
+        }
+
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = (id, notification);
+            Err(NotificationError::Unsupported)
+        }
+    }
+
+    /// Shows `notification` at `at`, then again every `repeat` until cancelled. Returns its id.
+    pub fn schedule(&self, notification: Notification, at: SystemTime, repeat: Option<Repeat>) -> Result<String, NotificationError> {
+        let scheduled = Scheduled{id: Self::new_id(), notification, at, repeat};
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+        {
+            #[cfg(any(target_os = "ios", target_os = "macos"))]
+            self.os.schedule(&scheduled)?;
+            self.schedule.insert(&scheduled)?;
+            Ok(scheduled.id)
+        }
+
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = scheduled;
+            Err(NotificationError::Unsupported)
+        }
+    }
+
+    /// Withdraws a scheduled notification or removes a shown one.
+    pub fn cancel(&self, id: &str) -> Result<(), NotificationError> {
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+        {
+            self.schedule.remove(id)?;
+            self.os.cancel(id)
+        }
+
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = id;
+            Err(NotificationError::Unsupported)
+        }
+    }
+
+    /// Changes the contents of a notification, in place when it is shown and keeping its time when it is
+    /// scheduled. An id that is neither is shown now.
+    pub fn replace(&self, id: &str, notification: Notification) -> Result<(), NotificationError> {
+        #[cfg(any(target_os = "ios", target_os = "macos", target_os = "linux"))]
+        {
+            match self.schedule.get(id)? {
+                Some(scheduled) => {
+                    let scheduled = Scheduled{notification, ..scheduled};
+                    #[cfg(any(target_os = "ios", target_os = "macos"))]
+                    self.os.schedule(&scheduled)?;
+                    self.schedule.insert(&scheduled)
+                },
+                None => self.os.show(id, &notification),
+            }
+        }
+
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = (id, notification);
+            Err(NotificationError::Unsupported)
+        }
+    }
+
+    /// Notifications still to be delivered, soonest first.
+    pub fn pending(&self) -> Result<Vec<Scheduled>, NotificationError> {
         #[cfg(any(target_os = "ios", target_os = "macos"))]
-        self.0.push(title, body);
-        
-        #[cfg(not(any(target_os = "ios", target_os = "macos")))]
-        panic!("not supported os");
+        return self.schedule.list(true);
+        #[cfg(target_os = "linux")]
+        return self.schedule.list(false);
+        #[cfg(not(any(target_os = "ios", target_os = "macos", target_os = "linux")))]
+        Ok(Vec::new())
+    }
+
+    /// Delivers the schedules that came due and returns the activations since the last tick.
+    pub(crate) fn tick(&self) -> Vec<Activation> {
+        #[cfg(target_os = "linux")]
+        match self.schedule.due() {
+            Ok(due) => for scheduled in due {
+                if let Err(e) = self.os.show(&scheduled.id, &scheduled.notification) {log::error!("{e}");}
+            },
+            Err(e) => log::error!("{e}"),
+        }
+        self.activations.take()
     }
-}
\ No newline at end of file
+}
@@ -14,19 +14,88 @@ use objc2_foundation::NSBundle;
 #[cfg(target_os = "macos")]
 use objc2::rc::autoreleasepool;
 
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use objc2_user_notifications::{
     UNAuthorizationOptions, UNUserNotificationCenter, UNNotificationRequest,
     UNMutableNotificationContent, UNTimeIntervalNotificationTrigger,
+    UNCalendarNotificationTrigger, UNNotificationTrigger, UNUserNotificationCenterDelegate,
+    UNNotificationResponse, UNTextInputNotificationResponse, UNNotificationDefaultActionIdentifier,
+    UNNotificationCategory, UNNotificationCategoryOptions, UNNotificationAction, UNNotificationActionOptions,
+    UNTextInputNotificationAction,
 };
-use block2::StackBlock;
-use objc2_foundation::{NSString, NSError};
+use block2::{DynBlock, StackBlock};
+use objc2::__framework_prelude::NSObject;
+use objc2::rc::Retained;
+use objc2::runtime::{NSObjectProtocol, ProtocolObject};
+use objc2::{define_class, AllocAnyThread, DeclaredClass};
+use objc2_foundation::{NSArray, NSCalendar, NSCalendarUnit, NSDate, NSSet, NSString, NSError};
+
+use super::{Activation, Activations, Notification, NotificationAction, NotificationError, Repeat, Scheduled};
+
+impl Delegate {
+    fn new(activations: Activations) -> Retained<Self> {
+        let this = Self::alloc();
+        let this = this.set_ivars(activations);
+        unsafe { objc2::msg_send![super(this), init] }
+    }
+}
+
+define_class!(
+    #[unsafe(super = NSObject)]
+    #[ivars = Activations]
+    #[derive(Debug)]
+    pub struct Delegate;
+
+    unsafe impl NSObjectProtocol for Delegate {}
+
+    unsafe impl UNUserNotificationCenterDelegate for Delegate {
+        #[unsafe(method(userNotificationCenter:didReceiveNotificationResponse:withCompletionHandler:))]
+        fn userNotificationCenter_didReceiveNotificationResponse_withCompletionHandler(
+            &self,
+            _center: &UNUserNotificationCenter,
+            response: &UNNotificationResponse,
+            completion_handler: &DynBlock<dyn Fn()>,
+        ) {
+            unsafe {
+                let id = response.notification().request().identifier().to_string();
+                let action = response.actionIdentifier();
+                let action = (&*action != UNNotificationDefaultActionIdentifier).then(|| action.to_string());
+                let reply_text = response.downcast_ref::<UNTextInputNotificationResponse>().map(|r| r.userText().to_string());
+                self.ivars().launched(Activation{id, action, reply_text});
+            }
+            completion_handler.call(());
+        }
+    }
+);
 
 #[derive(Clone)]
-pub struct OsNotifications;
+pub struct OsNotifications {
+    /// The actions of every category registered by this process, by category id.
+    categories: Arc<Mutex<HashMap<String, Vec<NotificationAction>>>>,
+}
 
 impl OsNotifications {
-    pub fn new() -> Self {
-        Self
+    /// Installs the delegate that reports activations. It has to be in place before launching finishes for the
+    /// activation that launched the application to be reported.
+    pub fn new(activations: Activations) -> Self {
+        static DELEGATE: Once = Once::new();
+        if Self::bundled() {
+            DELEGATE.call_once(|| {
+                let delegate = Delegate::new(activations);
+                unsafe {
+                    let center = UNUserNotificationCenter::currentNotificationCenter();
+                    center.setDelegate(Some(ProtocolObject::from_ref(&*delegate)));
+                }
+                // The center only keeps a weak reference.
+                std::mem::forget(delegate);
+            });
+        }
+        Self{categories: Arc::new(Mutex::new(HashMap::new()))}
     }
 
     #[cfg(target_os = "ios")]
@@ -115,62 +184,113 @@ impl OsNotifications {
     }
 
     #[cfg(target_os = "ios")]
-    pub fn push(&self, title: &str, body: &str) {
-        unsafe {
-            let content = UNMutableNotificationContent::new();
-            content.setTitle(&NSString::from_str(title));
-            content.setBody(&NSString::from_str(body));
-            content.setSound(Some(&objc2_user_notifications::UNNotificationSound::defaultSound()));
+    fn bundled() -> bool {true}
 
-            let trigger = UNTimeIntervalNotificationTrigger::triggerWithTimeInterval_repeats(1.0, false);
-            let identifier = NSString::from_str("demo-id");
+    /// The notification center is only available to applications run from a bundle.
+    #[cfg(target_os = "macos")]
+    fn bundled() -> bool {
+        let main_bundle = NSBundle::mainBundle();
+        let bundle_url = main_bundle.bundleURL();
+        let rust_str = autoreleasepool(|pool| {
+            let absolute_string = bundle_url.absoluteString().unwrap();
+            unsafe { absolute_string.to_str(pool).to_owned() }
+        });
 
-            let request = UNNotificationRequest::requestWithIdentifier_content_trigger(
-                &identifier,
-                &content,
-                Some(&trigger),
-            );
+        if !rust_str.ends_with(".app/") {
+            eprintln!("⚠️ No valid app bundle detected. Skipping notifications.");
+            return false;
+        }
+        true
+    }
 
+    /// Registers `actions` as a category and returns its id, equal sets of actions share one. Categories do not
+    /// outlive the process, notifications from an earlier run show no actions until theirs is registered again.
+    fn category(&self, actions: &[NotificationAction]) -> String {
+        let mut hasher = DefaultHasher::new();
+        actions.hash(&mut hasher);
+        let id = format!("maverick-{:016x}", hasher.finish());
+        let mut categories = self.categories.lock().unwrap();
+        if categories.insert(id.clone(), actions.to_vec()).is_some() {return id;}
+        unsafe {
+            let categories = categories.iter().map(|(id, actions)| {
+                let actions = actions.iter().map(|a| {
+                    let identifier = NSString::from_str(&a.id);
+                    let title = NSString::from_str(&a.label);
+                    match &a.reply {
+                        Some(placeholder) => Retained::into_super(
+                            UNTextInputNotificationAction::actionWithIdentifier_title_options_textInputButtonTitle_textInputPlaceholder(
+                                &identifier, &title, UNNotificationActionOptions::Foreground, &title, &NSString::from_str(placeholder),
+                            )
+                        ),
+                        None => UNNotificationAction::actionWithIdentifier_title_options(&identifier, &title, UNNotificationActionOptions::Foreground),
+                    }
+                }).collect::<Vec<_>>();
+                UNNotificationCategory::categoryWithIdentifier_actions_intentIdentifiers_options(
+                    &NSString::from_str(id), &NSArray::from_retained_slice(&actions), &NSArray::new(), UNNotificationCategoryOptions::empty(),
+                )
+            }).collect::<Vec<_>>();
             let center = UNUserNotificationCenter::currentNotificationCenter();
-            center.addNotificationRequest_withCompletionHandler(&request, None);
-
-            println!("Notification scheduled!");
+            center.setNotificationCategories(&NSSet::from_retained_slice(&categories));
         }
+        id
     }
 
-    #[cfg(target_os = "macos")]
-    pub fn push(&self, title: &str, body: &str) {
+    /// Adds a request under `id`, which replaces any pending or delivered notification with the same id.
+    fn request(&self, id: &str, notification: &Notification, trigger: Option<Retained<UNNotificationTrigger>>) -> Result<(), NotificationError> {
+        if !Self::bundled() {return Err(NotificationError::Unavailable("No app bundle".to_string()));}
+        let category = (!notification.actions.is_empty()).then(|| self.category(&notification.actions));
         unsafe {
-            let main_bundle = NSBundle::mainBundle();
-            let bundle_url = main_bundle.bundleURL();
-            let rust_str = autoreleasepool(|pool| {
-                let absolute_string = bundle_url.absoluteString().unwrap();
-                absolute_string.to_str(pool).to_owned()
-            });
-
-            if !rust_str.ends_with(".app/") {
-                eprintln!("⚠️ No valid app bundle detected. Skipping notification registration.");
-                return;
-            }
-
             let content = UNMutableNotificationContent::new();
-            content.setTitle(&NSString::from_str(title));
-            content.setBody(&NSString::from_str(body));
+            content.setTitle(&NSString::from_str(&notification.title));
+            content.setBody(&NSString::from_str(&notification.body));
             content.setSound(Some(&objc2_user_notifications::UNNotificationSound::defaultSound()));
-
-            let trigger = UNTimeIntervalNotificationTrigger::triggerWithTimeInterval_repeats(1.0, false);
-            let identifier = NSString::from_str("demo-id");
+            if let Some(category) = category {content.setCategoryIdentifier(&NSString::from_str(&category));}
 
             let request = UNNotificationRequest::requestWithIdentifier_content_trigger(
-                &identifier,
+                &NSString::from_str(id),
                 &content,
-                Some(&trigger),
+                trigger.as_deref(),
             );
 
             let center = UNUserNotificationCenter::currentNotificationCenter();
             center.addNotificationRequest_withCompletionHandler(&request, None);
+        }
+        Ok(())
+    }
+
+    pub fn show(&self, id: &str, notification: &Notification) -> Result<(), NotificationError> {
+        self.request(id, notification, None)
+    }
+
+    /// Daily and weekly repeats match the time of day, and weekday, of `at` in the current calendar.
+    pub fn schedule(&self, scheduled: &Scheduled) -> Result<(), NotificationError> {
+        let seconds = scheduled.at.duration_since(SystemTime::now()).unwrap_or_default().as_secs_f64().max(1.0);
+        let trigger = unsafe {
+            match scheduled.repeat {
+                None => Retained::into_super(UNTimeIntervalNotificationTrigger::triggerWithTimeInterval_repeats(seconds, false)),
+                Some(Repeat::Every(interval)) => Retained::into_super(
+                    UNTimeIntervalNotificationTrigger::triggerWithTimeInterval_repeats(interval.as_secs_f64().max(60.0), true)
+                ),
+                Some(repeat) => {
+                    let since_epoch = scheduled.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+                    let date = NSDate::dateWithTimeIntervalSince1970(since_epoch);
+                    let mut units = NSCalendarUnit::Hour | NSCalendarUnit::Minute | NSCalendarUnit::Second;
+                    if repeat == Repeat::Weekly {units |= NSCalendarUnit::Weekday;}
+                    let components = NSCalendar::currentCalendar().components_fromDate(units, &date);
+                    Retained::into_super(UNCalendarNotificationTrigger::triggerWithDateMatchingComponents_repeats(&components, true))
+                }
+            }
+        };
+        self.request(&scheduled.id, &scheduled.notification, Some(trigger))
+    }
 
-            println!("Notification scheduled on macOS!");
+    pub fn cancel(&self, id: &str) -> Result<(), NotificationError> {
+        unsafe {
+            let ids = NSArray::from_retained_slice(&[NSString::from_str(id)]);
+            let center = UNUserNotificationCenter::currentNotificationCenter();
+            center.removePendingNotificationRequestsWithIdentifiers(&ids);
+            center.removeDeliveredNotificationsWithIdentifiers(&ids);
         }
+        Ok(())
     }
-}
\ No newline at end of file
+}
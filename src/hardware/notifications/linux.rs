@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use zbus::blocking::{connection, Connection, Proxy};
+use zbus::zvariant::{Structure, Value};
+
+use super::{Activation, Activations, Notification, NotificationError, Urgency};
+
+const DESTINATION: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+/// The action servers report for a click on the notification itself.
+const DEFAULT: &str = "default";
+/// The action KDE turns into a text field, the only kind of reply the protocol has.
+const REPLY: &str = "inline-reply";
+
+/// A notification we showed.
+#[derive(Debug, Clone)]
+struct Shown {
+    /// The id the server gave it.
+    server: u32,
+    /// Our id of its reply action.
+    reply: Option<String>,
+}
+
+/// Talks to the notification server of the session bus named by `DBUS_SESSION_BUS_ADDRESS`. The bus is only
+/// connected to when the first notification is sent, from then on the server's signals are read on a thread of
+/// their own. They are only sent to the connection that showed the notification, so nothing is activated once
+/// the application exits.
+#[derive(Clone)]
+pub struct OsNotifications {
+    /// The bus to use instead of the session bus.
+    address: Option<String>,
+    connection: Arc<OnceLock<Connection>>,
+    application: String,
+    shown: Arc<Mutex<HashMap<String, Shown>>>,
+    activations: Activations,
+}
+
+impl OsNotifications {
+    pub fn new(activations: Activations) -> Self {
+        let application = std::env::current_exe().ok()
+            .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "maverick_os".to_string());
+        Self{address: None, connection: Arc::new(OnceLock::new()), application, shown: Arc::new(Mutex::new(HashMap::new())), activations}
+    }
+
+    #[cfg(test)]
+    fn on_bus(activations: Activations, address: &str) -> Self {
+        Self{address: Some(address.to_string()), ..Self::new(activations)}
+    }
+
+    fn proxy(&self) -> Result<Proxy<'static>, NotificationError> {
+        let connection = match self.connection.get() {
+            Some(connection) => connection.clone(),
+            None => {
+                let connection = match &self.address {
+                    Some(address) => connection::Builder::address(address.as_str()).and_then(|b| b.build()),
+                    None => Connection::session(),
+                }.map_err(|e| NotificationError::Unavailable(e.to_string()))?;
+                let mut connected = false;
+                let connection = self.connection.get_or_init(|| {connected = true; connection}).clone();
+                if connected {self.listen(connection.clone());}
+                connection
+            }
+        };
+        Proxy::new(&connection, DESTINATION, PATH, INTERFACE).map_err(|e| NotificationError::Unavailable(e.to_string()))
+    }
+
+    /// Subscribes before returning, so no signal about a notification shown afterwards is missed.
+    fn listen(&self, connection: Connection) {
+        let signals = match Proxy::new(&connection, DESTINATION, PATH, INTERFACE).and_then(|p| p.receive_all_signals()) {
+            Ok(signals) => signals,
+            Err(e) => {log::error!("Notification activations unavailable: {e}"); return;}
+        };
+        let this = self.clone();
+        std::thread::spawn(move || {
+            for message in signals {
+                let member = message.header().member().map(|m| m.to_string()).unwrap_or_default();
+                let body = message.body();
+                match member.as_str() {
+                    "ActionInvoked" => if let Ok((server, action)) = body.deserialize::<(u32, String)>() {
+                        this.activated(server, Some(action), None);
+                    },
+                    "NotificationReplied" => if let Ok((server, text)) = body.deserialize::<(u32, String)>() {
+                        this.activated(server, Some(REPLY.to_string()), Some(text));
+                    },
+                    "NotificationClosed" => if let Ok((server, _)) = body.deserialize::<(u32, u32)>() {
+                        this.shown.lock().unwrap().retain(|_, shown| shown.server != server);
+                    },
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    fn activated(&self, server: u32, action: Option<String>, reply_text: Option<String>) {
+        let shown = self.shown.lock().unwrap();
+        let Some((id, shown)) = shown.iter().find(|(_, shown)| shown.server == server) else {return;};
+        let action = match action.as_deref() {
+            Some(DEFAULT) => None,
+            Some(REPLY) => shown.reply.clone(),
+            _ => action,
+        };
+        self.activations.push(Activation{id: id.clone(), action, reply_text});
+    }
+
+    /// Nothing to ask for, checks that a server is running.
+    pub fn register(&self) {
+        match self.proxy().and_then(|p| p.call::<_, _, (String, String, String, String)>("GetServerInformation", &())
+            .map_err(|e| NotificationError::Unavailable(e.to_string()))) {
+            Ok((name, vendor, version, _)) => log::info!("Notifications go to {name} {version} by {vendor}"),
+            Err(e) => log::error!("{e}"),
+        }
+    }
+
+    /// Shows `notification` in place of the one with id `replaces`, or as a new one when it is 0.
+    /// Returns the id the server gave it.
+    fn notify(&self, notification: &Notification, replaces: u32) -> Result<u32, NotificationError> {
+        let reply = notification.actions.iter().find(|a| a.reply.is_some());
+        let mut actions = vec![DEFAULT, ""];
+        for action in &notification.actions {
+            match &action.reply {
+                None => actions.extend([action.id.as_str(), action.label.as_str()]),
+                Some(_) if reply == Some(action) => actions.extend([REPLY, action.label.as_str()]),
+                Some(_) => {}
+            }
+        }
+        let mut hints = HashMap::<&str, Value>::new();
+        if let Some(placeholder) = reply.and_then(|a| a.reply.as_deref()) {
+            hints.insert("x-kde-reply-placeholder-text", Value::from(placeholder));
+        }
+        hints.insert("urgency", Value::U8(match notification.urgency {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        }));
+        if let Some(icon) = &notification.icon {
+            hints.insert("image-data", Value::Structure(Structure::from((
+                icon.width() as i32, icon.height() as i32, icon.width() as i32 * 4, true, 8i32, 4i32, icon.as_raw().clone(),
+            ))));
+        }
+        let timeout = notification.timeout.map(|t| t.as_millis().min(i32::MAX as u128) as i32).unwrap_or(-1);
+        self.proxy()?.call("Notify", &(
+            self.application.as_str(), replaces, "", notification.title.as_str(), notification.body.as_str(), actions, hints, timeout,
+        )).map_err(|e| NotificationError::Rejected(e.to_string()))
+    }
+
+    /// Shows `notification`, replacing the one shown as `id` if it is still open. Only the first reply action is
+    /// offered.
+    pub fn show(&self, id: &str, notification: &Notification) -> Result<(), NotificationError> {
+        let replaces = self.shown.lock().unwrap().get(id).map(|s| s.server).unwrap_or(0);
+        let server = self.notify(notification, replaces)?;
+        let reply = notification.actions.iter().find(|a| a.reply.is_some()).map(|a| a.id.clone());
+        self.shown.lock().unwrap().insert(id.to_string(), Shown{server, reply});
+        Ok(())
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<(), NotificationError> {
+        let Some(shown) = self.shown.lock().unwrap().remove(id) else {return Ok(());};
+        self.proxy()?.call::<_, _, ()>("CloseNotification", &(shown.server,)).map_err(|e| NotificationError::Rejected(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::time::Duration;
+
+    use image::{Rgba, RgbaImage};
+    use zbus::zvariant::OwnedValue;
+
+    /// A `dbus-daemon` of our own, so the test neither needs nor disturbs a desktop session.
+    struct Bus(Child, String);
+    impl Bus {
+        fn start() -> Self {
+            let mut daemon = Command::new("dbus-daemon").args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped()).spawn().expect("dbus-daemon is needed to test notifications");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+            Bus(daemon, address.trim().to_string())
+        }
+    }
+    impl Drop for Bus {
+        fn drop(&mut self) {let _ = self.0.kill();}
+    }
+
+    #[derive(Debug)]
+    enum Call {
+        Notify{app: String, replaces: u32, summary: String, body: String, actions: Vec<String>, hints: HashMap<String, OwnedValue>, timeout: i32},
+        Close(u32),
+    }
+
+    /// Stands in for the notification server, handing every call to the test.
+    struct Server {
+        calls: Sender<Call>,
+        next: u32,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl Server {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(&mut self, app: String, replaces: u32, _icon: String, summary: String, body: String, actions: Vec<String>, hints: HashMap<String, OwnedValue>, timeout: i32) -> u32 {
+            let id = match replaces {0 => {self.next += 1; self.next}, id => id};
+            self.calls.send(Call::Notify{app, replaces, summary, body, actions, hints, timeout}).unwrap();
+            id
+        }
+
+        fn close_notification(&self, id: u32) {self.calls.send(Call::Close(id)).unwrap();}
+
+        fn get_server_information(&self) -> (String, String, String, String) {
+            ("stand-in".into(), "maverick".into(), "1".into(), "1.2".into())
+        }
+    }
+
+    fn serve(bus: &Bus) -> (Connection, Receiver<Call>) {
+        let (calls, received) = channel();
+        let connection = connection::Builder::address(bus.1.as_str()).unwrap()
+            .name(DESTINATION).unwrap()
+            .serve_at(PATH, Server{calls, next: 0}).unwrap()
+            .build().unwrap();
+        (connection, received)
+    }
+
+    fn next(calls: &Receiver<Call>) -> Call {calls.recv_timeout(Duration::from_secs(5)).expect("no call reached the server")}
+
+    #[test]
+    fn notify_and_close() {
+        let bus = Bus::start();
+        let (server, calls) = serve(&bus);
+        let activations = Activations::default();
+        let notifications = OsNotifications::on_bus(activations.clone(), &bus.1);
+
+        let icon = RgbaImage::from_pixel(2, 3, Rgba([1, 2, 3, 4]));
+        let notification = Notification::new("Title", "Body").icon(icon.clone()).urgency(Urgency::Critical)
+            .timeout(Duration::from_millis(1500)).action("open", "Open").reply("answer", "Answer", "Say something");
+        notifications.show("chat", &notification).unwrap();
+
+        let Call::Notify{app, replaces, summary, body, actions, mut hints, timeout} = next(&calls) else {panic!("expected Notify")};
+        assert_eq!(app, notifications.application);
+        assert_eq!((replaces, summary.as_str(), body.as_str(), timeout), (0, "Title", "Body", 1500));
+        assert_eq!(actions, [DEFAULT, "", "open", "Open", REPLY, "Answer"]);
+        assert_eq!(hints.remove("urgency").map(u8::try_from), Some(Ok(2)));
+        assert_eq!(hints.remove("x-kde-reply-placeholder-text").map(String::try_from), Some(Ok("Say something".to_string())));
+        let image = hints.remove("image-data").unwrap();
+        assert_eq!(image.value_signature().to_string(), "(iiibiiay)");
+        let image = <(i32, i32, i32, bool, i32, i32, Vec<u8>)>::try_from(image).unwrap();
+        assert_eq!(image, (2, 3, 8, true, 8, 4, icon.into_raw()));
+
+        notifications.show("chat", &Notification::new("Title", "Edited")).unwrap();
+        let Call::Notify{replaces, body, actions, hints, timeout, ..} = next(&calls) else {panic!("expected Notify")};
+        assert_eq!((replaces, body.as_str(), timeout), (1, "Edited", -1));
+        assert_eq!(actions, [DEFAULT, ""]);
+        assert!(!hints.contains_key("image-data"));
+
+        server.emit_signal(None::<()>, PATH, INTERFACE, "ActionInvoked", &(1u32, "open")).unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while activations.0.lock().unwrap().pending.is_empty() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(activations.take(), [Activation{id: "chat".into(), action: Some("open".into()), reply_text: None}]);
+
+        notifications.cancel("chat").unwrap();
+        assert!(matches!(next(&calls), Call::Close(1)));
+        notifications.cancel("chat").unwrap();
+        assert!(calls.recv_timeout(Duration::from_millis(200)).is_err(), "a closed notification was closed again");
+    }
+}
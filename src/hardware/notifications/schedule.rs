@@ -0,0 +1,168 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use image::RgbaImage;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Serialize, Deserialize};
+
+use super::{Notification, NotificationAction, NotificationError, Urgency, Scheduled};
+
+const DATABASE: &str = "./notifications.db";
+
+impl From<rusqlite::Error> for NotificationError {
+    fn from(e: rusqlite::Error) -> Self {NotificationError::Storage(e.to_string())}
+}
+
+/// `Notification` as it is stored, the icon is kept as raw RGBA.
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    title: String,
+    body: String,
+    icon: Option<(u32, u32, Vec<u8>)>,
+    urgency: u8,
+    timeout: Option<Duration>,
+    actions: Vec<(String, String, Option<String>)>,
+}
+impl From<&Notification> for Stored {
+    fn from(n: &Notification) -> Self {
+        Stored{
+            title: n.title.clone(),
+            body: n.body.clone(),
+            icon: n.icon.as_ref().map(|i| (i.width(), i.height(), i.as_raw().clone())),
+            urgency: n.urgency as u8,
+            timeout: n.timeout,
+            actions: n.actions.iter().map(|a| (a.id.clone(), a.label.clone(), a.reply.clone())).collect(),
+        }
+    }
+}
+impl From<Stored> for Notification {
+    fn from(s: Stored) -> Self {
+        Notification{
+            title: s.title,
+            body: s.body,
+            icon: s.icon.and_then(|(w, h, raw)| RgbaImage::from_raw(w, h, raw)),
+            urgency: match s.urgency {0 => Urgency::Low, 2 => Urgency::Critical, _ => Urgency::Normal},
+            timeout: s.timeout,
+            actions: s.actions.into_iter().map(|(id, label, reply)| NotificationAction{id, label, reply}).collect(),
+        }
+    }
+}
+
+fn millis(time: SystemTime) -> i64 {time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default()}
+fn time(millis: i64) -> SystemTime {UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)}
+
+/// Moves a repeating schedule to its first time after `now`.
+fn advance(mut scheduled: Scheduled, now: SystemTime) -> Scheduled {
+    if let Some(repeat) = scheduled.repeat && scheduled.at <= now {
+        let interval = repeat.interval();
+        let missed = now.duration_since(scheduled.at).unwrap_or_default().as_nanos() / interval.as_nanos();
+        scheduled.at += interval * (missed as u32 + 1);
+    }
+    scheduled
+}
+
+/// Scheduled notifications, kept in `./notifications.db` so they outlive the process.
+#[derive(Clone)]
+pub(crate) struct Schedule {
+    connection: Arc<Mutex<Option<Connection>>>,
+    /// No later than the earliest time anything is scheduled for, so ticks only read the database when something
+    /// may be due.
+    next: Arc<Mutex<Option<SystemTime>>>,
+}
+impl Schedule {
+    pub fn new() -> Self {Schedule{connection: Arc::new(Mutex::new(None)), next: Arc::new(Mutex::new(None))}}
+
+    /// Opens the database on first use, the working directory is only set up once `hardware::Context` exists.
+    fn with<T>(&self, f: impl FnOnce(&Connection) -> Result<T, rusqlite::Error>) -> Result<T, NotificationError> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            let opened = Connection::open(DATABASE)?;
+            opened.execute("CREATE TABLE if not exists Scheduled(
+                id TEXT NOT NULL PRIMARY KEY,
+                notification TEXT NOT NULL,
+                at INTEGER NOT NULL,
+                repeat TEXT
+            );", [])?;
+            *self.next.lock().unwrap() = earliest(&opened)?;
+            *connection = Some(opened);
+        }
+        Ok(f(connection.as_ref().unwrap())?)
+    }
+
+    pub fn insert(&self, scheduled: &Scheduled) -> Result<(), NotificationError> {
+        self.with(|c| c.execute(
+            "INSERT INTO Scheduled(id, notification, at, repeat) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT DO UPDATE SET notification=excluded.notification, at=excluded.at, repeat=excluded.repeat",
+            params![
+                scheduled.id, serde_json::to_string(&Stored::from(&scheduled.notification)).unwrap(),
+                millis(scheduled.at), scheduled.repeat.map(|r| serde_json::to_string(&r).unwrap()),
+            ]
+        ).map(|_| ()))?;
+        let mut next = self.next.lock().unwrap();
+        *next = Some(next.map_or(scheduled.at, |next| next.min(scheduled.at)));
+        Ok(())
+    }
+
+    /// Returns whether `id` was scheduled.
+    pub fn remove(&self, id: &str) -> Result<bool, NotificationError> {
+        self.with(|c| c.execute("DELETE FROM Scheduled WHERE id = ?1", [id]).map(|n| n > 0))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Scheduled>, NotificationError> {
+        self.with(|c| c.query_row("SELECT id, notification, at, repeat FROM Scheduled WHERE id = ?1", [id], row).optional())
+    }
+
+    /// Every schedule, soonest first. For platforms that deliver them natively, `expire` drops one-off schedules
+    /// whose time has passed and moves repeating ones to their next time.
+    pub fn list(&self, expire: bool) -> Result<Vec<Scheduled>, NotificationError> {
+        if expire {
+            let now = SystemTime::now();
+            self.with(|c| c.execute("DELETE FROM Scheduled WHERE repeat IS NULL AND at <= ?1", [millis(now)]))?;
+            for scheduled in self.passed(now)? {self.insert(&advance(scheduled, now))?;}
+        }
+        self.with(|c| {
+            let mut statement = c.prepare("SELECT id, notification, at, repeat FROM Scheduled ORDER BY at")?;
+            statement.query_map([], row)?.collect::<Result<Vec<_>, _>>()
+        })
+    }
+
+    /// Schedules whose time is not after `now`, soonest first.
+    fn passed(&self, now: SystemTime) -> Result<Vec<Scheduled>, NotificationError> {
+        self.with(|c| {
+            let mut statement = c.prepare("SELECT id, notification, at, repeat FROM Scheduled WHERE at <= ?1 ORDER BY at")?;
+            statement.query_map([millis(now)], row)?.collect::<Result<Vec<_>, _>>()
+        })
+    }
+
+    /// Takes the schedules that are due, moving repeating ones to their next time after now. Nothing is read
+    /// before the earliest schedule comes due.
+    pub fn due(&self) -> Result<Vec<Scheduled>, NotificationError> {
+        let now = SystemTime::now();
+        self.with(|_| Ok(()))?;
+        if self.next.lock().unwrap().is_none_or(|next| next > now) {return Ok(Vec::new());}
+        let due = self.passed(now)?;
+        for scheduled in &due {
+            match scheduled.repeat {
+                Some(_) => self.insert(&advance(scheduled.clone(), now))?,
+                None => {self.remove(&scheduled.id)?;}
+            }
+        }
+        let next = self.with(earliest)?;
+        *self.next.lock().unwrap() = next;
+        Ok(due)
+    }
+}
+
+fn earliest(c: &Connection) -> Result<Option<SystemTime>, rusqlite::Error> {
+    c.query_row("SELECT MIN(at) FROM Scheduled", [], |r| r.get::<_, Option<i64>>(0)).map(|at| at.map(time))
+}
+
+fn row(r: &rusqlite::Row) -> Result<Scheduled, rusqlite::Error> {
+    let corrupt = |e: serde_json::Error| rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e));
+    Ok(Scheduled{
+        id: r.get(0)?,
+        notification: serde_json::from_str::<Stored>(&r.get::<_, String>(1)?).map_err(corrupt)?.into(),
+        at: time(r.get(2)?),
+        repeat: r.get::<_, Option<String>>(3)?.map(|r| serde_json::from_str(&r)).transpose().map_err(corrupt)?,
+    })
+}
@@ -3,17 +3,23 @@ pub mod hardware;
 pub mod window;
 use window::{Window, Renderer, Surface, Input};
 
-pub use air;
+pub mod air;
+use crate::air::{Air, Contracts};
+
+mod runtime;
+use runtime::{Runtime, Services};
 
 #[cfg(target_os = "android")]
 use winit::platform::android::activity::AndroidApp;
 
-use air::{Air, Secret, Services};
-
 mod config;
 pub use config::{IS_MOBILE, IS_WEB};
 
-use rusqlite::OptionalExtension;
+pub mod profiles;
+use profiles::{Profiles, ProfileError};
+
+pub mod vault;
+use vault::{Vault, VaultError, KeySource};
 
 pub trait Application: 'static {
     type Renderer<'surface>: Renderer<'surface, Application=Self>;
@@ -21,61 +27,106 @@ pub trait Application: 'static {
     fn new(context: &mut Context) -> Self;
     fn on_input(&mut self, context: &mut Context, input: Input);
 
+    /// The contracts air runs, every contract an application creates or receives has to be registered.
+    fn contracts() -> Contracts {Contracts::default()}
+
     fn background_services() -> Services {Services::default()}
     fn services() -> Services {Services::default()}
+
+    /// Where the key encrypting the local databases comes from.
+    fn key_source() -> KeySource {KeySource::default()}
+
+    /// Asked for a passphrase whenever the key cannot open the local databases, because the platform has no
+    /// keystore, the keystore is unavailable or the passphrase was wrong. `None` gives up and the application
+    /// does not start.
+    fn passphrase(_error: &VaultError) -> Option<String> {None}
 }
 
 pub struct Context {
     pub hardware: hardware::Context,
     pub window: window::Context,
-    pub air: air::Context
+    pub air: air::Context,
+    pub profiles: Profiles,
 }
 
 pub struct MaverickOS<A: Application> {
     context: Context,
     surface: Surface<A>,
-    runtime: Air,
+    /// `None` only while air restarts.
+    runtime: Option<Runtime>,
     app: A,
 }
 
 impl<A: Application> MaverickOS<A> {
     pub fn start(#[cfg(target_os = "android")] app: AndroidApp) {Window::<A>::start()}
-    fn new(window: window::Context, surface: Surface<A>) -> Self {
+    fn new(window: window::Context, surface: Surface<A>) -> Result<Self, ProfileError> {
         let hardware = hardware::Context::new();
-        let conn = rusqlite::Connection::open("./SECRET.db").unwrap();
-        conn.execute("CREATE TABLE if not exists Cache(
-            key TEXT NOT NULL PRIMARY KEY,
-            value BLOB NOT NULL
-        );", []).unwrap();
-        let secret = match conn.query_row(
-            "SELECT value FROM Cache WHERE key='secret'",
-            [], |r| Ok(serde_json::from_slice(&r.get::<_, Vec<u8>>(0)?).ok()),
-        ).optional().unwrap().flatten() {
-            Some(secret) => secret,
-            None => {
-                let secret = Secret::new();
-                conn.execute(
-                    "INSERT INTO Cache(key, value) VALUES ('secret', ?1) ON CONFLICT DO UPDATE SET value=excluded.value;",
-                    [serde_json::to_vec(&secret).unwrap()],
-                ).unwrap();
-                secret
-            }
-        };
-        let (runtime, mut air) = Air::start(secret);
-        runtime.start_services(A::services(), &mut air);
-        runtime.start_services(A::background_services(), &mut air);
-        
+        let profiles = Self::open_profiles()?;
+        let (runtime, air) = Self::start_air(&hardware, &profiles)?;
+
         let mut context = Context{
             hardware,
             window,
-            air
+            air,
+            profiles,
         };
         let app = A::new(&mut context);
-        MaverickOS{
+        Ok(MaverickOS{
             context,
             surface,
-            runtime,
+            runtime: Some(runtime),
             app
+        })
+    }
+
+    /// Unlocks the vault and opens the profiles, asking the application for a passphrase until one works or
+    /// it gives up.
+    fn open_profiles() -> Result<Profiles, ProfileError> {
+        let mut source = A::key_source();
+        loop {
+            let error = match Vault::unlock(source).map_err(ProfileError::from).and_then(Profiles::open) {
+                Ok(profiles) => return Ok(profiles),
+                Err(ProfileError::Vault(e)) => e,
+                Err(e) => return Err(e),
+            };
+            log::warn!("Could not unlock the local databases: {error}");
+            source = KeySource::Passphrase(A::passphrase(&error).ok_or(error)?);
+        }
+    }
+
+    /// Opens the air cache of the current profile and starts air and every service on it.
+    fn start_air(hardware: &hardware::Context, profiles: &Profiles) -> Result<(Runtime, air::Context), ProfileError> {
+        let (air, context) = Air::start(hardware, A::contracts(), profiles.current(), profiles.secret()?, profiles.vault())?;
+        let runtime = Runtime::start(air, &context, A::services(), A::background_services());
+        Ok((runtime, context))
+    }
+
+    /// Restarts air and every service after `Profiles::switch` or `Profiles::rotate_key`, if either was called.
+    pub(crate) fn apply_profile_changes(&mut self) {
+        let rotation = self.context.profiles.take_rotation();
+        let previous = self.context.profiles.current().clone();
+        let switched = self.context.profiles.take_switch().unwrap_or_else(|e| {log::error!("Could not switch profile: {e}"); None}).map(|p| (previous, p));
+        if rotation.is_none() && switched.is_none() {return;}
+        let paused = self.runtime.as_ref().is_some_and(Runtime::is_paused);
+        if let Some(runtime) = self.runtime.take() {runtime.shutdown();}
+        if let Some(source) = rotation && let Err(e) = self.context.profiles.rotate(source) {
+            log::error!("Could not rotate database key: {e}");
+        }
+        let mut started = Self::start_air(&self.context.hardware, &self.context.profiles);
+        if let Err(e) = &started && let Some((previous, _)) = &switched {
+            log::error!("Could not start air for profile {}, staying on {}: {e}", self.context.profiles.current().name, previous.name);
+            started = self.context.profiles.make_current(previous.clone()).and_then(|_| Self::start_air(&self.context.hardware, &self.context.profiles));
+        }
+        match started {
+            Ok((mut runtime, air)) => {
+                if paused {runtime.pause();}
+                self.runtime = Some(runtime);
+                self.context.air = air;
+            },
+            Err(e) => {log::error!("Could not start air: {e}"); return;}
+        }
+        if let Some((previous, profile)) = switched && self.context.profiles.current() != &previous {
+            self.app.on_input(&mut self.context, Input::ProfileSwitched(profile.name));
         }
     }
 }
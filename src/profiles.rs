@@ -0,0 +1,206 @@
+//! Named local identities, each with its own secret and air cache.
+//!
+//! Secrets live in the `Profiles` table of `SECRET.db`, the secret of an installation that predates profiles
+//! becomes the `default` profile. The `default` profile keeps `./air_cache.db`, every other profile gets
+//! `./profiles/<name>/air_cache.db`.
+//!
+//! Both databases are encrypted with the key held by the [`Vault`].
+//!
+//! `switch` and `rotate_key` take effect once the current input has been handled, the air runtime and every
+//! service are restarted, after a switch under the new identity, and the application receives
+//! [`Input::ProfileSwitched`](crate::window::Input).
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::air::Secret;
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::vault::{Vault, VaultError, KeySource};
+
+pub const DEFAULT: &str = "default";
+
+const SECRETS: &str = "./SECRET.db";
+const CACHE: &str = "air_cache.db";
+const DIRECTORY: &str = "./profiles";
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Database(rusqlite::Error),
+    Io(std::io::Error),
+    Vault(VaultError),
+    /// Names are limited to letters, digits, `-` and `_`.
+    InvalidName(String),
+    Exists(String),
+    NotFound(String),
+    /// The active profile cannot be deleted, switch away from it first.
+    Active(String),
+    Corrupt(String),
+}
+impl std::fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProfileError::Database(e) => write!(f, "Profile database error: {e}"),
+            ProfileError::Io(e) => write!(f, "Profile io error: {e}"),
+            ProfileError::Vault(e) => write!(f, "{e}"),
+            ProfileError::InvalidName(n) => write!(f, "Invalid profile name {n:?}"),
+            ProfileError::Exists(n) => write!(f, "Profile {n} already exists"),
+            ProfileError::NotFound(n) => write!(f, "No profile named {n}"),
+            ProfileError::Active(n) => write!(f, "Profile {n} is active"),
+            ProfileError::Corrupt(e) => write!(f, "Profile secret is corrupt: {e}"),
+        }
+    }
+}
+impl std::error::Error for ProfileError {}
+impl From<rusqlite::Error> for ProfileError {fn from(e: rusqlite::Error) -> Self {ProfileError::Database(e)}}
+impl From<std::io::Error> for ProfileError {fn from(e: std::io::Error) -> Self {ProfileError::Io(e)}}
+impl From<VaultError> for ProfileError {fn from(e: VaultError) -> Self {ProfileError::Vault(e)}}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub created: u64,
+}
+impl Profile {
+    pub fn cache(&self) -> PathBuf {
+        match self.name.as_str() {
+            DEFAULT => PathBuf::from(CACHE),
+            name => PathBuf::from(DIRECTORY).join(name).join(CACHE),
+        }
+    }
+}
+
+pub struct Profiles {
+    connection: Connection,
+    vault: Vault,
+    current: Profile,
+    switch: Option<Profile>,
+    rotation: Option<KeySource>,
+}
+impl Profiles {
+    pub(crate) fn open(vault: Vault) -> Result<Self, ProfileError> {
+        let connection = vault.connect(Path::new(SECRETS))?;
+        connection.execute("CREATE TABLE if not exists Cache(
+            key TEXT NOT NULL PRIMARY KEY,
+            value BLOB NOT NULL
+        );", [])?;
+        connection.execute("CREATE TABLE if not exists Profiles(
+            name TEXT NOT NULL PRIMARY KEY,
+            secret BLOB NOT NULL,
+            created INTEGER NOT NULL
+        );", [])?;
+        if connection.query_row("SELECT COUNT(*) FROM Profiles", [], |r| r.get::<_, i64>(0))? == 0 {
+            let legacy = connection.query_row(
+                "SELECT value FROM Cache WHERE key='secret'",
+                [], |r| Ok(serde_json::from_slice::<Secret>(&r.get::<_, Vec<u8>>(0)?).ok()),
+            ).optional()?.flatten();
+            insert(&connection, DEFAULT, &legacy.unwrap_or_else(Secret::new))?;
+        }
+        let name = connection.query_row("SELECT value FROM Cache WHERE key='profile'", [], |r| r.get::<_, Vec<u8>>(0)).optional()?
+            .map(|n| String::from_utf8_lossy(&n).to_string());
+        let mut profiles = Profiles{connection, vault, current: Profile{name: DEFAULT.to_string(), created: 0}, switch: None, rotation: None};
+        profiles.current = match name.map(|n| profiles.get(&n)) {
+            Some(Ok(profile)) => profile,
+            _ => profiles.get(DEFAULT).or_else(|_| profiles.list()?.into_iter().next().ok_or_else(|| ProfileError::NotFound(DEFAULT.to_string())))?,
+        };
+        let caches = profiles.list()?.iter().map(Profile::cache).collect::<Vec<_>>();
+        profiles.vault.settle(&[&profiles.connection], &caches)?;
+        Ok(profiles)
+    }
+
+    pub fn current(&self) -> &Profile {&self.current}
+
+    pub fn list(&self) -> Result<Vec<Profile>, ProfileError> {
+        let mut statement = self.connection.prepare("SELECT name, created FROM Profiles ORDER BY created, name")?;
+        let profiles = statement.query_map([], |r| Ok(Profile{name: r.get(0)?, created: r.get::<_, i64>(1)? as u64}))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(profiles)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Profile, ProfileError> {
+        self.connection.query_row(
+            "SELECT name, created FROM Profiles WHERE name = ?1", [name],
+            |r| Ok(Profile{name: r.get(0)?, created: r.get::<_, i64>(1)? as u64})
+        ).optional()?.ok_or_else(|| ProfileError::NotFound(name.to_string()))
+    }
+
+    /// Creates a profile with a fresh secret, it is not switched to.
+    pub fn create(&mut self, name: &str) -> Result<Profile, ProfileError> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(ProfileError::InvalidName(name.to_string()));
+        }
+        if self.get(name).is_ok() {return Err(ProfileError::Exists(name.to_string()));}
+        insert(&self.connection, name, &Secret::new())?;
+        let profile = self.get(name)?;
+        if let Some(directory) = profile.cache().parent() {std::fs::create_dir_all(directory)?;}
+        Ok(profile)
+    }
+
+    /// Deletes a profile along with its secret and air cache, this cannot be undone.
+    pub fn delete(&mut self, name: &str) -> Result<(), ProfileError> {
+        let profile = self.get(name)?;
+        if profile == self.current || self.switch.as_ref() == Some(&profile) {return Err(ProfileError::Active(name.to_string()));}
+        self.connection.execute("DELETE FROM Profiles WHERE name = ?1", [name])?;
+        let removed = match name {
+            DEFAULT => std::fs::remove_file(profile.cache()),
+            _ => std::fs::remove_dir_all(PathBuf::from(DIRECTORY).join(name)),
+        };
+        match removed {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(())
+        }
+    }
+
+    /// Switches to `name` once the current input has been handled.
+    pub fn switch(&mut self, name: &str) -> Result<(), ProfileError> {
+        let profile = self.get(name)?;
+        self.switch = (profile != self.current).then_some(profile);
+        Ok(())
+    }
+
+    /// Re-encrypts `SECRET.db` and the air cache of every profile with a key from `source` once the current
+    /// input has been handled.
+    pub fn rotate_key(&mut self, source: KeySource) {self.rotation = Some(source);}
+
+    pub fn vault(&self) -> &Vault {&self.vault}
+
+    pub(crate) fn take_rotation(&mut self) -> Option<KeySource> {self.rotation.take()}
+
+    /// Must only run while no air runtime has a cache open.
+    pub(crate) fn rotate(&mut self, source: KeySource) -> Result<(), ProfileError> {
+        let caches = self.list()?.iter().map(Profile::cache).collect::<Vec<_>>();
+        Ok(self.vault.rotate(source, &[&self.connection], &caches)?)
+    }
+
+    pub(crate) fn secret(&self) -> Result<Secret, ProfileError> {
+        let secret = self.connection.query_row("SELECT secret FROM Profiles WHERE name = ?1", [&self.current.name], |r| r.get::<_, Vec<u8>>(0))?;
+        serde_json::from_slice(&secret).map_err(|e| ProfileError::Corrupt(e.to_string()))
+    }
+
+    /// Makes the requested profile current, returning it when there was one.
+    pub(crate) fn take_switch(&mut self) -> Result<Option<Profile>, ProfileError> {
+        let Some(profile) = self.switch.take() else {return Ok(None);};
+        self.make_current(profile.clone())?;
+        Ok(Some(profile))
+    }
+
+    /// Makes `profile` current and remembers it for the next launch.
+    pub(crate) fn make_current(&mut self, profile: Profile) -> Result<(), ProfileError> {
+        self.connection.execute(
+            "INSERT INTO Cache(key, value) VALUES ('profile', ?1) ON CONFLICT DO UPDATE SET value=excluded.value;",
+            [profile.name.as_bytes()],
+        )?;
+        self.current = profile;
+        Ok(())
+    }
+}
+
+fn insert(connection: &Connection, name: &str, secret: &Secret) -> Result<(), rusqlite::Error> {
+    let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default() as i64;
+    connection.execute(
+        "INSERT INTO Profiles(name, secret, created) VALUES (?1, ?2, ?3)",
+        params![name, serde_json::to_vec(secret).unwrap(), created],
+    )?;
+    Ok(())
+}
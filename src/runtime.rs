@@ -3,10 +3,13 @@ use tokio::time::sleep;
 
 use std::time::Duration;
 
-use air::{Air, Secret};
+use crate::air::{self, Air};
 
 pub use async_trait::async_trait;
 
+/// How long `shutdown` waits for air and the services to stop, so their databases are closed.
+const SHUTDOWN: Duration = Duration::from_secs(2);
+
 pub type Services = Vec<Box<dyn Service>>;
 
 #[async_trait]
@@ -32,18 +35,22 @@ impl Task {
     }
 }
 
+/// Runs air and the services of an application, services are paused while the application is in the
+/// background, background services keep running.
 pub(crate) struct Runtime(Option<tokio::runtime::Runtime>, Sender<bool>);
 impl Runtime {
-    pub fn start(secret: Secret, services: Services, background: Services) -> (Self, air::Context) {
-        let air = runtime.block_on(async {Air::start(secret)});
+    pub fn start(air: Air, context: &air::Context, services: Services, background: Services) -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        runtime.spawn(air.run());
         let (tx, rx) = channel(true);
-        background.into_iter().for_each(|s| {runtime.spawn(Task(s).run(air.clone(), None));});
-        services.into_iter().for_each(|s| {runtime.spawn(Task(s).run(air.clone(), Some(rx.clone())));});
+        background.into_iter().for_each(|s| {runtime.spawn(Task(s).run(context.clone(), None));});
+        services.into_iter().for_each(|s| {runtime.spawn(Task(s).run(context.clone(), Some(rx.clone())));});
 
-        (Runtime(Some(runtime), tx), air)
+        Runtime(Some(runtime), tx)
     }
 
     pub fn pause(&mut self) {let _ = self.1.send(false);}
     pub fn resume(&mut self) {let _ = self.1.send(true);}
-    pub fn shutdown(&mut self) {if let Some(r) = self.0.take() {r.shutdown_background();}}
+    pub fn is_paused(&self) -> bool {!*self.1.borrow()}
+    pub fn shutdown(mut self) {if let Some(r) = self.0.take() {r.shutdown_timeout(SHUTDOWN);}}
 }
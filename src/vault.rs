@@ -0,0 +1,398 @@
+//! Encryption at rest for the local databases.
+//!
+//! `SECRET.db` and every air cache are SQLCipher databases keyed with a 256 bit key. The key is either
+//! generated once and kept in the platform keystore, or derived from a user passphrase with Argon2id and a salt
+//! stored next to `SECRET.db`. A database that is still plaintext, from an install that predates encryption,
+//! is encrypted in place the first time it is opened.
+//!
+//! Rotating the key re-encrypts every database, see `Profiles::rotate_key`. The new key, and under it the old
+//! one, are saved before any database is touched and the old key is only dropped once every database moved, a
+//! rotation cut short is finished the next time the vault unlocks. A rotation that fails is rolled back, should a
+//! database not move back the new key is saved under the old one as well and that database is moved back when the
+//! vault next settles, the new key is never committed. There is no keystore on Android, and none on Linux without
+//! a secret service, applications are asked for a passphrase through
+//! [`Application::passphrase`](crate::Application::passphrase) when the keystore fails.
+
+use std::path::{Path, PathBuf};
+
+use rand::RngCore;
+
+use rusqlite::Connection;
+
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+const SERVICE: &str = "maverick_os";
+const ACCOUNT: &str = "database-key";
+const PENDING: &str = "database-key-pending";
+const SALT: &str = "./vault.salt";
+const SALT_PENDING: &str = "./vault.salt.pending";
+/// Holds the key a rotation replaces, encrypted with the key it is replaced by.
+const JOURNAL: &str = "./vault.rotation";
+/// Holds the key of a rotation that could not be fully rolled back, encrypted with the key it was rolled back to.
+const ROLLBACK: &str = "./vault.rollback";
+
+#[derive(Debug)]
+pub enum VaultError {
+    Database(rusqlite::Error),
+    Io(std::io::Error),
+    #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+    Keystore(keyring::Error),
+    /// The platform has no keystore, use a passphrase.
+    Unsupported,
+    Derivation(String),
+    /// The key does not decrypt the database.
+    WrongKey(PathBuf),
+}
+impl std::fmt::Display for VaultError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VaultError::Database(e) => write!(f, "Vault database error: {e}"),
+            VaultError::Io(e) => write!(f, "Vault io error: {e}"),
+            #[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+            VaultError::Keystore(e) => write!(f, "Keystore error: {e}"),
+            VaultError::Unsupported => write!(f, "No keystore on this platform"),
+            VaultError::Derivation(e) => write!(f, "Could not derive key: {e}"),
+            VaultError::WrongKey(p) => write!(f, "Key does not open {}", p.display()),
+        }
+    }
+}
+impl std::error::Error for VaultError {}
+impl From<rusqlite::Error> for VaultError {fn from(e: rusqlite::Error) -> Self {VaultError::Database(e)}}
+impl From<std::io::Error> for VaultError {fn from(e: std::io::Error) -> Self {VaultError::Io(e)}}
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+impl From<keyring::Error> for VaultError {fn from(e: keyring::Error) -> Self {VaultError::Keystore(e)}}
+
+/// Where the database key comes from.
+#[derive(Clone, Default)]
+pub enum KeySource {
+    #[default]
+    Keystore,
+    Passphrase(String),
+}
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeySource::Keystore => write!(f, "Keystore"),
+            KeySource::Passphrase(_) => write!(f, "Passphrase(..)"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Vault {
+    key: [u8; 32],
+    source: KeySource,
+    /// The key of an unfinished rotation, databases still on it are moved to `key` as they are opened.
+    previous: Option<[u8; 32]>,
+    /// `previous` is the key of a rotation that was rolled back, settling drops it instead of committing `key`.
+    rolled_back: bool,
+}
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {write!(f, "Vault({:?})", self.source)}
+}
+impl Vault {
+    /// Loads the key, creating one in the keystore or a new salt for the passphrase on first use. When a
+    /// rotation was cut short the key it moved to is loaded along with the one it replaces.
+    pub fn unlock(source: KeySource) -> Result<Self, VaultError> {
+        let pending = match &source {
+            KeySource::Keystore => load(PENDING)?,
+            KeySource::Passphrase(passphrase) => read(SALT_PENDING)?.map(|salt| derive(passphrase, &salt)).transpose()?,
+        };
+        if let Some(key) = pending && let Some(previous) = journal(JOURNAL, &key)? {
+            return Ok(Vault{key, source, previous: Some(previous), rolled_back: false});
+        }
+        let key = match &source {
+            KeySource::Keystore => match load(ACCOUNT)? {
+                Some(key) => key,
+                None => {let key = random(); store(ACCOUNT, &key)?; key}
+            },
+            KeySource::Passphrase(passphrase) => {
+                let salt = match read(SALT)? {
+                    Some(salt) => salt,
+                    None => {
+                        let salt = random::<16>().to_vec();
+                        std::fs::write(SALT, &salt)?;
+                        salt
+                    }
+                };
+                derive(passphrase, &salt)?
+            }
+        };
+        let previous = journal(ROLLBACK, &key)?;
+        Ok(Vault{key, source, rolled_back: previous.is_some(), previous})
+    }
+
+    pub fn source(&self) -> &KeySource {&self.source}
+
+    pub(crate) fn pragma(key: &[u8; 32]) -> String {format!("x'{}'", hex::encode(key))}
+
+    /// Opens an encrypted database, encrypting it first when it is still plaintext and moving it to the current
+    /// key when it is still on the one an unfinished rotation replaced.
+    pub fn connect(&self, path: &Path) -> Result<Connection, VaultError> {
+        if let Some(previous) = &self.previous && keyed(path, &self.key)?.is_none() && let Some(connection) = keyed(path, previous)? {
+            rekey(&connection, &self.key)?;
+            return Ok(connection);
+        }
+        open(path, &self.key)
+    }
+
+    /// Finishes a rotation that was cut short, or the rollback of one, moving `connections` and the databases at
+    /// `paths` to the current key before the other one is forgotten.
+    pub(crate) fn settle(&mut self, connections: &[&Connection], paths: &[PathBuf]) -> Result<(), VaultError> {
+        if self.previous.is_none() {return Ok(());}
+        // An open database may be one the rollback left on the new key.
+        if self.rolled_back {for connection in connections {rekey(connection, &self.key)?;}}
+        for path in paths.iter().filter(|p| p.exists()) {self.connect(path)?;}
+        match self.rolled_back {
+            true => abandon()?,
+            false => commit(&self.source, &self.key)?,
+        }
+        self.previous = None;
+        self.rolled_back = false;
+        Ok(())
+    }
+
+    /// Re-encrypts `connections` and the databases at `paths` with a key from `source`. The new key and the
+    /// journal holding the old one are saved first, the old key is only replaced once every database moved. If
+    /// any database fails, those already re-encrypted are switched back and the old key stays, the databases that
+    /// do not switch back are moved when the vault next settles.
+    pub fn rotate(&mut self, source: KeySource, connections: &[&Connection], paths: &[PathBuf]) -> Result<(), VaultError> {
+        self.settle(connections, paths)?;
+        let (key, salt) = match &source {
+            KeySource::Keystore => (random(), None),
+            KeySource::Passphrase(passphrase) => {
+                let salt = random::<16>().to_vec();
+                (derive(passphrase, &salt)?, Some(salt))
+            }
+        };
+        match &salt {
+            Some(salt) => std::fs::write(SALT_PENDING, salt)?,
+            None => store(PENDING, &key)?,
+        }
+        record(JOURNAL, &key, &self.key)?;
+        let targets = connections.iter().map(|c| Target::Open(c))
+            .chain(paths.iter().filter(|p| p.exists()).map(|p| Target::File(p))).collect::<Vec<_>>();
+        if let Err((e, stuck)) = rekey_all(&targets, &self.key, &key) {
+            if stuck.is_empty() {
+                if let Err(e) = abandon() {log::error!("Could not discard the new key: {e}");}
+                return Err(e);
+            }
+            for target in &stuck {log::error!("{target} is left on the new key until the vault settles");}
+            record(ROLLBACK, &key, &self.key)?;
+            self.previous = Some(key);
+            self.rolled_back = true;
+            return Err(e);
+        }
+        self.previous = Some(std::mem::replace(&mut self.key, key));
+        self.source = source;
+        commit(&self.source, &self.key)?;
+        self.previous = None;
+        Ok(())
+    }
+}
+
+/// A database a rotation moves.
+trait Rekey: std::fmt::Display {
+    fn rekey(&self, from: &[u8; 32], to: &[u8; 32]) -> Result<(), VaultError>;
+}
+
+enum Target<'a> {
+    Open(&'a Connection),
+    File(&'a Path),
+}
+impl Rekey for Target<'_> {
+    fn rekey(&self, from: &[u8; 32], to: &[u8; 32]) -> Result<(), VaultError> {
+        match self {
+            Target::Open(connection) => rekey(connection, to),
+            Target::File(path) => rekey(&open(path, from)?, to),
+        }
+    }
+}
+impl std::fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Target::Open(connection) => write!(f, "{}", connection.path().unwrap_or("An open database")),
+            Target::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Moves every target from `from` to `to`. When one fails those already moved are moved back, the error is
+/// returned along with the targets that are left on `to`.
+fn rekey_all<'a, T: Rekey>(targets: &'a [T], from: &[u8; 32], to: &[u8; 32]) -> Result<(), (VaultError, Vec<&'a T>)> {
+    for (i, target) in targets.iter().enumerate() {
+        if let Err(e) = target.rekey(from, to) {
+            let stuck = targets[..i].iter().filter(|done| done.rekey(to, from)
+                .inspect_err(|e| log::error!("Could not restore the key of {done}: {e}")).is_err()).collect();
+            return Err((e, stuck));
+        }
+    }
+    Ok(())
+}
+
+fn read(path: &str) -> Result<Option<Vec<u8>>, VaultError> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Saves `previous` in the journal at `file`, readable only with `key`.
+fn record(file: &str, key: &[u8; 32], previous: &[u8; 32]) -> Result<(), VaultError> {
+    let _ = std::fs::remove_file(file);
+    let connection = keyed(Path::new(file), key)?.ok_or_else(|| VaultError::WrongKey(PathBuf::from(file)))?;
+    connection.execute("CREATE TABLE Rotation(previous BLOB NOT NULL);", [])?;
+    connection.execute("INSERT INTO Rotation(previous) VALUES (?1)", [previous.as_slice()])?;
+    Ok(())
+}
+
+/// The key saved by [`record`] at `file`, `None` when there is no journal or `key` does not open it.
+fn journal(file: &str, key: &[u8; 32]) -> Result<Option<[u8; 32]>, VaultError> {
+    if !Path::new(file).exists() {return Ok(None);}
+    let Some(connection) = keyed(Path::new(file), key)? else {return Ok(None);};
+    let previous = connection.query_row("SELECT previous FROM Rotation", [], |r| r.get::<_, Vec<u8>>(0))?;
+    Ok(previous.try_into().ok())
+}
+
+/// Makes the pending key of a rotation the key and drops the journals.
+fn commit(source: &KeySource, key: &[u8; 32]) -> Result<(), VaultError> {
+    match source {
+        KeySource::Keystore => {store(ACCOUNT, key)?; forget(PENDING)?;},
+        KeySource::Passphrase(_) => std::fs::rename(SALT_PENDING, SALT)?,
+    }
+    std::fs::remove_file(JOURNAL)?;
+    remove(ROLLBACK)
+}
+
+/// Drops the pending key of a rotation that was rolled back, whichever source it came from, and the journals.
+fn abandon() -> Result<(), VaultError> {
+    // A pending key left in the keystore is harmless without the journal, and passphrase vaults may have no keystore.
+    let _ = forget(PENDING);
+    remove(SALT_PENDING)?;
+    remove(JOURNAL)?;
+    remove(ROLLBACK)
+}
+
+fn remove(path: &str) -> Result<(), VaultError> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn derive(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], VaultError> {
+    let mut key = [0; 32];
+    argon2::Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| VaultError::Derivation(e.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+fn load(account: &str) -> Result<Option<[u8; 32]>, VaultError> {
+    match keyring::Entry::new(SERVICE, account)?.get_password() {
+        Ok(key) => Ok(hex::decode(key).ok().and_then(|k| k.try_into().ok())),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+fn store(account: &str, key: &[u8; 32]) -> Result<(), VaultError> {
+    Ok(keyring::Entry::new(SERVICE, account)?.set_password(&hex::encode(key))?)
+}
+
+#[cfg(not(any(target_os = "android", target_arch = "wasm32")))]
+fn forget(account: &str) -> Result<(), VaultError> {
+    match keyring::Entry::new(SERVICE, account)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(any(target_os = "android", target_arch = "wasm32"))]
+fn load(_account: &str) -> Result<Option<[u8; 32]>, VaultError> {Err(VaultError::Unsupported)}
+
+#[cfg(any(target_os = "android", target_arch = "wasm32"))]
+fn store(_account: &str, _key: &[u8; 32]) -> Result<(), VaultError> {Err(VaultError::Unsupported)}
+
+#[cfg(any(target_os = "android", target_arch = "wasm32"))]
+fn forget(_account: &str) -> Result<(), VaultError> {Err(VaultError::Unsupported)}
+
+fn readable(connection: &Connection) -> bool {
+    connection.query_row("SELECT count(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0)).is_ok()
+}
+
+/// Opens `path` with `key`, `None` when the key does not decrypt it.
+fn keyed(path: &Path, key: &[u8; 32]) -> Result<Option<Connection>, VaultError> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "key", Vault::pragma(key))?;
+    Ok(readable(&connection).then_some(connection))
+}
+
+fn open(path: &Path, key: &[u8; 32]) -> Result<Connection, VaultError> {
+    if let Some(connection) = keyed(path, key)? {return Ok(connection);}
+    if !plaintext(path)? {return Err(VaultError::WrongKey(path.to_path_buf()));}
+    encrypt(path, key)?;
+    keyed(path, key)?.ok_or_else(|| VaultError::WrongKey(path.to_path_buf()))
+}
+
+fn plaintext(path: &Path) -> Result<bool, VaultError> {Ok(readable(&Connection::open(path)?))}
+
+fn rekey(connection: &Connection, key: &[u8; 32]) -> Result<(), VaultError> {
+    Ok(connection.pragma_update(None, "rekey", Vault::pragma(key))?)
+}
+
+/// Replaces a plaintext database with an encrypted copy.
+fn encrypt(path: &Path, key: &[u8; 32]) -> Result<(), VaultError> {
+    let encrypted = path.with_extension("encrypting");
+    let _ = std::fs::remove_file(&encrypted);
+    let connection = Connection::open(path)?;
+    connection.execute(&format!("ATTACH DATABASE ?1 AS encrypted KEY \"{}\"", Vault::pragma(key)), [encrypted.to_string_lossy()])?;
+    connection.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    connection.execute("DETACH DATABASE encrypted", [])?;
+    drop(connection);
+    std::fs::rename(&encrypted, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// A database on the key in `key` that refuses to move to the key in `refuses`.
+    struct Fake {name: &'static str, key: Cell<[u8; 32]>, refuses: Option<[u8; 32]>}
+    impl Fake {
+        fn new(name: &'static str, key: [u8; 32], refuses: Option<[u8; 32]>) -> Self {Fake{name, key: Cell::new(key), refuses}}
+    }
+    impl Rekey for Fake {
+        fn rekey(&self, from: &[u8; 32], to: &[u8; 32]) -> Result<(), VaultError> {
+            if self.key.get() != *from || self.refuses == Some(*to) {return Err(VaultError::WrongKey(PathBuf::from(self.name)));}
+            self.key.set(*to);
+            Ok(())
+        }
+    }
+    impl std::fmt::Display for Fake {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {write!(f, "{}", self.name)}
+    }
+
+    #[test]
+    fn rollback_failing_partway_reports_stranded_databases() {
+        let (old, new) = ([1; 32], [2; 32]);
+        let targets = [Fake::new("moves", old, None), Fake::new("stays", old, Some(old)), Fake::new("fails", old, Some(new))];
+        let Err((_, stuck)) = rekey_all(&targets, &old, &new) else {panic!("the rotation should fail")};
+        assert_eq!(stuck.iter().map(|t| t.name).collect::<Vec<_>>(), ["stays"]);
+        assert_eq!(targets.iter().map(|t| t.key.get()).collect::<Vec<_>>(), [old, new, old]);
+
+        let targets = [Fake::new("moves", old, None), Fake::new("also", old, None)];
+        assert!(rekey_all(&targets, &old, &new).is_ok());
+        assert!(targets.iter().all(|t| t.key.get() == new));
+    }
+}
@@ -19,6 +19,7 @@ pub use winit::keyboard::{NamedKey, SmolStr, Key};
 use winit::window::Window as WinitWindow;
 
 use crate::{MaverickOS, Application};
+use crate::hardware::{CapturedPhoto, ClipboardFormat};
 
 use raw_window_handle::{HasWindowHandle, HasDisplayHandle};
 
@@ -70,6 +71,11 @@ pub enum Input {
     Resized,
     Focused(bool),
     CameraFrame(RgbaImage),
+    /// A photo taken by [`Camera::capture_photo`](crate::hardware::Camera::capture_photo).
+    CapturedPhoto(CapturedPhoto),
+    /// A QR code or barcode came into sight while [`Camera::scan`](crate::hardware::Camera::scan) is enabled.
+    /// `corners` go clockwise from its top left, in pixels of the frame it was found in.
+    CodeDetected{text: String, corners: [(f32, f32); 4]},
     Photo(RgbaImage),
     PickedPhoto(RgbaImage),
     DroppedFile(PathBuf),
@@ -91,6 +97,13 @@ pub enum Input {
     Moved((i32, i32)),
     Touch(Touch),
     Device{device_id: DeviceId, event: DeviceEvent},
+    /// The clipboard holds new contents copied by another application, in these formats.
+    /// Only delivered while [`Clipboard::watch`](crate::hardware::Clipboard::watch) is enabled.
+    ClipboardChanged(Vec<ClipboardFormat>),
+    /// The notification `id` was clicked, or its action `action`. `reply_text` is what was typed into a reply action.
+    NotificationActivated{id: String, action: Option<String>, reply_text: Option<String>},
+    /// Air and services now run under the named profile.
+    ProfileSwitched(String),
 }
 
 pub(crate) struct Window<A: Application>(Option<MaverickOS<A>>);
@@ -119,7 +132,7 @@ impl<A: Application> ApplicationHandler for Window<A> {
 
     fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
         if let Some(maverick) = self.0.as_mut() {
-            maverick.runtime.pause();
+            if let Some(runtime) = maverick.runtime.as_mut() {runtime.pause();}
             maverick.surface.suspend();
         }
     }
@@ -132,14 +145,20 @@ impl<A: Application> ApplicationHandler for Window<A> {
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {match &mut self.0 {
         Some(maverick) => {
-            maverick.runtime.resume();
+            if let Some(runtime) = maverick.runtime.as_mut() {runtime.resume();}
             maverick.surface.resurface(&maverick.context.window);
         },
         none => {
             let window = event_loop.create_window(WinitWindow::default_attributes().with_title("orange")).unwrap();
             let context = Context::new(&window);
             let surface = Surface::new(window, &context);
-            *none = Some(MaverickOS::new(context, surface));
+            match MaverickOS::new(context, surface) {
+                Ok(maverick) => *none = Some(maverick),
+                Err(e) => {
+                    log::error!("Could not start: {e}");
+                    event_loop.exit();
+                }
+            }
         }
     }}
 
@@ -152,7 +171,7 @@ impl<A: Application> ApplicationHandler for Window<A> {
             let event = match event {
                 WindowEvent::CloseRequested | WindowEvent::Destroyed => {
                     let maverick = self.0.take().unwrap();
-                    maverick.runtime.shutdown();
+                    if let Some(runtime) = maverick.runtime {runtime.shutdown();}
                     event_loop.exit();
                     return;
                 },
@@ -163,6 +182,7 @@ impl<A: Application> ApplicationHandler for Window<A> {
                     for event in maverick.context.hardware.tick() {
                         maverick.app.on_input(&mut maverick.context, event);
                     }
+                    maverick.apply_profile_changes();
                     if let Some(surface) = maverick.surface.as_mut() {
                         surface.draw(&maverick.context.window, &maverick.app);
                     } else {log::warn!("Redraw Requested Without A Valid Surface");}
@@ -170,7 +190,7 @@ impl<A: Application> ApplicationHandler for Window<A> {
                 },
                 WindowEvent::Occluded(true) => {
                     #[cfg(target_os = "ios")]
-                    maverick.runtime.pause();
+                    if let Some(runtime) = maverick.runtime.as_mut() {runtime.pause();}
                     return;
                 },
                 WindowEvent::Resized(size) => {
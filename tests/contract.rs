@@ -0,0 +1,6 @@
+#[test]
+fn contract() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/contract/pass/*.rs");
+    cases.compile_fail("tests/contract/fail/*.rs");
+}
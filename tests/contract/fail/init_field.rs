@@ -0,0 +1,15 @@
+use maverick_os::air::contract;
+
+contract! {
+    Room {name: String, author: String}
+
+    init(String) {name: self, owner: author}
+
+    routes {"/name": [Rename]}
+
+    reactants {
+        Rename(String) {write self to /name}
+    }
+}
+
+fn main() {}
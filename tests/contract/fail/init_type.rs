@@ -0,0 +1,15 @@
+use maverick_os::air::{contract, Name};
+
+contract! {
+    Room {name: String, author: Name, size: u32}
+
+    init(String) {name: 5, author, size: 1}
+
+    routes {"/name": [Rename]}
+
+    reactants {
+        Rename(String) {write self to /name}
+    }
+}
+
+fn main() {}
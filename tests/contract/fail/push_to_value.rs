@@ -0,0 +1,13 @@
+use maverick_os::air::contract;
+
+contract! {
+    Room {name: String}
+
+    routes {"/name": [Send]}
+
+    reactants {
+        Send(String) {push self to /name}
+    }
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+use maverick_os::air::contract;
+
+contract! {
+    Room {name: String, messages: Vec<String>}
+
+    routes {"/messages": [Send]}
+
+    reactants {
+        Send(String) {push self to /message}
+    }
+}
+
+fn main() {}
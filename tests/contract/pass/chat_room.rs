@@ -0,0 +1,35 @@
+use maverick_os::air::{contract, Contract, Name};
+
+contract! {
+    ChatRoom {name: String, author: Name, topic: Option<String>, admins: Vec<Name>, messages: Vec<Message>}
+    Message {body: String, author: Name, timestamp: u64, comments: Vec<Comment>}
+    Comment {body: String, author: Name, timestamp: u64}
+
+    init(String) {name: self, author, admins: [author], messages: [Message{body: "Welcome", author, timestamp, comments: []}]}
+
+    routes {
+        "/name": [ChangeName],
+        "/messages": [SendMessage],
+        "/messages/*": [EditMessage],
+        "/messages/*/comments": [SendComment],
+    }
+
+    access {
+        "/name": owner(/author),
+        "/messages": anyone,
+        "/messages/*" [EditMessage]: owner(./author) | member(/admins),
+    }
+
+    reactants {
+        ChangeName(String) {if /author == author {write self to /name}}
+        SendMessage(String) {push Message{body: self, author, timestamp, comments: []} to ./}
+        EditMessage(String) {write self to ./body}
+        SendComment(String) {push Comment{body: self, author, timestamp} to ./}
+    }
+}
+
+fn main() {
+    let _ = <ChatRoom as Contract>::init as fn(String, Name, u64) -> ChatRoom;
+    let _ = SendComment::at("/messages/0/comments", "Hello".to_string());
+    let _ = ChangeName::new("The Room".to_string());
+}
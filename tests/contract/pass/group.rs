@@ -0,0 +1,30 @@
+use maverick_os::air::{contract, Contract, Members, Name, Role};
+
+contract! {
+    Group {name: String, members: Members, messages: Vec<String>}
+
+    init(String) {name: self, members: [author], messages: []}
+
+    builtins [Membership]
+
+    routes {
+        "/messages": [Post],
+    }
+
+    access {
+        "/messages": role(/members, Writer),
+        "/members" [Membership]: anyone,
+    }
+
+    reactants {
+        Post(String) {push self to ./}
+    }
+}
+
+fn owned(author: Name) -> bool {
+    <Group as Contract>::init("Friends".to_string(), author.clone(), 0).members.role(&author) == Some(Role::Owner)
+}
+
+fn main() {
+    let _ = owned;
+}
@@ -0,0 +1,67 @@
+use maverick_os::air::{Text, Edit, Id};
+
+/// Every order `edits` can arrive in.
+fn orders(edits: &[Edit]) -> Vec<Vec<Edit>> {
+    if edits.is_empty() {return vec![vec![]];}
+    (0..edits.len()).flat_map(|i| {
+        let mut rest = edits.to_vec();
+        let first = rest.remove(i);
+        orders(&rest).into_iter().map(move |mut order| {order.insert(0, first.clone()); order})
+    }).collect()
+}
+
+/// Applies `edits` to `base` in every order and checks all copies end up identical.
+fn converge(base: &Text, edits: &[Edit]) -> Text {
+    let copies = orders(edits).into_iter().map(|order| {
+        let mut copy = base.clone();
+        order.into_iter().for_each(|e| copy.apply(e));
+        copy
+    }).collect::<Vec<_>>();
+    for copy in &copies {assert_eq!(copy, &copies[0], "{copy} differs from {}", copies[0]);}
+    copies[0].clone()
+}
+
+/// Builds an edit on `replica` and applies it there, like an editor does before sending it.
+fn local(replica: &mut Text, build: impl FnOnce(&Text) -> Edit) -> Edit {
+    let edit = build(replica);
+    replica.apply(edit.clone());
+    edit
+}
+
+#[test]
+fn concurrent_edits_converge() {
+    let base = Text::from("hello world");
+    let (a, b, c) = (Id::hash("a"), Id::hash("b"), Id::hash("c"));
+    let (mut one, mut two, mut three) = (base.clone(), base.clone(), base.clone());
+    let edits = vec![
+        local(&mut one, |t| t.insert(5, ",", a)),
+        local(&mut one, |t| t.insert(6, " wide", a)),
+        local(&mut two, |t| t.delete(0..5)),
+        local(&mut two, |t| t.insert(0, "goodbye", b)),
+        local(&mut three, |t| t.insert(11, "!", c)),
+    ];
+    let text = converge(&base, &edits);
+    assert_eq!(text.to_string(), "goodbye, wide world!");
+}
+
+#[test]
+fn same_position_keeps_both() {
+    let base = Text::from("ab");
+    let edits = vec![base.insert(1, "x", Id::hash("phone")), base.insert(1, "y", Id::hash("laptop"))];
+    let text = converge(&base, &edits);
+    assert_eq!(text.len(), 4);
+    assert!(["axyb", "ayxb"].contains(&text.to_string().as_str()));
+}
+
+#[test]
+fn edits_wait_for_what_they_reference() {
+    let base = Text::default();
+    let site = Id::hash("a");
+    let mut replica = base.clone();
+    let first = local(&mut replica, |t| t.insert(0, "abc", site));
+    let second = local(&mut replica, |t| t.insert(3, "def", site));
+    let third = local(&mut replica, |t| t.delete(1..4));
+    let text = converge(&base, &[first, second, third]);
+    assert_eq!(text.to_string(), "aef");
+    assert_eq!(text, replica);
+}